@@ -0,0 +1,121 @@
+//! Throughput benchmarks of the sample extraction and classification hot loop,
+//! which also check that it does not allocate memory on every sample
+
+extern crate criterion;
+extern crate perf_script_analyze;
+
+use criterion::{Criterion, Throughput};
+use perf_script_analyze::analysis::SampleAnalyzer;
+use perf_script_analyze::mapped::MappedSamples;
+use perf_script_analyze::sample::{PerfSamples, SampleSource};
+use perf_script_analyze::stats::Statistics;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::env;
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+
+/// Memory allocator which counts how many allocations were performed
+struct CountingAllocator;
+//
+static NUM_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+//
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        NUM_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        NUM_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+//
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+
+/// Number of samples in the synthetic perf script output
+const NUM_SAMPLES: usize = 10_000;
+
+/// Generate some synthetic perf script output, featuring a mixture of normal
+/// and anomalous samples with stacks of various depths
+fn synthetic_script() -> Vec<u8> {
+    let mut script = String::new();
+    for i in 0..NUM_SAMPLES {
+        script.push_str(&format!("prog {:5} [{:03}] {}.{:06}:     250000 cycles:u: \n",
+                                 1000 + i % 7, i % 16, 12345 + i / 1000, i % 1000));
+        for depth in 0..(i % 32) {
+            script.push_str(&format!("\t    7f{:010x} func_{} (/usr/lib/libfoo.so)\n",
+                                     depth * 0x40, depth));
+        }
+        match i % 4 {
+            0 => script.push_str("\t    7f0000002000 __libc_start_main (/usr/lib/libc.so.6)\n"),
+            1 => script.push_str("\t    ffffffffffffffff [unknown] ([unknown])\n"),
+            2 => script.push_str("\t    7f0000003000 weird_root (/usr/lib/libbar.so)\n"),
+            _ => {},
+        }
+        script.push('\n');
+    }
+    script.into_bytes()
+}
+
+/// Extract and classify every sample from a source, like the main program does
+fn analyze<S: SampleSource>(samples: &mut S,
+                            analyzer: &SampleAnalyzer,
+                            stats: &mut Statistics) {
+    while let Some(sample) = samples.next_sample().unwrap() {
+        stats.record(&analyzer.classify(&sample));
+    }
+}
+
+/// Check that the analysis performs a bounded amount of memory allocations,
+/// which does not grow with the number of samples
+fn check_allocations(script: &[u8]) {
+    let analyzer = SampleAnalyzer::new();
+    let mut stats = Statistics::new();
+    let mut samples = PerfSamples::new(script);
+    let initial_allocations = NUM_ALLOCATIONS.load(Ordering::Relaxed);
+    analyze(&mut samples, &analyzer, &mut stats);
+    let num_allocations = NUM_ALLOCATIONS.load(Ordering::Relaxed) - initial_allocations;
+    assert_eq!(stats.num_samples, NUM_SAMPLES);
+    assert!(num_allocations < 100,
+            "Hot loop performed {} allocations for {} samples",
+            num_allocations, NUM_SAMPLES);
+}
+
+fn main() {
+    let script = synthetic_script();
+    check_allocations(&script);
+
+    let mut script_path = env::temp_dir();
+    script_path.push(format!("perf-script-analyze-bench-{}.txt", std::process::id()));
+    fs::write(&script_path, &script).unwrap();
+
+    let mut criterion = Criterion::default().configure_from_args();
+    {
+        let mut group = criterion.benchmark_group("analysis");
+        group.throughput(Throughput::Bytes(script.len() as u64));
+        let analyzer = SampleAnalyzer::new();
+        group.bench_function("buffered", |b| b.iter(|| {
+            let mut stats = Statistics::new();
+            analyze(&mut PerfSamples::new(&script[..]), &analyzer, &mut stats);
+            stats
+        }));
+        group.bench_function("mapped", |b| b.iter(|| {
+            let mut stats = Statistics::new();
+            let mut samples = MappedSamples::open(&script_path).unwrap();
+            analyze(&mut samples, &analyzer, &mut stats);
+            stats
+        }));
+        group.finish();
+    }
+    criterion.final_summary();
+
+    fs::remove_file(&script_path).unwrap();
+}
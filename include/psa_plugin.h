@@ -0,0 +1,68 @@
+/*
+ * Plugin ABI of perf-script-analyze, see src/plugin.rs for the semantics of
+ * each function. Only psa_plugin_abi_version must be exported, the other
+ * functions are optional.
+ *
+ * Strings and frames passed to the plugin are only valid during the call.
+ */
+
+#ifndef PSA_PLUGIN_H
+#define PSA_PLUGIN_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Version of the plugin ABI, which is bumped on every incompatible change */
+#define PSA_PLUGIN_ABI_VERSION 1
+
+/* Borrowed string, which is not NUL-terminated */
+typedef struct {
+    const char *ptr;
+    size_t len;
+} PluginStr;
+
+/* Decoded stack frame */
+typedef struct {
+    /* Instruction pointer, or 0 if it could not be decoded */
+    uint64_t ip;
+    PluginStr symbol;
+    PluginStr dso;
+} PluginFrame;
+
+/* Decoded sample */
+typedef struct {
+    /* Text of the sample, as printed by perf script */
+    PluginStr raw_text;
+
+    /* Name of the sampled thread, empty if unknown */
+    PluginStr comm;
+
+    /* Name of the sampled event, empty if unknown */
+    PluginStr event;
+
+    /* Process and thread ID, or -1 if unknown */
+    int64_t pid;
+    int64_t tid;
+
+    /* Stack frames, from the leaf function to the root of the stack */
+    const PluginFrame *frames;
+    size_t num_frames;
+} PluginSample;
+
+uint32_t psa_plugin_abi_version(void);
+void *psa_plugin_new(void);
+const char *psa_plugin_category(void *state, size_t index);
+int64_t psa_plugin_classify(void *state, const PluginSample *sample);
+void psa_plugin_record(void *state, const PluginSample *sample, PluginStr category);
+void psa_plugin_report(void *state);
+void psa_plugin_free(void *state);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
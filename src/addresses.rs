@@ -0,0 +1,189 @@
+//! Sanity checks on the instruction pointers of stack frames. A DSO's code is
+//! mapped in one piece, so all frames of a DSO within one process must lie in
+//! an address range that is smaller than the DSO itself, whatever address ASLR
+//! loaded it at. Frames which fall further away are unwinder garbage that perf
+//! happened to attribute to the DSO.
+
+use analysis::SampleCategory;
+use paths::PathMap;
+use sample::{Sample, StackFrame};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+
+
+/// Number of DSOs which are displayed in the report
+const NUM_REPORTED_DSOS: usize = 10;
+
+
+/// Sizes of the DSOs on disk, which bound the size of their text mapping. They
+/// are looked up lazily, as DSOs are encountered in samples.
+pub struct MappingSizes {
+    /// Rules for locating recorded DSOs on this machine
+    path_map: PathMap,
+
+    /// Size of each DSO which was looked up, or None if it could not be read
+    sizes: RefCell<HashMap<String, Option<u64>>>,
+}
+//
+impl MappingSizes {
+    /// Look up DSOs on this machine using some path translation rules
+    pub fn new(path_map: PathMap) -> Self {
+        Self {
+            path_map,
+            sizes: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Upper bound on the size of a DSO's text mapping, if known. Only regular
+    /// files can be checked, not pseudo-DSOs like "[vdso]" or "//anon".
+    pub fn size(&self, dso: &str) -> Option<u64> {
+        if !dso.starts_with('/') || dso.starts_with("//") {
+            return None;
+        }
+        if let Some(&size) = self.sizes.borrow().get(dso) {
+            return size;
+        }
+        let size = fs::metadata(self.path_map.resolve(dso)).ok()
+                                                            .filter(|m| m.is_file())
+                                                            .map(|m| m.len());
+        self.sizes.borrow_mut().insert(dso.to_owned(), size);
+        size
+    }
+
+    /// Find a DSO whose frames in some stack are spread over an address range
+    /// that is too wide to fit in the DSO
+    pub fn find_corrupt_dso<'a, I>(&self, frames: I) -> Option<&'a str>
+        where I: IntoIterator<Item=StackFrame<'a>>
+    {
+        frame_ranges(frames).into_iter().find_map(|(dso, min_ip, max_ip)| {
+            let size = self.size(dso)?;
+            Some(dso).filter(|_| max_ip - min_ip >= size)
+        })
+    }
+}
+///
+///
+/// Instruction pointer ranges of each DSO
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct AddressStatistics {
+    pub dsos: HashMap<String, DsoAddresses>,
+
+    /// Address range of each DSO in each process. This is only needed to
+    /// extend the widest range, so it is not saved in checkpoints.
+    #[serde(skip)]
+    process_ranges: HashMap<(u32, String), (u64, u64)>,
+}
+//
+impl AddressStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a newly classified sample, given the size of its DSOs
+    pub fn record<F>(&mut self, sample: &Sample, category: &SampleCategory, size: F)
+        where F: Fn(&str) -> Option<u64>
+    {
+        if let SampleCategory::CorruptFrameAddress(dso) = *category {
+            self.dso_entry(dso, &size).num_corrupt_samples += 1;
+        }
+        let pid = sample.pid();
+        for (dso, min_ip, max_ip) in frame_ranges(sample.frames()) {
+            let mut range = (min_ip, max_ip);
+            if let Some(pid) = pid {
+                let process_range = self.process_ranges
+                                        .entry((pid, dso.to_owned()))
+                                        .or_insert(range);
+                process_range.0 = process_range.0.min(min_ip);
+                process_range.1 = process_range.1.max(max_ip);
+                range = *process_range;
+            }
+            let entry = self.dso_entry(dso, &size);
+            entry.widest_range = entry.widest_range.max(range.1 - range.0);
+        }
+    }
+
+    /// Print the DSOs whose frames span the widest address ranges, compared to
+    /// the size of their mapping
+    pub fn print_report(&self) {
+        let mut dsos = self.dsos.iter().collect::<Vec<_>>();
+        dsos.sort_by(|a, b| {
+            b.1.num_corrupt_samples.cmp(&a.1.num_corrupt_samples)
+                                   .then(b.1.widest_range.cmp(&a.1.widest_range))
+                                   .then(a.0.cmp(b.0))
+        });
+        println!("Frame address ranges per DSO, most suspicious first: {} DSOs",
+                 dsos.len());
+        for (dso, addresses) in dsos.into_iter().take(NUM_REPORTED_DSOS) {
+            let size = addresses.mapping_size.map_or("unknown size".to_owned(),
+                                                     |size| format!("{} bytes", size));
+            let warning = match addresses.mapping_size {
+                Some(size) if addresses.widest_range >= size => " [warning]",
+                _ => "",
+            };
+            println!("- {}: frames span up to {} bytes in a process ({}), {} samples \
+                      with corrupt addresses{}",
+                     dso,
+                     addresses.widest_range,
+                     size,
+                     addresses.num_corrupt_samples,
+                     warning);
+        }
+    }
+
+    /// Counters of some DSO, which are created on first use
+    fn dso_entry<F>(&mut self, dso: &str, size: F) -> &mut DsoAddresses
+        where F: Fn(&str) -> Option<u64>
+    {
+        if !self.dsos.contains_key(dso) {
+            let addresses = DsoAddresses {
+                mapping_size: size(dso),
+                ..DsoAddresses::default()
+            };
+            self.dsos.insert(dso.to_owned(), addresses);
+        }
+        self.dsos.get_mut(dso).unwrap()
+    }
+}
+///
+///
+/// Instruction pointer statistics of one DSO
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct DsoAddresses {
+    /// Size of the DSO on disk, if known
+    pub mapping_size: Option<u64>,
+
+    /// Widest range of addresses that this DSO's frames spanned in a process
+    pub widest_range: u64,
+
+    /// Samples which were classified as having corrupt addresses in this DSO
+    pub num_corrupt_samples: usize,
+}
+
+
+/// Range of instruction pointers of each DSO within a stack. Frames without a
+/// meaningful address (e.g. stitched from the branch stack, or inferred from
+/// other samples) are left out, and so are pseudo-DSOs.
+fn frame_ranges<'a, I>(frames: I) -> Vec<(&'a str, u64, u64)>
+    where I: IntoIterator<Item=StackFrame<'a>>
+{
+    let mut ranges: Vec<(&str, u64, u64)> = Vec::new();
+    for frame in frames {
+        if frame.inferred || !frame.dso.starts_with('/') || frame.dso.starts_with("//") {
+            continue;
+        }
+        let ip = match u64::from_str_radix(frame.ip, 16) {
+            Ok(ip) if ip != 0 => ip,
+            _ => continue,
+        };
+        match ranges.iter_mut().find(|range| range.0 == frame.dso) {
+            Some(range) => {
+                range.1 = range.1.min(ip);
+                range.2 = range.2.max(ip);
+            },
+            None => ranges.push((frame.dso, ip, ip)),
+        }
+    }
+    ranges
+}
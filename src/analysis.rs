@@ -0,0 +1,640 @@
+//! Classification of perf samples into normal and anomalous categories
+
+use addresses::MappingSizes;
+use arch::Arch;
+use callsites::CallSites;
+use classifiers;
+use config::CategoryRule;
+use deleted::LiveMappings;
+use kernel::KernelSymbols;
+use runtime::Runtime;
+use sample::{Sample, StackFrame};
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+
+/// A stage of the sample classification pipeline
+///
+/// Classifiers are chained: each of them gets to look at a sample, and either
+/// decides on a category for it or returns None to let the next stage decide.
+/// Library users can insert their own stages, for example to recognize the
+/// root functions of a project-specific threading library.
+pub trait Classifier {
+    /// Try to classify a sample, or return None to defer to the next stage
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>>;
+}
+///
+///
+/// A sample with a stack trace, as seen by the classification pipeline
+pub struct SampleView<'s, 'a: 's> {
+    /// Sample under study
+    pub sample: &'s Sample<'a>,
+
+    /// Last (outermost) frame of the sample's stack trace, already resolved
+    pub last_frame: StackFrame<'a>,
+
+    /// Process ID of the sample, if it is needed to resolve stack frames
+    pid: Option<u32>,
+
+    /// Mechanism used to resolve unsymbolized stack frames
+    resolver: &'a FrameResolver,
+}
+//
+impl<'s, 'a> SampleView<'s, 'a> {
+    /// Iterate over the stack frames of the sample, with kernel frames and
+    /// frames from deleted libraries resolved if needed and possible
+    pub fn frames(&self) -> impl DoubleEndedIterator<Item=StackFrame<'a>> + 'a {
+        let (resolver, pid) = (self.resolver, self.pid);
+        self.sample.frames().map(move |frame| resolver.resolve(frame, pid))
+    }
+
+    /// CPU architecture that the sample was recorded on
+    pub fn arch(&self) -> Arch {
+        self.resolver.arch
+    }
+}
+///
+///
+/// Resolution of the stack frames that perf could not symbolize
+struct FrameResolver {
+    /// Kernel symbols provided by the user, if any
+    kernel_symbols: Option<KernelSymbols>,
+
+    /// Deleted libraries which running processes still have mapped, if any
+    live_mappings: Option<LiveMappings>,
+
+    /// CPU architecture that the samples were recorded on
+    arch: Arch,
+}
+//
+impl FrameResolver {
+    /// Resolve a kernel stack frame, or a frame from a deleted library that is
+    /// still mapped by the process which was sampled, if needed and possible
+    fn resolve<'a>(&'a self, frame: StackFrame<'a>, pid: Option<u32>) -> StackFrame<'a> {
+        if frame.deleted && frame.is_unknown_symbol() {
+            let symbol = self.live_mappings.as_ref().and_then(|live_mappings| {
+                let ip = u64::from_str_radix(frame.ip, 16).ok()?;
+                live_mappings.lookup(pid?, ip)
+            });
+            return match symbol {
+                Some(symbol) => StackFrame { symbol, ..frame },
+                None => frame,
+            };
+        }
+        match self.kernel_symbols {
+            Some(ref kernel_symbols) => kernel_symbols.resolve(frame, self.arch),
+            None => frame,
+        }
+    }
+}
+///
+///
+/// Mechanism to analyze pre-parsed data samples and detect anomalies
+pub struct SampleAnalyzer {
+    /// Kernel symbols and CPU architecture used to resolve stack frames
+    resolver: FrameResolver,
+
+    /// User-provided classifiers, which run before the built-in ones
+    classifiers_before: Vec<Box<dyn Classifier>>,
+
+    /// Built-in classification stages, in the order where they run
+    custom_rules: classifiers::CustomRules,
+    guest_samples: classifiers::GuestSamples,
+    kernel_symbols: classifiers::KernelSymbols,
+    interrupts: classifiers::Interrupts,
+    idle_samples: classifiers::IdleSamples,
+    gpu_waits: classifiers::GpuWaits,
+    vdso_roots: classifiers::VdsoRoots,
+    corrupt_addresses: classifiers::CorruptFrameAddresses,
+    suspect_return_addresses: classifiers::SuspectReturnAddresses,
+    expected_roots: classifiers::ExpectedRoots,
+    truncated_stacks: classifiers::TruncatedStacks,
+    cgo_transitions: classifiers::CgoTransitions,
+    signal_frames: classifiers::SignalFrames,
+    wasm_code: classifiers::WasmCode,
+    jit_code: classifiers::JitCode,
+    bpf_programs: classifiers::BpfPrograms,
+    anonymous_code: classifiers::AnonymousCode,
+    deleted_dsos: classifiers::DeletedDsos,
+    stale_dsos: classifiers::StaleDsos,
+    bad_dsos: classifiers::BadDsos,
+    windows_code: classifiers::WindowsCode,
+    broken_last_frame: classifiers::BrokenLastFrame,
+
+    /// User-provided classifiers, which run after the built-in ones
+    classifiers_after: Vec<Box<dyn Classifier>>,
+}
+//
+impl SampleAnalyzer {
+    /// Setup a sample analyzer
+    pub fn new() -> Self {
+        // These are the functions we expect to see on end of stack traces
+        let arch = Arch::default();
+        let expected_root_funcs = Runtime::Native.expected_root_funcs()
+                                                 .iter()
+                                                 .chain(arch.expected_root_funcs())
+                                                 .map(|&func| func.to_owned())
+                                                 .collect();
+
+        let mut expected_root_dsos = HashSet::new();
+        expected_root_dsos.insert("[kernel.kallsyms]");
+        expected_root_dsos.insert("/usr/bin/perf");
+
+        // These DSOs are known to break stack traces (how evil of them!)
+        let mut known_bad_dsos = HashSet::new();
+        known_bad_dsos.insert("/usr/lib64/xorg/modules/drivers/nvidia_drv.so");
+        known_bad_dsos.insert("/usr/lib64/libGLX_nvidia.so.384.98");
+        known_bad_dsos.insert("/usr/lib64/libGLX_nvidia.so.384.98");
+
+        // Return the analysis harness
+        Self {
+            resolver: FrameResolver {
+                kernel_symbols: None,
+                live_mappings: None,
+                arch,
+            },
+            classifiers_before: Vec::new(),
+            custom_rules: classifiers::CustomRules { rules: Vec::new() },
+            guest_samples: classifiers::GuestSamples,
+            kernel_symbols: classifiers::KernelSymbols,
+            interrupts: classifiers::Interrupts,
+            idle_samples: classifiers::IdleSamples,
+            gpu_waits: classifiers::GpuWaits { enabled: false },
+            vdso_roots: classifiers::VdsoRoots,
+            corrupt_addresses: classifiers::CorruptFrameAddresses { mapping_sizes: None },
+            suspect_return_addresses: classifiers::SuspectReturnAddresses {
+                call_sites: None,
+            },
+            expected_roots: classifiers::ExpectedRoots {
+                funcs: expected_root_funcs,
+                dsos: expected_root_dsos,
+                wine_preloader: false,
+            },
+            truncated_stacks: classifiers::TruncatedStacks,
+            cgo_transitions: classifiers::CgoTransitions { enabled: false },
+            signal_frames: classifiers::SignalFrames,
+            wasm_code: classifiers::WasmCode { enabled: false },
+            jit_code: classifiers::JitCode,
+            bpf_programs: classifiers::BpfPrograms,
+            anonymous_code: classifiers::AnonymousCode,
+            deleted_dsos: classifiers::DeletedDsos,
+            stale_dsos: classifiers::StaleDsos { dsos: HashSet::new() },
+            bad_dsos: classifiers::BadDsos { dsos: known_bad_dsos },
+            windows_code: classifiers::WindowsCode { enabled: false },
+            broken_last_frame: classifiers::BrokenLastFrame,
+            classifiers_after: Vec::new(),
+        }
+    }
+
+    /// Also expect the root functions of some language runtime
+    pub fn with_runtime(mut self, runtime: Runtime) -> Self {
+        match runtime {
+            Runtime::Go => self.cgo_transitions.enabled = true,
+            Runtime::Wine => {
+                self.expected_roots.wine_preloader = true;
+                self.windows_code.enabled = true;
+            },
+            Runtime::Gpu => self.gpu_waits.enabled = true,
+            Runtime::Wasm => self.wasm_code.enabled = true,
+            _ => {},
+        }
+        self.bad_dsos.dsos.extend(runtime.known_bad_dsos());
+        let funcs = runtime.expected_root_funcs().iter().map(|&func| func.to_owned());
+        self.with_expected_root_funcs(funcs)
+    }
+
+    /// Analyze samples recorded on some CPU architecture
+    pub fn with_arch(mut self, arch: Arch) -> Self {
+        self.resolver.arch = arch;
+        let funcs = arch.expected_root_funcs().iter().map(|&func| func.to_owned());
+        self.with_expected_root_funcs(funcs)
+    }
+
+    /// Also expect some user-specified root functions
+    pub fn with_expected_root_funcs<I>(mut self, funcs: I) -> Self
+        where I: IntoIterator<Item=String>
+    {
+        self.expected_roots.funcs.extend(funcs);
+        self
+    }
+
+    /// Forget about the built-in root functions, so that the user can start
+    /// over from a clean slate
+    pub fn without_expected_root_funcs(mut self) -> Self {
+        self.expected_roots.funcs.clear();
+        self
+    }
+
+    /// Use a kernel symbol table to resolve unsymbolized kernel stack frames
+    pub fn with_kernel_symbols(mut self, kernel_symbols: KernelSymbols) -> Self {
+        self.resolver.kernel_symbols = Some(kernel_symbols);
+        self
+    }
+
+    /// Resolve frames from deleted libraries which are still mapped by running
+    /// processes, which is only meaningful when analyzing a fresh profile
+    pub fn with_live_mappings(mut self, live_mappings: LiveMappings) -> Self {
+        self.resolver.live_mappings = Some(live_mappings);
+        self
+    }
+
+    /// Flag samples going through DSOs which changed since recording time
+    pub fn with_stale_dsos(mut self, stale_dsos: HashSet<String>) -> Self {
+        self.stale_dsos.dsos = stale_dsos;
+        self
+    }
+
+    /// Flag stacks whose frames cannot belong to their DSO, given the size of
+    /// the DSOs on disk
+    pub fn with_mapping_sizes(mut self, mapping_sizes: MappingSizes) -> Self {
+        self.corrupt_addresses.mapping_sizes = Some(mapping_sizes);
+        self
+    }
+
+    /// Size of a DSO's mapping, if frame addresses are being checked and the
+    /// DSO could be found on disk
+    pub fn mapping_size(&self, dso: &str) -> Option<u64> {
+        self.corrupt_addresses.mapping_sizes.as_ref()?.size(dso)
+    }
+
+    /// Flag stacks whose caller frames do not return right after a call
+    /// instruction, given the code of the DSOs on disk
+    pub fn with_call_sites(mut self, call_sites: CallSites) -> Self {
+        self.suspect_return_addresses.call_sites = Some(call_sites);
+        self
+    }
+
+    /// Truth that the return address of a caller frame follows a call, if
+    /// return addresses are being checked and this frame can be
+    pub fn follows_call(&self, frame: &StackFrame) -> Option<bool> {
+        self.suspect_return_addresses.call_sites.as_ref()?.follows_call(frame)
+    }
+
+    /// CPU architecture that the samples are assumed to come from
+    pub fn arch(&self) -> Arch {
+        self.resolver.arch
+    }
+
+    /// Put the samples which match some rules in user-defined categories
+    pub fn with_category_rules(mut self, rules: Vec<CategoryRule>) -> Self {
+        self.custom_rules.rules.extend(rules);
+        self
+    }
+
+    /// Run a user-provided classifier before the built-in ones. Classifiers
+    /// which are added this way run in insertion order.
+    pub fn with_classifier_before<C>(mut self, classifier: C) -> Self
+        where C: Classifier + 'static
+    {
+        self.classifiers_before.push(Box::new(classifier));
+        self
+    }
+
+    /// Run a user-provided classifier after the built-in ones, but before
+    /// samples are reported as having an unexpected last function. Classifiers
+    /// which are added this way run in insertion order.
+    pub fn with_classifier_after<C>(mut self, classifier: C) -> Self
+        where C: Classifier + 'static
+    {
+        self.classifiers_after.push(Box::new(classifier));
+        self
+    }
+
+    /// Classify a pre-parsed stack sample in various categories (see below)
+    pub fn classify<'a>(&'a self, sample: &Sample<'a>) -> SampleCategory<'a> {
+        // If there is no stack trace, report it
+        let pid = self.resolver.live_mappings.as_ref().and_then(|_| sample.pid());
+        let last_frame = sample.last_stack_frame.and_then(StackFrame::parse_bytes);
+        let last_frame = match last_frame {
+            Some(last_frame) => self.resolver.resolve(last_frame, pid),
+            None => return SampleCategory::NoStackTrace,
+        };
+        let view = SampleView {
+            sample,
+            last_frame,
+            pid,
+            resolver: &self.resolver,
+        };
+
+        // Otherwise, let each classification stage have a look in turn
+        let category = self.classifiers_before.iter().map(|c| &**c)
+                           .chain(self.builtin_classifiers().iter().cloned())
+                           .chain(self.classifiers_after.iter().map(|c| &**c))
+                           .filter_map(|classifier| classifier.classify(&view))
+                           .next();
+
+        // If no stage could tell what is going on, but the last DSO is valid,
+        // the top function of the stack trace is unexpected. It should be
+        // reported as a possible --max-stack problem.
+        category.unwrap_or(SampleCategory::UnexpectedLastFunc(view.last_frame.symbol))
+    }
+
+    /// Built-in classification stages, in the order where they should run
+    fn builtin_classifiers(&self) -> [&dyn Classifier; 22] {
+        [
+            &self.custom_rules,
+            &self.guest_samples,
+            &self.kernel_symbols,
+            &self.interrupts,
+            &self.idle_samples,
+            &self.gpu_waits,
+            &self.vdso_roots,
+            &self.corrupt_addresses,
+            &self.suspect_return_addresses,
+            &self.expected_roots,
+            &self.truncated_stacks,
+            &self.cgo_transitions,
+            &self.signal_frames,
+            &self.wasm_code,
+            &self.jit_code,
+            &self.bpf_programs,
+            &self.anonymous_code,
+            &self.deleted_dsos,
+            &self.stale_dsos,
+            &self.bad_dsos,
+            &self.windows_code,
+            &self.broken_last_frame,
+        ]
+    }
+}
+//
+impl Default for SampleAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+///
+///
+/// Output of SampleAnalyzer's evaluation of a perf sample's quality
+#[derive(Debug)]
+pub enum SampleCategory<'a> {
+    /// This sample looks the way we expect, nothing special here.
+    Normal,
+
+    /// This sample's stack ends in the vDSO or vsyscall page, which is
+    /// expected since perf cannot unwind through them.
+    VdsoRoot,
+
+    /// This sample was taken while the kernel was handling a hardware
+    /// interrupt, which stole time from the code that was running.
+    HardIrq,
+
+    /// This sample was taken while the kernel was running deferred interrupt
+    /// work (softirqs), e.g. network packet processing.
+    SoftIrq,
+
+    /// This sample was taken while the thread was idle or waiting for events
+    /// (e.g. in the kernel's idle loop or in epoll_wait), so it tells little
+    /// about where the program spends its computing time.
+    Idle,
+
+    /// This sample was taken in a virtual machine guest, whose code the host's
+    /// perf usually cannot symbolize nor unwind.
+    Guest,
+
+    /// This sample has no strack trace attached to it.
+    NoStackTrace,
+
+    /// This sample has frames whose instruction pointers are spread over a
+    /// wider address range than their DSO could be mapped at, so some of them
+    /// are unwinder garbage. The DSO is attached.
+    CorruptFrameAddress(&'a str),
+
+    /// This sample has a caller frame whose return address does not follow a
+    /// call instruction in the code of its DSO, so the frame is made up. The
+    /// DSO is attached.
+    SuspectReturnAddress(&'a str),
+
+    /// This sample went through the kernel, but none of its kernel frames
+    /// could be symbolized, most likely because /proc/kallsyms is restricted
+    /// or kernel symbols were not captured.
+    KernelSymbolsUnavailable,
+
+    /// This sample most likely originates from a truncated DWARF stack.
+    TruncatedStack,
+
+    /// This sample was taken in a signal handler, but the stack of the code
+    /// that it interrupted could not be properly unwound. The number of frames
+    /// that were unwound in the interrupted context is attached.
+    BrokenBySignal(usize),
+
+    /// This sample from a Go program has a broken stack trace which goes
+    /// through a transition between Go and C code.
+    BrokenByCgo,
+
+    /// This sample's stack ends in code that a WebAssembly runtime generated
+    /// from a wasm module, or in a trampoline between wasm and host code.
+    WasmCode,
+
+    /// This sample was identified by perf as originating from a JIT compiler.
+    /// The PID of the process which generated the code is attached.
+    JitCompiledBy(u32),
+
+    /// This sample's stack ends in a BPF program, which the kernel compiled
+    /// to native code and perf cannot unwind through. The tag of the program
+    /// is attached.
+    BpfProgram(&'a str),
+
+    /// This sample's stack goes through anonymous memory, most likely filled
+    /// by a JIT compiler which does not emit a perf map. The first anonymous
+    /// mapping (e.g. "//anon" or "[heap]") is attached.
+    AnonymousCode(&'a str),
+
+    /// This sample's last DSO has a (deleted) marker, because the library was
+    /// replaced on disk (e.g. upgraded) while the program was running. The DSO
+    /// is attached.
+    UpgradedLibrary(&'a str),
+
+    /// This sample's last DSO has a (deleted) marker, because the code was
+    /// loaded from an anonymous file such as a memfd or a shared memory
+    /// segment. The DSO is attached.
+    AnonymousExecutable(&'a str),
+
+    /// This sample goes through a DSO whose build-id changed since the profile
+    /// was recorded, so its symbols were resolved using the wrong binary.
+    StaleDSO(&'a str),
+
+    /// This sample has a broken stack trace, which features a DSO that is known
+    /// to be problematic. We still lost info, but at least we know why.
+    BrokenByBadDSO(&'static str),
+
+    /// This sample's stack ends in a Windows DSO running under Wine, which
+    /// perf usually cannot unwind through. The DSO is attached.
+    WindowsCode(&'a str),
+
+    /// The bottom of the stack trace is clearly broken for this sample, but
+    /// it is not clear how that could happen.
+    BrokenLastFrame,
+
+    /// This sample has an unusual function at the top of the stack trace for no
+    /// clear reason. You may want to check perf script's --max-stack parameter.
+    UnexpectedLastFunc(&'a str),
+
+    /// This sample matched a rule of the configuration file, which put it in
+    /// the user-defined category whose name is attached.
+    Custom(&'a str),
+}
+//
+impl<'a> SampleCategory<'a> {
+    /// Short identifier of this category, used to refer to it in configuration
+    /// files. User-defined categories are identified by their name.
+    pub fn key(&self) -> &'a str {
+        use self::SampleCategory::*;
+        match *self {
+            Normal => "normal",
+            VdsoRoot => "vdso_root",
+            HardIrq => "hard_irq",
+            SoftIrq => "soft_irq",
+            Idle => "idle",
+            Guest => "guest",
+            NoStackTrace => "no_stack_trace",
+            CorruptFrameAddress(_dso) => "corrupt_frame_address",
+            SuspectReturnAddress(_dso) => "suspect_return_address",
+            KernelSymbolsUnavailable => "kernel_symbols_unavailable",
+            TruncatedStack => "truncated_stack",
+            BrokenBySignal(_depth) => "broken_by_signal",
+            BrokenByCgo => "broken_by_cgo",
+            WasmCode => "wasm_code",
+            JitCompiledBy(_pid) => "jit_compiled",
+            BpfProgram(_tag) => "bpf_program",
+            AnonymousCode(_dso) => "anonymous_code",
+            UpgradedLibrary(_dso) => "upgraded_library",
+            AnonymousExecutable(_dso) => "anonymous_executable",
+            StaleDSO(_dso) => "stale_dso",
+            BrokenByBadDSO(_dso) => "broken_by_bad_dso",
+            WindowsCode(_dso) => "windows_code",
+            BrokenLastFrame => "broken_last_frame",
+            UnexpectedLastFunc(_name) => "unexpected_last_func",
+            Custom(name) => name,
+        }
+    }
+
+    /// How worried the user should be about samples of this category, unless
+    /// the configuration file says otherwise
+    pub fn default_severity(&self) -> Severity {
+        use self::SampleCategory::*;
+        match *self {
+            Normal | VdsoRoot | HardIrq | SoftIrq | Idle | Guest | JitCompiledBy(_) |
+            BpfProgram(_) | Custom(_) => Severity::Info,
+            CorruptFrameAddress(_) | SuspectReturnAddress(_) | StaleDSO(_) |
+            BrokenLastFrame => Severity::Error,
+            NoStackTrace | KernelSymbolsUnavailable | TruncatedStack |
+            BrokenBySignal(_) | BrokenByCgo | WasmCode | AnonymousCode(_) |
+            UpgradedLibrary(_) | AnonymousExecutable(_) | BrokenByBadDSO(_) |
+            WindowsCode(_) | UnexpectedLastFunc(_) => Severity::Warning,
+        }
+    }
+
+    /// What the user can do about samples of this category, if anything
+    pub fn hint(&self) -> Option<&'static str> {
+        use self::SampleCategory::*;
+        match *self {
+            Normal | VdsoRoot | HardIrq | SoftIrq | Idle | Guest | JitCompiledBy(_) |
+            BpfProgram(_) | Custom(_) => None,
+            NoStackTrace => {
+                Some("Record call graphs with perf record's -g or --call-graph option.")
+            },
+            CorruptFrameAddress(_) => {
+                Some("Try another unwinding method (--call-graph dwarf, fp or lbr), \
+                      and check that the DSO was not rebuilt since recording.")
+            },
+            SuspectReturnAddress(_) => {
+                Some("Rebuild the DSO with -fno-omit-frame-pointer or try another \
+                      unwinding method, and check that the DSO was not rebuilt \
+                      since recording.")
+            },
+            KernelSymbolsUnavailable => {
+                Some("Try running as root, or setting \"sysctl kernel.kptr_restrict=0\".")
+            },
+            TruncatedStack => {
+                Some("Increase the stack dump size of --call-graph dwarf, e.g. \
+                      --call-graph dwarf,65528.")
+            },
+            BrokenBySignal(_) => {
+                Some("Unwinding through signal frames requires unwinding info for \
+                      the signal trampoline, try --call-graph dwarf or lbr.")
+            },
+            BrokenByCgo => {
+                Some("Build the C side of cgo calls with -fno-omit-frame-pointer, \
+                      or use --call-graph dwarf.")
+            },
+            WasmCode => {
+                Some("wasmtime and V8 keep frame pointers in generated code, try \
+                      --call-graph fp, or have them emit jitdump files (wasmtime \
+                      --profile=jitdump) and run perf inject --jit on the profile.")
+            },
+            AnonymousCode(_) => {
+                Some("Enable jitdump support in the JIT compiler, then record with \
+                      perf record -k 1 and run perf inject --jit on the profile.")
+            },
+            UpgradedLibrary(_) => {
+                Some("Restart programs after upgrading their libraries, or analyze \
+                      the profile while they still run so that the old libraries \
+                      can be read from /proc/<pid>/map_files.")
+            },
+            AnonymousExecutable(_) => {
+                Some("Make the code generator which uses memfd or shared memory \
+                      emit a perf map or jitdump file.")
+            },
+            StaleDSO(_) => {
+                Some("Analyze the profile with the binaries that it was recorded \
+                      with, e.g. using perf buildid-cache or --sysroot.")
+            },
+            BrokenByBadDSO(_) => {
+                Some("Install debugging info for the problematic DSOs, or rebuild \
+                      them with frame pointers.")
+            },
+            WindowsCode(_) => {
+                Some("Windows DLLs usually lack DWARF unwinding info, try \
+                      --call-graph fp or lbr.")
+            },
+            BrokenLastFrame => {
+                Some("Try another unwinding method (--call-graph dwarf, fp or lbr).")
+            },
+            UnexpectedLastFunc(_) => {
+                Some("Check perf script's --max-stack parameter, or declare the \
+                      function as an expected root with --root-func.")
+            },
+        }
+    }
+}
+///
+///
+/// How worried the user should be about some category of samples
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+    /// Nothing to worry about, this is only reported for information
+    Info,
+
+    /// Some profiling information was lost, but the profile may still be usable
+    Warning,
+
+    /// The profile is misleading and should not be trusted
+    Error,
+}
+//
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}", name)
+    }
+}
+//
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "info" => Ok(Severity::Info),
+            "warning" => Ok(Severity::Warning),
+            "error" => Ok(Severity::Error),
+            _ => Err(format!("Unknown severity {}, expected one of info, warning \
+                              or error", name)),
+        }
+    }
+}
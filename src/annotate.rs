@@ -0,0 +1,82 @@
+//! Re-emission of perf script's output with the analysis inline, where each
+//! anomalous sample is preceded by a comment line describing what is wrong
+//! with it. Tools which consume perf script's output skip comment lines, so
+//! they keep working on the annotated output.
+
+use analysis::{SampleCategory, Severity};
+use sample::Sample;
+use std::io::{self, BufWriter, Error, Result, Stdout, Write};
+
+
+/// Mechanism to print the analyzed samples on stdout, along with annotations.
+/// Write errors are deferred until the output is finished, so that they do not
+/// get in the way of the analysis itself.
+pub struct SampleAnnotator {
+    output: BufWriter<Stdout>,
+
+    /// First error which occurred while writing, if any
+    error: Option<Error>,
+}
+//
+impl SampleAnnotator {
+    /// Prepare to print samples
+    pub fn new() -> Self {
+        Self {
+            output: BufWriter::new(io::stdout()),
+            error: None,
+        }
+    }
+
+    /// Print a newly classified sample, annotating it if it is anomalous
+    pub fn record(&mut self,
+                  sample: &Sample,
+                  category: &SampleCategory,
+                  severity: Severity) {
+        self.write(sample, Some((category, severity)));
+    }
+
+    /// Print a record which was not classified as is, e.g. a side-band record
+    /// or a sample which was filtered out, so that the output stays complete
+    pub fn pass_through(&mut self, sample: &Sample) {
+        self.write(sample, None);
+    }
+
+    /// Print a record with its classification, if any, deferring errors
+    fn write(&mut self, sample: &Sample, analysis: Option<(&SampleCategory, Severity)>) {
+        if self.error.is_none() {
+            if let Err(e) = self.try_write(sample, analysis) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    /// Print a record, reporting errors immediately
+    fn try_write(&mut self,
+                 sample: &Sample,
+                 analysis: Option<(&SampleCategory, Severity)>) -> Result<()> {
+        if let Some((category, severity)) = analysis {
+            if severity > Severity::Info {
+                writeln!(self.output,
+                         "# perf-script-analyze: {} [{}]",
+                         category.key(),
+                         severity)?;
+            }
+        }
+        self.output.write_all(sample.raw_sample_data)?;
+        self.output.write_all(b"\n")
+    }
+
+    /// Complete the output
+    pub fn finish(mut self) -> Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.output.flush()
+    }
+}
+//
+impl Default for SampleAnnotator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,169 @@
+//! Knowledge about the CPU architectures that perf profiles can come from,
+//! which affects address space layouts and kernel entry points
+
+use sample::StackFrame;
+use std::fmt;
+use std::str::FromStr;
+
+
+/// CPU architectures which we know something about
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Arch {
+    #[default]
+    X86_64,
+    X86,
+    Aarch64,
+    Arm,
+    Riscv64,
+}
+//
+impl Arch {
+    /// Architecture of the machine we are running on, if we know about it
+    pub fn host() -> Option<Self> {
+        ::std::env::consts::ARCH.parse().ok()
+    }
+
+    /// Detect the architecture from the metadata that perf script prints when
+    /// run with --header, which contains an "# arch : <name>" line
+    pub fn from_header(header: &str) -> Option<Self> {
+        header.lines()
+              .filter_map(|line| line.strip_prefix('#'))
+              .filter_map(|line| {
+                  let mut parts = line.splitn(2, ':');
+                  match (parts.next(), parts.next()) {
+                      (Some(key), Some(value)) if key.trim() == "arch" => {
+                          Some(value.trim())
+                      },
+                      _ => None,
+                  }
+              })
+              .find_map(|name| name.parse().ok())
+    }
+
+    /// Number of hexadecimal digits in an instruction pointer
+    pub fn ip_digits(self) -> usize {
+        match self {
+            Arch::X86_64 | Arch::Aarch64 | Arch::Riscv64 => 16,
+            Arch::X86 | Arch::Arm => 8,
+        }
+    }
+
+    /// Lowest address of the kernel's part of the address space
+    fn kernel_space_start(self) -> u64 {
+        match self {
+            Arch::X86_64 => 0xffff_8000_0000_0000,
+            // Covers all the page table layouts of these architectures
+            Arch::Aarch64 | Arch::Riscv64 => 0xffff_0000_0000_0000,
+            // Assuming the common 3G/1G user/kernel split
+            Arch::X86 | Arch::Arm => 0xc000_0000,
+        }
+    }
+
+    /// Truth that an instruction pointer is perf's marker for truncated DWARF
+    /// stacks, which is an IP where all bits are set
+    pub fn is_truncation_marker(self, ip: &str) -> bool {
+        ip.len() == self.ip_digits() && ip.chars().all(|c| c == 'f')
+    }
+
+    /// Truth that a frame belongs to the kernel, which is either stated by its
+    /// DSO or suggested by an instruction pointer in kernel space
+    pub fn is_kernel_frame(self, frame: &StackFrame) -> bool {
+        if frame.dso.starts_with("[kernel.") {
+            return true;
+        }
+        if self.is_truncation_marker(frame.ip) {
+            return false;
+        }
+        match u64::from_str_radix(frame.ip, 16) {
+            Ok(ip) => ip >= self.kernel_space_start(),
+            Err(_) => false,
+        }
+    }
+
+    /// Truth that a function lives in the vDSO
+    pub fn is_vdso_func(self, symbol: &str) -> bool {
+        match self {
+            // The ARM vDSOs name their functions differently
+            Arch::Aarch64 | Arch::Arm => {
+                symbol.starts_with("__kernel_") || symbol.starts_with("__vdso_")
+            },
+            _ => symbol.starts_with("__vdso_"),
+        }
+    }
+
+    /// Truth that a kernel function is an entry point of hardware interrupt
+    /// handling, which the interrupted code's frames are found beyond
+    pub fn is_hard_irq_entry(self, symbol: &str) -> bool {
+        match self {
+            Arch::X86_64 | Arch::X86 => {
+                symbol.starts_with("asm_sysvec_") ||
+                symbol.starts_with("sysvec_") ||
+                ["native_irq_return_iret", "common_interrupt", "asm_common_interrupt",
+                 "do_IRQ"].contains(&symbol)
+            },
+            Arch::Aarch64 => {
+                ["el1_interrupt", "el1h_64_irq", "el0t_64_irq", "el0t_32_irq",
+                 "gic_handle_irq"].contains(&symbol)
+            },
+            Arch::Arm => ["__irq_svc", "__irq_usr", "gic_handle_irq"].contains(&symbol),
+            Arch::Riscv64 => ["handle_riscv_irq", "do_irq"].contains(&symbol),
+        }
+    }
+
+    /// Kernel entry points which may be found at the root of stack traces
+    pub fn expected_root_funcs(self) -> &'static [&'static str] {
+        match self {
+            Arch::X86_64 | Arch::X86 => &[
+                "native_irq_return_iret",
+            ],
+            Arch::Aarch64 => &[
+                "ret_from_fork",
+                "el0t_64_sync",
+                "el0t_64_irq",
+                "el0t_32_sync",
+                "el0t_32_irq",
+            ],
+            Arch::Arm => &[
+                "ret_from_fork",
+                "ret_fast_syscall",
+                "__irq_usr",
+            ],
+            Arch::Riscv64 => &[
+                "ret_from_fork",
+                "ret_from_exception",
+                "handle_exception",
+            ],
+        }
+    }
+}
+//
+impl fmt::Display for Arch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Arch::X86_64 => "x86_64",
+            Arch::X86 => "x86",
+            Arch::Aarch64 => "aarch64",
+            Arch::Arm => "arm",
+            Arch::Riscv64 => "riscv64",
+        };
+        write!(f, "{}", name)
+    }
+}
+//
+impl FromStr for Arch {
+    type Err = String;
+
+    /// Decode the architecture names used by uname, perf and Rust
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "x86_64" | "amd64" => Ok(Arch::X86_64),
+            "x86" | "i386" | "i486" | "i586" | "i686" => Ok(Arch::X86),
+            "aarch64" | "arm64" => Ok(Arch::Aarch64),
+            "arm" => Ok(Arch::Arm),
+            "riscv64" => Ok(Arch::Riscv64),
+            _ if name.starts_with("armv") => Ok(Arch::Arm),
+            _ => Err(format!("Unknown architecture {}, expected one of x86_64, \
+                              x86, aarch64, arm or riscv64", name)),
+        }
+    }
+}
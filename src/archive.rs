@@ -0,0 +1,359 @@
+//! Compact archives of analyzed profiles, which contain the samples along with
+//! the analysis results and some metadata about the recording. Archives can be
+//! analyzed again instead of perf script's output, which is much smaller and
+//! does not require perf or the profiled binaries to be around anymore.
+//!
+//! An archive is a stream of records, each starting with a one-byte tag.
+//! Integers are encoded as LEB128 varints. Since stack frames repeat a lot,
+//! each distinct frame line is only stored once, in a dictionary record which
+//! precedes the first sample that uses it. Side-band records and trace errors
+//! are stored as samples, so that the reports which rely on them still work.
+//! The metadata and results records are encoded with bincode.
+
+use bincode;
+use memmap2::Mmap;
+use sample::{Sample, SampleSource};
+use stats::Statistics;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Result, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+/// Magic bytes at the start of every archive, including a format version
+const MAGIC: &[u8] = b"PSA\x03";
+
+/// Record holding the metadata
+const TAG_METADATA: u8 = 1;
+
+/// Record adding a stack frame line to the dictionary
+const TAG_FRAME: u8 = 2;
+
+/// Record holding a sample, as a header line and frame dictionary indices
+const TAG_SAMPLE: u8 = 3;
+
+/// Record holding the analysis results, after the last sample
+const TAG_RESULTS: u8 = 4;
+
+
+/// Information about how and where a profile was recorded
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ArchiveMetadata {
+    /// Version of perf-script-analyze which wrote the archive
+    pub tool_version: String,
+
+    /// Time at which the archive was written, in seconds since the Unix epoch
+    pub created: u64,
+
+    /// Where the samples came from (perf script's output file or arguments)
+    pub source: String,
+
+    /// CPU architecture that the profile was recorded on, if known
+    pub arch: Option<String>,
+
+    /// Header which perf script printed when run with --header, if any
+    pub perf_header: String,
+
+    /// Only 1 in this many samples was archived, if the analysis was subsampled
+    pub sample_period: Option<u64>,
+}
+//
+impl ArchiveMetadata {
+    /// Describe the current analysis
+    pub fn new(source: String) -> Self {
+        Self {
+            tool_version: env!("CARGO_PKG_VERSION").to_owned(),
+            created: SystemTime::now().duration_since(UNIX_EPOCH)
+                                      .map_or(0, |d| d.as_secs()),
+            source,
+            ..Self::default()
+        }
+    }
+}
+///
+///
+/// Mechanism to write the samples of an analysis into an archive. Write errors
+/// are deferred until the archive is finished, so that they do not get in the
+/// way of the analysis itself.
+pub struct ArchiveWriter {
+    output: BufWriter<File>,
+
+    /// Dictionary indices of the stack frame lines written so far
+    frame_ids: HashMap<Vec<u8>, u64>,
+
+    /// Scratch buffer for the frame indices of the current sample
+    sample_frames: Vec<u64>,
+
+    /// First error which occurred while writing, if any
+    error: Option<Error>,
+}
+//
+impl ArchiveWriter {
+    /// Create an archive, starting with the metadata
+    pub fn create(path: &Path, metadata: &ArchiveMetadata) -> Result<Self> {
+        let mut output = BufWriter::new(File::create(path)?);
+        output.write_all(MAGIC)?;
+        let metadata = bincode::serialize(metadata).map_err(Error::other)?;
+        write_record(&mut output, TAG_METADATA, &metadata)?;
+        Ok(Self {
+            output,
+            frame_ids: HashMap::new(),
+            sample_frames: Vec::new(),
+            error: None,
+        })
+    }
+
+    /// Add a sample to the archive
+    pub fn record(&mut self, sample: &Sample) {
+        if self.error.is_none() {
+            if let Err(e) = self.try_record(sample) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    /// Add a sample to the archive, reporting errors immediately
+    fn try_record(&mut self, sample: &Sample) -> Result<()> {
+        // Add the sample's frames to the dictionary if needed
+        self.sample_frames.clear();
+        let lines = sample.stack_trace.split_inclusive(|&b| b == b'\n');
+        for line in lines.filter(|line| !line.is_empty()) {
+            let id = match self.frame_ids.get(line) {
+                Some(&id) => id,
+                None => {
+                    let id = self.frame_ids.len() as u64;
+                    write_record(&mut self.output, TAG_FRAME, line)?;
+                    self.frame_ids.insert(line.to_owned(), id);
+                    id
+                },
+            };
+            self.sample_frames.push(id);
+        }
+
+        // Write the sample itself
+        self.output.write_all(&[TAG_SAMPLE])?;
+        write_varint(&mut self.output, sample.header.len() as u64)?;
+        self.output.write_all(sample.header)?;
+        write_varint(&mut self.output, self.sample_frames.len() as u64)?;
+        for &id in &self.sample_frames {
+            write_varint(&mut self.output, id)?;
+        }
+        Ok(())
+    }
+
+    /// Complete the archive with the analysis results
+    pub fn finish(mut self, results: &Statistics) -> Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        let results = bincode::serialize(results).map_err(Error::other)?;
+        write_record(&mut self.output, TAG_RESULTS, &results)?;
+        self.output.flush()
+    }
+}
+///
+///
+/// Mechanism to extract the samples of an archive, which is mapped in memory
+pub struct ArchiveSamples {
+    map: Mmap,
+    position: usize,
+
+    /// Location of each stack frame line of the dictionary in the archive
+    frames: Vec<(usize, usize)>,
+
+    /// Buffer in which the text of the current sample is reconstructed
+    buffer: Vec<u8>,
+
+    /// Metadata of the archive
+    metadata: ArchiveMetadata,
+
+    /// Analysis results, once the end of the archive has been reached
+    results: Option<Statistics>,
+}
+//
+impl ArchiveSamples {
+    /// Map an archive into memory and decode its metadata
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        // As with perf script dumps, archives are not supposed to be modified
+        // once written, so we take the risk of mapping them.
+        let map = unsafe { Mmap::map(&file)? };
+        if !map.starts_with(MAGIC) {
+            return Err(invalid_archive("not a perf-script-analyze archive"));
+        }
+        let mut result = Self {
+            map,
+            position: MAGIC.len(),
+            frames: Vec::new(),
+            buffer: Vec::new(),
+            metadata: ArchiveMetadata::default(),
+            results: None,
+        };
+        match result.read_record()? {
+            Some((TAG_METADATA, start, end)) => {
+                result.metadata = bincode::deserialize(&result.map[start..end])
+                                         .map_err(|e| invalid_archive(&e.to_string()))?;
+            },
+            _ => return Err(invalid_archive("missing metadata")),
+        }
+        Ok(result)
+    }
+
+    /// Variant of open() which skips the samples located before some byte
+    /// offset of the archive (e.g. when resuming)
+    pub fn open_at(path: &Path, offset: u64) -> Result<Self> {
+        let mut result = Self::open(path)?;
+        while (result.position as u64) < offset && result.next()?.is_some() {}
+        Ok(result)
+    }
+
+    /// Metadata of the archive
+    pub fn metadata(&self) -> &ArchiveMetadata {
+        &self.metadata
+    }
+
+    /// Analysis results that were stored in the archive, which are only known
+    /// once all samples have been extracted
+    pub fn results(&self) -> Option<&Statistics> {
+        self.results.as_ref()
+    }
+
+    /// Total size of the archive, in bytes
+    pub fn file_size(&self) -> u64 {
+        self.map.len() as u64
+    }
+
+    /// Extract the next sample from the archive, will return Ok(None) when the
+    /// end of the archive is reached.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<Sample<'_>>> {
+        loop {
+            let tag = match self.map.get(self.position) {
+                Some(&tag) => tag,
+                None => return Ok(None),
+            };
+            match tag {
+                TAG_SAMPLE => break,
+                TAG_RESULTS => {
+                    let (_, start, end) = self.read_record()?.unwrap();
+                    let results = bincode::deserialize(&self.map[start..end])
+                                          .map_err(|e| invalid_archive(&e.to_string()))?;
+                    self.results = Some(results);
+                },
+                TAG_FRAME => {
+                    let (_, start, end) = self.read_record()?.unwrap();
+                    self.frames.push((start, end));
+                },
+                _ => {
+                    self.read_record()?;
+                },
+            }
+        }
+
+        // Reconstruct the text of the sample, as perf script printed it
+        self.position += 1;
+        self.buffer.clear();
+        let header_len = self.read_varint()? as usize;
+        let (start, end) = self.read_bytes(header_len)?;
+        self.buffer.extend_from_slice(&self.map[start..end]);
+        let num_frames = self.read_varint()?;
+        let mut last_line_start = None;
+        for _ in 0..num_frames {
+            let id = self.read_varint()? as usize;
+            let &(start, end) = self.frames.get(id).ok_or_else(|| {
+                invalid_archive("reference to an unknown stack frame")
+            })?;
+            last_line_start = Some(self.buffer.len());
+            self.buffer.extend_from_slice(&self.map[start..end]);
+        }
+
+        let raw_sample_data = &self.buffer[..];
+        Ok(Some(Sample {
+            raw_sample_data,
+            header: &raw_sample_data[..header_len],
+            stack_trace: &raw_sample_data[header_len..],
+            last_stack_frame: last_line_start.map(|start| &raw_sample_data[start..]),
+        }))
+    }
+
+    /// Read a length-prefixed record, returning its tag and the location of
+    /// its payload, or None at the end of the archive
+    fn read_record(&mut self) -> Result<Option<(u8, usize, usize)>> {
+        let tag = match self.map.get(self.position) {
+            Some(&tag) => tag,
+            None => return Ok(None),
+        };
+        self.position += 1;
+        let len = self.read_varint()? as usize;
+        let (start, end) = self.read_bytes(len)?;
+        Ok(Some((tag, start, end)))
+    }
+
+    /// Read a varint-encoded integer
+    fn read_varint(&mut self) -> Result<u64> {
+        let mut result = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.map.get(self.position).ok_or_else(truncated_archive)?;
+            self.position += 1;
+            result |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+        }
+        Err(invalid_archive("oversized integer"))
+    }
+
+    /// Locate some number of bytes at the current position
+    fn read_bytes(&mut self, len: usize) -> Result<(usize, usize)> {
+        let start = self.position;
+        let end = start.checked_add(len)
+                       .filter(|&end| end <= self.map.len())
+                       .ok_or_else(truncated_archive)?;
+        self.position = end;
+        Ok((start, end))
+    }
+}
+//
+impl SampleSource for ArchiveSamples {
+    fn next_sample(&mut self) -> Result<Option<Sample<'_>>> {
+        self.next()
+    }
+
+    fn position(&self) -> u64 {
+        self.position as u64
+    }
+}
+
+
+/// Write a length-prefixed record
+fn write_record(output: &mut impl Write, tag: u8, payload: &[u8]) -> Result<()> {
+    output.write_all(&[tag])?;
+    write_varint(output, payload.len() as u64)?;
+    output.write_all(payload)
+}
+
+
+/// Write a varint-encoded integer
+fn write_varint(output: &mut impl Write, mut n: u64) -> Result<()> {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            return output.write_all(&[byte]);
+        }
+        output.write_all(&[byte | 0x80])?;
+    }
+}
+
+
+/// Error reported when an archive cannot be decoded
+fn invalid_archive(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("invalid archive: {}", message))
+}
+
+
+/// Error reported when an archive ends in the middle of a record
+fn truncated_archive() -> Error {
+    invalid_archive("unexpected end of file")
+}
@@ -0,0 +1,128 @@
+//! Recognition of the code of BPF programs, and of the time spent in them
+//!
+//! The kernel JIT-compiles BPF programs to native code, which perf names after
+//! the program's tag and name, e.g. "bpf_prog_6deef7357e7b4530_sd_fw_ingress".
+//! Depending on whether perf received the program's metadata, this name is
+//! either the symbol of the frame or the name of a pseudo-DSO.
+
+use sample::{Sample, StackFrame};
+use std::collections::HashMap;
+
+
+/// Prefix of the names which perf gives to JIT-compiled BPF programs
+const BPF_PROG_PREFIX: &str = "bpf_prog_";
+
+/// Length of a BPF program tag, which is 8 bytes printed in hexadecimal
+const TAG_LEN: usize = 16;
+
+/// Number of BPF programs which are displayed in the report
+const NUM_REPORTED_PROGRAMS: usize = 10;
+
+
+/// BPF program, as identified by its JIT-compiled code
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BpfProgram<'a> {
+    /// Tag of the program, a hash of its instructions
+    pub tag: &'a str,
+
+    /// Name of the program, which may be empty
+    pub name: &'a str,
+}
+//
+impl<'a> BpfProgram<'a> {
+    /// BPF program which a stack frame belongs to, if any
+    pub fn of_frame(frame: &StackFrame<'a>) -> Option<Self> {
+        Self::parse(frame.symbol).or_else(|| Self::parse(frame.dso))
+    }
+
+    /// Decode a name of the form "bpf_prog_<tag>[_<name>]", which may be
+    /// followed by an offset when perf script prints symbol offsets
+    fn parse(name: &'a str) -> Option<Self> {
+        let name = name.split('+').next()?;
+        let rest = name.strip_prefix(BPF_PROG_PREFIX)?;
+        let tag = rest.get(..TAG_LEN)?;
+        if !tag.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        let name = &rest[TAG_LEN..];
+        if !name.is_empty() && !name.starts_with('_') {
+            return None;
+        }
+        Some(Self { tag, name: name.trim_start_matches('_') })
+    }
+}
+///
+///
+/// Samples spent in each BPF program
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct BpfStatistics {
+    /// Number of samples with a stack trace which were taken into account
+    pub num_samples: usize,
+
+    /// Samples of each program, by tag
+    pub programs: HashMap<String, BpfProgramSamples>,
+}
+//
+impl BpfStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a new sample, which is attributed to its innermost BPF
+    /// program if any. Samples without a stack trace are ignored.
+    pub fn record(&mut self, sample: &Sample) {
+        if sample.frames().next().is_none() {
+            return;
+        }
+        self.num_samples += 1;
+        let program = sample.frames().find_map(|frame| BpfProgram::of_frame(&frame));
+        let program = match program {
+            Some(program) => program,
+            None => return,
+        };
+        let samples = match self.programs.get_mut(program.tag) {
+            Some(samples) => samples,
+            None => self.programs.entry(program.tag.to_owned()).or_insert_with(|| {
+                BpfProgramSamples {
+                    name: program.name.to_owned(),
+                    num_samples: 0,
+                }
+            }),
+        };
+        samples.num_samples += 1;
+    }
+
+    /// Print the share of samples spent in BPF programs, and the programs
+    /// which took the most time
+    pub fn print_report(&self) {
+        let num_bpf = self.programs.values().map(|p| p.num_samples).sum::<usize>();
+        if num_bpf == 0 {
+            println!("No sample in a BPF program");
+            return;
+        }
+        let percent = |count: usize| 100.0 * count as f64 / self.num_samples as f64;
+        println!("Samples in BPF programs: {} ({:.1}%)", num_bpf, percent(num_bpf));
+        let mut programs = self.programs.iter().collect::<Vec<_>>();
+        programs.sort_by(|a, b| b.1.num_samples.cmp(&a.1.num_samples).then(a.0.cmp(b.0)));
+        for (tag, program) in programs.into_iter().take(NUM_REPORTED_PROGRAMS) {
+            let name = if program.name.is_empty() { "<unnamed>" } else { &program.name };
+            println!("- {} ({}): {} ({:.1}%)",
+                     name,
+                     tag,
+                     program.num_samples,
+                     percent(program.num_samples));
+        }
+    }
+}
+///
+///
+/// Samples spent in one BPF program
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct BpfProgramSamples {
+    /// Name of the program, which may be empty
+    pub name: String,
+
+    /// Number of samples
+    pub num_samples: usize,
+}
@@ -0,0 +1,87 @@
+//! Detection of DSOs which changed on disk since the profile was recorded, by
+//! comparing the build-ids that perf recorded with those of the files on disk.
+//! When this happens, perf resolves symbols using the wrong binary and reports
+//! nonsensical stack traces.
+
+use elf::{truncated, Elf};
+use std::collections::HashSet;
+use std::fs;
+use std::io::Result;
+use paths::PathMap;
+use std::path::Path;
+
+
+/// Parse the output of perf buildid-list, which is a list of
+/// "<build-id> <path>" lines, into (build-id, path) pairs
+pub fn parse_buildid_list(text: &str) -> Vec<(String, String)> {
+    text.lines().filter_map(|line| {
+        let line = line.trim();
+        let separator = line.find(char::is_whitespace)?;
+        let build_id = &line[..separator];
+        let path = line[separator..].trim();
+        if build_id.is_empty() || path.is_empty() {
+            None
+        } else {
+            Some((build_id.to_ascii_lowercase(), path.to_owned()))
+        }
+    }).collect()
+}
+
+/// Find the DSOs whose on-disk build-id differs from the recorded one, looking
+/// them up on disk through some path mapping. DSOs which are not regular files
+/// (e.g. "[kernel.kallsyms]") or cannot be found on disk are not reported,
+/// since we cannot tell whether they changed.
+pub fn find_stale_dsos(recorded: &[(String, String)],
+                       path_map: &PathMap) -> HashSet<String> {
+    recorded.iter()
+            .filter(|(_, path)| !path.starts_with('['))
+            .filter(|(build_id, path)| {
+                match read_build_id(&path_map.resolve(path)) {
+                    Ok(Some(ref actual)) => actual != build_id,
+                    _ => false,
+                }
+            })
+            .map(|(_, path)| path.clone())
+            .collect()
+}
+
+/// Read the GNU build-id of an ELF file, as a lowercase hex string. Returns
+/// None if the file is a valid ELF file without a build-id.
+pub fn read_build_id(path: &Path) -> Result<Option<String>> {
+    let data = fs::read(path)?;
+    let elf = Elf::parse(&data)?;
+
+    // Look for a NT_GNU_BUILD_ID note in every SHT_NOTE section
+    const SHT_NOTE: u32 = 7;
+    const NT_GNU_BUILD_ID: u32 = 3;
+    for idx in 0..elf.section_count() {
+        let section = elf.section(idx)?;
+        if section.sh_type != SHT_NOTE {
+            continue;
+        }
+        let notes = elf.section_data(&section)?;
+        let mut pos = 0;
+        while pos + 12 <= notes.len() {
+            let name_size = elf.u32_at(notes, pos)? as usize;
+            let desc_size = elf.u32_at(notes, pos + 4)? as usize;
+            let note_type = elf.u32_at(notes, pos + 8)?;
+            let name_start = pos + 12;
+            let desc_start = name_start + align4(name_size);
+            let desc_end = desc_start + desc_size;
+            let name = notes.get(name_start..name_start+name_size).ok_or_else(truncated)?;
+            let desc = notes.get(desc_start..desc_end).ok_or_else(truncated)?;
+            if note_type == NT_GNU_BUILD_ID && name == b"GNU\0" {
+                let hex = desc.iter().map(|b| format!("{:02x}", b)).collect();
+                return Ok(Some(hex));
+            }
+            pos = desc_start + align4(desc_size);
+        }
+    }
+    Ok(None)
+}
+
+
+/// Round a size up to the 4-byte alignment of ELF notes
+fn align4(size: usize) -> usize {
+    (size + 3) & !3
+}
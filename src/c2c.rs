@@ -0,0 +1,171 @@
+//! Simplified perf c2c analysis, which finds the cachelines that bounce between
+//! CPU caches, from the output of perf script on a recording of `perf c2c
+//! record` (which samples loads and stores along with their data address and
+//! data source, printed with -F +addr,+data_src)
+//!
+//! Loads which hit a line that was modified in another core's cache (HITM) are
+//! the signature of contended cachelines. When several functions or threads
+//! are involved, the line is either truly shared data which should be
+//! accessed less often, or unrelated data which should be moved apart.
+
+use sample::Sample;
+use std::collections::HashMap;
+
+
+/// Size of a cacheline, in bytes
+const CACHELINE_SIZE: u64 = 64;
+
+/// Number of contended cachelines which are displayed in the report
+const NUM_REPORTED_CACHELINES: usize = 10;
+
+/// Number of functions and threads which are displayed for each cacheline
+const NUM_REPORTED_ACCESSORS: usize = 3;
+
+
+/// Loads, stores and HITMs of each cacheline
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct SharingStatistics {
+    /// Accesses to each cacheline, by cacheline address
+    pub cachelines: HashMap<u64, CachelineSharing>,
+}
+//
+impl SharingStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a new sample. Only loads and stores which tell their data
+    /// address are taken into account.
+    pub fn record(&mut self, sample: &Sample) {
+        let (operation, address) = match (sample.memory_operation(),
+                                          sample.data_address()) {
+            (Some(operation), Some(address)) if address != 0 => (operation, address),
+            _ => return,
+        };
+        let is_store = match operation {
+            "LOAD" => false,
+            "STORE" => true,
+            _ => return,
+        };
+        let line = self.cachelines.entry(address - address % CACHELINE_SIZE)
+                                  .or_default();
+        if is_store {
+            line.num_stores += 1;
+        } else {
+            line.num_loads += 1;
+            if sample.memory_snoop() == Some("HitM") {
+                let is_remote = sample.memory_level().is_some_and(|level| {
+                    level.starts_with("Remote")
+                });
+                if is_remote {
+                    line.num_remote_hitm += 1;
+                } else {
+                    line.num_local_hitm += 1;
+                }
+            }
+        }
+        if let Some(tid) = sample.tid() {
+            *line.threads.entry(tid).or_default() += 1;
+        }
+        if let Some(leaf) = sample.frames().next() {
+            let symbols = match line.functions.get_mut(leaf.dso) {
+                Some(symbols) => symbols,
+                None => line.functions.entry(leaf.dso.to_owned()).or_default(),
+            };
+            match symbols.get_mut(leaf.symbol) {
+                Some(count) => *count += 1,
+                None => { symbols.insert(leaf.symbol.to_owned(), 1); },
+            }
+        }
+    }
+
+    /// Print the cachelines with the most HITMs, along with the functions and
+    /// threads which accessed them
+    pub fn print_report(&self) {
+        let mut total = CachelineSharing::default();
+        for line in self.cachelines.values() {
+            total.num_loads += line.num_loads;
+            total.num_stores += line.num_stores;
+            total.num_local_hitm += line.num_local_hitm;
+            total.num_remote_hitm += line.num_remote_hitm;
+        }
+        if total.num_loads + total.num_stores == 0 {
+            println!("No load or store with a data address and source (record with \
+                      perf c2c record, and run perf script with -F +addr,+data_src)");
+            return;
+        }
+        println!("Cacheline sharing over {} loads and {} stores: {} local HITMs, {} \
+                  remote HITMs",
+                 total.num_loads,
+                 total.num_stores,
+                 total.num_local_hitm,
+                 total.num_remote_hitm);
+        let mut lines = self.cachelines.iter().filter(|&(_, line)| {
+            line.num_hitm() > 0
+        }).collect::<Vec<_>>();
+        if lines.is_empty() {
+            println!("No contended cacheline");
+            return;
+        }
+        lines.sort_by(|a, b| {
+            b.1.num_hitm().cmp(&a.1.num_hitm())
+                          .then(b.1.num_stores.cmp(&a.1.num_stores))
+                          .then(a.0.cmp(b.0))
+        });
+        println!("Contended cachelines, most HITMs first:");
+        for (address, line) in lines.into_iter().take(NUM_REPORTED_CACHELINES) {
+            let warning = if line.threads.len() > 1 { " [warning]" } else { "" };
+            println!("- {:#x}: {} HITMs ({} remote), {} loads, {} stores from {} \
+                      threads{}",
+                     address,
+                     line.num_hitm(),
+                     line.num_remote_hitm,
+                     line.num_loads,
+                     line.num_stores,
+                     line.threads.len(),
+                     warning);
+            let mut functions = line.functions.iter().flat_map(|(dso, symbols)| {
+                symbols.iter().map(move |(symbol, &count)| (count, symbol, dso))
+            }).collect::<Vec<_>>();
+            functions.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(b.1)).then(a.2.cmp(b.2)));
+            for (count, symbol, dso) in functions.into_iter()
+                                                 .take(NUM_REPORTED_ACCESSORS) {
+                println!("  - {} ({}): {} accesses", symbol, dso, count);
+            }
+            let mut threads = line.threads.iter().collect::<Vec<_>>();
+            threads.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            let threads = threads.into_iter().take(NUM_REPORTED_ACCESSORS)
+                                 .map(|(tid, count)| format!("{} ({})", tid, count))
+                                 .collect::<Vec<_>>();
+            println!("  - Threads: {}", threads.join(", "));
+        }
+    }
+}
+///
+///
+/// Sampled accesses to one cacheline
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct CachelineSharing {
+    /// Number of sampled loads and stores
+    pub num_loads: usize,
+    pub num_stores: usize,
+
+    /// Number of loads which hit a line modified by a core of the same socket,
+    /// or of another socket
+    pub num_local_hitm: usize,
+    pub num_remote_hitm: usize,
+
+    /// Number of accesses by each thread, by TID
+    pub threads: HashMap<u32, usize>,
+
+    /// Number of accesses by each leaf function, by DSO and symbol
+    pub functions: HashMap<String, HashMap<String, usize>>,
+}
+//
+impl CachelineSharing {
+    /// Number of loads which hit a modified line in another core's cache
+    pub fn num_hitm(&self) -> usize {
+        self.num_local_hitm + self.num_remote_hitm
+    }
+}
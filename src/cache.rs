@@ -0,0 +1,208 @@
+//! Attribution of cache misses to the functions and DSOs which caused them,
+//! for profiles recorded with cache miss events (e.g. LLC-load-misses)
+//!
+//! When the profile was recorded with memory access sampling events (e.g.
+//! `perf mem record`, or `perf record -e cpu/mem-loads/P -d`) and perf script
+//! printed their data source (with -F +data_src), the level of the memory
+//! hierarchy which served each sampled access is broken down too.
+
+use sample::Sample;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+
+/// Number of functions which are displayed for each event
+const NUM_REPORTED_FUNCTIONS: usize = 10;
+
+/// Number of DSOs which are displayed for each event
+const NUM_REPORTED_DSOS: usize = 5;
+
+
+/// Misses of each cache miss event, and data sources of memory accesses
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct CacheStatistics {
+    /// Estimated misses of each leaf function, by event, DSO and symbol
+    pub events: HashMap<String, HashMap<String, HashMap<String, u64>>>,
+
+    /// Number of sampled memory accesses served by each level of the memory
+    /// hierarchy
+    pub data_sources: HashMap<DataSource, usize>,
+}
+//
+impl CacheStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a new sample. Samples of events which are not cache
+    /// misses, and samples without a stack trace, are only checked for a data
+    /// source.
+    pub fn record(&mut self, sample: &Sample) {
+        if let Some(source) = sample.memory_level().and_then(DataSource::classify) {
+            *self.data_sources.entry(source).or_default() += 1;
+        }
+        let event = match sample.event_base_name() {
+            Some(event) if event.contains("miss") => event,
+            _ => return,
+        };
+        let leaf = match sample.frames().next() {
+            Some(leaf) => leaf,
+            None => return,
+        };
+        let period = sample.period().unwrap_or(1);
+        let dsos = match self.events.get_mut(event) {
+            Some(dsos) => dsos,
+            None => self.events.entry(event.to_owned()).or_default(),
+        };
+        let symbols = match dsos.get_mut(leaf.dso) {
+            Some(symbols) => symbols,
+            None => dsos.entry(leaf.dso.to_owned()).or_default(),
+        };
+        match symbols.get_mut(leaf.symbol) {
+            Some(misses) => *misses = misses.saturating_add(period),
+            None => { symbols.insert(leaf.symbol.to_owned(), period); },
+        }
+    }
+
+    /// Print the DSOs and functions which caused the most misses of each
+    /// event, and the breakdown of memory accesses by data source
+    pub fn print_report(&self) {
+        if self.events.is_empty() && self.data_sources.is_empty() {
+            println!("No cache miss event or data source in the profile (record \
+                      e.g. LLC-load-misses, or use perf mem record and perf \
+                      script -F +data_src)");
+            return;
+        }
+        let mut events = self.events.iter().collect::<Vec<_>>();
+        events.sort_by(|a, b| a.0.cmp(b.0));
+        for (idx, (event, dsos)) in events.into_iter().enumerate() {
+            if idx > 0 {
+                println!();
+            }
+            Self::print_event(event, dsos);
+        }
+        if !self.data_sources.is_empty() {
+            if !self.events.is_empty() {
+                println!();
+            }
+            self.print_data_sources();
+        }
+    }
+
+    /// Print the DSOs and functions which caused the most misses of an event
+    fn print_event(event: &str, dsos: &HashMap<String, HashMap<String, u64>>) {
+        let mut dso_misses = dsos.iter().map(|(dso, symbols)| {
+            (dso, symbols.values().sum::<u64>())
+        }).collect::<Vec<_>>();
+        let total = dso_misses.iter().map(|&(_, misses)| misses).sum::<u64>();
+        let percent = |misses: u64| 100.0 * misses as f64 / total.max(1) as f64;
+        println!("Estimated {} over {} DSOs: {}", event, dsos.len(), total);
+        dso_misses.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        println!("{:>7}  DSO", "Misses");
+        for (dso, misses) in dso_misses.into_iter().take(NUM_REPORTED_DSOS) {
+            println!("{:>6.1}%  {}", percent(misses), dso);
+        }
+
+        let mut functions = dsos.iter().flat_map(|(dso, symbols)| {
+            symbols.iter().map(move |(symbol, &misses)| (symbol, dso, misses))
+        }).collect::<Vec<_>>();
+        functions.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(b.0)).then(a.1.cmp(b.1)));
+        println!("{:>7}  Function", "Misses");
+        for (symbol, dso, misses) in functions.into_iter().take(NUM_REPORTED_FUNCTIONS) {
+            println!("{:>6.1}%  {} ({})", percent(misses), symbol, dso);
+        }
+    }
+
+    /// Print the breakdown of sampled memory accesses by data source
+    fn print_data_sources(&self) {
+        let total = self.data_sources.values().sum::<usize>();
+        println!("Data sources of {} sampled memory accesses:", total);
+        for &source in DataSource::ALL.iter() {
+            let count = self.data_sources.get(&source).cloned().unwrap_or(0);
+            if count > 0 {
+                println!("- {}: {} ({:.1}%)",
+                         source,
+                         count,
+                         100.0 * count as f64 / total as f64);
+            }
+        }
+    }
+}
+///
+///
+/// Level of the memory hierarchy which served a memory access
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum DataSource {
+    /// L1 data cache, or line fill buffer of an access which already missed
+    L1,
+    L2,
+    Llc,
+    /// Cache of another socket
+    RemoteCache,
+    /// Local or remote DRAM
+    Dram,
+    /// Persistent memory, I/O, uncached memory, or an unknown level
+    Other,
+}
+//
+impl DataSource {
+    /// Every data source, from the closest to the farthest
+    pub const ALL: [DataSource; 6] = [
+        DataSource::L1,
+        DataSource::L2,
+        DataSource::Llc,
+        DataSource::RemoteCache,
+        DataSource::Dram,
+        DataSource::Other,
+    ];
+
+    /// Classify the memory level which perf script printed for an access.
+    /// Returns None if perf did not know it.
+    pub fn classify(level: &str) -> Option<Self> {
+        if level == "N/A" {
+            return None;
+        }
+        let source = if !level.contains("hit") {
+            DataSource::Other
+        } else if level.contains("RAM") {
+            DataSource::Dram
+        } else if level.contains("Remote Cache") {
+            DataSource::RemoteCache
+        } else if level.contains("L3") || level.contains("LLC") {
+            DataSource::Llc
+        } else if level.contains("L2") {
+            DataSource::L2
+        } else if level.contains("L1") || level.contains("LFB") {
+            DataSource::L1
+        } else {
+            DataSource::Other
+        };
+        Some(source)
+    }
+}
+//
+impl FromStr for DataSource {
+    type Err = String;
+
+    fn from_str(name: &str) -> ::std::result::Result<Self, String> {
+        DataSource::ALL.iter().cloned()
+                       .find(|source| source.to_string() == name)
+                       .ok_or_else(|| format!("Unknown data source {}", name))
+    }
+}
+//
+impl fmt::Display for DataSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            DataSource::L1 => "L1",
+            DataSource::L2 => "L2",
+            DataSource::Llc => "LLC",
+            DataSource::RemoteCache => "Remote cache",
+            DataSource::Dram => "DRAM",
+            DataSource::Other => "Other",
+        };
+        write!(f, "{}", name)
+    }
+}
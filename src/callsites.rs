@@ -0,0 +1,258 @@
+//! Validation of return addresses against the code of the DSOs on disk. The
+//! address of a caller frame is the return address of a call, so the bytes
+//! right before it must decode as a call instruction. When they do not, the
+//! frame was made up by the unwinder, e.g. from a stale value on the stack
+//! which perf happened to symbolize.
+//!
+//! This requires perf script to print symbol offsets (-F +symoff) and to keep
+//! symbols mangled (--no-demangle), since return addresses are located by
+//! looking up the frame's symbol in the ELF symbol tables of its DSO.
+
+use elf::Elf;
+use memmap2::Mmap;
+use paths::PathMap;
+use sample::{Sample, StackFrame};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Result;
+
+
+/// ELF machine identifiers of the architectures whose calls can be decoded
+const EM_386: u16 = 3;
+const EM_X86_64: u16 = 62;
+const EM_AARCH64: u16 = 183;
+
+/// Longest x86 indirect call which is decoded, excluding prefixes
+const MAX_X86_CALL_LEN: usize = 7;
+
+
+/// Code and symbols of the DSOs on disk, which are loaded lazily, as DSOs are
+/// encountered in samples
+pub struct CallSites {
+    /// Rules for locating recorded DSOs on this machine
+    path_map: PathMap,
+
+    /// Each DSO which was looked up, or None if it could not be read
+    dsos: RefCell<HashMap<String, Option<DsoCode>>>,
+}
+//
+impl CallSites {
+    /// Look up DSOs on this machine using some path translation rules
+    pub fn new(path_map: PathMap) -> Self {
+        Self {
+            path_map,
+            dsos: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Truth that the return address of a caller frame follows a call
+    /// instruction, if this can be checked. Only frames with a symbol offset,
+    /// from a regular ELF file of a known architecture, can be checked.
+    pub fn follows_call(&self, frame: &StackFrame) -> Option<bool> {
+        let offset = frame.offset?;
+        if !frame.dso.starts_with('/') || frame.dso.starts_with("//") || frame.deleted {
+            return None;
+        }
+        if !self.dsos.borrow().contains_key(frame.dso) {
+            let code = DsoCode::load(&self.path_map, frame.dso).ok();
+            self.dsos.borrow_mut().insert(frame.dso.to_owned(), code);
+        }
+        let dsos = self.dsos.borrow();
+        let code = dsos.get(frame.dso)?.as_ref()?;
+
+        // PLT stubs are not in symbol tables, and symbol versions (e.g.
+        // "memcpy@GLIBC_2.14") may or may not be. Static functions may share a
+        // name, and the frame is only suspect if none of them has a call at
+        // the return address.
+        if frame.symbol.ends_with("@plt") {
+            return None;
+        }
+        let functions = code.functions.get(frame.symbol).or_else(|| {
+            let (name, _version) = frame.symbol.split_once('@')?;
+            code.functions.get(name)
+        })?;
+        Some(functions.iter().any(|&(address, size)| {
+            (size == 0 || offset <= size) && code.follows_call(address + offset)
+        }))
+    }
+
+    /// First caller frame of a sample whose return address does not follow a
+    /// call instruction, if any
+    pub fn find_suspect_frame<'a>(&self, sample: &Sample<'a>) -> Option<StackFrame<'a>> {
+        sample.callers()
+              .map(|(_, frame)| frame)
+              .find(|frame| self.follows_call(frame) == Some(false))
+    }
+}
+///
+///
+/// Code and function symbols of a DSO
+struct DsoCode {
+    /// Contents of the ELF file
+    map: Mmap,
+
+    /// Virtual address and size of each function, by name
+    functions: HashMap<String, Vec<(u64, u64)>>,
+}
+//
+impl DsoCode {
+    /// Map a DSO from disk and index its functions
+    fn load(path_map: &PathMap, dso: &str) -> Result<Self> {
+        let file = File::open(path_map.resolve(dso))?;
+        let map = unsafe { Mmap::map(&file)? };
+        let mut functions = HashMap::<String, Vec<(u64, u64)>>::new();
+        for symbol in Elf::parse(&map)?.functions()? {
+            let entry = functions.entry(symbol.name.to_owned()).or_default();
+            if !entry.contains(&(symbol.address, symbol.size)) {
+                entry.push((symbol.address, symbol.size));
+            }
+        }
+        Ok(Self { map, functions })
+    }
+
+    /// Truth that the code right before some virtual address is a call, or
+    /// that this cannot be told because the architecture is not supported
+    fn follows_call(&self, return_address: u64) -> bool {
+        let elf = match Elf::parse(&self.map) {
+            Ok(elf) => elf,
+            Err(_) => return true,
+        };
+        match elf.machine() {
+            Ok(EM_386) | Ok(EM_X86_64) => {
+                elf.bytes_before(return_address, MAX_X86_CALL_LEN)
+                   .is_some_and(follows_x86_call)
+            },
+            Ok(EM_AARCH64) => {
+                let word = elf.bytes_before(return_address, 4)
+                              .filter(|bytes| bytes.len() == 4)
+                              .and_then(|bytes| elf.u32_at(bytes, 0).ok());
+                word.is_some_and(is_aarch64_call)
+            },
+            _ => true,
+        }
+    }
+}
+
+
+/// Truth that some x86 code ends with a direct call (E8 rel32) or with an
+/// indirect call (FF /2) whose operand ends right at the end of the code
+fn follows_x86_call(code: &[u8]) -> bool {
+    let len = code.len();
+    if len >= 5 && code[len - 5] == 0xe8 {
+        return true;
+    }
+    (2..=len).any(|call_len| {
+        let (opcode, modrm) = (code[len - call_len], code[len - call_len + 1]);
+        let sib = code.get(len - call_len + 2).copied();
+        opcode == 0xff && (modrm >> 3) & 7 == 2 &&
+        x86_modrm_operand_len(modrm, sib) == Some(call_len - 1)
+    })
+}
+
+
+/// Length of the operand of an x86 instruction, i.e. of its ModR/M byte and
+/// the SIB byte and displacement that it calls for, if the SIB byte is known
+fn x86_modrm_operand_len(modrm: u8, sib: Option<u8>) -> Option<usize> {
+    let (mode, rm) = (modrm >> 6, modrm & 7);
+    if mode == 3 {
+        return Some(1);
+    }
+    let mut len = 1;
+    if rm == 4 {
+        len += 1;
+        if mode == 0 && sib? & 7 == 5 {
+            len += 4;
+        }
+    }
+    len += match mode {
+        0 if rm == 5 => 4,
+        1 => 1,
+        2 => 4,
+        _ => 0,
+    };
+    Some(len)
+}
+
+
+/// Truth that an aarch64 instruction is a call: BL, BLR, or one of the pointer
+/// authenticating BLRAA, BLRAAZ, BLRAB and BLRABZ
+fn is_aarch64_call(instruction: u32) -> bool {
+    instruction & 0xfc00_0000 == 0x9400_0000 ||
+    instruction & 0xffff_fc1f == 0xd63f_0000 ||
+    instruction & 0xfeff_f800 == 0xd63f_0800
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn x86_calls() {
+        // call rel32, after a mov
+        assert!(follows_x86_call(&[0x89, 0xc7, 0xe8, 0x10, 0x20, 0x30, 0x40]));
+        // call rax
+        assert!(follows_x86_call(&[0x48, 0x89, 0xc7, 0x31, 0xc0, 0xff, 0xd0]));
+        // call r11, with a REX prefix
+        assert!(follows_x86_call(&[0x00, 0x00, 0x00, 0x00, 0x41, 0xff, 0xd3]));
+        // call [rip + disp32]
+        assert!(follows_x86_call(&[0x90, 0xff, 0x15, 0x10, 0x20, 0x30, 0x40]));
+        // call [rsp + 8]
+        assert!(follows_x86_call(&[0x31, 0xc0, 0x90, 0xff, 0x54, 0x24, 0x08]));
+        // call [rax*8 + disp32], which is as long as decoded calls get
+        assert!(follows_x86_call(&[0xff, 0x14, 0xc5, 0x10, 0x20, 0x30, 0x40]));
+    }
+
+    #[test]
+    fn x86_non_calls() {
+        // mov rbp, rsp; pop rbp; ret
+        assert!(!follows_x86_call(&[0x00, 0x00, 0x48, 0x89, 0xe5, 0x5d, 0xc3]));
+        // jmp rax
+        assert!(!follows_x86_call(&[0x48, 0x89, 0xc7, 0x31, 0xc0, 0xff, 0xe0]));
+        // jmp [rip + disp32]
+        assert!(!follows_x86_call(&[0x90, 0xff, 0x25, 0x10, 0x20, 0x30, 0x40]));
+        // call [rsp + 8], whose operand does not end at the return address
+        assert!(!follows_x86_call(&[0x90, 0xff, 0x54, 0x24, 0x08, 0x90, 0x90]));
+        // Too little code to hold a call
+        assert!(!follows_x86_call(&[0xd0]));
+        assert!(!follows_x86_call(&[]));
+    }
+
+    #[test]
+    fn x86_operand_lengths() {
+        // Register operand
+        assert_eq!(x86_modrm_operand_len(0xd0, None), Some(1));
+        // RIP-relative operand
+        assert_eq!(x86_modrm_operand_len(0x15, None), Some(5));
+        // Register-indirect operand, without and with displacement
+        assert_eq!(x86_modrm_operand_len(0x10, None), Some(1));
+        assert_eq!(x86_modrm_operand_len(0x50, None), Some(2));
+        assert_eq!(x86_modrm_operand_len(0x90, None), Some(5));
+        // SIB operand, with an 8-bit displacement or a 32-bit one and no base
+        assert_eq!(x86_modrm_operand_len(0x54, Some(0x24)), Some(3));
+        assert_eq!(x86_modrm_operand_len(0x14, Some(0xc5)), Some(6));
+        assert_eq!(x86_modrm_operand_len(0x14, Some(0x24)), Some(2));
+        // The length of an SIB operand without displacement depends on the SIB
+        assert_eq!(x86_modrm_operand_len(0x14, None), None);
+    }
+
+    #[test]
+    fn aarch64_calls() {
+        // BL, forwards and backwards
+        assert!(is_aarch64_call(0x9400_0010));
+        assert!(is_aarch64_call(0x97ff_ffe0));
+        // BLR x1
+        assert!(is_aarch64_call(0xd63f_0020));
+        // BLRAA x1, x2 and BLRAAZ x1
+        assert!(is_aarch64_call(0xd73f_0822));
+        assert!(is_aarch64_call(0xd63f_083f));
+
+        // B, BR x1, BRAA x1, x2, RET and NOP
+        assert!(!is_aarch64_call(0x1400_0010));
+        assert!(!is_aarch64_call(0xd61f_0020));
+        assert!(!is_aarch64_call(0xd71f_0822));
+        assert!(!is_aarch64_call(0xd65f_03c0));
+        assert!(!is_aarch64_call(0xd503_201f));
+    }
+}
@@ -0,0 +1,130 @@
+//! Per-cgroup stack trace quality, which tells container users which of their
+//! containers run binaries without debug info.
+//!
+//! This needs samples to carry their cgroup path, which perf script prints with
+//! -F +cgroup for recordings made with perf record --all-cgroups (the matching
+//! PERF_RECORD_CGROUP records, shown by --show-cgroup-events, are what perf
+//! uses to turn cgroup IDs into paths).
+
+use analysis::SampleCategory;
+use sample::Sample;
+use std::collections::HashMap;
+
+
+/// Number of cgroups which are displayed in the report
+const NUM_REPORTED_CGROUPS: usize = 10;
+
+/// Number of unsymbolized DSOs which are displayed for each cgroup
+const NUM_REPORTED_DSOS: usize = 3;
+
+
+/// Stack trace quality counters of each cgroup, by cgroup path
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct CgroupStatistics {
+    pub cgroups: HashMap<String, CgroupQuality>,
+
+    /// Samples which did not tell which cgroup they belong to
+    pub num_unknown: usize,
+}
+//
+impl CgroupStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a newly classified sample
+    pub fn record(&mut self, sample: &Sample, category: &SampleCategory) {
+        let path = match sample.cgroup() {
+            Some(path) => path,
+            None => {
+                self.num_unknown += 1;
+                return;
+            },
+        };
+        let cgroup = match self.cgroups.get_mut(path) {
+            Some(cgroup) => cgroup,
+            None => self.cgroups.entry(path.to_owned()).or_default(),
+        };
+        let weight = sample.period().unwrap_or(1);
+        cgroup.num_samples += 1;
+        cgroup.total_weight += weight;
+        if let SampleCategory::Normal = *category {
+            cgroup.normal_weight += weight;
+        }
+        for frame in sample.frames() {
+            cgroup.num_frames += 1;
+            if frame.is_unknown_symbol() {
+                cgroup.num_unknown_frames += 1;
+                *cgroup.unsymbolized_dsos.entry(frame.dso.to_owned()).or_default() += 1;
+            }
+        }
+    }
+
+    /// Print the cgroups with the worst stack trace quality, along with the
+    /// DSOs which most often lack symbols in each of them
+    pub fn print_report(&self) {
+        if self.cgroups.is_empty() {
+            println!("No sample had a cgroup (record with perf record --all-cgroups \
+                      and run perf script -F +cgroup)");
+            return;
+        }
+        let mut cgroups = self.cgroups.iter().collect::<Vec<_>>();
+        cgroups.sort_by(|&(path1, cgroup1), &(path2, cgroup2)| {
+            cgroup1.score().total_cmp(&cgroup2.score())
+                           .then(cgroup2.total_weight.cmp(&cgroup1.total_weight))
+                           .then(path1.cmp(path2))
+        });
+        println!("Stack trace quality per cgroup, worst first: {} cgroups",
+                 cgroups.len());
+        for (path, cgroup) in cgroups.into_iter().take(NUM_REPORTED_CGROUPS) {
+            println!("- {}: {:.1}% normal over {} samples, {:.1}% of frames unsymbolized",
+                     path,
+                     100.0 * cgroup.score(),
+                     cgroup.num_samples,
+                     100.0 * cgroup.num_unknown_frames as f64
+                           / cgroup.num_frames.max(1) as f64);
+            let mut dsos = cgroup.unsymbolized_dsos.iter().collect::<Vec<_>>();
+            dsos.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            for (dso, count) in dsos.into_iter().take(NUM_REPORTED_DSOS) {
+                println!("  - {}: {} unsymbolized frames", dso, count);
+            }
+        }
+        if self.num_unknown > 0 {
+            println!("- (no cgroup): {} samples", self.num_unknown);
+        }
+    }
+}
+///
+///
+/// Stack trace quality counters of one cgroup
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct CgroupQuality {
+    /// Number of samples of this cgroup
+    pub num_samples: usize,
+
+    /// Sum of the periods of this cgroup's samples
+    pub total_weight: u64,
+
+    /// Sum of the periods of this cgroup's Normal samples
+    pub normal_weight: u64,
+
+    /// Number of stack frames in this cgroup's samples
+    pub num_frames: usize,
+
+    /// Number of those frames which perf could not symbolize
+    pub num_unknown_frames: usize,
+
+    /// Number of unsymbolized frames in each DSO
+    pub unsymbolized_dsos: HashMap<String, usize>,
+}
+//
+impl CgroupQuality {
+    /// Fraction of this cgroup's sampled time which has a normal stack trace
+    pub fn score(&self) -> f64 {
+        if self.total_weight == 0 {
+            return 0.0;
+        }
+        self.normal_weight as f64 / self.total_weight as f64
+    }
+}
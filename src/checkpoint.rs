@@ -0,0 +1,190 @@
+//! Periodic persistence of the analysis state, so that an interrupted analysis
+//! of a huge perf script dump can be resumed instead of restarted
+
+use bincode;
+use dump::AnomalyDump;
+use stats::Statistics;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Result, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+
+/// Snapshot of the analysis state at some point of the input stream
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Checkpoint {
+    /// Input which was being analyzed
+    pub input: InputIdentity,
+
+    /// Options which the analysis was started with
+    pub options: AnalysisOptions,
+
+    /// Offset of the first input byte which was not analyzed yet
+    pub offset: u64,
+
+    /// Aggregate statistics of the samples analyzed so far
+    pub stats: Statistics,
+
+    /// Anomalous samples which were kept for the final dump so far
+    pub dump: AnomalyDump,
+}
+//
+impl Checkpoint {
+    /// Load a checkpoint from disk, returns None if there is no such file
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        bincode::deserialize_from(BufReader::new(file))
+            .map(Some)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Save a checkpoint to disk. A temporary file is written and renamed over
+    /// the destination, so that an interruption never leaves it half-written.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut tmp_path = PathBuf::from(path).into_os_string();
+        tmp_path.push(".tmp");
+        let mut out = BufWriter::new(File::create(&tmp_path)?);
+        bincode::serialize_into(&mut out, self).map_err(Error::other)?;
+        out.flush()?;
+        fs::rename(&tmp_path, path)
+    }
+}
+///
+///
+/// Identity of an analysis input, which tells whether a checkpoint can be
+/// resumed from. Offsets are meaningless if the input changed in between.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct InputIdentity {
+    /// Input file, or command line of perf script
+    pub source: String,
+
+    /// Size of the input file, or of the perf.data file read by perf script,
+    /// if it is a regular file
+    pub size: Option<u64>,
+
+    /// Modification time of that file, in seconds since the Unix epoch
+    pub modified: Option<u64>,
+}
+//
+impl InputIdentity {
+    /// Identify an input by some description and the file it comes from
+    pub fn new(source: String, file: &Path) -> Self {
+        let metadata = fs::metadata(file).ok().filter(|metadata| metadata.is_file());
+        let modified = metadata.as_ref()
+                               .and_then(|metadata| metadata.modified().ok())
+                               .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                               .map(|duration| duration.as_secs());
+        Self {
+            source,
+            size: metadata.map(|metadata| metadata.len()),
+            modified,
+        }
+    }
+}
+///
+///
+/// Options which shape the results of an analysis. Resuming an analysis with
+/// other options would mix up the results of two different analyses, e.g. a
+/// report which was only enabled on resumption would miss the samples which
+/// were analyzed before.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct AnalysisOptions {
+    /// Filters which select the analyzed samples, in textual form
+    pub filters: Vec<String>,
+
+    /// Reports, categories and stack fixups which are enabled, along with
+    /// their settings
+    pub reports: Vec<String>,
+
+    /// Period and seed of the subsampling, if only some samples are analyzed
+    pub sample_rate: Option<(u64, u64)>,
+}
+//
+impl AnalysisOptions {
+    /// Describe what differs from some other options, if anything
+    pub fn difference(&self, other: &Self) -> Option<&'static str> {
+        if self.filters != other.filters {
+            Some("other filters")
+        } else if self.reports != other.reports {
+            Some("other reports")
+        } else if self.sample_rate != other.sample_rate {
+            Some("another sample rate")
+        } else {
+            None
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analysis::SampleAnalyzer;
+    use hotspots::FunctionStatistics;
+    use sample::Sample;
+    use std::env;
+    use std::process;
+    use threads::ThreadStatistics;
+
+    #[test]
+    fn save_and_load() {
+        let header = "prog 1/2 [000] 1.0: 1 cycles: \n";
+        let text = format!("{}\t    7f00 foo (/lib/libfoo.so)\n\
+                            \t    7f10 _start (/usr/bin/prog)\n", header);
+        let sample = Sample::from_raw(text.as_bytes(), header.len());
+        let analyzer = SampleAnalyzer::new();
+        let category = analyzer.classify(&sample);
+        let mut stats = Statistics::new();
+        stats.sample_period = Some(4);
+        stats.add_custom_category("mine");
+        stats.functions = Some(FunctionStatistics::new(10));
+        stats.threads = Some(ThreadStatistics::new());
+        stats.record(&category);
+        stats.functions.as_mut().unwrap().record(&sample, &category);
+        stats.threads.as_mut().unwrap().record(&sample, &category);
+        let mut dump = AnomalyDump::new();
+        dump.record("Weird", &sample);
+        let checkpoint = Checkpoint {
+            input: InputIdentity { source: "perf script".to_owned(),
+                                   size: Some(42),
+                                   modified: None },
+            options: AnalysisOptions {
+                filters: vec!["exclude idle".to_owned()],
+                reports: vec!["thread".to_owned(), "top 10 functions".to_owned()],
+                sample_rate: Some((4, 7)),
+            },
+            offset: text.len() as u64,
+            stats,
+            dump,
+        };
+
+        let path = env::temp_dir().join(format!("psa-checkpoint-{}", process::id()));
+        let _ = fs::remove_file(&path);
+        assert_eq!(Checkpoint::load(&path).unwrap(), None);
+        checkpoint.save(&path).unwrap();
+        let loaded = Checkpoint::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, Some(checkpoint));
+    }
+
+    #[test]
+    fn options_difference() {
+        let options = AnalysisOptions {
+            filters: Vec::new(),
+            reports: vec!["thread".to_owned()],
+            sample_rate: Some((4, 0)),
+        };
+        assert_eq!(options.difference(&options.clone()), None);
+        let other_seed = AnalysisOptions { sample_rate: Some((4, 1)), ..options.clone() };
+        assert_eq!(options.difference(&other_seed), Some("another sample rate"));
+        let no_report = AnalysisOptions { reports: Vec::new(), ..options.clone() };
+        assert_eq!(options.difference(&no_report), Some("other reports"));
+        let filtered = AnalysisOptions { filters: vec!["exclude idle".to_owned()],
+                                         ..options.clone() };
+        assert_eq!(options.difference(&filtered), Some("other filters"));
+    }
+}
@@ -0,0 +1,467 @@
+//! Built-in stages of the sample classification pipeline. Each of them looks
+//! for one kind of anomaly, and lets the next stage decide if it can't tell.
+
+use addresses::MappingSizes;
+use analysis::{Classifier, SampleCategory, SampleView};
+use bpf::BpfProgram;
+use callsites::CallSites;
+use config::{CategoryRule, Pattern};
+use deleted::DeletedKind;
+use gpu;
+use guest;
+use jvm;
+use runtime;
+use std::collections::HashSet;
+use wasm::WasmCodeKind;
+
+
+/// Puts samples in the user-defined categories of the configuration file
+pub struct CustomRules {
+    /// User-defined category rules, the first one which matches wins
+    pub rules: Vec<CategoryRule>,
+}
+//
+impl Classifier for CustomRules {
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>> {
+        // Function and DSO patterns must be matched by the same stack frame
+        let matches = |rule: &CategoryRule| {
+            pattern_matches(&rule.comm, sample.sample.comm()) &&
+            pattern_matches(&rule.event, sample.sample.event()) &&
+            ((rule.function.is_none() && rule.dso.is_none()) ||
+             sample.frames().any(|frame| {
+                 pattern_matches(&rule.function, Some(frame.symbol)) &&
+                 pattern_matches(&rule.dso, Some(frame.dso))
+             }))
+        };
+        self.rules.iter()
+                  .find(|rule| matches(rule))
+                  .map(|rule| SampleCategory::Custom(&rule.name))
+    }
+}
+
+
+/// Recognizes samples taken in virtual machine guests, whose stacks perf
+/// cannot symbolize nor unwind like those of the host
+pub struct GuestSamples;
+//
+impl Classifier for GuestSamples {
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>> {
+        guest::guest_of(sample.sample).map(|_| SampleCategory::Guest)
+    }
+}
+
+
+/// Truth that an optional pattern of a category rule accepts some text. Rules
+/// which do not specify a pattern accept anything, even missing text.
+fn pattern_matches(pattern: &Option<Pattern>, text: Option<&str>) -> bool {
+    match *pattern {
+        None => true,
+        Some(ref pattern) => text.is_some_and(|text| pattern.matches(text)),
+    }
+}
+
+
+/// Detects samples whose kernel frames could not be symbolized
+pub struct KernelSymbols;
+//
+impl Classifier for KernelSymbols {
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>> {
+        // If the sample went through the kernel, but perf could not symbolize
+        // any of its kernel frames, kernel symbols were most likely not
+        // available. Kernel frames are all at the leaf end of the stack.
+        let arch = sample.arch();
+        let mut kernel_frames = sample.frames()
+                                      .take_while(|frame| arch.is_kernel_frame(frame))
+                                      .peekable();
+        if kernel_frames.peek().is_some() &&
+           kernel_frames.all(|frame| frame.is_unknown_symbol())
+        {
+            Some(SampleCategory::KernelSymbolsUnavailable)
+        } else {
+            None
+        }
+    }
+}
+
+
+/// Kernel functions which run deferred interrupt work (softirqs)
+const SOFT_IRQ_FUNCS: &[&str] = &[
+    "__softirqentry_text_start", "__do_softirq", "handle_softirqs", "run_ksoftirqd",
+];
+
+/// Recognizes samples taken while the kernel was handling interrupts, which
+/// steal time from whatever code was running at the time
+pub struct Interrupts;
+//
+impl Classifier for Interrupts {
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>> {
+        // Softirqs usually run on the way out of a hardware interrupt, so the
+        // innermost interrupt entry point tells what was running
+        let arch = sample.arch();
+        sample.frames()
+              .take_while(|frame| arch.is_kernel_frame(frame))
+              .find_map(|frame| {
+                  if SOFT_IRQ_FUNCS.contains(&frame.symbol) {
+                      Some(SampleCategory::SoftIrq)
+                  } else if arch.is_hard_irq_entry(frame.symbol) {
+                      Some(SampleCategory::HardIrq)
+                  } else {
+                      None
+                  }
+              })
+    }
+}
+
+
+/// Functions where threads wait for something to happen instead of computing,
+/// either in the kernel's idle loop or in a blocking system call
+const IDLE_FUNCS: &[&str] = &[
+    "intel_idle", "acpi_idle_do_entry", "default_idle", "poll_idle", "mwait_idle",
+    "native_safe_halt", "arch_cpu_idle", "cpu_idle_poll", "epoll_wait",
+    "__epoll_wait", "epoll_pwait", "do_epoll_wait", "futex_wait", "__futex_wait",
+    "futex_wait_queue", "futex_wait_queue_me", "__futex_abstimed_wait_common",
+    "poll", "__poll", "ppoll", "do_sys_poll", "select", "pselect", "do_select",
+];
+
+/// Recognizes samples of threads which are idle or waiting for events
+pub struct IdleSamples;
+//
+impl Classifier for IdleSamples {
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>> {
+        // Idle samples are recognized by the function that was executing
+        let leaf = sample.frames().next()?;
+        if leaf.symbol.starts_with("cpuidle") || IDLE_FUNCS.contains(&leaf.symbol) {
+            Some(SampleCategory::Idle)
+        } else {
+            None
+        }
+    }
+}
+
+
+/// Recognizes samples of threads which are waiting for the GPU to complete some
+/// work, inside of a driver ioctl
+pub struct GpuWaits {
+    /// Truth that the GPU driver preset is enabled
+    pub enabled: bool,
+}
+//
+impl Classifier for GpuWaits {
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>> {
+        if self.enabled && sample.frames().any(|frame| gpu::is_gpu_wait(frame.symbol)) {
+            Some(SampleCategory::Idle)
+        } else {
+            None
+        }
+    }
+}
+
+
+/// Recognizes stacks ending in the vDSO or vsyscall page
+pub struct VdsoRoots;
+//
+impl Classifier for VdsoRoots {
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>> {
+        // Stacks may legitimately end in the vDSO or vsyscall page, since
+        // their code is called directly and perf cannot unwind through it.
+        let last_frame = &sample.last_frame;
+        if last_frame.dso == "[vdso]" ||
+           last_frame.dso == "[vsyscall]" ||
+           sample.arch().is_vdso_func(last_frame.symbol)
+        {
+            Some(SampleCategory::VdsoRoot)
+        } else {
+            None
+        }
+    }
+}
+
+
+/// Detects stacks with frames whose address cannot belong to their DSO
+pub struct CorruptFrameAddresses {
+    /// Sizes of the DSOs on disk, if this check is enabled
+    pub mapping_sizes: Option<MappingSizes>,
+}
+//
+impl Classifier for CorruptFrameAddresses {
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>> {
+        // If the frames of a DSO are spread over a wider address range than the
+        // DSO itself, some of them are garbage, even if the stack happens to
+        // end in an expected root function.
+        self.mapping_sizes.as_ref()?
+                          .find_corrupt_dso(sample.frames())
+                          .map(SampleCategory::CorruptFrameAddress)
+    }
+}
+
+
+/// Detects stacks with caller frames which do not return after a call
+pub struct SuspectReturnAddresses {
+    /// Code of the DSOs on disk, if this check is enabled
+    pub call_sites: Option<CallSites>,
+}
+//
+impl Classifier for SuspectReturnAddresses {
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>> {
+        // A return address which does not follow a call cannot have been
+        // pushed by one, so the unwinder made up its frame, even if the stack
+        // happens to end in an expected root function.
+        self.call_sites.as_ref()?
+                       .find_suspect_frame(sample.sample)
+                       .map(|frame| SampleCategory::SuspectReturnAddress(frame.dso))
+    }
+}
+
+
+/// Recognizes stacks which end where we expect them to
+pub struct ExpectedRoots {
+    /// These are the functions we expect to see at the end of stack traces
+    pub funcs: HashSet<String>,
+
+    /// These are the DSOs that we expect to see at the end of stack traces
+    pub dsos: HashSet<&'static str>,
+
+    /// Truth that Wine's preloader is an expected root
+    pub wine_preloader: bool,
+}
+//
+impl Classifier for ExpectedRoots {
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>> {
+        // If the top function or DSO matches our expectations, we're good
+        let last_frame = &sample.last_frame;
+        let last_func = runtime::strip_rust_hash(last_frame.symbol);
+        if self.dsos.contains(last_frame.dso) ||
+           self.funcs.contains(last_func) ||
+           (self.wine_preloader && runtime::is_wine_preloader(last_frame.dso))
+        {
+            Some(SampleCategory::Normal)
+        } else {
+            None
+        }
+    }
+}
+
+
+/// Detects truncated DWARF stacks
+pub struct TruncatedStacks;
+//
+impl Classifier for TruncatedStacks {
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>> {
+        // Perf uses an IP which is entirely composed of hex 'f's to denote
+        // incomplete DWARF stacks
+        if sample.arch().is_truncation_marker(sample.last_frame.ip) {
+            Some(SampleCategory::TruncatedStack)
+        } else {
+            None
+        }
+    }
+}
+
+
+/// Detects Go stacks broken by a transition to C code
+pub struct CgoTransitions {
+    /// Truth that the Go runtime preset is enabled
+    pub enabled: bool,
+}
+//
+impl Classifier for CgoTransitions {
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>> {
+        // Unwinding tends to go wrong in Go programs which call into C
+        if self.enabled &&
+           sample.frames().any(|frame| runtime::is_cgo_transition(frame.symbol))
+        {
+            Some(SampleCategory::BrokenByCgo)
+        } else {
+            None
+        }
+    }
+}
+
+
+/// Detects stacks broken at a signal frame
+pub struct SignalFrames;
+//
+impl Classifier for SignalFrames {
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>> {
+        // Unwinding often goes wrong when crossing a signal frame. If this
+        // sample was taken in a signal handler, and we get here, then the stack
+        // of the interrupted code was not properly unwound.
+        sample.sample.split_at_signal().map(|(_handler, interrupted)| {
+            SampleCategory::BrokenBySignal(interrupted.count())
+        })
+    }
+}
+
+
+/// Recognizes stacks ending in code generated by a WebAssembly runtime
+pub struct WasmCode {
+    /// Truth that the WebAssembly preset is enabled
+    pub enabled: bool,
+}
+//
+impl Classifier for WasmCode {
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>> {
+        // This must run before JIT detection, as wasm runtimes tell perf about
+        // their generated code in the same way as other JIT compilers
+        let last_kind = WasmCodeKind::of_frame(&sample.last_frame);
+        if self.enabled && last_kind.is_wasm_generated() {
+            Some(SampleCategory::WasmCode)
+        } else {
+            None
+        }
+    }
+}
+
+
+/// Recognizes stacks ending in JIT-compiled code
+pub struct JitCode;
+//
+impl Classifier for JitCode {
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>> {
+        // Perhaps the caller was JIT-compiled? Perf can detect this quite well,
+        // from perf maps or from the DSOs that perf inject --jit generates.
+        jvm::jit_pid(sample.last_frame.dso).map(SampleCategory::JitCompiledBy)
+    }
+}
+
+
+/// Recognizes stacks ending in a JIT-compiled BPF program
+pub struct BpfPrograms;
+//
+impl Classifier for BpfPrograms {
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>> {
+        // Unwinding usually stops in BPF programs, or right after them in an
+        // unknown frame, so look at the outermost frame with a known DSO
+        let last_known = sample.frames().rev().find(|frame| frame.dso != "[unknown]")?;
+        BpfProgram::of_frame(&last_known).map(|program| {
+            SampleCategory::BpfProgram(program.tag)
+        })
+    }
+}
+
+
+/// Detects stacks going through anonymous memory, which perf cannot symbolize
+/// or unwind through unless the code's generator tells it what is there
+pub struct AnonymousCode;
+//
+impl Classifier for AnonymousCode {
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>> {
+        // This is most likely code from a JIT compiler without a perf map
+        sample.frames()
+              .find(|frame| frame.is_in_anonymous_memory())
+              .map(|frame| SampleCategory::AnonymousCode(frame.dso))
+    }
+}
+
+
+/// Detects stacks ending in a DSO that perf flagged as deleted
+pub struct DeletedDsos;
+//
+impl Classifier for DeletedDsos {
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>> {
+        // Perf inserts "deleted" markers next to the names of DSOs which were
+        // removed from the filesystem, which it then cannot symbolize. If we
+        // could not resolve the symbol ourselves, tell why the file is gone.
+        let last_dso = sample.last_frame.dso;
+        if !sample.last_frame.deleted {
+            return None;
+        }
+        Some(match DeletedKind::of_dso(last_dso) {
+            DeletedKind::UpgradedLibrary => SampleCategory::UpgradedLibrary(last_dso),
+            DeletedKind::AnonymousExecutable => {
+                SampleCategory::AnonymousExecutable(last_dso)
+            },
+        })
+    }
+}
+
+
+/// Detects stacks going through DSOs which changed since recording time
+pub struct StaleDsos {
+    /// DSOs which changed on disk since the profile was recorded, so that perf
+    /// resolved their symbols using the wrong binary
+    pub dsos: HashSet<String>,
+}
+//
+impl Classifier for StaleDsos {
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>> {
+        // If a DSO was modified since the profile was recorded, perf used the
+        // wrong binary to resolve symbols, and the stack trace is nonsense.
+        if self.dsos.is_empty() {
+            return None;
+        }
+        sample.frames()
+              .find(|frame| self.dsos.contains(frame.dso))
+              .map(|frame| SampleCategory::StaleDSO(frame.dso))
+    }
+}
+
+
+/// Detects stacks broken by a DSO which is known to be problematic
+pub struct BadDsos {
+    /// These "bad" DSOs are known to leave broken stack frames around, most
+    /// likely because we don't have DWARF debugging info for them
+    pub dsos: HashSet<&'static str>,
+}
+//
+impl Classifier for BadDsos {
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>> {
+        // Perhaps it comes from a library that is known to break stack traces?
+        // Let us try to find the last sensible DSO in the trace to check.
+        let last_valid_dso =
+            // Iterate over stack frames in reverse order
+            sample.frames().rev()
+                           // Find the DSO associated with each frame
+                           .map(|frame| frame.dso)
+                           // Look for the first valid DSO in the stack trace
+                           .find(|&dso| dso != "[unknown]");
+
+        // Did we find a single sensible DSO in that stack? If so, does it
+        // belong to our list of known-bad DSOs?
+        let valid_dso = last_valid_dso?;
+        let file_name = runtime::dso_file_name(valid_dso);
+        let bad_dso = self.dsos.get(valid_dso).or_else(|| {
+            self.dsos.get(file_name)
+        }).or_else(|| {
+            self.dsos.get(runtime::strip_so_version(file_name))
+        })?;
+
+        // If so, report that to the user as the cause of the bad sample
+        Some(SampleCategory::BrokenByBadDSO(bad_dso))
+    }
+}
+
+
+/// Recognizes stacks ending in Windows code running under Wine
+pub struct WindowsCode {
+    /// Truth that the Wine preset is enabled
+    pub enabled: bool,
+}
+//
+impl Classifier for WindowsCode {
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>> {
+        // Windows code often comes without the unwinding info that perf needs
+        let last_dso = sample.last_frame.dso;
+        if self.enabled && runtime::is_pe_dso(last_dso) {
+            Some(SampleCategory::WindowsCode(last_dso))
+        } else {
+            None
+        }
+    }
+}
+
+
+/// Detects stacks whose last frame is broken
+pub struct BrokenLastFrame;
+//
+impl Classifier for BrokenLastFrame {
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>> {
+        // If the last DSO is "[unkown]", the stack trace is clearly broken, but
+        // at this stage I am out of ideas as for how that could happen
+        if sample.last_frame.dso == "[unknown]" {
+            Some(SampleCategory::BrokenLastFrame)
+        } else {
+            None
+        }
+    }
+}
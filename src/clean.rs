@@ -0,0 +1,119 @@
+//! Sanitized copy of perf script's output, where samples with a broken stack
+//! trace are removed or trimmed, so that tools like flamegraph.pl do not render
+//! garbage roots for them.
+
+use analysis::{SampleCategory, Severity};
+use runtime;
+use sample::Sample;
+use std::fs::File;
+use std::io::{BufWriter, Error, Result, Write};
+use std::path::Path;
+
+
+/// Mechanism to write the cleaned up samples into a file. Write errors are
+/// deferred until the output is finished, so that they do not get in the way
+/// of the analysis itself.
+pub struct CleanOutput {
+    output: BufWriter<File>,
+
+    /// Truth that samples broken by a bad DSO should be cut at that DSO
+    trim_bad_dsos: bool,
+
+    /// Buffer in which trimmed samples are built
+    buffer: Vec<u8>,
+
+    /// First error which occurred while writing, if any
+    error: Option<Error>,
+}
+//
+impl CleanOutput {
+    /// Create the output file
+    pub fn create(path: &Path) -> Result<Self> {
+        Ok(Self {
+            output: BufWriter::new(File::create(path)?),
+            trim_bad_dsos: false,
+            buffer: Vec::new(),
+            error: None,
+        })
+    }
+
+    /// Keep samples which are broken by a bad DSO, up to that DSO
+    pub fn with_bad_dso_trimming(mut self) -> Self {
+        self.trim_bad_dsos = true;
+        self
+    }
+
+    /// Write a newly classified sample, unless its stack trace is broken
+    pub fn record(&mut self,
+                  sample: &Sample,
+                  category: &SampleCategory,
+                  severity: Severity) {
+        if self.error.is_none() {
+            if let Err(e) = self.try_record(sample, category, severity) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    /// Write a sample, reporting errors immediately
+    fn try_record(&mut self,
+                  sample: &Sample,
+                  category: &SampleCategory,
+                  severity: Severity) -> Result<()> {
+        let trimmed;
+        let sample = if severity == Severity::Info {
+            sample
+        } else {
+            match trimmed_len(sample, category, self.trim_bad_dsos) {
+                Some(num_frames) if num_frames > 0 => {
+                    trimmed = sample.truncated(num_frames, &mut self.buffer);
+                    &trimmed
+                },
+                _ => return Ok(()),
+            }
+        };
+        self.output.write_all(sample.raw_sample_data)?;
+        self.output.write_all(b"\n")
+    }
+
+    /// Complete the output
+    pub fn finish(mut self) -> Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.output.flush()
+    }
+}
+
+
+/// Number of frames which remain meaningful once the broken bottom of an
+/// anomalous sample's stack is cut off, or None if the whole sample should be
+/// left out. Samples broken by a bad DSO are only cut if asked to.
+pub fn trimmed_len(sample: &Sample,
+                   category: &SampleCategory,
+                   trim_bad_dsos: bool) -> Option<usize> {
+    match *category {
+        // Truncated DWARF stacks end with frames that could not be unwound
+        SampleCategory::TruncatedStack => {
+            let frames = sample.frames().collect::<Vec<_>>();
+            frames.iter().rposition(|frame| frame.symbol != "[unknown]")
+                         .map(|last_known| last_known + 1)
+        },
+
+        // Stacks broken at a signal frame are fine up to the trampoline
+        SampleCategory::BrokenBySignal(num_interrupted) => {
+            sample.frames().count().checked_sub(num_interrupted)
+        },
+
+        // Stacks broken by a bad DSO are fine up to its first frame, and a
+        // stack which was already cut there is kept as a whole
+        SampleCategory::BrokenByBadDSO(bad_dso) if trim_bad_dsos => {
+            let is_bad = |dso| dso == bad_dso || runtime::dso_file_name(dso) == bad_dso;
+            let first_bad = sample.frames().position(|frame| is_bad(frame.dso));
+            Some(first_bad.unwrap_or_else(|| sample.frames().count()))
+        },
+
+        // Other anomalous samples cannot be fixed by trimming
+        _ => None,
+    }
+}
@@ -0,0 +1,133 @@
+//! User configuration of the analysis, loaded from a JSON file
+
+use analysis::{SampleCategory, Severity};
+use serde::de::{Deserialize, Deserializer, Error as DeError};
+use serde_json;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+
+/// Analysis settings which are too complex to be passed on the command line
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    /// Rules which map samples to user-defined categories
+    pub categories: Vec<CategoryRule>,
+
+    /// Severities which override the default ones, by category key
+    #[serde(deserialize_with = "deserialize_severities")]
+    pub severities: Vec<(String, Severity)>,
+}
+//
+impl Config {
+    /// Load a configuration file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let config: Self = serde_json::from_str(&text)?;
+        if let Some(rule) = config.categories.iter().find(|rule| !rule.has_pattern()) {
+            return Err(Error::new(ErrorKind::InvalidData,
+                                  format!("category \"{}\" has no pattern", rule.name)));
+        }
+        Ok(config)
+    }
+
+    /// How worried the user should be about some category of samples
+    pub fn severity(&self, category: &SampleCategory) -> Severity {
+        let key = category.key();
+        match self.severities.iter().find(|(category, _)| category == key) {
+            Some(&(_, severity)) => severity,
+            None => category.default_severity(),
+        }
+    }
+}
+///
+///
+/// Rule which puts the samples that it matches in a user-defined category. All
+/// the patterns which are specified must match for the rule to apply.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct CategoryRule {
+    /// Name of the category, as displayed in the summary
+    pub name: String,
+
+    /// Pattern that some function of the stack trace must match
+    pub function: Option<Pattern>,
+
+    /// Pattern that some DSO of the stack trace must match
+    pub dso: Option<Pattern>,
+
+    /// Pattern that the name of the sampled thread must match
+    pub comm: Option<Pattern>,
+
+    /// Pattern that the name of the sampled event must match
+    pub event: Option<Pattern>,
+
+    /// Truth that the samples in this category should be printed out
+    #[serde(default)]
+    pub dump: bool,
+}
+//
+impl CategoryRule {
+    /// Truth that this rule specifies at least one pattern, as a rule without
+    /// any pattern would swallow every sample
+    fn has_pattern(&self) -> bool {
+        self.function.is_some() || self.dso.is_some() ||
+        self.comm.is_some() || self.event.is_some()
+    }
+}
+///
+///
+/// Text pattern, where '*' stands for any sequence of characters
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(from = "String")]
+pub struct Pattern {
+    /// Pieces of text which lie between the wildcards, in order
+    pieces: Vec<String>,
+}
+//
+impl Pattern {
+    /// Build a pattern from its textual form
+    pub fn new(pattern: &str) -> Self {
+        Self { pieces: pattern.split('*').map(str::to_owned).collect() }
+    }
+
+    /// Truth that some text matches this pattern
+    pub fn matches(&self, text: &str) -> bool {
+        // The first and last pieces are anchored, the others may float around
+        let (first, pieces) = self.pieces.split_first().unwrap();
+        let mut rest = match text.strip_prefix(first.as_str()) {
+            Some(rest) => rest,
+            None => return false,
+        };
+        let (last, middle) = match pieces.split_last() {
+            Some(split) => split,
+            None => return rest.is_empty(),
+        };
+        for piece in middle {
+            match rest.find(piece.as_str()) {
+                Some(pos) => rest = &rest[pos+piece.len()..],
+                None => return false,
+            }
+        }
+        rest.ends_with(last.as_str())
+    }
+}
+//
+impl From<String> for Pattern {
+    fn from(pattern: String) -> Self {
+        Self::new(&pattern)
+    }
+}
+
+
+/// Parse the severity overrides, which map category keys to severity names
+fn deserialize_severities<'de, D>(deserializer: D)
+    -> ::std::result::Result<Vec<(String, Severity)>, D::Error>
+    where D: Deserializer<'de>
+{
+    let severities = BTreeMap::<String, String>::deserialize(deserializer)?;
+    severities.into_iter()
+              .map(|(key, name)| Ok((key, name.parse().map_err(D::Error::custom)?)))
+              .collect()
+}
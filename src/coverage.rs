@@ -0,0 +1,173 @@
+//! Wall-clock coverage of each thread, i.e. how much of the time between its
+//! first and last sample it was sampled on a CPU. Threads which were around
+//! for most of the recording but were barely sampled were either mostly
+//! sleeping, or running while the event was not being counted, which happens
+//! when events are multiplexed or when the thread was starved by the scheduler.
+//!
+//! Sample periods are converted to time using the rate at which each event's
+//! periods accumulate on the busiest CPU, which is assumed to be fully busy.
+//! Software clock events (cpu-clock, task-clock) directly count nanoseconds.
+
+use sample::Sample;
+use std::collections::HashMap;
+
+
+/// Number of threads which are displayed in the report
+const NUM_REPORTED_THREADS: usize = 10;
+
+/// Fraction of the recording that a thread must span to be reported
+const MIN_SPAN_FRACTION: f64 = 0.1;
+
+/// Coverage below which a long-lived thread is considered barely sampled
+const LOW_COVERAGE: f64 = 0.05;
+
+
+/// Time range and sampled periods of each thread and CPU
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct CoverageStatistics {
+    /// Activity of each thread, by thread ID
+    pub threads: HashMap<u32, Activity>,
+
+    /// Activity of each CPU, by CPU number
+    pub cpus: HashMap<u32, Activity>,
+}
+//
+impl CoverageStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a new sample. Samples which do not tell when they were
+    /// taken are ignored.
+    pub fn record(&mut self, sample: &Sample) {
+        let time = match sample.timestamp() {
+            Some(time) => (time * 1e9).round() as u64,
+            None => return,
+        };
+        let event = sample.event().unwrap_or("");
+        let period = sample.period().unwrap_or(1);
+        if let Some(tid) = sample.tid() {
+            let thread = self.threads.entry(tid).or_default();
+            if thread.comm.is_empty() {
+                thread.comm = sample.comm().unwrap_or("").to_owned();
+            }
+            thread.record(time, event, period);
+        }
+        if let Some(cpu) = sample.cpu() {
+            self.cpus.entry(cpu).or_default().record(time, event, period);
+        }
+    }
+
+    /// Print the wall-clock coverage of the longest-lived threads, lowest
+    /// coverage first
+    pub fn print_report(&self) {
+        let (start, end) = self.threads.values().fold((u64::MAX, 0), |(start, end), t| {
+            (start.min(t.first_time), end.max(t.last_time))
+        });
+        if self.threads.is_empty() || end <= start {
+            println!("No thread coverage (perf script must print the tid and time \
+                      fields)");
+            return;
+        }
+        let rates = self.period_rates();
+        let min_span = (MIN_SPAN_FRACTION * (end - start) as f64) as u64;
+        let mut threads = self.threads.iter().filter(|(_, thread)| {
+            thread.span() >= min_span.max(1)
+        }).map(|(&tid, thread)| {
+            (tid, thread, thread.coverage(&rates))
+        }).collect::<Vec<_>>();
+        threads.sort_by(|a, b| a.2.total_cmp(&b.2).then(a.0.cmp(&b.0)));
+        println!("Wall-clock coverage of threads spanning {:.0}% of the recording or \
+                  more, lowest first: {} threads",
+                 100.0 * MIN_SPAN_FRACTION,
+                 threads.len());
+        for (tid, thread, coverage) in threads.into_iter().take(NUM_REPORTED_THREADS) {
+            let warning = if coverage < LOW_COVERAGE { " [warning]" } else { "" };
+            println!("- {} ({}): sampled {:.1}% of its {:.3}s lifetime over {} \
+                      samples{}",
+                     thread.comm,
+                     tid,
+                     100.0 * coverage,
+                     thread.span() as f64 * 1e-9,
+                     thread.num_samples,
+                     warning);
+        }
+    }
+
+    /// Rate at which the periods of each event accumulate on a fully busy
+    /// CPU, in periods per nanosecond
+    fn period_rates(&self) -> HashMap<&str, f64> {
+        let mut rates = HashMap::new();
+        for cpu in self.cpus.values() {
+            for (event, &period) in &cpu.periods {
+                let rate = period as f64 / cpu.span().max(1) as f64;
+                let max_rate = rates.entry(event.as_str()).or_insert(rate);
+                *max_rate = max_rate.max(rate);
+            }
+        }
+        for (event, rate) in rates.iter_mut() {
+            if is_clock_event(event) {
+                *rate = 1.0;
+            }
+        }
+        rates
+    }
+}
+///
+///
+/// Time range and sampled periods of a thread or CPU
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Activity {
+    /// Name of the thread, empty for CPUs
+    pub comm: String,
+
+    /// Timestamps of the first and last sample, in nanoseconds
+    pub first_time: u64,
+    pub last_time: u64,
+
+    /// Number of samples
+    pub num_samples: usize,
+
+    /// Sum of the sample periods of each event
+    pub periods: HashMap<String, u64>,
+}
+//
+impl Activity {
+    /// Account for a new sample of some event
+    fn record(&mut self, time: u64, event: &str, period: u64) {
+        if self.num_samples == 0 {
+            self.first_time = time;
+        }
+        self.first_time = self.first_time.min(time);
+        self.last_time = self.last_time.max(time);
+        self.num_samples += 1;
+        match self.periods.get_mut(event) {
+            Some(total) => *total = total.saturating_add(period),
+            None => { self.periods.insert(event.to_owned(), period); },
+        }
+    }
+
+    /// Time between the first and last sample, in nanoseconds
+    pub fn span(&self) -> u64 {
+        self.last_time - self.first_time
+    }
+
+    /// Fraction of the span during which this was sampled on a CPU, given the
+    /// rate at which each event's periods accumulate. When several events were
+    /// sampled, the one which saw the most of it is trusted.
+    pub fn coverage(&self, rates: &HashMap<&str, f64>) -> f64 {
+        let on_cpu = self.periods.iter().filter_map(|(event, &period)| {
+            let rate = rates.get(event.as_str()).filter(|&&rate| rate > 0.0)?;
+            Some(period as f64 / rate)
+        }).fold(0.0, f64::max);
+        (on_cpu / self.span().max(1) as f64).min(1.0)
+    }
+}
+
+
+/// Truth that an event's periods are nanoseconds of CPU time
+fn is_clock_event(event: &str) -> bool {
+    let name = event.split(':').next().unwrap_or(event);
+    name == "cpu-clock" || name == "task-clock"
+}
@@ -0,0 +1,158 @@
+//! Hot data regions of profiles recorded with memory sampling events (e.g.
+//! `perf mem record`, or `perf record -e cpu/mem-loads/P -d`), whose samples
+//! tell which data address was accessed when perf script is run with -F +addr
+//!
+//! Addresses are clustered by page and by cacheline. Cachelines whose different
+//! parts are accessed by different CPUs are likely victims of false sharing,
+//! i.e. unrelated data which happens to share a cacheline and keeps bouncing
+//! between CPU caches as a result.
+
+use sample::Sample;
+use std::collections::HashMap;
+
+
+/// Size of a cacheline, in bytes
+const CACHELINE_SIZE: u64 = 64;
+
+/// Size of a page, in bytes
+const PAGE_SIZE: u64 = 4096;
+
+/// Granularity at which the parts of a cacheline accessed by each CPU are
+/// tracked, in bytes
+const WORD_SIZE: u64 = 8;
+
+/// Number of pages and cachelines which are displayed in the report
+const NUM_REPORTED_REGIONS: usize = 10;
+
+
+/// Sampled data accesses, by page and by cacheline
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct DataAddressStatistics {
+    /// Number of accesses to each page, by page address
+    pub pages: HashMap<u64, usize>,
+
+    /// Accesses to each cacheline, by cacheline address
+    pub cachelines: HashMap<u64, CachelineAccesses>,
+}
+//
+impl DataAddressStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a new sample. Only samples of memory sampling events which
+    /// tell their data address are taken into account.
+    pub fn record(&mut self, sample: &Sample) {
+        let is_memory_event = sample.memory_level().is_some() ||
+                              sample.event_base_name().is_some_and(|event| {
+                                  event.contains("mem")
+                              });
+        let address = match sample.data_address() {
+            Some(address) if is_memory_event && address != 0 => address,
+            _ => return,
+        };
+        *self.pages.entry(address - address % PAGE_SIZE).or_default() += 1;
+        let cacheline = self.cachelines.entry(address - address % CACHELINE_SIZE)
+                                       .or_default();
+        cacheline.num_samples += 1;
+        if let Some(cpu) = sample.cpu() {
+            let word = (address % CACHELINE_SIZE) / WORD_SIZE;
+            *cacheline.cpu_words.entry(cpu).or_default() |= 1 << word;
+        }
+        if let Some(leaf) = sample.frames().next() {
+            let symbols = match cacheline.functions.get_mut(leaf.dso) {
+                Some(symbols) => symbols,
+                None => cacheline.functions.entry(leaf.dso.to_owned()).or_default(),
+            };
+            match symbols.get_mut(leaf.symbol) {
+                Some(count) => *count += 1,
+                None => { symbols.insert(leaf.symbol.to_owned(), 1); },
+            }
+        }
+    }
+
+    /// Print the most accessed pages and cachelines, and the cachelines which
+    /// are likely to be falsely shared
+    pub fn print_report(&self) {
+        let num_samples = self.pages.values().sum::<usize>();
+        if num_samples == 0 {
+            println!("No data address (record memory sampling events with e.g. perf \
+                      mem record, and run perf script with -F +addr)");
+            return;
+        }
+        let percent = |count: usize| 100.0 * count as f64 / num_samples as f64;
+        println!("Data addresses of {} sampled memory accesses", num_samples);
+
+        let mut pages = self.pages.iter().collect::<Vec<_>>();
+        pages.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        println!("Hottest {}-byte pages:", PAGE_SIZE);
+        for (page, &count) in pages.into_iter().take(NUM_REPORTED_REGIONS) {
+            println!("- {:#x}: {} accesses ({:.1}%)", page, count, percent(count));
+        }
+
+        let mut cachelines = self.cachelines.iter().collect::<Vec<_>>();
+        cachelines.sort_by(|a, b| {
+            b.1.num_samples.cmp(&a.1.num_samples).then(a.0.cmp(b.0))
+        });
+        println!("Hottest {}-byte cachelines:", CACHELINE_SIZE);
+        for &(address, cacheline) in cachelines.iter().take(NUM_REPORTED_REGIONS) {
+            println!("- {:#x}: {} accesses ({:.1}%) from {} CPUs, mostly by {}",
+                     address,
+                     cacheline.num_samples,
+                     percent(cacheline.num_samples),
+                     cacheline.cpu_words.len(),
+                     cacheline.hottest_function().unwrap_or_else(|| "?".to_owned()));
+        }
+
+        let false_sharing = cachelines.into_iter().filter(|&(_, cacheline)| {
+            cacheline.is_falsely_shared()
+        }).take(NUM_REPORTED_REGIONS).collect::<Vec<_>>();
+        if false_sharing.is_empty() {
+            return;
+        }
+        println!("False sharing candidates (CPUs accessing different parts of the \
+                  same cacheline):");
+        for (address, cacheline) in false_sharing {
+            println!("- {:#x}: {} accesses from {} CPUs, mostly by {} [warning]",
+                     address,
+                     cacheline.num_samples,
+                     cacheline.cpu_words.len(),
+                     cacheline.hottest_function().unwrap_or_else(|| "?".to_owned()));
+        }
+    }
+}
+///
+///
+/// Sampled accesses to one cacheline
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct CachelineAccesses {
+    /// Number of accesses
+    pub num_samples: usize,
+
+    /// Bitmask of the 8-byte words of the cacheline which each CPU accessed
+    pub cpu_words: HashMap<u32, u8>,
+
+    /// Number of accesses by each leaf function, by DSO and symbol
+    pub functions: HashMap<String, HashMap<String, usize>>,
+}
+//
+impl CachelineAccesses {
+    /// Truth that several CPUs accessed this cacheline, and that some of them
+    /// accessed different parts of it
+    pub fn is_falsely_shared(&self) -> bool {
+        let mut words = self.cpu_words.values();
+        match words.next() {
+            Some(first) => words.any(|other| other != first),
+            None => false,
+        }
+    }
+
+    /// Function which accessed this cacheline the most, as "symbol (dso)"
+    fn hottest_function(&self) -> Option<String> {
+        self.functions.iter().flat_map(|(dso, symbols)| {
+            symbols.iter().map(move |(symbol, &count)| (count, symbol, dso))
+        }).max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(a.1)).then(b.2.cmp(a.2)))
+          .map(|(_, symbol, dso)| format!("{} ({})", symbol, dso))
+    }
+}
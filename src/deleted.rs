@@ -0,0 +1,153 @@
+//! Handling of the DSOs which perf flags with a "(deleted)" marker. This marker
+//! means that the file which was mapped in memory was removed from the
+//! filesystem, usually because a library was upgraded while the profiled
+//! program was running, or because the code lives in a memfd or shared memory
+//! segment which never had a real file name in the first place.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use symbols::SymbolTable;
+
+
+/// Kinds of deleted DSOs
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DeletedKind {
+    /// A regular file was replaced on disk, e.g. by a package upgrade
+    UpgradedLibrary,
+
+    /// Code was loaded from an anonymous file, such as a memfd or a shared
+    /// memory segment, as JIT compilers and sandboxes sometimes do
+    AnonymousExecutable,
+}
+//
+impl DeletedKind {
+    /// Figure out what a deleted DSO used to be from its name
+    pub fn of_dso(dso: &str) -> Self {
+        const ANONYMOUS_PREFIXES: &[&str] = &["/memfd:", "/dev/zero", "/SYSV",
+                                              "/dev/shm/", "/[aio]"];
+        if ANONYMOUS_PREFIXES.iter().any(|prefix| dso.starts_with(prefix)) {
+            DeletedKind::AnonymousExecutable
+        } else {
+            DeletedKind::UpgradedLibrary
+        }
+    }
+}
+///
+///
+/// Symbols of the deleted libraries which are still mapped by running
+/// processes. The kernel keeps the old inode alive as long as it is mapped, and
+/// exposes it through /proc/<pid>/map_files, so when the profiled processes are
+/// still around, we can resolve the frames that perf could not symbolize.
+pub struct LiveMappings {
+    /// Process which each known thread belongs to
+    thread_pids: HashMap<u32, u32>,
+
+    /// Deleted executable mappings of each process
+    mappings: HashMap<u32, Vec<DeletedMapping>>,
+}
+//
+impl LiveMappings {
+    /// Scan running processes for deleted executable mappings. Processes which
+    /// we are not allowed to inspect are silently ignored.
+    pub fn scan() -> Self {
+        let mut result = Self {
+            thread_pids: HashMap::new(),
+            mappings: HashMap::new(),
+        };
+        let pids = fs::read_dir("/proc").into_iter().flatten().filter_map(|entry| {
+            entry.ok()?.file_name().to_str()?.parse::<u32>().ok()
+        });
+        for pid in pids {
+            let mappings = DeletedMapping::scan(pid);
+            if mappings.is_empty() { continue; }
+            let tasks = fs::read_dir(format!("/proc/{}/task", pid));
+            let tids = tasks.into_iter().flatten().filter_map(|entry| {
+                entry.ok()?.file_name().to_str()?.parse::<u32>().ok()
+            });
+            for tid in tids {
+                result.thread_pids.insert(tid, pid);
+            }
+            result.thread_pids.insert(pid, pid);
+            result.mappings.insert(pid, mappings);
+        }
+        result
+    }
+
+    /// Truth that no deleted mapping could be inspected
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+
+    /// Name of the function which a process or thread was executing at some
+    /// instruction pointer of a deleted mapping, if it can be found
+    pub fn lookup(&self, pid: u32, ip: u64) -> Option<&str> {
+        let pid = self.thread_pids.get(&pid)?;
+        let mapping = self.mappings.get(pid)?.iter().find(|mapping| {
+            mapping.start <= ip && ip < mapping.end
+        })?;
+        mapping.lookup(ip)
+    }
+}
+///
+///
+/// Executable mapping of a deleted library in a running process
+struct DeletedMapping {
+    /// Start of the mapping in the process' address space
+    start: u64,
+
+    /// End of the mapping in the process' address space
+    end: u64,
+
+    /// Offset of the mapping in the library file
+    offset: u64,
+
+    /// Symbols of the library
+    symbols: SymbolTable,
+}
+//
+impl DeletedMapping {
+    /// Find and load the deleted executable mappings of a process
+    fn scan(pid: u32) -> Vec<Self> {
+        let maps = match fs::read_to_string(format!("/proc/{}/maps", pid)) {
+            Ok(maps) => maps,
+            Err(_) => return Vec::new(),
+        };
+        maps.lines().filter_map(|line| {
+            // Lines look like "<start>-<end> <perms> <offset> <dev> <inode> <path>"
+            let mut columns = line.split_whitespace();
+            let range = columns.next()?;
+            let perms = columns.next()?;
+            let offset = u64::from_str_radix(columns.next()?, 16).ok()?;
+            if !perms.contains('x') || !line.ends_with(" (deleted)") {
+                return None;
+            }
+            let path = line.splitn(6, char::is_whitespace).last()?.trim_start();
+            if DeletedKind::of_dso(path) != DeletedKind::UpgradedLibrary {
+                return None;
+            }
+
+            // Read the symbols of the old library through its still-open inode,
+            // falling back to the dynamic symbol table if it was stripped
+            let (start, end) = range.split_at(range.find('-')?);
+            let file = PathBuf::from(format!("/proc/{}/map_files/{}", pid, range));
+            let mut symbols = SymbolTable::from_elf(&file, &[]).ok()?;
+            if symbols.is_empty() {
+                symbols = SymbolTable::from_elf(&file, &["-D"]).ok()?;
+            }
+            Some(Self {
+                start: u64::from_str_radix(start, 16).ok()?,
+                end: u64::from_str_radix(&end[1..], 16).ok()?,
+                offset,
+                symbols,
+            })
+        }).collect()
+    }
+
+    /// Look up the function containing an instruction pointer. This assumes
+    /// that the executable segment is loaded at the same virtual address as its
+    /// file offset, which is what common linkers do for shared libraries.
+    fn lookup(&self, ip: u64) -> Option<&str> {
+        self.symbols.lookup(ip - self.start + self.offset)
+    }
+}
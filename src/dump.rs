@@ -0,0 +1,214 @@
+//! Dump of anomalous samples. Anomalies often come as thousands of copies of
+//! the same stack trace, so identical traces are only printed once per category
+//! along with a repeat count and the stack's identifier.
+
+use sample::{Sample, StackId};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::mem;
+use truncate::StackTruncation;
+
+
+/// Deduplicated anomalous samples, grouped by category
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct AnomalyDump {
+    /// Dumped samples of each category, in order of first appearance
+    categories: Vec<DumpedCategory>,
+
+    /// Maximal number of samples to be kept per category, if any. This comes
+    /// from the command line, so it is not saved in checkpoints.
+    #[serde(skip)]
+    max_per_category: Option<usize>,
+
+    /// Cap on the number of frames of printed stacks, which is not saved in
+    /// checkpoints either
+    #[serde(skip)]
+    truncation: StackTruncation,
+
+    /// Random number generator used for reservoir sampling
+    rng: XorShift,
+}
+//
+impl AnomalyDump {
+    /// Start with an empty dump, which keeps every sample
+    pub fn new() -> Self {
+        Self {
+            categories: Vec::new(),
+            max_per_category: None,
+            truncation: StackTruncation::none(),
+            rng: XorShift::new(),
+        }
+    }
+
+    /// Only keep a bounded number of samples per category. Once that number is
+    /// reached, samples are replaced using reservoir sampling, so that the ones
+    /// which are kept remain a uniform random selection.
+    pub fn with_max_per_category(mut self, max_samples: usize) -> Self {
+        self.max_per_category = Some(max_samples);
+        self
+    }
+
+    /// Replace the middle frames of very deep stacks with a marker line
+    pub fn with_stack_truncation(mut self, truncation: StackTruncation) -> Self {
+        self.truncation = truncation;
+        self
+    }
+
+    /// Carry on from the samples of a dump which was saved in a checkpoint
+    pub fn with_checkpoint(mut self, saved: AnomalyDump) -> Self {
+        self.categories = saved.categories;
+        self.rng = saved.rng;
+        self
+    }
+
+    /// Record a sample under some category heading
+    pub fn record(&mut self, heading: &str, sample: &Sample) {
+        let position = self.categories.iter().position(|c| c.heading == heading);
+        let category_idx = match position {
+            Some(idx) => idx,
+            None => {
+                self.categories.push(DumpedCategory {
+                    heading: heading.to_owned(),
+                    stacks: HashMap::new(),
+                    reservoir: Vec::new(),
+                    num_samples: 0,
+                });
+                self.categories.len() - 1
+            }
+        };
+        let category = &mut self.categories[category_idx];
+        category.num_samples += 1;
+
+        // Without a limit, every sample is kept
+        let max_samples = match self.max_per_category {
+            Some(max_samples) => max_samples,
+            None => {
+                category.add(sample, self.truncation);
+                return;
+            }
+        };
+
+        // Otherwise, use reservoir sampling (Vitter's algorithm R)
+        if category.reservoir.len() < max_samples {
+            category.add(sample, self.truncation);
+            category.reservoir.push(sample.stack_trace.to_owned());
+        } else {
+            let slot = self.rng.below(category.num_samples as u64) as usize;
+            if slot < max_samples {
+                let new_stack = sample.stack_trace.to_owned();
+                let old_stack = mem::replace(&mut category.reservoir[slot], new_stack);
+                category.remove(&old_stack);
+                category.add(sample, self.truncation);
+            }
+        }
+    }
+
+    /// Print each unique stack trace once, most frequent first
+    pub fn print(&self) {
+        for category in &self.categories {
+            let mut stacks = category.stacks.values().collect::<Vec<_>>();
+            stacks.sort_by_key(|stack| Reverse(stack.count));
+            for stack in stacks {
+                match stack.stack_id {
+                    Some(stack_id) => {
+                        println!("{} (x{}, stack {}):",
+                                 category.heading, stack.count, stack_id);
+                    },
+                    None => println!("{} (x{}):", category.heading, stack.count),
+                }
+                println!("{}", stack.raw_text);
+            }
+            let num_kept = category.stacks.values()
+                                          .map(|stack| stack.count)
+                                          .sum::<usize>();
+            if num_kept < category.num_samples {
+                println!("({} more samples of this category were suppressed, the \
+                          ones above were picked at random)\n",
+                         category.num_samples - num_kept);
+            }
+        }
+    }
+}
+//
+impl Default for AnomalyDump {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+///
+///
+/// Samples which were dumped for one category
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+struct DumpedCategory {
+    /// Heading under which the samples are printed
+    heading: String,
+
+    /// Unique stack traces which were kept
+    stacks: HashMap<Vec<u8>, DumpedStack>,
+
+    /// Stack traces of the samples which were kept by reservoir sampling
+    reservoir: Vec<Vec<u8>>,
+
+    /// Number of samples which fell in this category
+    num_samples: usize,
+}
+//
+impl DumpedCategory {
+    /// Keep a sample, whose stack is printed with some truncation
+    fn add(&mut self, sample: &Sample, truncation: StackTruncation) {
+        if let Some(stack) = self.stacks.get_mut(sample.stack_trace) {
+            stack.count += 1;
+            return;
+        }
+        self.stacks.insert(sample.stack_trace.to_owned(), DumpedStack {
+            count: 1,
+            stack_id: sample.stack_id(),
+            raw_text: truncation.raw_text(sample).into_owned(),
+        });
+    }
+
+    /// Forget about a sample with some stack trace
+    fn remove(&mut self, stack_trace: &[u8]) {
+        let stack = self.stacks.get_mut(stack_trace).unwrap();
+        stack.count -= 1;
+        if stack.count == 0 {
+            self.stacks.remove(stack_trace);
+        }
+    }
+}
+///
+///
+/// Unique stack trace which was dumped, along with an example sample
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+struct DumpedStack {
+    /// Number of kept samples with this stack trace
+    count: usize,
+
+    /// Identifier of the stack trace, if the samples have one
+    stack_id: Option<StackId>,
+
+    /// Data of the first sample with this stack trace, truncated if needed
+    raw_text: String,
+}
+///
+///
+/// Small pseudo-random number generator (xorshift64*), which is good enough
+/// for picking samples and makes the dump reproducible across runs
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+struct XorShift(u64);
+//
+impl XorShift {
+    /// Start from a fixed seed
+    fn new() -> Self {
+        XorShift(0x9e37_79b9_7f4a_7c15)
+    }
+
+    /// Generate a number between 0 (included) and some bound (excluded)
+    fn below(&mut self, bound: u64) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) % bound
+    }
+}
+
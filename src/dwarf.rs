@@ -0,0 +1,190 @@
+//! Advice on the stack dump size of perf record --call-graph dwarf, for
+//! recordings whose stacks were truncated because the dump was too small.
+//!
+//! perf script does not tell how many bytes each frame takes on the stack, so
+//! this is estimated: if a truncated stack unwound N user frames out of a dump
+//! of D bytes, its frames take about D/N bytes each. The number of frames that
+//! are missing is estimated from complete stacks, which tell how far the last
+//! function of the truncated stack is from the root.
+
+use analysis::SampleCategory;
+use arch::Arch;
+use sample::{Sample, StackFrame};
+use std::collections::HashMap;
+
+
+/// Stack dump size which perf record uses when none is specified
+const DEFAULT_DUMP_SIZE: u64 = 8192;
+
+/// Largest stack dump size that perf record accepts
+const MAX_DUMP_SIZE: u64 = 65528;
+
+/// Fraction of truncated samples that the recommended dump size should cover
+const TARGET_COVERAGE: f64 = 0.95;
+
+
+/// Stack depth statistics of complete and truncated stacks
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct DwarfSizeStatistics {
+    /// Largest number of frames seen between each function and the root of a
+    /// complete stack, by DSO and symbol
+    pub root_distances: HashMap<String, HashMap<String, usize>>,
+
+    /// Number of truncated stacks of each shape
+    pub truncated: HashMap<TruncatedChain, usize>,
+
+    /// Truncated stacks whose user frames are all unsymbolized, which cannot
+    /// be matched with complete stacks
+    pub num_unknown: usize,
+}
+//
+impl DwarfSizeStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a newly classified sample. Only user frames are counted,
+    /// since the kernel stack is not part of the dump.
+    pub fn record(&mut self, sample: &Sample, category: &SampleCategory, arch: Arch) {
+        let frames = sample.frames().filter(|frame| {
+            !arch.is_kernel_frame(frame) && !arch.is_truncation_marker(frame.ip)
+        }).collect::<Vec<_>>();
+        match *category {
+            SampleCategory::Normal => {
+                let num_frames = frames.len();
+                for (idx, frame) in frames.iter().enumerate() {
+                    if frame.is_unknown_symbol() {
+                        continue;
+                    }
+                    let distance = num_frames - 1 - idx;
+                    let symbols = match self.root_distances.get_mut(frame.dso) {
+                        Some(symbols) => symbols,
+                        None => self.root_distances.entry(frame.dso.to_owned())
+                                                   .or_default(),
+                    };
+                    match symbols.get_mut(frame.symbol) {
+                        Some(max_distance) => {
+                            *max_distance = (*max_distance).max(distance)
+                        },
+                        None => {
+                            symbols.insert(frame.symbol.to_owned(), distance);
+                        },
+                    }
+                }
+            },
+            SampleCategory::TruncatedStack => {
+                match frames.iter().rposition(|frame| !frame.is_unknown_symbol()) {
+                    Some(idx) => {
+                        let chain = TruncatedChain::new(&frames[idx],
+                                                        idx + 1,
+                                                        frames.len());
+                        *self.truncated.entry(chain).or_default() += 1;
+                    },
+                    None => self.num_unknown += 1,
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Print how much of the truncated samples larger stack dumps would have
+    /// covered, and recommend one, given the dump size which was used if known
+    pub fn print_report(&self, dump_size: Option<u64>) {
+        let num_truncated = self.truncated.values().sum::<usize>() + self.num_unknown;
+        if num_truncated == 0 {
+            println!("No truncated stack, the DWARF stack dump size is large enough");
+            return;
+        }
+        let (dump_size, assumed) = match dump_size {
+            Some(size) => (size, ""),
+            None => (DEFAULT_DUMP_SIZE, ", perf's default"),
+        };
+
+        // Estimate how many bytes each truncated stack needed
+        let mut needed = self.truncated.iter().filter_map(|(chain, &count)| {
+            Some((chain.needed_bytes(dump_size, self.root_distance(chain)?), count))
+        }).collect::<Vec<_>>();
+        needed.sort_unstable();
+        let num_estimated = needed.iter().map(|&(_, count)| count).sum::<usize>();
+        println!("DWARF stack dump size for {} truncated samples ({}-byte dumps{}):",
+                 num_truncated,
+                 dump_size,
+                 assumed);
+        if num_estimated == 0 {
+            println!("- No complete stack went through the truncated stacks' functions");
+            return;
+        }
+        println!("- Estimated from complete stacks for {} samples", num_estimated);
+
+        // Try larger dump sizes, up to the largest one that perf accepts
+        let coverage = |size: u64| {
+            let covered = needed.iter().take_while(|&&(bytes, _)| bytes <= size)
+                                .map(|&(_, count)| count)
+                                .sum::<usize>();
+            covered as f64 / num_estimated as f64
+        };
+        let mut candidates = Vec::new();
+        let mut size = dump_size;
+        while size < MAX_DUMP_SIZE {
+            size = (2 * size).min(MAX_DUMP_SIZE);
+            candidates.push(size);
+        }
+        for &size in &candidates {
+            println!("- {} bytes would have covered {:.1}% of truncated samples",
+                     size,
+                     100.0 * coverage(size));
+        }
+        match candidates.iter().find(|&&size| coverage(size) >= TARGET_COVERAGE) {
+            Some(size) => {
+                println!("Recommendation: perf record --call-graph dwarf,{}", size)
+            },
+            None => {
+                println!("Even the largest stack dump is not enough for {:.0}% of \
+                          truncated samples, consider frame pointers or LBR \
+                          call graphs instead",
+                         100.0 * TARGET_COVERAGE)
+            },
+        }
+    }
+
+    /// Number of frames between a truncated stack's last function and the root,
+    /// if a complete stack went through that function
+    fn root_distance(&self, chain: &TruncatedChain) -> Option<usize> {
+        self.root_distances.get(&chain.dso)?.get(&chain.symbol).cloned()
+    }
+}
+///
+///
+/// Shape of a truncated stack, as far as dump size estimation is concerned
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct TruncatedChain {
+    /// DSO and symbol of the last symbolized user frame
+    pub dso: String,
+    pub symbol: String,
+
+    /// Number of user frames up to that frame, included
+    pub depth: usize,
+
+    /// Number of user frames which were unwound from the dump
+    pub num_frames: usize,
+}
+//
+impl TruncatedChain {
+    /// Describe a truncated stack from its last symbolized user frame
+    fn new(last_known: &StackFrame, depth: usize, num_frames: usize) -> Self {
+        Self {
+            dso: last_known.dso.to_owned(),
+            symbol: last_known.symbol.to_owned(),
+            depth,
+            num_frames,
+        }
+    }
+
+    /// Estimated stack dump size which would have reached the root, assuming
+    /// that all frames are as large as the ones which were unwound
+    fn needed_bytes(&self, dump_size: u64, root_distance: usize) -> u64 {
+        let total_frames = (self.depth + root_distance) as u64;
+        dump_size * total_frames / self.num_frames.max(1) as u64
+    }
+}
@@ -0,0 +1,212 @@
+//! Just enough of an ELF parser to locate sections and read symbol tables,
+//! which is all that build-id and return address checks need
+
+use std::io::{Error, ErrorKind, Result};
+use std::str;
+
+
+/// Section type of symbol tables
+const SHT_SYMTAB: u32 = 2;
+
+/// Section type of dynamic symbol tables
+const SHT_DYNSYM: u32 = 11;
+
+/// Section type of sections which take no space in the file, like .bss
+const SHT_NOBITS: u32 = 8;
+
+/// Section flag of sections which are loaded in memory
+const SHF_ALLOC: u64 = 0x2;
+
+/// Symbol types of functions, including GNU indirect functions
+const STT_FUNC: u8 = 2;
+const STT_GNU_IFUNC: u8 = 10;
+
+
+/// ELF file, borrowed from its contents
+pub struct Elf<'a> {
+    data: &'a [u8],
+    is_64bit: bool,
+    little_endian: bool,
+}
+//
+impl<'a> Elf<'a> {
+    /// Check the ELF header of some file contents
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        if data.len() < 64 || &data[..4] != b"\x7fELF" {
+            return Err(Error::new(ErrorKind::InvalidData, "not an ELF file"));
+        }
+        Ok(Self {
+            data,
+            is_64bit: data[4] == 2,
+            little_endian: data[5] == 1,
+        })
+    }
+
+    /// Architecture of the code (e_machine), e.g. 62 for x86_64
+    pub fn machine(&self) -> Result<u16> {
+        self.u16_at(self.data, 0x12)
+    }
+
+    /// Truth that multi-byte values, including instructions, are little-endian
+    pub fn is_little_endian(&self) -> bool {
+        self.little_endian
+    }
+
+    /// Number of section headers
+    pub fn section_count(&self) -> usize {
+        let offset = if self.is_64bit { 0x3c } else { 0x30 };
+        self.u16_at(self.data, offset).unwrap_or(0) as usize
+    }
+
+    /// Header of a section
+    pub fn section(&self, idx: usize) -> Result<Section> {
+        let data = self.data;
+        let (table_offset, entry_size) = if self.is_64bit {
+            (self.u64_at(data, 0x28)?, self.u16_at(data, 0x3a)?)
+        } else {
+            (self.u32_at(data, 0x20)? as u64, self.u16_at(data, 0x2e)?)
+        };
+        let header = table_offset as usize + idx * entry_size as usize;
+        let sh_type = self.u32_at(data, header + 4)?;
+        if self.is_64bit {
+            Ok(Section {
+                sh_type,
+                flags: self.u64_at(data, header + 0x08)?,
+                address: self.u64_at(data, header + 0x10)?,
+                offset: self.u64_at(data, header + 0x18)? as usize,
+                size: self.u64_at(data, header + 0x20)? as usize,
+                link: self.u32_at(data, header + 0x28)? as usize,
+            })
+        } else {
+            Ok(Section {
+                sh_type,
+                flags: self.u32_at(data, header + 0x08)? as u64,
+                address: self.u32_at(data, header + 0x0c)? as u64,
+                offset: self.u32_at(data, header + 0x10)? as usize,
+                size: self.u32_at(data, header + 0x14)? as usize,
+                link: self.u32_at(data, header + 0x18)? as usize,
+            })
+        }
+    }
+
+    /// Contents of a section
+    pub fn section_data(&self, section: &Section) -> Result<&'a [u8]> {
+        self.data.get(section.offset..section.offset + section.size)
+                 .ok_or_else(truncated)
+    }
+
+    /// Up to some number of bytes of the file which are loaded right before
+    /// some virtual address, in the same section
+    pub fn bytes_before(&self, address: u64, max_len: usize) -> Option<&'a [u8]> {
+        (0..self.section_count()).filter_map(|idx| self.section(idx).ok())
+                                 .filter(|s| s.flags & SHF_ALLOC != 0)
+                                 .filter(|s| s.sh_type != SHT_NOBITS)
+                                 .find(|s| {
+                                     address > s.address &&
+                                     address <= s.address + s.size as u64
+                                 })
+                                 .and_then(|s| {
+                                     let data = self.section_data(&s).ok()?;
+                                     let end = (address - s.address) as usize;
+                                     Some(&data[end.saturating_sub(max_len)..end])
+                                 })
+    }
+
+    /// Functions of the symbol table and dynamic symbol table, with their
+    /// virtual address and size. Functions may be listed in both tables.
+    pub fn functions(&self) -> Result<Vec<ElfSymbol<'a>>> {
+        let mut functions = Vec::new();
+        for idx in 0..self.section_count() {
+            let section = self.section(idx)?;
+            if section.sh_type != SHT_SYMTAB && section.sh_type != SHT_DYNSYM {
+                continue;
+            }
+            let symbols = self.section_data(&section)?;
+            let names = self.section_data(&self.section(section.link)?)?;
+            let entry_size = if self.is_64bit { 24 } else { 16 };
+            for entry in symbols.chunks_exact(entry_size) {
+                let (info, address, size) = if self.is_64bit {
+                    (entry[4], self.u64_at(entry, 8)?, self.u64_at(entry, 16)?)
+                } else {
+                    (entry[12],
+                     self.u32_at(entry, 4)? as u64,
+                     self.u32_at(entry, 8)? as u64)
+                };
+                if !matches!(info & 0xf, STT_FUNC | STT_GNU_IFUNC) || address == 0 {
+                    continue;
+                }
+                let name_start = self.u32_at(entry, 0)? as usize;
+                let name = names.get(name_start..).ok_or_else(truncated)?;
+                let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+                if let Ok(name) = str::from_utf8(&name[..len]) {
+                    functions.push(ElfSymbol { name, address, size });
+                }
+            }
+        }
+        Ok(functions)
+    }
+
+    /// Read an integer of some size at some offset of a byte slice
+    pub fn uint_at(&self, data: &[u8], offset: usize, size: usize) -> Result<u64> {
+        let bytes = data.get(offset..offset+size).ok_or_else(truncated)?;
+        let fold = |acc: u64, &byte: &u8| (acc << 8) | byte as u64;
+        Ok(if self.little_endian {
+            bytes.iter().rev().fold(0, fold)
+        } else {
+            bytes.iter().fold(0, fold)
+        })
+    }
+
+    pub fn u16_at(&self, data: &[u8], offset: usize) -> Result<u16> {
+        self.uint_at(data, offset, 2).map(|x| x as u16)
+    }
+
+    pub fn u32_at(&self, data: &[u8], offset: usize) -> Result<u32> {
+        self.uint_at(data, offset, 4).map(|x| x as u32)
+    }
+
+    pub fn u64_at(&self, data: &[u8], offset: usize) -> Result<u64> {
+        self.uint_at(data, offset, 8)
+    }
+}
+///
+///
+/// Header of an ELF section
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Section {
+    /// Kind of section, e.g. SHT_NOTE
+    pub sh_type: u32,
+
+    /// SHF_* flags of the section
+    pub flags: u64,
+
+    /// Virtual address where the section is loaded, if it is
+    pub address: u64,
+
+    /// Location of the section in the file
+    pub offset: usize,
+    pub size: usize,
+
+    /// Index of a related section, e.g. the string table of a symbol table
+    pub link: usize,
+}
+///
+///
+/// Symbol of an ELF symbol table
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ElfSymbol<'a> {
+    /// Name of the symbol, as it appears in the file (i.e. mangled)
+    pub name: &'a str,
+
+    /// Virtual address of the symbol
+    pub address: u64,
+
+    /// Size of the symbol, in bytes, or 0 if unknown
+    pub size: u64,
+}
+
+
+/// Error reported when an ELF file ends unexpectedly
+pub fn truncated() -> Error {
+    Error::new(ErrorKind::InvalidData, "truncated ELF file")
+}
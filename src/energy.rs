@@ -0,0 +1,168 @@
+//! Rough attribution of energy consumption to functions, for profiles which
+//! sampled energy events (e.g. power/energy-pkg/) along with regular events
+//!
+//! Energy counters cannot tell which code consumed the energy, so the energy
+//! measured during each time window is split between the functions which were
+//! sampled during that window, in proportion of their number of samples.
+
+use sample::Sample;
+use std::collections::HashMap;
+use std::mem;
+
+
+/// Energy of one unit of the RAPL energy counters, in Joules (2^-32 J)
+const ENERGY_UNIT: f64 = 1.0 / 4_294_967_296.0;
+
+/// Duration of the windows over which energy is attributed, in nanoseconds
+const WINDOW_DURATION: u64 = 100_000_000;
+
+/// Number of functions which are displayed for each event
+const NUM_REPORTED_FUNCTIONS: usize = 10;
+
+
+/// Energy measured by each energy event, and attributed to functions
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct EnergyStatistics {
+    /// Energy measured by each event, in Joules
+    pub events: HashMap<String, f64>,
+
+    /// Energy attributed to each leaf function, by event, DSO and symbol, in
+    /// Joules
+    pub functions: HashMap<String, HashMap<String, HashMap<String, f64>>>,
+
+    /// Energy measured by each event during windows where no function was
+    /// sampled, in Joules
+    pub unattributed: HashMap<String, f64>,
+
+    /// Time window whose energy is not attributed yet
+    pub window: EnergyWindow,
+}
+//
+impl EnergyStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a new sample. Samples which do not tell when they were
+    /// taken are ignored.
+    pub fn record(&mut self, sample: &Sample) {
+        let time = match sample.timestamp() {
+            Some(time) => (time * 1e9).round() as u64,
+            None => return,
+        };
+        let index = time / WINDOW_DURATION;
+        if index > self.window.index {
+            self.attribute_window();
+            self.window.index = index;
+        }
+        match sample.event_base_name() {
+            Some(event) if event.starts_with("energy-") => {
+                let energy = sample.period().unwrap_or(0) as f64 * ENERGY_UNIT;
+                *get_or_insert(&mut self.events, event) += energy;
+                *get_or_insert(&mut self.window.energy, event) += energy;
+            },
+            _ => {
+                if let Some(leaf) = sample.frames().next() {
+                    let symbols = get_or_insert(&mut self.window.functions, leaf.dso);
+                    *get_or_insert(symbols, leaf.symbol) += 1;
+                }
+            },
+        }
+    }
+
+    /// Split the energy of the current window between the functions which
+    /// were sampled during that window, and start a new window
+    fn attribute_window(&mut self) {
+        let window = mem::take(&mut self.window);
+        let num_samples = window.functions.values()
+                                          .flat_map(|symbols| symbols.values())
+                                          .sum::<usize>();
+        for (event, energy) in window.energy {
+            if num_samples == 0 {
+                *get_or_insert(&mut self.unattributed, &event) += energy;
+                continue;
+            }
+            let dsos = get_or_insert(&mut self.functions, &event);
+            for (dso, symbols) in &window.functions {
+                let attributed = get_or_insert(dsos, dso);
+                for (symbol, &count) in symbols {
+                    *get_or_insert(attributed, symbol) +=
+                        energy * count as f64 / num_samples as f64;
+                }
+            }
+        }
+    }
+
+    /// Print the energy measured by each event, and the functions which were
+    /// running while most of it was consumed
+    pub fn print_report(&self) {
+        if self.events.is_empty() {
+            println!("No energy event in the profile (record e.g. power/energy-pkg/ \
+                      along with a sampling event)");
+            return;
+        }
+        let mut stats = self.clone();
+        stats.attribute_window();
+        let mut events = stats.events.iter().collect::<Vec<_>>();
+        events.sort_by(|a, b| a.0.cmp(b.0));
+        for (idx, (event, &energy)) in events.into_iter().enumerate() {
+            if idx > 0 {
+                println!();
+            }
+            println!("Energy measured by {}: {:.3} J, attributed over {}ms windows",
+                     event,
+                     energy,
+                     WINDOW_DURATION / 1_000_000);
+            let percent = |joules: f64| 100.0 * joules / energy.max(f64::MIN_POSITIVE);
+            let mut functions = stats.functions.get(event).into_iter().flat_map(|dsos| {
+                dsos.iter().flat_map(|(dso, symbols)| {
+                    symbols.iter().map(move |(symbol, &joules)| (symbol, dso, joules))
+                })
+            }).collect::<Vec<_>>();
+            functions.sort_by(|a, b| {
+                b.2.total_cmp(&a.2).then(a.0.cmp(b.0)).then(a.1.cmp(b.1))
+            });
+            println!("{:>10} {:>7}  Function", "Energy", "Share");
+            for (symbol, dso, joules) in functions.into_iter()
+                                                  .take(NUM_REPORTED_FUNCTIONS) {
+                println!("{:>8.3} J {:>6.1}%  {} ({})",
+                         joules,
+                         percent(joules),
+                         symbol,
+                         dso);
+            }
+            if let Some(&joules) = stats.unattributed.get(event.as_str()) {
+                println!("{:>8.3} J {:>6.1}%  <no sampled function>",
+                         joules,
+                         percent(joules));
+            }
+        }
+    }
+}
+///
+///
+/// Energy measurements and function samples of one time window
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct EnergyWindow {
+    /// Index of the window since the start of the clock
+    pub index: u64,
+
+    /// Energy measured by each event, in Joules
+    pub energy: HashMap<String, f64>,
+
+    /// Number of samples of each leaf function, by DSO and symbol
+    pub functions: HashMap<String, HashMap<String, usize>>,
+}
+
+
+/// Look up an entry of a map with string keys, inserting a default value if
+/// there is none, without allocating a key when the entry already exists
+fn get_or_insert<'m, V>(map: &'m mut HashMap<String, V>, key: &str) -> &'m mut V
+    where V: Default
+{
+    if !map.contains_key(key) {
+        map.insert(key.to_owned(), V::default());
+    }
+    map.get_mut(key).unwrap()
+}
@@ -0,0 +1,129 @@
+//! Diversity of the stack traces sampled in each process, which tells how
+//! concentrated its profile is and whether there are enough samples for a
+//! flamegraph of it to be meaningful
+//!
+//! The diversity is measured by the Shannon entropy of the distribution of
+//! unique stacks, along with the effective number of stacks that it amounts
+//! to. Whether the profile was sampled enough is estimated with the Good-Turing
+//! coverage, i.e. the probability that one more sample would have a stack
+//! which was already seen.
+
+use sample::Sample;
+use std::collections::HashMap;
+
+
+/// Number of processes which are displayed in the report
+const NUM_REPORTED_PROCESSES: usize = 10;
+
+/// Coverage below which a process has too few samples for its flamegraph
+/// to be trusted
+const MIN_COVERAGE: f64 = 0.9;
+
+
+/// Unique stacks of each process
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct EntropyStatistics {
+    /// Stacks of each process, by PID
+    pub processes: HashMap<u32, ProcessStacks>,
+}
+//
+impl EntropyStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a new sample. Samples which do not tell which process they
+    /// come from, or have no stack trace, are ignored.
+    pub fn record(&mut self, sample: &Sample) {
+        let pid = match sample.pid() {
+            Some(pid) => pid,
+            None => return,
+        };
+        let stack = sample.frames().map(|frame| frame.symbol).collect::<Vec<_>>();
+        if stack.is_empty() {
+            return;
+        }
+        let process = self.processes.entry(pid).or_insert_with(|| ProcessStacks {
+            comm: sample.comm().unwrap_or("").to_owned(),
+            ..ProcessStacks::default()
+        });
+        let stack = stack.join(";");
+        match process.stacks.get_mut(&stack) {
+            Some(count) => *count += 1,
+            None => { process.stacks.insert(stack, 1); },
+        }
+    }
+
+    /// Print the diversity of the stacks of the processes with the most
+    /// samples, and whether they were sampled enough
+    pub fn print_report(&self) {
+        if self.processes.is_empty() {
+            println!("No stack trace to measure the diversity of");
+            return;
+        }
+        let mut processes = self.processes.iter().collect::<Vec<_>>();
+        processes.sort_by(|a, b| {
+            b.1.num_samples().cmp(&a.1.num_samples()).then(a.0.cmp(b.0))
+        });
+        println!("Stack diversity per process, most sampled first:");
+        let mut num_undersampled = 0;
+        for (pid, process) in processes.into_iter().take(NUM_REPORTED_PROCESSES) {
+            let entropy = process.entropy();
+            let coverage = process.coverage();
+            let undersampled = coverage < MIN_COVERAGE;
+            num_undersampled += undersampled as usize;
+            println!("- {} ({}): {} samples, {} unique stacks, entropy {:.2} bits \
+                      (~{:.0} effective stacks), coverage {:.1}%{}",
+                     process.comm,
+                     pid,
+                     process.num_samples(),
+                     process.stacks.len(),
+                     entropy,
+                     entropy.exp2(),
+                     100.0 * coverage,
+                     if undersampled { " [warning]" } else { "" });
+        }
+        if num_undersampled > 0 {
+            println!("Processes with a coverage below {:.0}% have too few samples for \
+                      a meaningful flamegraph, record them for longer or at a higher \
+                      frequency",
+                     100.0 * MIN_COVERAGE);
+        }
+    }
+}
+///
+///
+/// Unique stacks of one process
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ProcessStacks {
+    /// Name of the process, as reported by perf
+    pub comm: String,
+
+    /// Number of samples of each stack, by symbols from leaf to root joined
+    /// with semicolons
+    pub stacks: HashMap<String, usize>,
+}
+//
+impl ProcessStacks {
+    /// Number of samples with a stack trace
+    pub fn num_samples(&self) -> usize {
+        self.stacks.values().sum()
+    }
+
+    /// Shannon entropy of the distribution of stacks, in bits
+    pub fn entropy(&self) -> f64 {
+        let num_samples = self.num_samples() as f64;
+        -self.stacks.values().map(|&count| {
+            let p = count as f64 / num_samples;
+            p * p.log2()
+        }).sum::<f64>()
+    }
+
+    /// Good-Turing estimate of the probability that one more sample would have
+    /// a stack which was already seen, based on the stacks seen only once
+    pub fn coverage(&self) -> f64 {
+        let num_singletons = self.stacks.values().filter(|&&count| count == 1).count();
+        1.0 - num_singletons as f64 / self.num_samples().max(1) as f64
+    }
+}
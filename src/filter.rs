@@ -0,0 +1,445 @@
+//! Sample filter expressions, which restrict the analysis to a subset of the
+//! samples, e.g. `comm == "firefox" && event == "cycles" && depth > 10`
+//!
+//! Expressions compare sample fields to literals, and can be combined with
+//! `&&`, `||`, `!` and parentheses. Text fields are compared with `==` and
+//! `!=`, where the literal may contain `*` wildcards. Numeric fields also
+//! support `<`, `<=`, `>` and `>=`. Comparisons involving a field which the
+//! sample does not have (e.g. no period was printed) are false.
+//!
+//! Samples can also be selected based on the frames of their stack trace.
+
+use config::Pattern;
+use regex::Regex;
+use sample::Sample;
+use std::str::FromStr;
+
+
+/// Parsed filter expression
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filter {
+    expr: Expr,
+}
+//
+impl Filter {
+    /// Truth that a sample passes the filter
+    pub fn matches(&self, sample: &Sample) -> bool {
+        self.expr.eval(sample)
+    }
+}
+//
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let mut parser = Parser { tokens: tokenize(text)?, pos: 0 };
+        let expr = parser.parse_or()?;
+        match parser.peek() {
+            None => Ok(Self { expr }),
+            Some(token) => Err(format!("Unexpected {} in filter", token)),
+        }
+    }
+}
+///
+///
+/// Selection of samples based on the frames of their stack trace
+#[derive(Clone, Debug)]
+pub enum FrameFilter {
+    /// Only keep samples with a frame whose function or DSO matches a regex
+    Grep(Regex),
+
+    /// Leave out samples with a frame whose function or DSO matches a regex
+    ExcludeFrame(Regex),
+
+    /// Leave out samples with a frame from a DSO which matches a pattern
+    ExcludeDso(Pattern),
+}
+//
+impl FrameFilter {
+    /// Truth that a sample passes the filter
+    pub fn matches(&self, sample: &Sample) -> bool {
+        match *self {
+            FrameFilter::Grep(ref regex) => sample.frames().any(|frame| {
+                regex.is_match(frame.symbol) || regex.is_match(frame.dso)
+            }),
+            FrameFilter::ExcludeFrame(ref regex) => !sample.frames().any(|frame| {
+                regex.is_match(frame.symbol) || regex.is_match(frame.dso)
+            }),
+            FrameFilter::ExcludeDso(ref pattern) => {
+                !sample.frames().any(|frame| pattern.matches(frame.dso))
+            },
+        }
+    }
+}
+
+
+/// Node of a filter expression's syntax tree
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Text(TextField, bool, Pattern),
+    Number(NumberField, Comparison, u64),
+}
+//
+impl Expr {
+    /// Evaluate the expression for some sample
+    fn eval(&self, sample: &Sample) -> bool {
+        match *self {
+            Expr::Or(ref left, ref right) => left.eval(sample) || right.eval(sample),
+            Expr::And(ref left, ref right) => left.eval(sample) && right.eval(sample),
+            Expr::Not(ref expr) => !expr.eval(sample),
+            Expr::Text(field, equal, ref pattern) => {
+                field.value(sample).is_some_and(|text| pattern.matches(text) == equal)
+            },
+            Expr::Number(field, comparison, literal) => {
+                field.value(sample).is_some_and(|n| comparison.eval(n, literal))
+            },
+        }
+    }
+}
+///
+///
+/// Sample fields which are compared as text
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TextField {
+    Comm,
+    Event,
+    Leaf,
+    Dso,
+    Cgroup,
+}
+//
+impl TextField {
+    /// Value of the field for some sample, if known
+    fn value<'a>(self, sample: &Sample<'a>) -> Option<&'a str> {
+        match self {
+            TextField::Comm => sample.comm(),
+            TextField::Event => sample.event(),
+            TextField::Leaf => sample.frames().next().map(|frame| frame.symbol),
+            TextField::Dso => sample.frames().next().map(|frame| frame.dso),
+            TextField::Cgroup => sample.cgroup(),
+        }
+    }
+}
+///
+///
+/// Sample fields which are compared as numbers
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum NumberField {
+    Pid,
+    Tid,
+    Period,
+    Depth,
+    Freq,
+}
+//
+impl NumberField {
+    /// Value of the field for some sample, if known
+    fn value(self, sample: &Sample) -> Option<u64> {
+        match self {
+            NumberField::Pid => sample.pid().map(u64::from),
+            NumberField::Tid => sample.tid().map(u64::from),
+            NumberField::Period => sample.period(),
+            NumberField::Depth => Some(sample.frames().count() as u64),
+            NumberField::Freq => sample.cpu_frequency(),
+        }
+    }
+}
+///
+///
+/// Comparison operators
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+//
+impl Comparison {
+    /// Compare a sample field to a literal
+    fn eval(self, field: u64, literal: u64) -> bool {
+        match self {
+            Comparison::Equal => field == literal,
+            Comparison::NotEqual => field != literal,
+            Comparison::Less => field < literal,
+            Comparison::LessOrEqual => field <= literal,
+            Comparison::Greater => field > literal,
+            Comparison::GreaterOrEqual => field >= literal,
+        }
+    }
+}
+
+
+/// Lexical tokens of filter expressions
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Text(String),
+    Number(u64),
+    Compare(Comparison),
+    And,
+    Or,
+    Not,
+    LeftParen,
+    RightParen,
+}
+//
+impl ::std::fmt::Display for Token {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Token::Ident(ref ident) => write!(f, "\"{}\"", ident),
+            Token::Text(ref text) => write!(f, "string \"{}\"", text),
+            Token::Number(n) => write!(f, "number {}", n),
+            Token::Compare(_) => write!(f, "comparison"),
+            Token::And => write!(f, "\"&&\""),
+            Token::Or => write!(f, "\"||\""),
+            Token::Not => write!(f, "\"!\""),
+            Token::LeftParen => write!(f, "\"(\""),
+            Token::RightParen => write!(f, "\")\""),
+        }
+    }
+}
+
+
+/// Split a filter expression into tokens
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let mut next_is = |expected| {
+            let found = chars.peek().map(|&(_, c)| c) == Some(expected);
+            if found {
+                chars.next();
+            }
+            found
+        };
+        let token = match c {
+            _ if c.is_whitespace() => continue,
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            '&' if next_is('&') => Token::And,
+            '|' if next_is('|') => Token::Or,
+            '=' if next_is('=') => Token::Compare(Comparison::Equal),
+            '!' if next_is('=') => Token::Compare(Comparison::NotEqual),
+            '!' => Token::Not,
+            '<' if next_is('=') => Token::Compare(Comparison::LessOrEqual),
+            '<' => Token::Compare(Comparison::Less),
+            '>' if next_is('=') => Token::Compare(Comparison::GreaterOrEqual),
+            '>' => Token::Compare(Comparison::Greater),
+            '"' => {
+                let mut literal = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c)) => literal.push(c),
+                            None => return Err("Unterminated string in filter".into()),
+                        },
+                        Some((_, c)) => literal.push(c),
+                        None => return Err("Unterminated string in filter".into()),
+                    }
+                }
+                Token::Text(literal)
+            },
+            _ if c.is_ascii_alphanumeric() || c == '_' => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(pos, c)) = chars.peek() {
+                    if !c.is_ascii_alphanumeric() && c != '_' {
+                        break;
+                    }
+                    end = pos + c.len_utf8();
+                    chars.next();
+                }
+                let word = &text[start..end];
+                if c.is_ascii_digit() {
+                    Token::Number(word.parse().map_err(|_| {
+                        format!("Invalid number {} in filter", word)
+                    })?)
+                } else {
+                    Token::Ident(word.to_owned())
+                }
+            },
+            _ => return Err(format!("Unexpected character '{}' in filter", c)),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+
+/// Recursive descent parser of filter expressions
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+//
+impl Parser {
+    /// Look at the next token without consuming it
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    /// Consume the next token
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token.ok_or_else(|| "Unexpected end of filter".to_owned())
+    }
+
+    /// Parse a disjunction, which has the lowest precedence
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    /// Parse a conjunction
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    /// Parse a negation, a parenthesized expression or a comparison
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        match self.next()? {
+            Token::Not => Ok(Expr::Not(Box::new(self.parse_unary()?))),
+            Token::LeftParen => {
+                let expr = self.parse_or()?;
+                match self.next()? {
+                    Token::RightParen => Ok(expr),
+                    token => Err(format!("Expected \")\" in filter, found {}", token)),
+                }
+            },
+            Token::Ident(field) => self.parse_comparison(&field),
+            token => Err(format!("Expected a field name in filter, found {}", token)),
+        }
+    }
+
+    /// Parse the rest of a comparison, given the name of the field
+    fn parse_comparison(&mut self, field: &str) -> Result<Expr, String> {
+        let comparison = match self.next()? {
+            Token::Compare(comparison) => comparison,
+            token => return Err(format!("Expected a comparison after {}, found {}",
+                                        field, token)),
+        };
+        let text_field = match field {
+            "comm" => Some(TextField::Comm),
+            "event" => Some(TextField::Event),
+            "leaf" => Some(TextField::Leaf),
+            "dso" => Some(TextField::Dso),
+            "cgroup" => Some(TextField::Cgroup),
+            _ => None,
+        };
+        let number_field = match field {
+            "pid" => Some(NumberField::Pid),
+            "tid" => Some(NumberField::Tid),
+            "period" => Some(NumberField::Period),
+            "depth" => Some(NumberField::Depth),
+            "freq" => Some(NumberField::Freq),
+            _ => None,
+        };
+        match (text_field, number_field, self.next()?) {
+            (Some(field), _, Token::Text(text)) => {
+                let equal = match comparison {
+                    Comparison::Equal => true,
+                    Comparison::NotEqual => false,
+                    _ => return Err("Text fields only support == and !=".into()),
+                };
+                Ok(Expr::Text(field, equal, Pattern::new(&text)))
+            },
+            (_, Some(field), Token::Number(n)) => Ok(Expr::Number(field, comparison, n)),
+            (Some(_), _, token) | (_, Some(_), token) => {
+                Err(format!("Unexpected {} after {}", token, field))
+            },
+            (None, None, _) => {
+                Err(format!("Unknown filter field {}, expected one of comm, event, \
+                             leaf, dso, cgroup, pid, tid, period, depth or freq",
+                            field))
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Expr, String> {
+        text.parse::<Filter>().map(|filter| filter.expr)
+    }
+
+    fn pid_is(pid: u64) -> Box<Expr> {
+        Box::new(Expr::Number(NumberField::Pid, Comparison::Equal, pid))
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(parse("pid == 1 || pid == 2 && pid == 3"),
+                   Ok(Expr::Or(pid_is(1), Box::new(Expr::And(pid_is(2), pid_is(3))))));
+        assert_eq!(parse("(pid == 1 || pid == 2) && pid == 3"),
+                   Ok(Expr::And(Box::new(Expr::Or(pid_is(1), pid_is(2))), pid_is(3))));
+        assert_eq!(parse("!pid == 1 && pid == 2"),
+                   Ok(Expr::And(Box::new(Expr::Not(pid_is(1))), pid_is(2))));
+        assert_eq!(parse("!(pid == 1 || pid == 2)"),
+                   Ok(Expr::Not(Box::new(Expr::Or(pid_is(1), pid_is(2))))));
+
+        let header = "app 1/1 [000] 1.000000: 1000 cycles:\n";
+        let sample = Sample {
+            raw_sample_data: header.as_bytes(),
+            header: header.as_bytes(),
+            stack_trace: b"",
+            last_stack_frame: None,
+        };
+        let matches = |text: &str| text.parse::<Filter>().unwrap().matches(&sample);
+        assert!(matches("pid == 1 || pid == 2 && pid == 3"));
+        assert!(!matches("(pid == 1 || pid == 2) && pid == 3"));
+        assert!(!matches("!(pid < 2)"));
+        assert!(matches("comm == \"a*\" && period >= 1000 && !(event != \"cycles\")"));
+        assert_eq!(parse("(pid == 1"), Err("Unexpected end of filter".to_owned()));
+        assert_eq!(parse("pid == 1)"), Err("Unexpected \")\" in filter".to_owned()));
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(parse(r#"comm == "a \"b\" \\ c""#),
+                   Ok(Expr::Text(TextField::Comm, true, Pattern::new(r#"a "b" \ c"#))));
+        assert_eq!(parse(r#"dso != "/usr/lib/*""#),
+                   Ok(Expr::Text(TextField::Dso, false, Pattern::new("/usr/lib/*"))));
+        let unterminated = Err("Unterminated string in filter".to_owned());
+        assert_eq!(parse(r#"comm == "app"#), unterminated);
+        assert_eq!(parse(r#"comm == "app\"#), unterminated);
+    }
+
+    #[test]
+    fn invalid_comparisons() {
+        let text_only = Err("Text fields only support == and !=".to_owned());
+        assert_eq!(parse(r#"comm < "x""#), text_only);
+        assert_eq!(parse(r#"leaf >= "x""#), text_only);
+        assert_eq!(parse(r#"pid == "x""#),
+                   Err("Unexpected string \"x\" after pid".to_owned()));
+        assert_eq!(parse("comm == 1"), Err("Unexpected number 1 after comm".to_owned()));
+    }
+
+    #[test]
+    fn unknown_fields() {
+        let error = parse("user == 1").unwrap_err();
+        assert!(error.starts_with("Unknown filter field user, expected one of"));
+        assert_eq!(parse("pid = 1"),
+                   Err("Unexpected character '=' in filter".to_owned()));
+        assert_eq!(parse("== 1"),
+                   Err("Expected a field name in filter, found comparison".to_owned()));
+    }
+}
@@ -0,0 +1,276 @@
+//! Compatibility with the various text layouts of perf script. The layout of
+//! sample headers changed across perf versions (whether the CPU column is
+//! bracketed, whether timestamps and event names are followed by a colon).
+//! Stack frames with symbol offsets (e.g. "main+0x1f") need no translation,
+//! since the offset is told apart from the symbol when frames are decoded.
+//!
+//! Rather than teaching every parser of this crate about every layout, samples
+//! are translated into the canonical layout of modern perf versions as soon as
+//! they are read. The layout is sniffed from the first sample that has a stack
+//! trace, unless the user tells which perf version produced the output.
+
+use sample::{Sample, SampleSource};
+use std::io::Result;
+use std::str::{self, FromStr};
+
+
+/// First perf version whose default layout is the canonical one. Older
+/// versions printed the CPU column without brackets, and did not follow
+/// timestamps and event names with a colon.
+const FIRST_CANONICAL_VERSION: (u32, u32) = (3, 0);
+
+
+/// Layout of perf script's textual output
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PerfFormat {
+    /// Truth that the CPU column is surrounded by brackets, as in "[003]"
+    pub bracketed_cpu: bool,
+
+    /// Truth that timestamps are followed by a colon, as in "1234.567890:"
+    pub timestamp_colon: bool,
+
+    /// Truth that event names are followed by a colon, as in "cycles:u:"
+    pub event_colon: bool,
+}
+//
+impl PerfFormat {
+    /// Layout which the rest of this crate expects
+    pub fn canonical() -> Self {
+        Self {
+            bracketed_cpu: true,
+            timestamp_colon: true,
+            event_colon: true,
+        }
+    }
+
+    /// Default layout of some perf version, given as "major.minor"
+    pub fn for_version(major: u32, minor: u32) -> Self {
+        if (major, minor) >= FIRST_CANONICAL_VERSION {
+            Self::canonical()
+        } else {
+            Self {
+                bracketed_cpu: false,
+                timestamp_colon: false,
+                event_colon: false,
+            }
+        }
+    }
+
+    /// Guess the layout of perf script's output from one of its samples.
+    /// Whatever cannot be told from the sample is assumed to be canonical.
+    pub fn sniff(sample: &Sample) -> Self {
+        let mut format = Self::canonical();
+        let header = match str::from_utf8(sample.header) {
+            Ok(header) => header,
+            Err(_) => return format,
+        };
+        let layout = HeaderLayout::parse(header);
+        if let Some(cpu) = layout.cpu {
+            format.bracketed_cpu = layout.columns[cpu].1.starts_with('[');
+        }
+        if let Some(time) = layout.time {
+            format.timestamp_colon = layout.columns[time].1.ends_with(':');
+        }
+        if let Some(event) = layout.event {
+            format.event_colon = layout.columns[event].1.ends_with(':');
+        }
+        format
+    }
+
+    /// Truth that samples of this layout can be analyzed as they are
+    pub fn is_canonical(&self) -> bool {
+        *self == Self::canonical()
+    }
+
+    /// Translate a sample of this layout into the canonical layout. The copy
+    /// is built in a caller-provided buffer, so that it can be reused.
+    pub fn normalize<'b>(&self, sample: &Sample, buffer: &'b mut Vec<u8>) -> Sample<'b> {
+        buffer.clear();
+        match str::from_utf8(sample.header) {
+            Ok(header) => self.normalize_header(header, buffer),
+            Err(_) => buffer.extend_from_slice(sample.header),
+        }
+        let header_len = buffer.len();
+        buffer.extend_from_slice(sample.stack_trace);
+        Sample::from_raw(buffer, header_len)
+    }
+
+    /// Translate a sample header of this layout into the canonical layout
+    fn normalize_header(&self, header: &str, output: &mut Vec<u8>) {
+        let layout = HeaderLayout::parse(header);
+        let mut edits = Vec::new();
+        if let (false, Some(cpu)) = (self.bracketed_cpu, layout.cpu) {
+            edits.push((cpu, "[", "]"));
+        }
+        if let (false, Some(time)) = (self.timestamp_colon, layout.time) {
+            edits.push((time, "", ":"));
+        }
+        if let (false, Some(event)) = (self.event_colon, layout.event) {
+            edits.push((event, "", ":"));
+        }
+        let mut copied = 0;
+        for (idx, prefix, suffix) in edits {
+            let (start, column) = layout.columns[idx];
+            output.extend_from_slice(&header.as_bytes()[copied..start]);
+            output.extend_from_slice(prefix.as_bytes());
+            output.extend_from_slice(column.as_bytes());
+            output.extend_from_slice(suffix.as_bytes());
+            copied = start + column.len();
+        }
+        output.extend_from_slice(&header.as_bytes()[copied..]);
+    }
+}
+//
+impl Default for PerfFormat {
+    fn default() -> Self {
+        Self::canonical()
+    }
+}
+//
+impl FromStr for PerfFormat {
+    type Err = String;
+
+    /// Parse a perf version, as printed by perf --version (e.g. "6.8.12" or
+    /// "5.15.0-91-generic"), into the layout of that version
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        let version = s.trim_start_matches("perf version ");
+        let mut numbers = version.split(['.', '-']).map(str::parse::<u32>);
+        match (numbers.next(), numbers.next()) {
+            (Some(Ok(major)), Some(Ok(minor))) => Ok(Self::for_version(major, minor)),
+            _ => Err(format!("Invalid perf version \"{}\", expected e.g. \"6.8\"", s)),
+        }
+    }
+}
+///
+///
+/// Positions of the header columns whose layout varies across perf versions
+struct HeaderLayout<'a> {
+    /// Whitespace-separated columns, along with their byte offset
+    columns: Vec<(usize, &'a str)>,
+
+    /// Index of the CPU column, if any
+    cpu: Option<usize>,
+
+    /// Index of the timestamp column, if any
+    time: Option<usize>,
+
+    /// Index of the event name column, if any
+    event: Option<usize>,
+}
+//
+impl<'a> HeaderLayout<'a> {
+    /// Locate the columns of a sample header. The CPU column follows the PID
+    /// column, and the event name follows the timestamp and optional period.
+    fn parse(header: &'a str) -> Self {
+        let columns = header.split_whitespace().map(|column| {
+            (column.as_ptr() as usize - header.as_ptr() as usize, column)
+        }).collect::<Vec<_>>();
+        let find = |predicate: fn(&str) -> bool| {
+            columns.iter().skip(1).position(|&(_, column)| predicate(column))
+                   .map(|idx| idx + 1)
+        };
+        let pid = find(is_pid);
+        let time = find(is_timestamp);
+        let cpu = pid.map(|pid| pid + 1).filter(|&cpu| {
+            time.is_some_and(|time| cpu < time) && is_cpu(columns[cpu].1)
+        });
+        let event = time.map(|time| {
+            let is_period = columns.get(time + 1).is_some_and(|&(_, column)| {
+                column.bytes().all(|b| b.is_ascii_digit())
+            });
+            time + 1 + is_period as usize
+        }).filter(|&event| {
+            columns.get(event).is_some_and(|&(_, column)| {
+                !column.starts_with("PERF_RECORD_")
+            })
+        });
+        Self { columns, cpu, time, event }
+    }
+}
+
+
+/// Truth that a header column is a "pid" or "pid/tid" column
+fn is_pid(column: &str) -> bool {
+    column.split('/').all(|id| !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()))
+}
+
+
+/// Truth that a header column is a CPU number, with or without brackets
+fn is_cpu(column: &str) -> bool {
+    let cpu = column.strip_prefix('[')
+                    .and_then(|column| column.strip_suffix(']'))
+                    .unwrap_or(column);
+    !cpu.is_empty() && cpu.bytes().all(|b| b.is_ascii_digit())
+}
+
+
+/// Truth that a header column is a timestamp, with or without a colon. Unlike
+/// other numeric columns, timestamps always have a fractional part.
+fn is_timestamp(column: &str) -> bool {
+    let time = column.strip_suffix(':').unwrap_or(column);
+    time.contains('.') &&
+    !time.starts_with('.') &&
+    time.bytes().all(|b| b.is_ascii_digit() || b == b'.')
+}
+///
+///
+/// Source of samples which translates another source's samples into the
+/// canonical layout, sniffing their layout first if it is not known
+pub struct NormalizedSamples<'s, S: SampleSource> {
+    /// Source of samples in their original layout
+    samples: &'s mut S,
+
+    /// Layout of the samples, if known yet
+    format: Option<PerfFormat>,
+
+    /// Storage for translated samples
+    buffer: Vec<u8>,
+}
+//
+impl<'s, S: SampleSource> NormalizedSamples<'s, S> {
+    /// Translate some samples of known layout, or sniff it if None
+    pub fn new(samples: &'s mut S, format: Option<PerfFormat>) -> Self {
+        Self {
+            samples,
+            format,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Layout of the samples, if it is known yet
+    pub fn format(&self) -> Option<PerfFormat> {
+        self.format
+    }
+}
+//
+impl<'s, S: SampleSource> SampleSource for NormalizedSamples<'s, S> {
+    fn next_sample(&mut self) -> Result<Option<Sample<'_>>> {
+        if self.format.is_some_and(|format| format.is_canonical()) {
+            return self.samples.next_sample();
+        }
+        let sample = match self.samples.next_sample()? {
+            Some(sample) => sample,
+            None => return Ok(None),
+        };
+
+        // Samples before the first stack trace are left as they are, since
+        // their layout cannot be reliably told
+        let format = match self.format {
+            Some(format) => format,
+            None if sample.last_stack_frame.is_some() => {
+                let format = PerfFormat::sniff(&sample);
+                self.format = Some(format);
+                format
+            },
+            None => return Ok(Some(sample)),
+        };
+        if format.is_canonical() {
+            return Ok(Some(sample));
+        }
+        Ok(Some(format.normalize(&sample, &mut self.buffer)))
+    }
+
+    fn position(&self) -> u64 {
+        self.samples.position()
+    }
+}
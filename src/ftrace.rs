@@ -0,0 +1,246 @@
+//! Call duration trees built from function tracer events, for users who
+//! recorded ftrace data through perf (e.g. `perf record -e ftrace:function`)
+//! instead of sampling stacks
+//!
+//! Function graph events (ftrace:funcgraph_entry and ftrace:funcgraph_exit)
+//! tell exactly when each call starts and ends. Plain function events only
+//! tell when a function is entered and who called it ("vfs_read <-- ksys_read"),
+//! so a call is considered to last until a function which is not one of its
+//! callees is entered on the same CPU. This overestimates the duration of
+//! calls which are followed by untraced code, but gives a good idea of where
+//! the time goes.
+
+use intervals;
+use sample::Sample;
+use std::collections::BTreeMap;
+
+
+/// Maximal depth of the call trees which are displayed in the report
+const MAX_REPORTED_DEPTH: usize = 8;
+
+/// Fraction of the traced time of a CPU below which calls are not displayed
+const MIN_REPORTED_SHARE: f64 = 0.01;
+
+
+/// Call trees of each CPU
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct FunctionTraceStatistics {
+    /// Calls of each CPU, by CPU number
+    pub cpus: BTreeMap<u32, CpuCalls>,
+}
+//
+impl FunctionTraceStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a new sample. Only function tracer events which tell on
+    /// which CPU and when they were recorded are taken into account.
+    pub fn record(&mut self, sample: &Sample) {
+        let (event, payload) = match (sample.event(), sample.event_payload()) {
+            (Some(event), Some(payload)) => (event, payload),
+            _ => return,
+        };
+        let (cpu, time) = match (sample.cpu(), sample.timestamp()) {
+            (Some(cpu), Some(time)) => (cpu, (time * 1e9).round() as u64),
+            _ => return,
+        };
+        match event {
+            "ftrace:function" => {
+                let (function, caller) = match payload.split_once(" <-- ") {
+                    Some((function, caller)) => (function.trim(), caller.trim()),
+                    None => match (field(payload, "ip"), field(payload, "parent_ip")) {
+                        (Some(function), Some(caller)) => (function, caller),
+                        _ => return,
+                    },
+                };
+                self.cpus.entry(cpu).or_default().enter(function, Some(caller), time);
+            },
+            "ftrace:funcgraph_entry" => {
+                let function = match payload.strip_prefix("-->") {
+                    Some(rest) => rest.split_whitespace().next(),
+                    None => field(payload, "func"),
+                };
+                if let Some(function) = function {
+                    self.cpus.entry(cpu).or_default().enter(function, None, time);
+                }
+            },
+            "ftrace:funcgraph_exit" => {
+                let function = match payload.strip_prefix("<--") {
+                    Some(rest) => rest.split_whitespace().next(),
+                    None => field(payload, "func"),
+                };
+                let duration = match (call_time(payload, "start:", "calltime"),
+                                      call_time(payload, "end:", "rettime")) {
+                    (Some(start), Some(end)) => Some(end.saturating_sub(start)),
+                    _ => None,
+                };
+                if let Some(function) = function {
+                    self.cpus.entry(cpu).or_default().exit(function, time, duration);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Print the call tree of each CPU, longest calls first
+    pub fn print_report(&self) {
+        if self.cpus.is_empty() {
+            println!("No function tracer event (record e.g. ftrace:function or \
+                      ftrace:funcgraph_entry and ftrace:funcgraph_exit)");
+            return;
+        }
+        for (idx, (cpu, calls)) in self.cpus.iter().enumerate() {
+            if idx > 0 {
+                println!();
+            }
+            let mut calls = calls.clone();
+            calls.finish();
+            let total_time = calls.tree.children.values()
+                                                .map(|node| node.total_time)
+                                                .sum::<u64>();
+            println!("Call tree of CPU {} over {} of traced calls:",
+                     cpu,
+                     intervals::format_nanoseconds(total_time));
+            calls.tree.print(0, total_time);
+        }
+    }
+}
+///
+///
+/// Calls of one CPU
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct CpuCalls {
+    /// Completed calls, whose root node only has children
+    pub tree: CallTree,
+
+    /// Calls which are still running, as function name and start time in
+    /// nanoseconds, outermost call first
+    pub stack: Vec<(String, u64)>,
+
+    /// Time of the last event, in nanoseconds
+    pub last_time: u64,
+}
+//
+impl CpuCalls {
+    /// Account for a function being entered. If its caller is known, the
+    /// calls which are not part of the caller's are considered finished.
+    fn enter(&mut self, function: &str, caller: Option<&str>, time: u64) {
+        self.last_time = time;
+        if let Some(caller) = caller {
+            match self.stack.iter().rposition(|(name, _)| name == caller) {
+                Some(depth) => self.unwind(depth + 1, time),
+                None => {
+                    self.unwind(0, time);
+                    self.stack.push((caller.to_owned(), time));
+                },
+            }
+        }
+        self.stack.push((function.to_owned(), time));
+    }
+
+    /// Account for a function returning, with its duration if it is known.
+    /// The calls which it did not return from yet are considered finished.
+    fn exit(&mut self, function: &str, time: u64, duration: Option<u64>) {
+        self.last_time = time;
+        let depth = match self.stack.iter().rposition(|(name, _)| name == function) {
+            Some(depth) => depth,
+            None => return,
+        };
+        self.unwind(depth + 1, time);
+        let start = match duration {
+            Some(duration) => time.saturating_sub(duration),
+            None => self.stack[depth].1,
+        };
+        self.stack[depth].1 = start;
+        self.unwind(depth, time);
+    }
+
+    /// Finish the calls which are deeper than some depth at a given time
+    fn unwind(&mut self, depth: usize, time: u64) {
+        while self.stack.len() > depth {
+            let mut node = &mut self.tree;
+            for (name, _) in &self.stack {
+                if !node.children.contains_key(name.as_str()) {
+                    node.children.insert(name.clone(), CallTree::default());
+                }
+                node = node.children.get_mut(name.as_str()).unwrap();
+            }
+            let (_, start) = self.stack.pop().unwrap();
+            node.num_calls += 1;
+            node.total_time += time.saturating_sub(start);
+        }
+    }
+
+    /// Finish all the calls which are still running at the time of the last
+    /// event
+    fn finish(&mut self) {
+        let last_time = self.last_time;
+        self.unwind(0, last_time);
+    }
+}
+///
+///
+/// Calls of one function from one call path, and of its callees
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct CallTree {
+    /// Number of calls
+    pub num_calls: usize,
+
+    /// Total duration of the calls, callees included, in nanoseconds
+    pub total_time: u64,
+
+    /// Callees, by function name
+    pub children: BTreeMap<String, CallTree>,
+}
+//
+impl CallTree {
+    /// Print the callees of this node, longest first, with some indentation
+    fn print(&self, depth: usize, cpu_time: u64) {
+        if depth >= MAX_REPORTED_DEPTH {
+            return;
+        }
+        let mut children = self.children.iter().filter(|&(_, child)| {
+            child.total_time as f64 >= MIN_REPORTED_SHARE * cpu_time as f64
+        }).collect::<Vec<_>>();
+        children.sort_by(|a, b| b.1.total_time.cmp(&a.1.total_time).then(a.0.cmp(b.0)));
+        for (name, child) in children {
+            println!("{}- {}: {} calls, {} ({:.1}%), {} per call",
+                     "  ".repeat(depth),
+                     name,
+                     child.num_calls,
+                     intervals::format_nanoseconds(child.total_time),
+                     100.0 * child.total_time as f64 / cpu_time.max(1) as f64,
+                     intervals::format_nanoseconds(child.total_time /
+                                                   child.num_calls.max(1) as u64));
+            child.print(depth + 1, cpu_time);
+        }
+    }
+}
+
+
+/// Value of a field of a tracepoint's payload, printed either as "key=value"
+/// or as "key value" (e.g. "(start: 1a2b")
+fn field<'p>(payload: &'p str, key: &str) -> Option<&'p str> {
+    let mut columns = payload.split_whitespace();
+    while let Some(column) = columns.next() {
+        if column.trim_start_matches('(') == key {
+            return columns.next().map(|value| value.trim_end_matches(')'));
+        }
+        if let Some(value) = column.strip_prefix(key).and_then(|c| c.strip_prefix('=')) {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Start or end time of a call from a function graph exit event, in
+/// nanoseconds, which the kernel formats in hexadecimal (e.g. "start: 1a2b")
+/// and perf prints in decimal when it cannot use the kernel's format
+fn call_time(payload: &str, formatted_key: &str, raw_key: &str) -> Option<u64> {
+    match field(payload, formatted_key) {
+        Some(time) => u64::from_str_radix(time.trim_start_matches("0x"), 16).ok(),
+        None => field(payload, raw_key)?.parse().ok(),
+    }
+}
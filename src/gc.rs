@@ -0,0 +1,202 @@
+//! Split of the CPU time of garbage-collected programs between the mutator
+//! (the program's own code) and the garbage collector, for the JVM and Go
+//! runtime presets
+//!
+//! HotSpot runs its collectors (G1, ZGC, Shenandoah, Parallel...) in dedicated
+//! native threads with recognizable names, whereas Go runs its collector in
+//! goroutines which can be scheduled on any thread, so GC samples are also
+//! recognized by the runtime functions on their stack. GC code is mostly
+//! native code that perf unwinds differently from the mutator's code, so the
+//! stack trace quality of GC samples is tracked separately.
+
+use analysis::SampleCategory;
+use sample::Sample;
+use std::collections::HashMap;
+
+
+/// Number of processes which are displayed in the report
+const NUM_REPORTED_PROCESSES: usize = 10;
+
+/// Prefixes of the names of HotSpot's GC threads, as truncated by perf to 15
+/// characters (e.g. "GC Thread#0", "G1 Conc#1", "ZWorker#2")
+const JVM_GC_THREAD_PREFIXES: &[&str] = &[
+    "GC Thread",
+    "G1 ",
+    "ZDriver",
+    "ZDirector",
+    "ZWorker",
+    "ZThread",
+    "ZStat",
+    "ZUnmapper",
+    "ZUncommitter",
+    "Shenandoah",
+];
+
+/// Prefixes of the names of HotSpot's GC classes, which show up in the stacks
+/// of mutator threads that help with collection (e.g. allocation stalls)
+const JVM_GC_SYMBOL_PREFIXES: &[&str] = &[
+    "G1",
+    "ZCollectedHeap::",
+    "ZHeap::",
+    "ZMark",
+    "ZRelocate",
+    "ZGeneration",
+    "ZDriver",
+    "Shenandoah",
+    "PSScavenge::",
+    "PSParallelCompact::",
+    "PSPromotionManager::",
+    "ParallelScavengeHeap::",
+    "SerialHeap::",
+    "DefNewGeneration::",
+    "TenuredGeneration::",
+];
+
+/// Functions of the Go runtime which only run on behalf of the collector,
+/// besides those whose name starts with "runtime.gc"
+const GO_GC_FUNCTIONS: &[&str] = &[
+    "runtime.markroot",
+    "runtime.scanobject",
+    "runtime.scanblock",
+    "runtime.scanstack",
+    "runtime.greyobject",
+    "runtime.bgsweep",
+    "runtime.sweepone",
+    "runtime.bgscavenge",
+    "runtime.wbBufFlush",
+];
+
+
+/// Truth that a thread is dedicated to garbage collection
+pub fn is_gc_thread(comm: &str) -> bool {
+    JVM_GC_THREAD_PREFIXES.iter().any(|prefix| comm.starts_with(prefix))
+}
+
+/// Truth that a function belongs to a garbage collector
+pub fn is_gc_function(symbol: &str) -> bool {
+    JVM_GC_SYMBOL_PREFIXES.iter().any(|prefix| symbol.starts_with(prefix)) ||
+    symbol.starts_with("runtime.gc") ||
+    GO_GC_FUNCTIONS.contains(&symbol)
+}
+
+/// Truth that a sample was taken while collecting garbage
+pub fn is_gc_sample(sample: &Sample) -> bool {
+    sample.comm().is_some_and(is_gc_thread) ||
+    sample.frames().any(|frame| is_gc_function(frame.symbol))
+}
+///
+///
+/// Mutator and GC samples of each garbage-collected process
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct GcStatistics {
+    /// Samples of each process, by PID
+    pub processes: HashMap<u32, ProcessSplit>,
+
+    /// Number of mutator samples of each category, by category key
+    pub mutator_categories: HashMap<String, usize>,
+
+    /// Number of GC samples of each category, by category key
+    pub gc_categories: HashMap<String, usize>,
+}
+//
+impl GcStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a newly classified sample. Samples which do not tell which
+    /// process they come from are ignored.
+    pub fn record(&mut self, sample: &Sample, category: &SampleCategory) {
+        let pid = match sample.pid() {
+            Some(pid) => pid,
+            None => return,
+        };
+        let process = self.processes.entry(pid).or_default();
+        if process.comm.is_empty() || sample.tid() == Some(pid) {
+            if let Some(comm) = sample.comm() {
+                if process.comm != comm {
+                    process.comm = comm.to_owned();
+                }
+            }
+        }
+        let categories = if is_gc_sample(sample) {
+            process.num_gc += 1;
+            &mut self.gc_categories
+        } else {
+            process.num_mutator += 1;
+            &mut self.mutator_categories
+        };
+        let key = category.key();
+        match categories.get_mut(key) {
+            Some(count) => *count += 1,
+            None => { categories.insert(key.to_owned(), 1); },
+        }
+    }
+
+    /// Print the processes which spent the most time collecting garbage, and
+    /// the stack trace quality of GC and mutator samples
+    pub fn print_report(&self) {
+        let num_gc = self.gc_categories.values().sum::<usize>();
+        let num_mutator = self.mutator_categories.values().sum::<usize>();
+        if num_gc == 0 {
+            println!("No garbage collection sample over {} samples", num_mutator);
+            return;
+        }
+        let mut processes = self.processes.iter()
+                                          .filter(|(_, split)| split.num_gc > 0)
+                                          .collect::<Vec<_>>();
+        processes.sort_by(|a, b| {
+            b.1.gc_share().total_cmp(&a.1.gc_share())
+                          .then(b.1.num_gc.cmp(&a.1.num_gc))
+                          .then(a.0.cmp(b.0))
+        });
+        println!("Mutator and GC samples of each process, most GC first:");
+        for (pid, split) in processes.into_iter().take(NUM_REPORTED_PROCESSES) {
+            println!("- {} ({}): {} mutator, {} GC ({:.1}% in GC)",
+                     split.comm,
+                     pid,
+                     split.num_mutator,
+                     split.num_gc,
+                     100.0 * split.gc_share());
+        }
+        println!("Stack trace quality of GC samples:");
+        print_categories(&self.gc_categories, num_gc);
+        println!("Stack trace quality of mutator samples:");
+        print_categories(&self.mutator_categories, num_mutator);
+    }
+}
+///
+///
+/// Mutator and GC samples of one process
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ProcessSplit {
+    /// Name of the process, as reported by perf
+    pub comm: String,
+
+    /// Number of samples taken in the program's own code
+    pub num_mutator: usize,
+
+    /// Number of samples taken while collecting garbage
+    pub num_gc: usize,
+}
+//
+impl ProcessSplit {
+    /// Fraction of the samples which were taken while collecting garbage
+    fn gc_share(&self) -> f64 {
+        self.num_gc as f64 / (self.num_gc + self.num_mutator).max(1) as f64
+    }
+}
+
+
+/// Print how a set of samples was classified, most frequent category first
+fn print_categories(categories: &HashMap<String, usize>, num_samples: usize) {
+    let mut categories = categories.iter().collect::<Vec<_>>();
+    categories.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    for (key, &count) in categories {
+        println!("- {}: {} ({:.1}%)",
+                 key,
+                 count,
+                 100.0 * count as f64 / num_samples.max(1) as f64);
+    }
+}
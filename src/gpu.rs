@@ -0,0 +1,219 @@
+//! Knowledge about GPU driver stacks (Mesa, amdgpu, i915 and NVIDIA), and a
+//! breakdown of the time which programs spend submitting work to the GPU
+//! versus running their own code or waiting for the GPU
+//!
+//! GPU work goes from the application through a user-space driver (a Mesa
+//! driver or NVIDIA's libraries), which submits it to a kernel driver through
+//! ioctls. Samples are attributed to the driver of their innermost driver
+//! frame, which is where the time was actually spent.
+
+use runtime;
+use sample::{Sample, StackFrame};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+
+/// Kernel functions where a thread waits for the GPU to complete some work,
+/// typically inside of a wait ioctl
+const GPU_WAIT_FUNCS: &[&str] = &[
+    "dma_fence_wait_timeout", "dma_fence_default_wait", "dma_fence_wait_any_timeout",
+    "dma_resv_wait_timeout", "drm_syncobj_array_wait", "drm_syncobj_array_wait_timeout",
+    "amdgpu_cs_wait_ioctl", "amdgpu_cs_wait_fences_ioctl", "amdgpu_gem_wait_idle_ioctl",
+    "i915_request_wait", "i915_gem_wait_ioctl", "i915_gem_object_wait",
+    "xe_wait_user_fence_ioctl", "nouveau_fence_wait",
+];
+
+/// Number of driver DSOs which are displayed in the report
+const NUM_REPORTED_DSOS: usize = 5;
+
+
+/// Truth that a function waits for the GPU to complete some work
+pub fn is_gpu_wait(symbol: &str) -> bool {
+    GPU_WAIT_FUNCS.contains(&symbol)
+}
+
+
+/// GPU drivers which we know something about
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum GpuDriver {
+    /// Mesa's user-space OpenGL and Vulkan drivers, whatever the GPU vendor
+    Mesa,
+
+    /// AMD's kernel driver, and its libdrm wrapper
+    Amdgpu,
+
+    /// Intel's kernel drivers (i915 and xe), and their libdrm wrapper
+    I915,
+
+    /// NVIDIA's proprietary user-space and kernel drivers
+    Nvidia,
+
+    /// Vendor-neutral DRM infrastructure of the kernel and of libdrm
+    Drm,
+}
+//
+impl GpuDriver {
+    /// Every GPU driver
+    pub const ALL: [GpuDriver; 5] = [
+        GpuDriver::Mesa,
+        GpuDriver::Amdgpu,
+        GpuDriver::I915,
+        GpuDriver::Nvidia,
+        GpuDriver::Drm,
+    ];
+
+    /// GPU driver which a stack frame belongs to, if any
+    pub fn of_frame(frame: &StackFrame) -> Option<Self> {
+        // Kernel drivers are either modules or built into the kernel
+        match frame.dso {
+            "[amdgpu]" | "[amdxcp]" => return Some(GpuDriver::Amdgpu),
+            "[i915]" | "[xe]" => return Some(GpuDriver::I915),
+            "[drm]" | "[drm_kms_helper]" | "[gpu_sched]" | "[ttm]" => {
+                return Some(GpuDriver::Drm)
+            },
+            "[kernel.kallsyms]" => {
+                let symbol = frame.symbol;
+                return if symbol.starts_with("amdgpu_") {
+                    Some(GpuDriver::Amdgpu)
+                } else if symbol.starts_with("i915_") || symbol.starts_with("xe_") {
+                    Some(GpuDriver::I915)
+                } else if symbol.starts_with("drm_") ||
+                          symbol.starts_with("dma_fence_") ||
+                          symbol.starts_with("ttm_") {
+                    Some(GpuDriver::Drm)
+                } else {
+                    None
+                };
+            },
+            dso if dso.starts_with("[nvidia") => return Some(GpuDriver::Nvidia),
+            _ => {},
+        }
+
+        // User-space drivers are recognized by their file name
+        let file_name = runtime::dso_file_name(frame.dso);
+        let library = runtime::strip_so_version(file_name);
+        if file_name.contains("nvidia") || library == "libcuda.so" {
+            Some(GpuDriver::Nvidia)
+        } else if library == "libdrm_amdgpu.so" {
+            Some(GpuDriver::Amdgpu)
+        } else if library == "libdrm_intel.so" {
+            Some(GpuDriver::I915)
+        } else if library == "libdrm.so" {
+            Some(GpuDriver::Drm)
+        } else if library.ends_with("_dri.so") ||
+                  library.starts_with("libgallium") ||
+                  library.starts_with("libvulkan_") ||
+                  library.starts_with("libGLX_mesa") ||
+                  library.starts_with("libEGL_mesa") ||
+                  library == "libglapi.so" {
+            Some(GpuDriver::Mesa)
+        } else {
+            None
+        }
+    }
+}
+//
+impl FromStr for GpuDriver {
+    type Err = String;
+
+    fn from_str(name: &str) -> ::std::result::Result<Self, String> {
+        GpuDriver::ALL.iter().cloned()
+                      .find(|driver| driver.to_string() == name)
+                      .ok_or_else(|| format!("Unknown GPU driver {}", name))
+    }
+}
+//
+impl fmt::Display for GpuDriver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            GpuDriver::Mesa => "Mesa",
+            GpuDriver::Amdgpu => "amdgpu",
+            GpuDriver::I915 => "i915",
+            GpuDriver::Nvidia => "NVIDIA",
+            GpuDriver::Drm => "DRM",
+        };
+        write!(f, "{}", name)
+    }
+}
+///
+///
+/// Breakdown of samples between application code, GPU drivers, and waits for
+/// the GPU
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct GpuStatistics {
+    /// Number of samples which were taken into account
+    pub num_samples: usize,
+
+    /// Number of samples which were waiting for the GPU
+    pub num_waiting: usize,
+
+    /// Number of samples spent in each driver
+    pub drivers: HashMap<GpuDriver, usize>,
+
+    /// Number of samples spent in each driver DSO
+    pub dsos: HashMap<String, usize>,
+}
+//
+impl GpuStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a new sample. Samples without a stack trace are ignored.
+    pub fn record(&mut self, sample: &Sample) {
+        if sample.frames().next().is_none() {
+            return;
+        }
+        self.num_samples += 1;
+        if sample.frames().any(|frame| is_gpu_wait(frame.symbol)) {
+            self.num_waiting += 1;
+            return;
+        }
+        let driver_frame = sample.frames().find_map(|frame| {
+            GpuDriver::of_frame(&frame).map(|driver| (driver, frame.dso))
+        });
+        if let Some((driver, dso)) = driver_frame {
+            *self.drivers.entry(driver).or_default() += 1;
+            match self.dsos.get_mut(dso) {
+                Some(count) => *count += 1,
+                None => { self.dsos.insert(dso.to_owned(), 1); },
+            }
+        }
+    }
+
+    /// Print how samples are split between the application, the drivers, and
+    /// waits for the GPU
+    pub fn print_report(&self) {
+        if self.num_samples == 0 {
+            println!("No sample with a stack trace, cannot tell GPU driver activity");
+            return;
+        }
+        let percent = |count: usize| 100.0 * count as f64 / self.num_samples as f64;
+        let num_driver = self.drivers.values().sum::<usize>();
+        let num_application = self.num_samples - num_driver - self.num_waiting;
+        println!("GPU driver activity over {} samples:", self.num_samples);
+        println!("- Application code: {} ({:.1}%)",
+                 num_application,
+                 percent(num_application));
+        println!("- Driver submission: {} ({:.1}%)", num_driver, percent(num_driver));
+        for &driver in GpuDriver::ALL.iter() {
+            if let Some(&count) = self.drivers.get(&driver) {
+                println!("  - {}: {} ({:.1}%)", driver, count, percent(count));
+            }
+        }
+        println!("- Waiting for the GPU: {} ({:.1}%)",
+                 self.num_waiting,
+                 percent(self.num_waiting));
+        if self.dsos.is_empty() {
+            return;
+        }
+        let mut dsos = self.dsos.iter().collect::<Vec<_>>();
+        dsos.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        println!("Busiest driver DSOs:");
+        for (dso, &count) in dsos.into_iter().take(NUM_REPORTED_DSOS) {
+            println!("- {}: {} ({:.1}%)", dso, count, percent(count));
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Recognition of the samples which were taken in virtual machine guests, as
+//! recorded by perf kvm or with the :G and :H event modifiers
+//!
+//! perf names the DSO of guest kernel code "[guest.kernel.kallsyms]", with the
+//! PID of the hypervisor process appended when several guests were profiled
+//! (e.g. "[guest.kernel.kallsyms.1234]"). Events with the :G modifier only
+//! count while a guest runs, and events with the :H modifier only count on the
+//! host, which tells where samples come from even without a stack trace.
+
+use sample::{Sample, StackFrame};
+use std::collections::HashMap;
+
+
+/// Prefix of the names which perf gives to the DSOs of guest code
+const GUEST_DSO_PREFIX: &str = "[guest.";
+
+/// Number of guests which are displayed in the report
+const NUM_REPORTED_GUESTS: usize = 10;
+
+
+/// Truth that a stack frame belongs to the code of a virtual machine guest
+pub fn is_guest_frame(frame: &StackFrame) -> bool {
+    frame.dso.starts_with(GUEST_DSO_PREFIX)
+}
+
+/// DSO of the guest code which a sample was taken in, if it was taken in a
+/// guest, or an empty string if only the event modifiers tell so
+pub fn guest_of<'a>(sample: &Sample<'a>) -> Option<&'a str> {
+    if let Some(frame) = sample.frames().find(is_guest_frame) {
+        return Some(frame.dso);
+    }
+    let modifiers = sample.event_modifiers();
+    if modifiers.contains('G') && !modifiers.contains('H') {
+        Some("")
+    } else {
+        None
+    }
+}
+///
+///
+/// Split of the samples of each event between the host and its guests
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct GuestStatistics {
+    /// Samples of each event, by event name
+    pub events: HashMap<String, EventSplit>,
+
+    /// Samples of each guest, by guest kernel DSO, or by an empty string for
+    /// guest samples which do not tell which guest they come from
+    pub guests: HashMap<String, usize>,
+}
+//
+impl GuestStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a new sample. Samples which show no sign of coming from a
+    /// guest are attributed to the host.
+    pub fn record(&mut self, sample: &Sample) {
+        let event = sample.event().unwrap_or("(unknown event)");
+        let split = match self.events.get_mut(event) {
+            Some(split) => split,
+            None => self.events.entry(event.to_owned()).or_default(),
+        };
+        let guest = match guest_of(sample) {
+            Some(guest) => guest,
+            None => {
+                split.num_host += 1;
+                return;
+            },
+        };
+        split.num_guest += 1;
+        match self.guests.get_mut(guest) {
+            Some(count) => *count += 1,
+            None => { self.guests.insert(guest.to_owned(), 1); },
+        }
+    }
+
+    /// Print the share of guest samples for each event, and the guests which
+    /// were sampled the most
+    pub fn print_report(&self) {
+        if self.events.is_empty() {
+            println!("No sample to split between host and guests");
+            return;
+        }
+        let mut events = self.events.iter().collect::<Vec<_>>();
+        events.sort_by(|a, b| a.0.cmp(b.0));
+        println!("Host and guest samples of each event:");
+        for (event, split) in events {
+            let total = split.num_host + split.num_guest;
+            println!("- {}: {} host, {} guest ({:.1}% in guests)",
+                     event,
+                     split.num_host,
+                     split.num_guest,
+                     100.0 * split.num_guest as f64 / total.max(1) as f64);
+        }
+        if self.guests.is_empty() {
+            println!("No guest sample (record guests with perf kvm, or with the :G \
+                      event modifier)");
+            return;
+        }
+        let num_guest = self.guests.values().sum::<usize>();
+        let mut guests = self.guests.iter().collect::<Vec<_>>();
+        guests.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        println!("Samples of each guest:");
+        for (guest, &count) in guests.into_iter().take(NUM_REPORTED_GUESTS) {
+            let guest = if guest.is_empty() { "<unknown guest>" } else { guest };
+            println!("- {}: {} ({:.1}%)",
+                     guest,
+                     count,
+                     100.0 * count as f64 / num_guest as f64);
+        }
+    }
+}
+///
+///
+/// Split of the samples of one event between the host and its guests
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct EventSplit {
+    /// Number of samples taken on the host
+    pub num_host: usize,
+
+    /// Number of samples taken in guests
+    pub num_guest: usize,
+}
@@ -0,0 +1,266 @@
+//! Ranking of the functions where the most samples were taken, which gives a
+//! quick perf report-like overview of the profile
+
+use analysis::SampleCategory;
+use sample::{Sample, StackFrame};
+use significance::Significance;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+
+/// Exclusive and inclusive sample counts of each function
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct FunctionStatistics {
+    /// Sample counts of each function, by DSO and symbol name
+    pub functions: HashMap<String, HashMap<String, FunctionCounts>>,
+
+    /// Number of samples which were taken into account
+    pub num_samples: usize,
+
+    /// Number of functions which are displayed in the report
+    pub num_reported: usize,
+
+    /// Truth that only Normal samples are taken into account, so that broken
+    /// stack traces do not skew inclusive counts
+    pub normal_only: bool,
+
+    /// How leaf functions are corrected for IP skid
+    pub skid: SkidCorrection,
+}
+//
+impl FunctionStatistics {
+    /// Start with all counters at zero, and report some number of functions
+    pub fn new(num_reported: usize) -> Self {
+        Self {
+            num_reported,
+            ..Self::default()
+        }
+    }
+
+    /// Only take Normal samples into account
+    pub fn normal_only(mut self) -> Self {
+        self.normal_only = true;
+        self
+    }
+
+    /// Correct leaf functions for IP skid
+    pub fn with_skid_correction(mut self, skid: SkidCorrection) -> Self {
+        self.skid = skid;
+        self
+    }
+
+    /// Account for a newly classified sample
+    pub fn record(&mut self, sample: &Sample, category: &SampleCategory) {
+        if self.normal_only && !matches!(*category, SampleCategory::Normal) {
+            return;
+        }
+        let mut frames = sample.frames().collect::<Vec<_>>();
+        if frames.len() > 1 && self.skid.shifts(sample) {
+            frames.remove(0);
+        }
+        if frames.is_empty() {
+            return;
+        }
+        self.num_samples += 1;
+
+        // The leaf function gets an exclusive sample...
+        self.counts(&frames[0]).exclusive += 1;
+
+        // ...and every function of the stack gets an inclusive sample, but
+        // recursive functions must only be counted once
+        for (idx, frame) in frames.iter().enumerate() {
+            let is_recursion = frames[..idx].iter().any(|caller| {
+                caller.symbol == frame.symbol && caller.dso == frame.dso
+            });
+            if !is_recursion {
+                self.counts(frame).inclusive += 1;
+            }
+        }
+    }
+
+    /// Counters associated with the function of some stack frame
+    fn counts(&mut self, frame: &StackFrame) -> &mut FunctionCounts {
+        if !self.functions.contains_key(frame.dso) {
+            self.functions.insert(frame.dso.to_owned(), HashMap::new());
+        }
+        let symbols = self.functions.get_mut(frame.dso).unwrap();
+        if !symbols.contains_key(frame.symbol) {
+            symbols.insert(frame.symbol.to_owned(), FunctionCounts::default());
+        }
+        symbols.get_mut(frame.symbol).unwrap()
+    }
+
+    /// Print the functions with the most exclusive samples, dealing with those
+    /// which have too few inclusive samples as specified
+    pub fn print_report(&self, significance: &Significance) {
+        let mut functions = self.functions.iter().flat_map(|(dso, symbols)| {
+            symbols.iter().map(move |(symbol, counts)| (symbol, dso, counts))
+        }).collect::<Vec<_>>();
+        functions.sort_by_key(|&(symbol, dso, counts)| {
+            (Reverse(counts.exclusive), Reverse(counts.inclusive), symbol, dso)
+        });
+        let num_hidden = significance.select(&mut functions, |f| f.2.inclusive);
+        let percent = |count: usize| 100.0 * count as f64 / self.num_samples as f64;
+        println!("Top functions over {} {}samples:",
+                 self.num_samples,
+                 if self.normal_only { "normal " } else { "" });
+        println!("{:>7} {:>9} {:>7} {:>10}  Function",
+                 "Self", "Children", "Self #", "Children #");
+        let mut num_flagged = 0;
+        for (symbol, dso, counts) in functions.into_iter().take(self.num_reported) {
+            num_flagged += !significance.is_significant(counts.inclusive) as usize;
+            println!("{:>6.1}% {:>8.1}% {:>7} {:>10}  {} ({}){}",
+                     percent(counts.exclusive),
+                     percent(counts.inclusive),
+                     counts.exclusive,
+                     counts.inclusive,
+                     symbol,
+                     dso,
+                     significance.marker(counts.inclusive));
+        }
+        significance.print_notice(num_hidden, num_flagged);
+    }
+}
+///
+///
+/// Correction of the leaf frame for IP skid, i.e. the hardware reporting an
+/// instruction pointer which is a bit past the one that caused the event. When
+/// the sampled instruction was a return, the wrong function gets the blame.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum SkidCorrection {
+    /// Trust the leaf frame
+    #[default]
+    None,
+
+    /// Attribute each sample to the caller of the leaf frame
+    Shift,
+
+    /// Attribute samples to the caller of the leaf frame, unless the event
+    /// was recorded with the :pp or :ppp precise modifiers
+    Auto,
+}
+//
+impl SkidCorrection {
+    /// Truth that the leaf frame of some sample should be skipped
+    fn shifts(self, sample: &Sample) -> bool {
+        match self {
+            SkidCorrection::None => false,
+            SkidCorrection::Shift => true,
+            SkidCorrection::Auto => sample.event_precision() < 2,
+        }
+    }
+}
+//
+impl FromStr for SkidCorrection {
+    type Err = String;
+
+    fn from_str(name: &str) -> ::std::result::Result<Self, String> {
+        match name {
+            "none" => Ok(SkidCorrection::None),
+            "shift" => Ok(SkidCorrection::Shift),
+            "auto" => Ok(SkidCorrection::Auto),
+            _ => Err(format!("Unknown skid correction {}, expected one of none, \
+                              shift or auto", name)),
+        }
+    }
+}
+//
+impl fmt::Display for SkidCorrection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            SkidCorrection::None => "none",
+            SkidCorrection::Shift => "shift",
+            SkidCorrection::Auto => "auto",
+        };
+        write!(f, "{}", name)
+    }
+}
+///
+///
+/// Sample counts of one function
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct FunctionCounts {
+    /// Samples where this function was executing (leaf frame)
+    pub exclusive: usize,
+
+    /// Samples where this function was on the stack
+    pub inclusive: usize,
+}
+
+
+/// Leaf functions of the samples of each anomalous category, which tells
+/// whether broken stack traces cluster in some specific hot code
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct LeafStatistics {
+    /// Sample counts of each leaf function, by category key, DSO and symbol
+    pub categories: HashMap<String, HashMap<String, HashMap<String, usize>>>,
+}
+//
+impl LeafStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a newly classified sample
+    pub fn record(&mut self, sample: &Sample, category: &SampleCategory) {
+        if let SampleCategory::Normal = *category {
+            return;
+        }
+        let leaf = match sample.frames().next() {
+            Some(leaf) => leaf,
+            None => return,
+        };
+        let key = category.key();
+        if !self.categories.contains_key(key) {
+            self.categories.insert(key.to_owned(), HashMap::new());
+        }
+        let dsos = self.categories.get_mut(key).unwrap();
+        if !dsos.contains_key(leaf.dso) {
+            dsos.insert(leaf.dso.to_owned(), HashMap::new());
+        }
+        let symbols = dsos.get_mut(leaf.dso).unwrap();
+        if !symbols.contains_key(leaf.symbol) {
+            symbols.insert(leaf.symbol.to_owned(), 0);
+        }
+        *symbols.get_mut(leaf.symbol).unwrap() += 1;
+    }
+
+    /// Print the most frequent leaf functions of each anomalous category,
+    /// given the labeled counters of the sample statistics, dealing with those
+    /// which have too few samples as specified
+    pub fn print_report(&self,
+                        counters: &[(SampleCategory, &str, usize)],
+                        significance: &Significance) {
+        println!("Leaf functions of anomalous samples:");
+        let (mut num_hidden, mut num_flagged) = (0, 0);
+        for &(ref category, label, count) in counters {
+            let dsos = match self.categories.get(category.key()) {
+                Some(dsos) if count > 0 => dsos,
+                _ => continue,
+            };
+            let mut leaves = dsos.iter().flat_map(|(dso, symbols)| {
+                symbols.iter().map(move |(symbol, &count)| (symbol, dso, count))
+            }).collect::<Vec<_>>();
+            leaves.sort_by_key(|&(symbol, dso, count)| (Reverse(count), symbol, dso));
+            num_hidden += significance.select(&mut leaves, |leaf| leaf.2);
+            println!("- {}:", label);
+            for (symbol, dso, leaves) in leaves.into_iter().take(NUM_REPORTED_LEAVES) {
+                num_flagged += !significance.is_significant(leaves) as usize;
+                println!("  * {} ({}): {} ({:.1}%){}",
+                         symbol,
+                         dso,
+                         leaves,
+                         100.0 * leaves as f64 / count as f64,
+                         significance.marker(leaves));
+            }
+        }
+        significance.print_notice(num_hidden, num_flagged);
+    }
+}
+
+
+/// Number of leaf functions which are displayed for each category
+const NUM_REPORTED_LEAVES: usize = 5;
@@ -0,0 +1,229 @@
+//! Index of the samples of a perf script dump, which is built by a first pass
+//! over the file and saved next to it, so that later runs can go straight to
+//! specific samples instead of parsing the whole dump again
+//!
+//! The index holds the byte offset and timestamp of each sample, along with a
+//! digest of its header line which tells whether the file was modified since
+//! the index was built. Samples are numbered from 0 in file order, like the
+//! sample_id column of exported tables, and side-band records are not numbered.
+
+use mapped::MappedSamples;
+use sample::{self, Sample};
+use std::convert::TryInto;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+
+/// Magic bytes at the start of every index, including a format version
+const MAGIC: &[u8] = b"PSI\x02";
+
+
+/// Location of each sample of a perf script dump
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SampleIndex {
+    /// Size of the indexed file, in bytes
+    pub file_size: u64,
+
+    /// Location, timestamp and header digest of each sample, in file order
+    pub samples: Vec<IndexEntry>,
+}
+//
+impl SampleIndex {
+    /// Where the index of some perf script dump is saved
+    pub fn path_for(input: &Path) -> PathBuf {
+        let mut path = PathBuf::from(input).into_os_string();
+        path.push(".index");
+        path.into()
+    }
+
+    /// Load the index of a perf script dump if it is up to date, otherwise
+    /// (or if it is unreadable) build it and save it for the next runs
+    pub fn open(input: &Path) -> Result<Self> {
+        let path = Self::path_for(input);
+        let mut samples = MappedSamples::open(input)?;
+        match Self::load(&path) {
+            Ok(index) if index.matches(&mut samples)? => return Ok(index),
+            Ok(_) => {},
+            Err(ref e) if matches!(e.kind(),
+                                   ErrorKind::NotFound | ErrorKind::InvalidData) => {},
+            Err(e) => return Err(e),
+        }
+        let index = Self::build(&mut samples)?;
+        index.save(&path)?;
+        Ok(index)
+    }
+
+    /// Index the samples of a perf script dump, in a full pass over it
+    pub fn build(samples: &mut MappedSamples) -> Result<Self> {
+        samples.seek(0)?;
+        let mut entries = Vec::new();
+        loop {
+            let offset = samples.position();
+            let sample = match samples.next()? {
+                Some(sample) => sample,
+                None => break,
+            };
+            if sample::is_sideband_record(sample.header) ||
+               sample::is_trace_error(sample.header)
+            {
+                continue;
+            }
+            entries.push(IndexEntry {
+                offset,
+                timestamp: sample.timestamp(),
+                digest: digest(sample.header),
+            });
+        }
+        Ok(Self {
+            file_size: samples.file_size(),
+            samples: entries,
+        })
+    }
+
+    /// Load an index from disk
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path)?;
+        let words = match bytes.strip_prefix(MAGIC) {
+            Some(words) if words.len() % 8 == 0 => words,
+            _ => return Err(invalid_index("not a sample index")),
+        };
+        let mut words = words.chunks(8).map(|word| {
+            u64::from_le_bytes(word.try_into().unwrap())
+        });
+        let file_size = words.next().ok_or_else(|| invalid_index("no file size"))?;
+        let mut samples = Vec::new();
+        while let Some(offset) = words.next() {
+            let mut next_word = || {
+                words.next().ok_or_else(|| invalid_index("truncated entry"))
+            };
+            let timestamp = f64::from_bits(next_word()?);
+            samples.push(IndexEntry {
+                offset,
+                timestamp: Some(timestamp).filter(|timestamp| !timestamp.is_nan()),
+                digest: next_word()?,
+            });
+        }
+        Ok(Self { file_size, samples })
+    }
+
+    /// Save an index to disk. A temporary file is written and renamed over
+    /// the destination, so that an interruption never leaves it half-written.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut bytes = Vec::with_capacity(MAGIC.len() + 8 + 24 * self.samples.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&self.file_size.to_le_bytes());
+        for entry in &self.samples {
+            let timestamp = entry.timestamp.unwrap_or(f64::NAN);
+            bytes.extend_from_slice(&entry.offset.to_le_bytes());
+            bytes.extend_from_slice(&timestamp.to_bits().to_le_bytes());
+            bytes.extend_from_slice(&entry.digest.to_le_bytes());
+        }
+        let mut tmp_path = PathBuf::from(path).into_os_string();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, bytes)?;
+        fs::rename(&tmp_path, path)
+    }
+
+    /// Truth that the index still describes a perf script dump, as far as its
+    /// size and the first and last samples tell
+    pub fn matches(&self, samples: &mut MappedSamples) -> Result<bool> {
+        if self.file_size != samples.file_size() {
+            return Ok(false);
+        }
+        for number in [0, self.samples.len().saturating_sub(1)] {
+            if number < self.samples.len() && self.sample(samples, number)?.is_none() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Number of the sample designated by some locator, if there is one. A
+    /// timestamp designates the sample which was taken closest to it.
+    pub fn locate(&self, locator: SampleLocator) -> Option<usize> {
+        match locator {
+            SampleLocator::Number(number) if number < self.samples.len() => Some(number),
+            SampleLocator::Number(_) => None,
+            SampleLocator::Timestamp(timestamp) => {
+                self.samples.iter()
+                            .enumerate()
+                            .filter_map(|(number, entry)| {
+                                entry.timestamp.map(|t| (number, (t - timestamp).abs()))
+                            })
+                            .min_by(|a, b| a.1.total_cmp(&b.1))
+                            .map(|(number, _)| number)
+            },
+        }
+    }
+
+    /// Extract the sample with some number from a perf script dump, or None
+    /// if that sample is not where the index says it is
+    pub fn sample<'a>(&self,
+                      samples: &'a mut MappedSamples,
+                      number: usize) -> Result<Option<Sample<'a>>> {
+        let entry = match self.samples.get(number) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        if entry.offset > samples.file_size() {
+            return Ok(None);
+        }
+        samples.seek(entry.offset)?;
+        Ok(samples.next()?.filter(|sample| digest(sample.header) == entry.digest))
+    }
+}
+///
+///
+/// Location of one sample in a perf script dump
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct IndexEntry {
+    /// Byte offset of the sample, which may be preceded by blank lines
+    pub offset: u64,
+
+    /// Time at which the sample was taken, in seconds, if perf printed it
+    pub timestamp: Option<f64>,
+
+    /// Digest of the sample's header line
+    pub digest: u64,
+}
+
+///
+///
+/// Way to designate one sample of a perf script dump
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SampleLocator {
+    /// Number of the sample, counting from 0 in file order
+    Number(usize),
+
+    /// Time at which the sample was taken, in seconds
+    Timestamp(f64),
+}
+
+
+/// Parse a list of sample numbers and inclusive ranges thereof, e.g. "3,10-12"
+pub fn parse_sample_numbers(list: &str)
+    -> ::std::result::Result<Vec<RangeInclusive<usize>>, String>
+{
+    list.split(',').map(|item| {
+        let (first, last) = item.split_once('-').unwrap_or((item, item));
+        match (first.trim().parse(), last.trim().parse()) {
+            (Ok(first), Ok(last)) if first <= last => Ok(first..=last),
+            _ => Err(format!("Invalid sample numbers {}, expected a list of numbers \
+                              and ranges like 3,10-12", item)),
+        }
+    }).collect()
+}
+
+
+/// FNV-1a digest of a sample header
+fn digest(header: &[u8]) -> u64 {
+    sample::fnv1a(sample::FNV_OFFSET_BASIS, header)
+}
+
+
+/// Error reported when an index cannot be decoded
+fn invalid_index(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("invalid sample index: {}", message))
+}
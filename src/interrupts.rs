@@ -0,0 +1,67 @@
+//! Per-CPU accounting of the samples taken in interrupt handlers, which tells
+//! how much time IRQs and softirqs steal from the code running on each CPU
+
+use analysis::SampleCategory;
+use sample::Sample;
+use std::collections::HashMap;
+
+
+/// Interrupt sample counters of each CPU
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct InterruptStatistics {
+    pub cpus: HashMap<u32, CpuInterrupts>,
+}
+//
+impl InterruptStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a newly classified sample. Samples which do not tell which
+    /// CPU they were taken on are ignored.
+    pub fn record(&mut self, sample: &Sample, category: &SampleCategory) {
+        let cpu = match sample.cpu() {
+            Some(cpu) => cpu,
+            None => return,
+        };
+        let counters = self.cpus.entry(cpu).or_default();
+        counters.num_samples += 1;
+        match *category {
+            SampleCategory::HardIrq => counters.num_hard_irqs += 1,
+            SampleCategory::SoftIrq => counters.num_soft_irqs += 1,
+            _ => {},
+        }
+    }
+
+    /// Print the fraction of samples stolen by interrupts on each CPU
+    pub fn print_report(&self) {
+        let mut cpus = self.cpus.iter().collect::<Vec<_>>();
+        cpus.sort_by_key(|&(&cpu, _)| cpu);
+        println!("Samples stolen by interrupts per CPU:");
+        for (cpu, counters) in cpus {
+            let percent = |count: usize| {
+                100.0 * count as f64 / counters.num_samples as f64
+            };
+            println!("- CPU {}: {:.1}% IRQ, {:.1}% softirq over {} samples",
+                     cpu,
+                     percent(counters.num_hard_irqs),
+                     percent(counters.num_soft_irqs),
+                     counters.num_samples);
+        }
+    }
+}
+///
+///
+/// Interrupt sample counters of one CPU
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct CpuInterrupts {
+    /// Samples taken on this CPU
+    pub num_samples: usize,
+
+    /// Samples taken in hardware interrupt handlers
+    pub num_hard_irqs: usize,
+
+    /// Samples taken in softirq handlers
+    pub num_soft_irqs: usize,
+}
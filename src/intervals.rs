@@ -0,0 +1,200 @@
+//! Distribution of the time between consecutive samples of each event on each
+//! CPU. Sampling is supposed to be regular, so long intervals are gaps during
+//! which nothing was recorded (e.g. ring buffer overruns, or a CPU in a deep
+//! sleep state), which explains why hot code may appear to be missing from a
+//! profile.
+
+use sample::Sample;
+use std::collections::{BTreeMap, HashMap};
+
+
+/// Width of the histogram bars, in characters
+const BAR_WIDTH: usize = 40;
+
+/// Number of histogram buckets. Bucket N holds intervals from 2^N to 2^(N+1)
+/// nanoseconds, so this covers intervals of up to 2^40ns (about 18 minutes).
+const NUM_BUCKETS: usize = 41;
+
+/// Number of buckets above the median interval's bucket from which intervals
+/// are considered to be sampling gaps (i.e. 16x longer than usual)
+const GAP_BUCKETS: usize = 4;
+
+/// Number of CPUs with sampling gaps which are displayed for each event
+const NUM_REPORTED_CPUS: usize = 5;
+
+
+/// Intervals between consecutive samples of each event, on each CPU
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct IntervalStatistics {
+    pub events: HashMap<String, BTreeMap<u32, IntervalHistogram>>,
+
+    /// Time of the last sample of each event on each CPU, in nanoseconds. This
+    /// is only needed to compute intervals, so it is not saved in checkpoints.
+    #[serde(skip)]
+    last_times: HashMap<(String, u32), u64>,
+}
+//
+impl IntervalStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a new sample. Samples which do not tell which event, CPU
+    /// and time they come from are ignored.
+    pub fn record(&mut self, sample: &Sample) {
+        let (event, cpu, time) = match (sample.event(),
+                                        sample.cpu(),
+                                        sample.timestamp()) {
+            (Some(event), Some(cpu), Some(time)) => (event, cpu, time),
+            _ => return,
+        };
+        let time = (time * 1e9).round() as u64;
+        let key = (event.to_owned(), cpu);
+        let last_time = self.last_times.insert(key, time);
+        if let Some(last_time) = last_time {
+            let cpus = match self.events.get_mut(event) {
+                Some(cpus) => cpus,
+                None => self.events.entry(event.to_owned()).or_default(),
+            };
+            cpus.entry(cpu).or_default().record(time.saturating_sub(last_time));
+        }
+    }
+
+    /// Print a histogram of the intervals between samples of each event, and
+    /// the CPUs on which sampling had the largest gaps
+    pub fn print_report(&self) {
+        if self.events.is_empty() {
+            println!("No interval between samples (perf script must print the event, \
+                      CPU and time fields)");
+            return;
+        }
+        let mut events = self.events.iter().collect::<Vec<_>>();
+        events.sort_by(|a, b| a.0.cmp(b.0));
+        for (idx, (event, cpus)) in events.into_iter().enumerate() {
+            if idx > 0 {
+                println!();
+            }
+            let mut total = IntervalHistogram::default();
+            for histogram in cpus.values() {
+                total.merge(histogram);
+            }
+            println!("Time between samples of {} on {} CPUs:", event, cpus.len());
+            total.print();
+
+            // Gaps are relative to each CPU's usual sampling rate
+            let mut gaps = cpus.iter().filter_map(|(&cpu, histogram)| {
+                let num_gaps = histogram.num_gaps();
+                Some((cpu, num_gaps, histogram.max_interval)).filter(|_| num_gaps > 0)
+            }).collect::<Vec<_>>();
+            gaps.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)));
+            if !gaps.is_empty() {
+                println!("CPUs with sampling gaps ({}x the median interval or more):",
+                         1 << GAP_BUCKETS);
+            }
+            for (cpu, num_gaps, longest) in gaps.into_iter().take(NUM_REPORTED_CPUS) {
+                println!("- CPU {}: {} gaps, the longest one lasting {} [warning]",
+                         cpu,
+                         num_gaps,
+                         format_nanoseconds(longest));
+            }
+        }
+    }
+}
+///
+///
+/// Logarithmic histogram of the intervals between consecutive samples
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct IntervalHistogram {
+    /// Number of intervals in each power-of-two bucket
+    pub counts: Vec<usize>,
+
+    /// Longest interval, in nanoseconds
+    pub max_interval: u64,
+}
+//
+impl IntervalHistogram {
+    /// Account for a new interval, in nanoseconds
+    pub fn record(&mut self, interval: u64) {
+        let bucket = (64 - interval.leading_zeros() as usize).saturating_sub(1)
+                                                             .min(NUM_BUCKETS - 1);
+        if self.counts.len() <= bucket {
+            self.counts.resize(bucket + 1, 0);
+        }
+        self.counts[bucket] += 1;
+        self.max_interval = self.max_interval.max(interval);
+    }
+
+    /// Add the intervals of another histogram to this one
+    pub fn merge(&mut self, other: &Self) {
+        if self.counts.len() < other.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (count, &other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.max_interval = self.max_interval.max(other.max_interval);
+    }
+
+    /// Number of intervals which are much longer than the median one
+    pub fn num_gaps(&self) -> usize {
+        let total = self.counts.iter().sum::<usize>();
+        let mut cumulated = 0;
+        let median_bucket = self.counts.iter().position(|&count| {
+            cumulated += count;
+            2 * cumulated >= total
+        });
+        match median_bucket {
+            Some(bucket) => self.counts.iter().skip(bucket + GAP_BUCKETS).sum(),
+            None => 0,
+        }
+    }
+
+    /// Estimate a percentile of the intervals (e.g. 99.0), in nanoseconds, by
+    /// linear interpolation within the bucket where it falls. Returns None if
+    /// the histogram is empty.
+    pub fn percentile(&self, percent: f64) -> Option<u64> {
+        let total = self.counts.iter().sum::<usize>();
+        if total == 0 {
+            return None;
+        }
+        let rank = (percent / 100.0 * total as f64).max(1.0);
+        let mut cumulated = 0;
+        for (bucket, &count) in self.counts.iter().enumerate() {
+            if count > 0 && (cumulated + count) as f64 >= rank {
+                let start = 1u64 << bucket;
+                let end = (1u64 << (bucket + 1)).min(self.max_interval.max(start));
+                let fraction = (rank - cumulated as f64) / count as f64;
+                return Some(start + (fraction * (end - start) as f64).round() as u64);
+            }
+            cumulated += count;
+        }
+        Some(self.max_interval)
+    }
+
+    /// Print the histogram as text, one line per bucket
+    pub fn print(&self) {
+        let first = self.counts.iter().position(|&count| count > 0).unwrap_or(0);
+        let max_count = self.counts.iter().cloned().max().unwrap_or(0).max(1);
+        for (bucket, &count) in self.counts.iter().enumerate().skip(first) {
+            let filled = (count * BAR_WIDTH).div_ceil(max_count);
+            println!("  {:>8} - {:<8} |{:<width$}| {}",
+                     format_nanoseconds(1 << bucket),
+                     format_nanoseconds(1 << (bucket + 1)),
+                     "#".repeat(filled),
+                     count,
+                     width = BAR_WIDTH);
+        }
+    }
+}
+
+
+/// Format a duration in nanoseconds with a suitable unit
+pub fn format_nanoseconds(ns: u64) -> String {
+    match ns {
+        0..=999 => format!("{}ns", ns),
+        1_000..=999_999 => format!("{:.1}us", ns as f64 * 1e-3),
+        1_000_000..=999_999_999 => format!("{:.1}ms", ns as f64 * 1e-6),
+        _ => format!("{:.1}s", ns as f64 * 1e-9),
+    }
+}
@@ -0,0 +1,143 @@
+//! Instructions per cycle of each function and DSO, for profiles where both
+//! the cycles and instructions events were sampled
+//!
+//! The two events are sampled separately, so the IPC of a function is
+//! approximated by the ratio of the periods of the samples where it was the
+//! leaf function, i.e. of the estimated event counts. Hot functions with a low
+//! IPC are stalling the CPU pipeline, and are the first candidates for
+//! microarchitectural optimization.
+
+use sample::Sample;
+use std::collections::HashMap;
+
+
+/// Number of functions which are displayed in the report
+const NUM_REPORTED_FUNCTIONS: usize = 10;
+
+/// Number of DSOs which are displayed in the report
+const NUM_REPORTED_DSOS: usize = 5;
+
+/// IPC below which a hot function is considered to be stalling
+const LOW_IPC: f64 = 0.5;
+
+/// Fraction of all cycles that a function must take to be considered hot
+const HOT_FUNCTION_CYCLES: f64 = 0.01;
+
+
+/// Cycles and instructions of each leaf function
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct IpcStatistics {
+    /// Counts of each leaf function, by DSO and symbol name
+    pub functions: HashMap<String, HashMap<String, IpcCounts>>,
+}
+//
+impl IpcStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a new sample. Samples of other events than cycles and
+    /// instructions, and samples without a stack trace, are ignored.
+    pub fn record(&mut self, sample: &Sample) {
+        let is_cycles = match sample.event_base_name() {
+            Some("cycles") | Some("cpu-cycles") => true,
+            Some("instructions") => false,
+            _ => return,
+        };
+        let leaf = match sample.frames().next() {
+            Some(leaf) => leaf,
+            None => return,
+        };
+        let period = sample.period().unwrap_or(1);
+        let symbols = match self.functions.get_mut(leaf.dso) {
+            Some(symbols) => symbols,
+            None => self.functions.entry(leaf.dso.to_owned()).or_default(),
+        };
+        let counts = match symbols.get_mut(leaf.symbol) {
+            Some(counts) => counts,
+            None => symbols.entry(leaf.symbol.to_owned()).or_default(),
+        };
+        if is_cycles {
+            counts.cycles = counts.cycles.saturating_add(period);
+        } else {
+            counts.instructions = counts.instructions.saturating_add(period);
+        }
+    }
+
+    /// Print the IPC of the profile, of the DSOs and of the functions which
+    /// took the most cycles, flagging hot functions with a low IPC
+    pub fn print_report(&self) {
+        let mut total = IpcCounts::default();
+        let mut dsos = self.functions.iter().map(|(dso, symbols)| {
+            let mut counts = IpcCounts::default();
+            for symbol_counts in symbols.values() {
+                counts.merge(symbol_counts);
+            }
+            total.merge(&counts);
+            (dso, counts)
+        }).collect::<Vec<_>>();
+        if total.cycles == 0 || total.instructions == 0 {
+            println!("No IPC (the profile must sample both the cycles and \
+                      instructions events)");
+            return;
+        }
+        println!("Instructions per cycle: {:.2} overall", total.ipc());
+
+        dsos.sort_by(|a, b| b.1.cycles.cmp(&a.1.cycles).then(a.0.cmp(b.0)));
+        println!("{:>7} {:>6}  DSO", "Cycles", "IPC");
+        for (dso, counts) in dsos.into_iter().take(NUM_REPORTED_DSOS) {
+            Self::print_counts(&counts, &total, dso, false);
+        }
+
+        let mut functions = self.functions.iter().flat_map(|(dso, symbols)| {
+            symbols.iter().map(move |(symbol, counts)| (symbol, dso, counts))
+        }).collect::<Vec<_>>();
+        functions.sort_by(|a, b| {
+            b.2.cycles.cmp(&a.2.cycles).then(a.0.cmp(b.0)).then(a.1.cmp(b.1))
+        });
+        println!("{:>7} {:>6}  Function", "Cycles", "IPC");
+        for (symbol, dso, counts) in functions.into_iter().take(NUM_REPORTED_FUNCTIONS) {
+            let name = format!("{} ({})", symbol, dso);
+            Self::print_counts(counts, &total, &name, true);
+        }
+    }
+
+    /// Print one line of an IPC table, flagging low IPC if requested
+    fn print_counts(counts: &IpcCounts, total: &IpcCounts, name: &str, flag: bool) {
+        let cycles = counts.cycles as f64 / total.cycles as f64;
+        let ipc = match counts.instructions {
+            0 => "-".to_owned(),
+            _ => format!("{:.2}", counts.ipc()),
+        };
+        let low_ipc = counts.instructions > 0 && counts.ipc() < LOW_IPC;
+        let warning = if flag && low_ipc && cycles >= HOT_FUNCTION_CYCLES {
+            " [warning]"
+        } else {
+            ""
+        };
+        println!("{:>6.1}% {:>6}  {}{}", 100.0 * cycles, ipc, name, warning);
+    }
+}
+///
+///
+/// Estimated cycles and instructions, i.e. the sum of the sample periods of
+/// each event
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct IpcCounts {
+    pub cycles: u64,
+    pub instructions: u64,
+}
+//
+impl IpcCounts {
+    /// Add the counts of another function to these
+    fn merge(&mut self, other: &Self) {
+        self.cycles = self.cycles.saturating_add(other.cycles);
+        self.instructions = self.instructions.saturating_add(other.instructions);
+    }
+
+    /// Instructions per cycle
+    pub fn ipc(&self) -> f64 {
+        self.instructions as f64 / self.cycles.max(1) as f64
+    }
+}
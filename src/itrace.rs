@@ -0,0 +1,217 @@
+//! Aggregation of the branch and instruction records which perf script decodes
+//! from hardware traces, such as Intel PT recordings scripted with --itrace=b
+//! (one record per taken branch) or --itrace=i (one record per instruction)
+//!
+//! Branch records are aggregated per branch, and consecutive branches of the
+//! same thread delimit the basic blocks that it executed. Decode errors, which
+//! perf script reports inline, tell where the trace is incomplete.
+
+use sample::{self, Sample, StackFrame};
+use std::collections::HashMap;
+
+
+/// Number of basic blocks, branches and instructions which are displayed in
+/// the report
+const NUM_REPORTED_LOCATIONS: usize = 10;
+
+
+/// Branches, basic blocks and instructions of a hardware trace
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct BranchTraceStatistics {
+    /// Number of branch and instruction records
+    pub num_branches: usize,
+    pub num_instructions: usize,
+
+    /// Number of times each branch was taken, by source and target address
+    pub branches: HashMap<(u64, u64), usize>,
+
+    /// Number of times each basic block was executed, by start address and
+    /// address of its final branch
+    pub blocks: HashMap<(u64, u64), usize>,
+
+    /// Number of times each instruction was executed, by address
+    pub instructions: HashMap<u64, usize>,
+
+    /// Location of each traced address, as "symbol (dso)"
+    pub locations: HashMap<u64, String>,
+
+    /// Number of decode errors, by error message
+    pub errors: HashMap<String, usize>,
+
+    /// Start address of the basic block which each thread is executing, by TID
+    pub block_starts: HashMap<u32, u64>,
+}
+//
+impl BranchTraceStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a new sample. Only branch and instruction records, and
+    /// decode errors, are taken into account.
+    pub fn record(&mut self, sample: &Sample) {
+        if sample::is_trace_error(sample.header) {
+            self.record_error(&String::from_utf8_lossy(sample.header));
+            return;
+        }
+        if !sample::is_trace_record(sample.header) {
+            return;
+        }
+        let payload = match sample.event_payload() {
+            Some(payload) => payload,
+            None => return,
+        };
+        let (source, target) = match payload.split_once(" => ") {
+            Some((source, target)) => (source, Some(target)),
+            None => (payload, None),
+        };
+        let source = match self.locate(source) {
+            Some(source) => source,
+            None => return,
+        };
+        let target = match target {
+            Some(target) => match self.locate(target) {
+                Some(target) => target,
+                None => return,
+            },
+            None => {
+                self.num_instructions += 1;
+                *self.instructions.entry(source).or_default() += 1;
+                return;
+            },
+        };
+        self.num_branches += 1;
+        *self.branches.entry((source, target)).or_default() += 1;
+
+        // Trace starts and ends are printed as branches from or to address 0
+        let tid = match sample.tid() {
+            Some(tid) => tid,
+            None => return,
+        };
+        let start = if target != 0 {
+            self.block_starts.insert(tid, target)
+        } else {
+            self.block_starts.remove(&tid)
+        };
+        if let Some(start) = start {
+            if source != 0 && source >= start {
+                *self.blocks.entry((start, source)).or_default() += 1;
+            }
+        }
+    }
+
+    /// Account for a decode error. The block which the thread was executing is
+    /// forgotten, since the trace has a hole from that point on.
+    fn record_error(&mut self, line: &str) {
+        let mut columns = line.split_whitespace();
+        let tid = columns.by_ref().skip_while(|&column| column != "tid")
+                                  .nth(1)
+                                  .and_then(|tid| tid.parse().ok());
+        match tid {
+            Some(tid) => { self.block_starts.remove(&tid); },
+            None => self.block_starts.clear(),
+        }
+        let message = match line.find("code ") {
+            Some(start) => line[start..].split_once(": ")
+                                        .map_or("", |(_, message)| message)
+                                        .trim(),
+            None => "",
+        };
+        let message = if message.is_empty() { "Unknown error" } else { message };
+        match self.errors.get_mut(message) {
+            Some(count) => *count += 1,
+            None => { self.errors.insert(message.to_owned(), 1); },
+        }
+    }
+
+    /// Decode the address of one side of a trace record, which looks like
+    /// "[flags] <ip> <symbol> (<dso>)", and remember its location
+    fn locate(&mut self, text: &str) -> Option<u64> {
+        let ip = text.split_whitespace().find(|column| {
+            u64::from_str_radix(column, 16).is_ok()
+        })?;
+        let ip_start = ip.as_ptr() as usize - text.as_ptr() as usize;
+        let frame = StackFrame::parse(&text[ip_start..])?;
+        let address = u64::from_str_radix(frame.ip, 16).ok()?;
+        if address != 0 && !self.locations.contains_key(&address) {
+            self.locations.insert(address, format!("{} ({})", frame.symbol, frame.dso));
+        }
+        Some(address)
+    }
+
+    /// Location of an address, or the address itself if it is unknown
+    fn location(&self, address: u64) -> String {
+        self.locations.get(&address)
+                      .cloned()
+                      .unwrap_or_else(|| format!("{:#x}", address))
+    }
+
+    /// Print the hottest basic blocks, branches and instructions, and the
+    /// decode errors
+    pub fn print_report(&self) {
+        let num_errors = self.errors.values().sum::<usize>();
+        if self.num_branches + self.num_instructions + num_errors == 0 {
+            println!("No hardware trace record (decode e.g. Intel PT traces with perf \
+                      script --itrace=b or --itrace=i)");
+            return;
+        }
+        println!("Hardware trace: {} branches, {} instructions, {} decode errors",
+                 self.num_branches,
+                 self.num_instructions,
+                 num_errors);
+
+        let num_block_runs = self.blocks.values().sum::<usize>();
+        if num_block_runs > 0 {
+            let mut blocks = self.blocks.iter().collect::<Vec<_>>();
+            blocks.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            println!("Hottest basic blocks:");
+            for (&(start, end), &count) in blocks.into_iter()
+                                                 .take(NUM_REPORTED_LOCATIONS) {
+                println!("- {:#x}-{:#x} {}: {} runs ({:.1}%)",
+                         start,
+                         end,
+                         self.location(start),
+                         count,
+                         100.0 * count as f64 / num_block_runs as f64);
+            }
+        }
+
+        if self.num_branches > 0 {
+            let mut branches = self.branches.iter().collect::<Vec<_>>();
+            branches.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            println!("Hottest branches:");
+            for (&(source, target), &count) in branches.into_iter()
+                                                       .take(NUM_REPORTED_LOCATIONS) {
+                println!("- {} => {}: {} ({:.1}%)",
+                         self.location(source),
+                         self.location(target),
+                         count,
+                         100.0 * count as f64 / self.num_branches as f64);
+            }
+        }
+
+        if self.num_instructions > 0 {
+            let mut instructions = self.instructions.iter().collect::<Vec<_>>();
+            instructions.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            println!("Hottest instructions:");
+            for (&address, &count) in instructions.into_iter()
+                                                  .take(NUM_REPORTED_LOCATIONS) {
+                println!("- {:#x} {}: {} ({:.1}%)",
+                         address,
+                         self.location(address),
+                         count,
+                         100.0 * count as f64 / self.num_instructions as f64);
+            }
+        }
+
+        if num_errors > 0 {
+            let mut errors = self.errors.iter().collect::<Vec<_>>();
+            errors.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            println!("Decode errors, the trace has holes:");
+            for (message, count) in errors {
+                println!("- {}: {} [warning]", message, count);
+            }
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! JVM-specific analysis, which tells apart the samples taken in interpreted,
+//! JIT-compiled and native code
+
+use runtime;
+use sample::{Sample, StackFrame};
+
+
+/// Where a JVM was executing code when a sample was taken
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum JvmCodeKind {
+    /// The bytecode interpreter was running
+    Interpreted,
+
+    /// Code from the JIT compiler, which perf maps via /tmp/perf-<pid>.map
+    JitCompiled,
+
+    /// Native code (the JVM itself, JNI libraries, the kernel...)
+    Native,
+}
+//
+impl JvmCodeKind {
+    /// Tell where the leaf frame of a sample was executing
+    pub fn of_frame(frame: &StackFrame) -> Self {
+        if frame.symbol == "Interpreter" || frame.symbol.starts_with("Interpreter ") {
+            JvmCodeKind::Interpreted
+        } else if is_perf_map(frame.dso) || is_jitdump_dso(frame.dso) {
+            JvmCodeKind::JitCompiled
+        } else {
+            JvmCodeKind::Native
+        }
+    }
+}
+
+
+/// Truth that a DSO is a perf map file, which JITs use to tell perf about the
+/// code that they generated
+pub fn is_perf_map(dso: &str) -> bool {
+    dso.starts_with("/tmp/perf-") && dso.ends_with(".map")
+}
+
+/// Truth that a DSO was generated by perf inject --jit from a jitdump file,
+/// which holds one function of JIT-compiled code (e.g. "jitted-1234-56.so")
+pub fn is_jitdump_dso(dso: &str) -> bool {
+    let file_name = runtime::dso_file_name(dso);
+    file_name.starts_with("jitted-") && file_name.ends_with(".so")
+}
+
+/// PID of the process whose JIT compiler generated the code of a DSO, if the
+/// DSO is a perf map or was generated from a jitdump file
+pub fn jit_pid(dso: &str) -> Option<u32> {
+    if is_perf_map(dso) {
+        dso["/tmp/perf-".len()..dso.len() - ".map".len()].parse().ok()
+    } else if is_jitdump_dso(dso) {
+        let file_name = runtime::dso_file_name(dso);
+        file_name["jitted-".len()..].split('-').next()?.parse().ok()
+    } else {
+        None
+    }
+}
+
+
+/// Breakdown of the samples from a JVM workload
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct JvmStatistics {
+    pub num_interpreted: usize,
+    pub num_jit_compiled: usize,
+    pub num_native: usize,
+    pub num_compiler_thread: usize,
+}
+//
+impl JvmStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a new sample. Samples without a stack trace are ignored.
+    pub fn record(&mut self, sample: &Sample) {
+        let leaf_frame = match sample.frames().next() {
+            Some(frame) => frame,
+            None => return,
+        };
+        match JvmCodeKind::of_frame(&leaf_frame) {
+            JvmCodeKind::Interpreted => self.num_interpreted += 1,
+            JvmCodeKind::JitCompiled => self.num_jit_compiled += 1,
+            JvmCodeKind::Native => self.num_native += 1,
+        }
+
+        // Perf truncates thread names to 15 characters, hence the odd spelling
+        const COMPILER_THREAD_SUFFIX: &str = " CompilerThre";
+        let header = sample.header_text();
+        let comm = header.trim_start();
+        if comm.starts_with('C') && comm[1..].trim_start_matches(char::is_numeric)
+                                               .starts_with(COMPILER_THREAD_SUFFIX)
+        {
+            self.num_compiler_thread += 1;
+        }
+    }
+
+    /// Print the JVM section of the report
+    pub fn print_report(&self) {
+        let total = self.num_interpreted + self.num_jit_compiled + self.num_native;
+        let percent = |count: usize| {
+            if total == 0 { 0.0 } else { 100.0 * count as f64 / total as f64 }
+        };
+        println!("JVM samples: {}", total);
+        println!("- Interpreted: {} ({:.1}%)",
+                 self.num_interpreted, percent(self.num_interpreted));
+        println!("- JIT-compiled: {} ({:.1}%)",
+                 self.num_jit_compiled, percent(self.num_jit_compiled));
+        println!("- Native: {} ({:.1}%)",
+                 self.num_native, percent(self.num_native));
+        println!("- Of which in JIT compiler threads: {}", self.num_compiler_thread);
+    }
+}
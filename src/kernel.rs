@@ -0,0 +1,90 @@
+//! Symbolization of kernel stack frames which perf left as "[unknown]", using
+//! a kallsyms dump or a vmlinux image provided by the user
+
+use arch::Arch;
+use sample::StackFrame;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use symbols::SymbolTable;
+
+
+/// Table of kernel symbols, sorted by address
+pub struct KernelSymbols {
+    symbols: SymbolTable,
+
+    /// Modules which the symbol table has symbols of
+    modules: BTreeSet<String>,
+}
+//
+impl KernelSymbols {
+    /// Load kernel symbols from a copy of /proc/kallsyms
+    pub fn from_kallsyms(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut symbols = Self::new(SymbolTable::parse(&text))?;
+        symbols.modules = kallsyms_modules(&text);
+        Ok(symbols)
+    }
+
+    /// Load kernel symbols from a vmlinux image
+    pub fn from_vmlinux(path: &Path) -> Result<Self> {
+        Self::new(SymbolTable::from_elf(path, &[])?)
+    }
+
+    /// Check that a kernel symbol table is usable
+    fn new(symbols: SymbolTable) -> Result<Self> {
+        // When kernel pointers are restricted, kallsyms is full of zeroes
+        if symbols.addresses_hidden() {
+            return Err(Error::new(ErrorKind::InvalidData,
+                                  "no usable kernel symbol address, the symbol \
+                                   table was probably dumped with \
+                                   kptr_restrict enabled"));
+        }
+        Ok(Self { symbols, modules: BTreeSet::new() })
+    }
+
+    /// Names of the kernel modules which have symbols in the table, which is
+    /// only known for kallsyms dumps
+    pub fn modules(&self) -> &BTreeSet<String> {
+        &self.modules
+    }
+
+    /// Name of the kernel function containing an instruction pointer, if any
+    pub fn lookup(&self, ip: u64) -> Option<&str> {
+        self.symbols.lookup(ip)
+    }
+
+    /// Fill in the symbol of a kernel stack frame that perf left unresolved.
+    /// Other stack frames are returned as is.
+    pub fn resolve<'a>(&'a self,
+                       frame: StackFrame<'a>,
+                       arch: Arch) -> StackFrame<'a> {
+        if !(arch.is_kernel_frame(&frame) && frame.is_unknown_symbol()) {
+            return frame;
+        }
+        let symbol = u64::from_str_radix(frame.ip, 16).ok()
+                                                      .and_then(|ip| self.lookup(ip));
+        match symbol {
+            Some(symbol) => StackFrame {
+                symbol,
+                dso: match frame.dso {
+                    "[unknown]" => "[kernel.kallsyms]",
+                    dso => dso,
+                },
+                ..frame
+            },
+            None => frame,
+        }
+    }
+}
+
+
+/// Names of the modules which a kallsyms dump lists symbols of, which it does
+/// in a bracketed fourth column (e.g. "ffffffffc0a01000 t xfs_init [xfs]")
+fn kallsyms_modules(text: &str) -> BTreeSet<String> {
+    text.lines().filter_map(|line| {
+        let module = line.split_whitespace().nth(3)?;
+        Some(module.trim_start_matches('[').trim_end_matches(']').to_owned())
+    }).collect()
+}
@@ -0,0 +1,161 @@
+//! Breakdown of the profile by phase of the profiled program, as marked by
+//! user-chosen events such as USDT probes (e.g. "sdt_app:request_start" with
+//! a request ID argument, or "sdt_jvm:gc_begin")
+//!
+//! Each hit of a label event labels the samples of the thread which hit it,
+//! until the thread hits another label event or an end event. The label is the
+//! name of the event followed by the arguments of the hit, so that e.g. each
+//! request ID gets its own label.
+
+use analysis::SampleCategory;
+use probes::ProbeHit;
+use sample::Sample;
+use std::collections::HashMap;
+
+
+/// Number of labels which are displayed in the report
+const NUM_REPORTED_LABELS: usize = 10;
+
+/// Number of leaf functions which are displayed for each label
+const NUM_REPORTED_FUNCTIONS: usize = 3;
+
+
+/// Samples taken under each label
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct LabelStatistics {
+    /// Events which start labeling samples
+    pub label_events: Vec<String>,
+
+    /// Events which stop labeling samples
+    pub end_events: Vec<String>,
+
+    /// Samples of each label, by label, or by an empty string for samples
+    /// which were taken without a label
+    pub labels: HashMap<String, LabelSamples>,
+
+    /// Label which is active in each thread, by TID
+    pub active_labels: HashMap<u32, String>,
+}
+//
+impl LabelStatistics {
+    /// Start with all counters at zero, with samples labeled by some events
+    pub fn new(label_events: Vec<String>, end_events: Vec<String>) -> Self {
+        Self {
+            label_events,
+            end_events,
+            ..Self::default()
+        }
+    }
+
+    /// Account for a newly classified sample, which either changes the label
+    /// of its thread or is counted under the active label
+    pub fn record(&mut self, sample: &Sample, category: &SampleCategory) {
+        let event = sample.event().unwrap_or("");
+        if self.label_events.iter().any(|label_event| label_event == event) {
+            if let Some(tid) = sample.tid() {
+                self.active_labels.insert(tid, label_of(sample, event));
+            }
+            return;
+        }
+        if self.end_events.iter().any(|end_event| end_event == event) {
+            if let Some(tid) = sample.tid() {
+                self.active_labels.remove(&tid);
+            }
+            return;
+        }
+        let active_labels = &self.active_labels;
+        let label = sample.tid()
+                          .and_then(|tid| active_labels.get(&tid))
+                          .map_or("", String::as_str);
+        let samples = match self.labels.get_mut(label) {
+            Some(samples) => samples,
+            None => self.labels.entry(label.to_owned()).or_default(),
+        };
+        samples.num_samples += 1;
+        let key = category.key();
+        match samples.categories.get_mut(key) {
+            Some(count) => *count += 1,
+            None => { samples.categories.insert(key.to_owned(), 1); },
+        }
+        if let Some(leaf) = sample.frames().next() {
+            let function = format!("{} ({})", leaf.symbol, leaf.dso);
+            *samples.functions.entry(function).or_insert(0) += 1;
+        }
+    }
+
+    /// Print the labels with the most samples, and for each of them how its
+    /// samples were classified and which functions were the hottest
+    pub fn print_report(&self) {
+        let num_labeled = self.labels.iter()
+                                     .filter(|(label, _)| !label.is_empty())
+                                     .map(|(_, samples)| samples.num_samples)
+                                     .sum::<usize>();
+        if num_labeled == 0 {
+            println!("No labeled sample (events {} were never hit before a sample)",
+                     self.label_events.join(", "));
+            return;
+        }
+        let num_samples = self.labels.values().map(|s| s.num_samples).sum::<usize>();
+        let mut labels = self.labels.iter().collect::<Vec<_>>();
+        labels.sort_by(|a, b| b.1.num_samples.cmp(&a.1.num_samples).then(a.0.cmp(b.0)));
+        println!("Samples per label: {} labels", labels.len());
+        for (label, samples) in labels.into_iter().take(NUM_REPORTED_LABELS) {
+            let percent = |count: usize| {
+                100.0 * count as f64 / samples.num_samples.max(1) as f64
+            };
+            let num_normal = samples.categories.get("normal").cloned().unwrap_or(0);
+            println!("- {}: {} samples ({:.1}%), {:.1}% normal",
+                     if label.is_empty() { "(no label)" } else { label },
+                     samples.num_samples,
+                     100.0 * samples.num_samples as f64 / num_samples as f64,
+                     percent(num_normal));
+            let mut categories = samples.categories.iter()
+                                                   .filter(|(key, _)| *key != "normal")
+                                                   .collect::<Vec<_>>();
+            categories.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            for (key, &count) in categories {
+                println!("  - {}: {:.1}%", key, percent(count));
+            }
+            let mut functions = samples.functions.iter().collect::<Vec<_>>();
+            functions.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            for (function, &count) in functions.into_iter().take(NUM_REPORTED_FUNCTIONS) {
+                println!("  - in {}: {:.1}%", function, percent(count));
+            }
+        }
+    }
+}
+///
+///
+/// Samples taken under one label
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct LabelSamples {
+    /// Number of samples
+    pub num_samples: usize,
+
+    /// Number of samples of each category, by category key
+    pub categories: HashMap<String, usize>,
+
+    /// Number of samples of each leaf function, as "symbol (dso)"
+    pub functions: HashMap<String, usize>,
+}
+
+
+/// Label that a hit of a label event gives to the samples of its thread: the
+/// name of the event, followed by the arguments of the hit if there are any
+fn label_of(sample: &Sample, event: &str) -> String {
+    let name = event.split_once(':').map_or(event, |(_, name)| name);
+    let arguments = match ProbeHit::parse(sample) {
+        Some(hit) => {
+            hit.arguments.iter()
+                         .map(|(name, value)| format!("{}={}", name, value))
+                         .collect::<Vec<_>>()
+                         .join(" ")
+        },
+        None => sample.event_payload().unwrap_or("").to_owned(),
+    };
+    if arguments.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{} {}", name, arguments)
+    }
+}
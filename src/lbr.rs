@@ -0,0 +1,97 @@
+//! Reconciliation of DWARF call chains with the branch stack, for recordings
+//! which have both (e.g. `perf record --call-graph dwarf -j any_call,u` and
+//! `perf script -F +brstacksym`).
+//!
+//! DWARF stacks get truncated when the stack is deeper than perf's stack dump,
+//! but the last branch records (LBR) may still remember the calls which led to
+//! the outermost function that could be unwound. Each call record links a call
+//! site (in the caller) to the entry of the callee, so a truncated stack can be
+//! extended by walking from callees to callers until the records run out.
+
+use sample::Sample;
+
+
+/// Branch record, as printed by perf script's brstacksym field
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct BranchRecord<'a> {
+    /// Function that the branch originates from
+    from: &'a str,
+
+    /// DSO that the branch originates from, if printed
+    from_dso: Option<&'a str>,
+
+    /// Function that the branch goes to
+    to: &'a str,
+}
+
+
+/// Extend a truncated DWARF stack with the branch stack of its sample. The
+/// extended sample is built in a caller-provided buffer, so that it can be
+/// reused across samples. Returns None if the branch stack does not help.
+pub fn stitch<'b>(sample: &Sample, buffer: &'b mut Vec<u8>) -> Option<Sample<'b>> {
+    // Find the outermost function which DWARF unwinding could figure out
+    let frames = sample.frames().collect::<Vec<_>>();
+    let num_known = frames.iter().rposition(|frame| frame.symbol != "[unknown]")? + 1;
+    let mut current = frames[num_known - 1].symbol;
+
+    // Walk up the branch stack, from callees to callers. Each record is used
+    // at most once, so that recursion cannot send us into an endless loop.
+    let header = String::from_utf8_lossy(sample.header);
+    let mut records = header.split_whitespace()
+                            .filter_map(parse_branch_record)
+                            .collect::<Vec<_>>();
+    let mut callers = Vec::new();
+    while let Some(idx) = records.iter().position(|record| record.to == current) {
+        let record = records.swap_remove(idx);
+        callers.push(record);
+        current = record.from;
+    }
+    if callers.is_empty() {
+        return None;
+    }
+
+    // Build the extended sample, where the frames from the branch stack have
+    // no known address
+    sample.truncated(num_known, buffer);
+    for caller in callers {
+        let dso = caller.from_dso.unwrap_or("[unknown]");
+        let line = format!("\t               0 {} ({})\n", caller.from, dso);
+        buffer.extend_from_slice(line.as_bytes());
+    }
+    Some(Sample::from_raw(buffer, sample.header.len()))
+}
+
+
+/// Parse a branch record of the form `from/to/flags...`, where from and to are
+/// of the form `symbol+offset`, optionally followed by a parenthesized DSO.
+fn parse_branch_record(token: &str) -> Option<BranchRecord<'_>> {
+    let (from, from_dso, rest) = parse_location(token)?;
+    let (to, _to_dso, rest) = parse_location(rest.strip_prefix('/')?)?;
+    let flags = rest.strip_prefix('/')?;
+    if !flags.starts_with(['P', 'M', '-']) {
+        return None;
+    }
+    Some(BranchRecord { from, from_dso, to })
+}
+
+
+/// Parse a branch location, returning the function, the DSO if any, and the
+/// rest of the record
+fn parse_location(text: &str) -> Option<(&str, Option<&str>, &str)> {
+    let end = text.find(['/', '('])?;
+    let (location, mut rest) = text.split_at(end);
+    let mut dso = None;
+    if rest.starts_with('(') {
+        let dso_end = rest.find(')')?;
+        dso = Some(&rest[1..dso_end]);
+        rest = &rest[dso_end + 1..];
+    }
+    let symbol = match location.rfind("+0x") {
+        Some(offset) => &location[..offset],
+        None => location,
+    };
+    if symbol.is_empty() || symbol == "[unknown]" {
+        return None;
+    }
+    Some((symbol, dso, rest))
+}
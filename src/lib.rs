@@ -0,0 +1,90 @@
+//! Library side of perf-script-analyze: decoding of perf script's output into
+//! samples and stack frames, which the main program then analyzes
+
+extern crate bincode;
+extern crate libloading;
+extern crate memchr;
+extern crate memmap2;
+extern crate parquet;
+extern crate regex;
+extern crate rhai;
+extern crate rusqlite;
+extern crate serde;
+#[macro_use] extern crate serde_derive;
+extern crate serde_json;
+
+pub mod addresses;
+pub mod analysis;
+pub mod annotate;
+pub mod arch;
+pub mod archive;
+pub mod bpf;
+pub mod buildid;
+pub mod c2c;
+pub mod cache;
+pub mod callsites;
+pub mod cgroups;
+pub mod checkpoint;
+pub mod classifiers;
+pub mod clean;
+pub mod config;
+pub mod coverage;
+pub mod data;
+pub mod deleted;
+pub mod dump;
+pub mod dwarf;
+pub mod elf;
+pub mod energy;
+pub mod entropy;
+pub mod filter;
+pub mod format;
+pub mod ftrace;
+pub mod gc;
+pub mod gpu;
+pub mod guest;
+pub mod hotspots;
+pub mod index;
+pub mod interrupts;
+pub mod intervals;
+pub mod ipc;
+pub mod itrace;
+pub mod jvm;
+pub mod kernel;
+pub mod labels;
+pub mod lbr;
+pub mod mapped;
+pub mod metadata;
+pub mod metrics;
+pub mod modules;
+pub mod node;
+pub mod otlp;
+pub mod paths;
+pub mod periods;
+pub mod plugin;
+pub mod preflight;
+pub mod probes;
+pub mod progress;
+pub mod python;
+pub mod query;
+pub mod redact;
+pub mod repl;
+pub mod repair;
+pub mod runtime;
+pub mod sample;
+pub mod script;
+pub mod significance;
+pub mod smt;
+pub mod spikes;
+pub mod stats;
+pub mod subsample;
+pub mod symbolization;
+pub mod symbols;
+pub mod tables;
+pub mod tasks;
+pub mod tee;
+pub mod threads;
+pub mod throttle;
+pub mod topdown;
+pub mod truncate;
+pub mod unwinding;
+pub mod wasm;
@@ -1,363 +1,2518 @@
 //! This program wraps perf script and looks for fishy things in its output
 
+extern crate perf_script_analyze;
+extern crate regex;
+
+use perf_script_analyze::addresses::{AddressStatistics, MappingSizes};
+use perf_script_analyze::analysis::{SampleAnalyzer, SampleCategory};
+use perf_script_analyze::annotate::SampleAnnotator;
+use perf_script_analyze::arch::Arch;
+use perf_script_analyze::archive::{ArchiveMetadata, ArchiveSamples, ArchiveWriter};
+use perf_script_analyze::bpf::BpfStatistics;
+use perf_script_analyze::buildid;
+use perf_script_analyze::c2c::SharingStatistics;
+use perf_script_analyze::cache::CacheStatistics;
+use perf_script_analyze::callsites::CallSites;
+use perf_script_analyze::cgroups::CgroupStatistics;
+use perf_script_analyze::checkpoint::{AnalysisOptions, Checkpoint, InputIdentity};
+use perf_script_analyze::clean::{self, CleanOutput};
+use perf_script_analyze::config::{Config, Pattern};
+use perf_script_analyze::coverage::CoverageStatistics;
+use perf_script_analyze::data::DataAddressStatistics;
+use perf_script_analyze::deleted::LiveMappings;
+use perf_script_analyze::dump::AnomalyDump;
+use perf_script_analyze::dwarf::DwarfSizeStatistics;
+use perf_script_analyze::energy::EnergyStatistics;
+use perf_script_analyze::entropy::EntropyStatistics;
+use perf_script_analyze::filter::{Filter, FrameFilter};
+use perf_script_analyze::format::{NormalizedSamples, PerfFormat};
+use perf_script_analyze::ftrace::FunctionTraceStatistics;
+use perf_script_analyze::gc::GcStatistics;
+use perf_script_analyze::gpu::GpuStatistics;
+use perf_script_analyze::guest::GuestStatistics;
+use perf_script_analyze::hotspots::{FunctionStatistics, LeafStatistics, SkidCorrection};
+use perf_script_analyze::index::{self, SampleIndex, SampleLocator};
+use perf_script_analyze::interrupts::InterruptStatistics;
+use perf_script_analyze::intervals::IntervalStatistics;
+use perf_script_analyze::ipc::IpcStatistics;
+use perf_script_analyze::itrace::BranchTraceStatistics;
+use perf_script_analyze::jvm::JvmStatistics;
+use perf_script_analyze::kernel::KernelSymbols;
+use perf_script_analyze::labels::LabelStatistics;
+use perf_script_analyze::lbr;
+use perf_script_analyze::mapped::{InputSamples, MappedSamples};
+use perf_script_analyze::metadata::RecordingMetadata;
+use perf_script_analyze::metrics::MetricsServer;
+use perf_script_analyze::modules::ModuleStatistics;
+use perf_script_analyze::node;
+use perf_script_analyze::otlp::ProfileAggregator;
+use perf_script_analyze::paths::PathMap;
+use perf_script_analyze::periods::PeriodStatistics;
+use perf_script_analyze::plugin::{Plugin, PluginClassifier};
+use perf_script_analyze::preflight::SystemSettings;
+use perf_script_analyze::probes::ProbeStatistics;
+use perf_script_analyze::progress::Progress;
+use perf_script_analyze::python::PythonStatistics;
+use perf_script_analyze::query::SampleDatabase;
+use perf_script_analyze::redact;
+use perf_script_analyze::repair::StackRepair;
+use perf_script_analyze::repl::Repl;
+use perf_script_analyze::runtime::Runtime;
+use perf_script_analyze::sample::{self, PerfSamples, SampleSource};
+use perf_script_analyze::script::SampleScript;
+use perf_script_analyze::significance::Significance;
+use perf_script_analyze::smt::{SmtStatistics, SmtTopology};
+use perf_script_analyze::spikes::SpikeStatistics;
+use perf_script_analyze::stats::Statistics;
+use perf_script_analyze::subsample::SampleRate;
+use perf_script_analyze::symbolization::SymbolizationStatistics;
+use perf_script_analyze::tables::TableExport;
+use perf_script_analyze::tasks::{self, TaskStatistics};
+use perf_script_analyze::tee::TeeReader;
+use perf_script_analyze::threads::ThreadStatistics;
+use perf_script_analyze::throttle::ThrottleStatistics;
+use perf_script_analyze::topdown::TopDownStatistics;
+use perf_script_analyze::truncate::{StackTruncation, TruncatedFrame};
+use perf_script_analyze::unwinding::UnwindStatistics;
+use perf_script_analyze::wasm::WasmStatistics;
+use regex::Regex;
+use std::borrow::Cow;
 use std::collections::HashSet;
 use std::env;
-use std::io::{BufRead, BufReader, Read, Result};
-use std::process::{Command, Stdio};
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+use std::rc::Rc;
+
+
+/// Exit code used when the input could not be opened or read
+const EXIT_BAD_INPUT: i32 = 2;
+
+/// Exit code used when perf could not be started at all
+const EXIT_PERF_NOT_STARTED: i32 = 3;
+
+/// Exit code used when perf script reported a failure
+const EXIT_PERF_FAILED: i32 = 4;
+
+/// Exit code used when the results could not be exported
+const EXIT_EXPORT_FAILED: i32 = 5;
+
+
+/// Command-line options of the analyzer. Any argument which is not recognized
+/// here is passed down to perf script.
+struct Options {
+    /// Run this SQL query on the samples instead of printing the usual reports
+    query: Option<String>,
+
+    /// Print this sample with its analysis instead of analyzing all samples
+    show: Option<SampleLocator>,
+
+    /// Explore the samples of the archive interactively instead of analyzing
+    /// them all
+    repl: bool,
+
+    /// Print the query's result as CSV instead of an aligned table
+    csv: bool,
+
+    /// Reprint perf script's output with annotations instead of the usual
+    /// reports
+    annotate: bool,
+
+    /// Read perf script output from this file instead of running perf script
+    input_script: Option<PathBuf>,
+
+    /// Index the samples of the input file in a first pass, so that later
+    /// runs can go straight to specific samples
+    index: bool,
+
+    /// Only print the samples with these numbers, as located by the index of
+    /// the input file, instead of analyzing the whole file
+    show_samples: Option<Vec<RangeInclusive<usize>>>,
+
+    /// Read samples from an archive written by --save-analysis instead
+    load_analysis: Option<PathBuf>,
+
+    /// Save the analyzed samples and the results into this archive
+    save_analysis: Option<PathBuf>,
+
+    /// Export the analyzed samples as Parquet tables into this directory
+    parquet_dir: Option<PathBuf>,
+
+    /// Dynamic libraries providing custom classifiers and reports
+    plugins: Vec<PathBuf>,
+
+    /// Rhai script which gets to see every sample
+    script: Option<PathBuf>,
+
+    /// Copy perf script's output into this file while analyzing it
+    tee: Option<PathBuf>,
+
+    /// Write a copy of perf script's output without broken samples here
+    clean_output: Option<PathBuf>,
+
+    /// Hash command names, host names and non-system DSO paths in all outputs
+    redact: bool,
+
+    /// Extend truncated DWARF stacks using the branch stack, if recorded
+    stitch_lbr: bool,
+
+    /// Repair truncated stacks using recent complete stacks of the same thread
+    repair_stacks: bool,
+
+    /// Cut samples broken by a bad DSO at that DSO in exports, instead of
+    /// leaving them out or exporting them as they are
+    trim_bad_dsos: bool,
+
+    /// Collapse consecutive duplicate frames in exports and call trees
+    collapse_duplicate_frames: bool,
+
+    /// Strip V8's annotations from JavaScript symbols in exports and call
+    /// trees, keeping only function names
+    strip_v8_annotations: bool,
+
+    /// Periodically save the analysis state in this file, and resume from it
+    checkpoint: Option<PathBuf>,
+
+    /// Number of samples between two checkpoints
+    checkpoint_interval: usize,
+
+    /// Load advanced analysis settings from this configuration file
+    config: Option<PathBuf>,
+
+    /// Resolve unsymbolized kernel frames using this vmlinux image...
+    vmlinux: Option<PathBuf>,
+
+    /// ...or this copy of /proc/kallsyms
+    kallsyms: Option<PathBuf>,
+
+    /// Compare build-ids from this saved output of perf buildid-list...
+    buildid_list: Option<PathBuf>,
+
+    /// ...or from a run of perf buildid-list, with the DSOs on disk
+    check_build_ids: bool,
+
+    /// Check that frame addresses fit in the DSOs on disk
+    check_frame_addresses: bool,
+
+    /// Check that return addresses follow a call in the DSOs on disk
+    check_return_addresses: bool,
+
+    /// Where DSOs recorded inside of a container or chroot lie on this machine
+    path_map: PathMap,
+
+    /// CPU architecture that the profile was recorded on, if not autodetected
+    arch: Option<Arch>,
+
+    /// Layout of perf script's output, if not sniffed from the first samples
+    perf_format: Option<PerfFormat>,
+
+    /// Language runtimes whose root functions should be expected
+    runtimes: Vec<Runtime>,
+
+    /// Additional functions to be expected at the root of stack traces
+    root_funcs: Vec<String>,
+
+    /// Do not expect the built-in root functions
+    no_default_roots: bool,
+
+    /// Report which DSOs have the most unsymbolized frames
+    dso_report: bool,
+
+    /// Only analyze the samples which match this filter expression
+    filter: Option<Filter>,
+
+    /// Only analyze this fraction of the samples, for a fast preview
+    sample_rate: Option<SampleRate>,
+
+    /// Seed of the choice of the samples which are analyzed
+    sample_seed: u64,
+
+    /// Only analyze the samples whose stack trace passes these filters
+    frame_filters: Vec<FrameFilter>,
+
+    /// Report this many functions with the most samples
+    top_functions: Option<usize>,
+
+    /// Only take Normal samples into account in the function report
+    top_functions_normal_only: bool,
+
+    /// Correction of the function report's leaf frames for IP skid
+    skid_correction: SkidCorrection,
+
+    /// Report the leaf functions of each anomalous sample category
+    leaf_functions: bool,
+
+    /// Break the issue slots of leaf functions down into Top-Down categories
+    topdown: bool,
+
+    /// Report the instructions per cycle of leaf functions and DSOs
+    ipc_report: bool,
+
+    /// Rank the functions and DSOs which caused the most cache misses
+    cache_report: bool,
 
+    /// Report hot data regions and false sharing candidates
+    data_report: bool,
 
-/// Mechanism to extract individual samples from perf script's output
-struct PerfSamples<Input: Read> {
-    input: BufReader<Input>,
-    buffer: String,
-    header_len: usize,
-    last_line_len: Option<usize>,
+    /// Report contended cachelines from perf c2c record's loads and stores
+    c2c_report: bool,
+
+    /// Attribute the energy measured by energy events to sampled functions
+    energy_report: bool,
+
+    /// Report the time spent in GPU drivers and waiting for the GPU
+    gpu_report: bool,
+
+    /// Report how samples split between the host and virtual machine guests
+    guest_report: bool,
+
+    /// Report the time spent in each BPF program
+    bpf_report: bool,
+
+    /// Count the hits of dynamic probes and the time between them
+    probe_report: bool,
+
+    /// Aggregate the branches and basic blocks of a decoded hardware trace
+    itrace_report: bool,
+
+    /// Build call duration trees from function tracer events
+    ftrace_report: bool,
+
+    /// Report the kernel modules whose frames perf could not symbolize
+    module_report: bool,
+
+    /// Event marking latency spikes, whose preceding samples should be reported
+    spike_event: Option<String>,
+
+    /// Events which label the samples of their thread, e.g. USDT probes
+    label_events: Vec<String>,
+
+    /// Events which stop labeling the samples of their thread
+    label_end_events: Vec<String>,
+
+    /// Keep idle samples out of the function reports and exported profile
+    exclude_idle: bool,
+
+    /// Rank threads by stack trace quality
+    thread_report: bool,
+
+    /// Report the fraction of samples stolen by interrupts on each CPU
+    irq_report: bool,
+
+    /// Report the cores whose SMT siblings were busy at the same time
+    smt_report: bool,
+
+    /// SMT siblings of each core, if not autodetected
+    smt_siblings: Option<SmtTopology>,
+
+    /// Check the sampling periods of each event for signs of unreliability
+    period_report: bool,
+
+    /// Report how long each event was throttled, from throttle records
+    throttle_report: bool,
+
+    /// Histogram the time between consecutive samples of each CPU
+    interval_report: bool,
+
+    /// Report how much of their lifetime threads were sampled
+    coverage_report: bool,
+
+    /// Report how diverse the stacks of each process are
+    entropy_report: bool,
+
+    /// Rank cgroups by stack trace quality
+    cgroup_report: bool,
+
+    /// Recommend a stack dump size for --call-graph dwarf
+    dwarf_size_report: bool,
+
+    /// Break stack statistics down per event and unwinding mode
+    unwind_report: bool,
+
+    /// Aggregate unsymbolized frames by address ranges of this size
+    address_bucket: Option<u64>,
+
+    /// Maximal number of anomalous samples to be dumped per category
+    max_dump_per_category: Option<usize>,
+
+    /// Cap on the number of frames of printed and exported stacks
+    stack_truncation: StackTruncation,
+
+    /// Display confidence intervals of this level in the summary
+    confidence: Option<f64>,
+
+    /// How to deal with report entries which have too few samples
+    significance: Significance,
+
+    /// Serve Prometheus metrics on this address while the analysis runs
+    metrics_addr: Option<String>,
+
+    /// Push the aggregated profile to this OTLP/HTTP collector
+    otlp_endpoint: Option<String>,
+
+    /// Do not display the analysis progress
+    quiet: bool,
+
+    /// Do not check the system settings before running perf script
+    skip_preflight: bool,
+
+    /// Arguments which will be passed down to perf script
+    perf_args: Vec<String>,
 }
 //
-impl<Input: Read> PerfSamples<Input> {
-    /// Initialize with a Rust reader plugging into the output of perf script
-    /// (can be stdin, a pipe to a child process, a file... anything goes)
-    pub fn new(input: Input) -> Self {
-        Self {
-            input: BufReader::new(input),
-            buffer: String::new(),
-            header_len: 0,
-            last_line_len: None,
+impl Options {
+    /// Decode the command-line arguments. Everything after a "--" separator is
+    /// unconditionally passed down to perf script.
+    pub fn parse<I: Iterator<Item=String>>(args: I) -> Self {
+        let mut options = Self {
+            query: None,
+            show: None,
+            repl: false,
+            csv: false,
+            input_script: None,
+            index: false,
+            show_samples: None,
+            load_analysis: None,
+            save_analysis: None,
+            parquet_dir: None,
+            plugins: Vec::new(),
+            script: None,
+            tee: None,
+            clean_output: None,
+            redact: false,
+            stitch_lbr: false,
+            repair_stacks: false,
+            trim_bad_dsos: false,
+            collapse_duplicate_frames: false,
+            strip_v8_annotations: false,
+            annotate: false,
+            checkpoint: None,
+            checkpoint_interval: 1_000_000,
+            config: None,
+            vmlinux: None,
+            kallsyms: None,
+            buildid_list: None,
+            check_build_ids: false,
+            check_frame_addresses: false,
+            check_return_addresses: false,
+            path_map: PathMap::new(),
+            arch: None,
+            perf_format: None,
+            runtimes: Vec::new(),
+            root_funcs: Vec::new(),
+            no_default_roots: false,
+            dso_report: false,
+            filter: None,
+            sample_rate: None,
+            sample_seed: 0,
+            frame_filters: Vec::new(),
+            top_functions: None,
+            top_functions_normal_only: false,
+            skid_correction: SkidCorrection::None,
+            leaf_functions: false,
+            topdown: false,
+            ipc_report: false,
+            cache_report: false,
+            data_report: false,
+            c2c_report: false,
+            energy_report: false,
+            gpu_report: false,
+            guest_report: false,
+            bpf_report: false,
+            probe_report: false,
+            itrace_report: false,
+            ftrace_report: false,
+            module_report: false,
+            spike_event: None,
+            label_events: Vec::new(),
+            label_end_events: Vec::new(),
+            exclude_idle: false,
+            thread_report: false,
+            irq_report: false,
+            smt_report: false,
+            smt_siblings: None,
+            period_report: false,
+            throttle_report: false,
+            interval_report: false,
+            coverage_report: false,
+            entropy_report: false,
+            cgroup_report: false,
+            dwarf_size_report: false,
+            unwind_report: false,
+            address_bucket: None,
+            max_dump_per_category: None,
+            stack_truncation: StackTruncation::none(),
+            confidence: None,
+            significance: Significance::new(),
+            metrics_addr: None,
+            otlp_endpoint: None,
+            quiet: false,
+            skip_preflight: false,
+            perf_args: Vec::new(),
+        };
+
+        // The "query" subcommand takes the SQL query as its argument, whereas
+        // the "show" subcommand takes the sample to be shown as options, and
+        // the "repl" subcommand reads its commands from standard input
+        let mut args = args.peekable();
+        if args.peek().map(String::as_str) == Some("query") {
+            args.next();
+            options.query = Some(args.next().unwrap_or_else(|| {
+                eprintln!("Missing SQL query");
+                process::exit(1);
+            }));
+        }
+        let show = args.peek().map(String::as_str) == Some("show");
+        if show {
+            args.next();
+        }
+        if args.peek().map(String::as_str) == Some("repl") {
+            args.next();
+            options.repl = true;
         }
-    }
 
-    // Reset the reader's state, to be invoked when moving to a new sample.
-    fn reset(&mut self) {
-        self.buffer.clear();
-        self.header_len = 0;
-        self.last_line_len = None;
+        while let Some(arg) = args.next() {
+            // Support both the "--opt value" and "--opt=value" syntaxes
+            let (name, inline_value) = match arg.find('=') {
+                Some(pos) if arg.starts_with("--") => {
+                    (arg[..pos].to_owned(), Some(arg[pos+1..].to_owned()))
+                },
+                _ => (arg.clone(), None),
+            };
+            let mut value = |name: &str| {
+                inline_value.clone().or_else(|| args.next()).unwrap_or_else(|| {
+                    eprintln!("Missing value for option {}", name);
+                    process::exit(1);
+                })
+            };
+            match name.as_str() {
+                "--input-script" => {
+                    options.input_script = Some(value(&name).into());
+                },
+                "--index" => {
+                    options.index = true;
+                },
+                "--sample" => {
+                    let number = value(&name);
+                    options.show = match number.parse() {
+                        Ok(number) => Some(SampleLocator::Number(number)),
+                        Err(_) => {
+                            eprintln!("Invalid sample number: {}", number);
+                            process::exit(1);
+                        }
+                    };
+                },
+                "--at" => {
+                    let timestamp = value(&name);
+                    options.show = match timestamp.parse() {
+                        Ok(timestamp) => Some(SampleLocator::Timestamp(timestamp)),
+                        Err(_) => {
+                            eprintln!("Invalid timestamp: {}", timestamp);
+                            process::exit(1);
+                        }
+                    };
+                },
+                "--show-samples" => {
+                    match index::parse_sample_numbers(&value(&name)) {
+                        Ok(numbers) => options.show_samples = Some(numbers),
+                        Err(message) => {
+                            eprintln!("{}", message);
+                            process::exit(1);
+                        }
+                    }
+                },
+                "--load-analysis" => {
+                    options.load_analysis = Some(value(&name).into());
+                },
+                "--save-analysis" => {
+                    options.save_analysis = Some(value(&name).into());
+                },
+                "--csv" => {
+                    options.csv = true;
+                },
+                "--parquet-dir" => {
+                    options.parquet_dir = Some(value(&name).into());
+                },
+                "--plugin" => {
+                    options.plugins.push(value(&name).into());
+                },
+                "--script" => {
+                    options.script = Some(value(&name).into());
+                },
+                "--tee" => {
+                    options.tee = Some(value(&name).into());
+                },
+                "--clean-output" => {
+                    options.clean_output = Some(value(&name).into());
+                },
+                "--redact" => {
+                    options.redact = true;
+                },
+                "--stitch-lbr" => {
+                    options.stitch_lbr = true;
+                },
+                "--repair-stacks" => {
+                    options.repair_stacks = true;
+                },
+                "--trim-bad-dsos" => {
+                    options.trim_bad_dsos = true;
+                },
+                "--collapse-duplicate-frames" => {
+                    options.collapse_duplicate_frames = true;
+                },
+                "--strip-v8-annotations" => {
+                    options.strip_v8_annotations = true;
+                },
+                "--annotate" => {
+                    options.annotate = true;
+                },
+                "--checkpoint" => {
+                    options.checkpoint = Some(value(&name).into());
+                },
+                "--checkpoint-interval" => {
+                    let interval = value(&name);
+                    options.checkpoint_interval = match interval.parse() {
+                        Ok(n) if n > 0 => n,
+                        _ => {
+                            eprintln!("Invalid checkpoint interval: {}", interval);
+                            process::exit(1);
+                        }
+                    };
+                },
+                "--config" => {
+                    options.config = Some(value(&name).into());
+                },
+                "--vmlinux" => {
+                    options.vmlinux = Some(value(&name).into());
+                },
+                "--kallsyms" => {
+                    options.kallsyms = Some(value(&name).into());
+                },
+                "--buildid-list" => {
+                    options.buildid_list = Some(value(&name).into());
+                },
+                "--check-build-ids" => {
+                    options.check_build_ids = true;
+                },
+                "--check-frame-addresses" => {
+                    options.check_frame_addresses = true;
+                },
+                "--check-return-addresses" => {
+                    options.check_return_addresses = true;
+                },
+                "--sysroot" => {
+                    options.path_map.set_sysroot(value(&name));
+                },
+                "--path-map" => {
+                    let mapping = value(&name);
+                    if let Err(message) = options.path_map.add_mapping(&mapping) {
+                        eprintln!("{}", message);
+                        process::exit(1);
+                    }
+                },
+                "--arch" => {
+                    let arch = value(&name);
+                    match arch.parse() {
+                        Ok(arch) => options.arch = Some(arch),
+                        Err(message) => {
+                            eprintln!("{}", message);
+                            process::exit(1);
+                        }
+                    }
+                },
+                "--perf-format" => {
+                    let version = value(&name);
+                    match version.parse() {
+                        Ok(format) => options.perf_format = Some(format),
+                        Err(message) => {
+                            eprintln!("{}", message);
+                            process::exit(1);
+                        }
+                    }
+                },
+                "--runtime" => {
+                    let runtime = value(&name);
+                    match runtime.parse() {
+                        Ok(runtime) => options.runtimes.push(runtime),
+                        Err(message) => {
+                            eprintln!("{}", message);
+                            process::exit(1);
+                        }
+                    }
+                },
+                "--root-func" => {
+                    options.root_funcs.push(value(&name));
+                },
+                "--no-default-roots" => {
+                    options.no_default_roots = true;
+                },
+                "--dso-report" => {
+                    options.dso_report = true;
+                },
+                "--filter" => {
+                    options.filter = match value(&name).parse() {
+                        Ok(filter) => Some(filter),
+                        Err(message) => {
+                            eprintln!("{}", message);
+                            process::exit(1);
+                        }
+                    };
+                },
+                "--grep-frame" | "--exclude-frame" => {
+                    let regex = value(&name);
+                    let regex = Regex::new(&regex).unwrap_or_else(|error| {
+                        eprintln!("Invalid frame regex {}: {}", regex, error);
+                        process::exit(1);
+                    });
+                    options.frame_filters.push(if name == "--grep-frame" {
+                        FrameFilter::Grep(regex)
+                    } else {
+                        FrameFilter::ExcludeFrame(regex)
+                    });
+                },
+                "--sample-rate" => {
+                    match value(&name).parse() {
+                        Ok(rate) => options.sample_rate = Some(rate),
+                        Err(message) => {
+                            eprintln!("{}", message);
+                            process::exit(1);
+                        }
+                    }
+                },
+                "--sample-seed" => {
+                    let seed = value(&name);
+                    options.sample_seed = match seed.parse() {
+                        Ok(seed) => seed,
+                        Err(_) => {
+                            eprintln!("Invalid sample seed: {}", seed);
+                            process::exit(1);
+                        }
+                    };
+                },
+                "--exclude-dso" => {
+                    let pattern = Pattern::new(&value(&name));
+                    options.frame_filters.push(FrameFilter::ExcludeDso(pattern));
+                },
+                "--top-functions" => {
+                    let count = value(&name);
+                    options.top_functions = match count.parse() {
+                        Ok(count) => Some(count),
+                        Err(_) => {
+                            eprintln!("Invalid function count: {}", count);
+                            process::exit(1);
+                        }
+                    };
+                },
+                "--top-functions-normal-only" => {
+                    options.top_functions_normal_only = true;
+                },
+                "--skid-correction" => {
+                    match value(&name).parse() {
+                        Ok(skid) => options.skid_correction = skid,
+                        Err(message) => {
+                            eprintln!("{}", message);
+                            process::exit(1);
+                        }
+                    }
+                },
+                "--leaf-functions" => {
+                    options.leaf_functions = true;
+                },
+                "--topdown" => {
+                    options.topdown = true;
+                },
+                "--ipc-report" => {
+                    options.ipc_report = true;
+                },
+                "--cache-report" => {
+                    options.cache_report = true;
+                },
+                "--data-report" => {
+                    options.data_report = true;
+                },
+                "--c2c-report" => {
+                    options.c2c_report = true;
+                },
+                "--energy-report" => {
+                    options.energy_report = true;
+                },
+                "--gpu" => {
+                    options.gpu_report = true;
+                },
+                "--guest-report" => {
+                    options.guest_report = true;
+                },
+                "--bpf-report" => {
+                    options.bpf_report = true;
+                },
+                "--probe-report" => {
+                    options.probe_report = true;
+                },
+                "--itrace-report" => {
+                    options.itrace_report = true;
+                },
+                "--ftrace-report" => {
+                    options.ftrace_report = true;
+                },
+                "--module-report" => {
+                    options.module_report = true;
+                },
+                "--spike-event" => {
+                    options.spike_event = Some(value(&name));
+                },
+                "--label-event" => {
+                    options.label_events.push(value(&name));
+                },
+                "--label-end-event" => {
+                    options.label_end_events.push(value(&name));
+                },
+                "--exclude-idle" => {
+                    options.exclude_idle = true;
+                },
+                "--thread-report" => {
+                    options.thread_report = true;
+                },
+                "--irq-report" => {
+                    options.irq_report = true;
+                },
+                "--smt-report" => {
+                    options.smt_report = true;
+                },
+                "--smt-siblings" => {
+                    let siblings = value(&name);
+                    match siblings.parse() {
+                        Ok(siblings) => options.smt_siblings = Some(siblings),
+                        Err(message) => {
+                            eprintln!("{}", message);
+                            process::exit(1);
+                        }
+                    }
+                },
+                "--period-report" => {
+                    options.period_report = true;
+                },
+                "--throttle-report" => {
+                    options.throttle_report = true;
+                },
+                "--interval-report" => {
+                    options.interval_report = true;
+                },
+                "--coverage-report" => {
+                    options.coverage_report = true;
+                },
+                "--entropy-report" => {
+                    options.entropy_report = true;
+                },
+                "--cgroup-report" => {
+                    options.cgroup_report = true;
+                },
+                "--dwarf-size-report" => {
+                    options.dwarf_size_report = true;
+                },
+                "--unwind-report" => {
+                    options.unwind_report = true;
+                },
+                "--address-bucket" => {
+                    let size = value(&name);
+                    options.address_bucket = match size.parse::<u64>() {
+                        Ok(size) if size.is_power_of_two() => Some(size),
+                        _ => {
+                            eprintln!("Invalid address bucket size: {}, expected a \
+                                       power of two such as 64 or 4096", size);
+                            process::exit(1);
+                        }
+                    };
+                },
+                "--max-dump-per-category" => {
+                    let max_samples = value(&name);
+                    options.max_dump_per_category = match max_samples.parse() {
+                        Ok(n) => Some(n),
+                        Err(_) => {
+                            eprintln!("Invalid sample count: {}", max_samples);
+                            process::exit(1);
+                        }
+                    };
+                },
+                "--truncate-stacks" => {
+                    let max_frames = value(&name);
+                    options.stack_truncation = match max_frames.parse() {
+                        Ok(n) if n > 0 => StackTruncation::new(n),
+                        _ => {
+                            eprintln!("Invalid frame count: {}", max_frames);
+                            process::exit(1);
+                        }
+                    };
+                },
+                "--confidence" => {
+                    let level = value(&name);
+                    options.confidence = match level.parse() {
+                        Ok(level) if level > 0.0 && level < 1.0 => Some(level),
+                        _ => {
+                            eprintln!("Invalid confidence level: {}, expected a \
+                                       number between 0 and 1", level);
+                            process::exit(1);
+                        }
+                    };
+                },
+                "--min-samples" => {
+                    let count = value(&name);
+                    options.significance.min_samples = match count.parse() {
+                        Ok(count) => count,
+                        Err(_) => {
+                            eprintln!("Invalid sample count: {}", count);
+                            process::exit(1);
+                        }
+                    };
+                },
+                "--hide-insignificant" => {
+                    options.significance.hide = true;
+                },
+                "--metrics-addr" => {
+                    options.metrics_addr = Some(value(&name));
+                },
+                "--otlp-endpoint" => {
+                    options.otlp_endpoint = Some(value(&name));
+                },
+                "--quiet" | "-q" => {
+                    options.quiet = true;
+                },
+                "--skip-preflight" => {
+                    options.skip_preflight = true;
+                },
+                "--" => {
+                    options.perf_args.extend(args);
+                    break;
+                },
+                _ => options.perf_args.push(arg),
+            }
+        }
+        if options.tee.is_some()
+           && (options.input_script.is_some() || options.load_analysis.is_some())
+        {
+            eprintln!("--tee only applies when running perf script");
+            process::exit(1);
+        }
+        if options.tee.is_some() && options.redact {
+            eprintln!("--tee copies perf script's output as is, it cannot be redacted");
+            process::exit(1);
+        }
+        if show != options.show.is_some() {
+            eprintln!("The show subcommand takes either --sample or --at, which \
+                       only apply to it");
+            process::exit(1);
+        }
+        if (options.index || options.show_samples.is_some() || show)
+           && options.input_script.is_none()
+        {
+            eprintln!("--index, --show-samples and show only apply to --input-script");
+            process::exit(1);
+        }
+        if options.checkpoint.is_some()
+           && (options.save_analysis.is_some() || options.parquet_dir.is_some()
+               || options.query.is_some() || options.otlp_endpoint.is_some()
+               || options.clean_output.is_some() || options.annotate
+               || options.script.is_some() || !options.plugins.is_empty())
+        {
+            eprintln!("--checkpoint only saves the statistics and anomaly dump, it \
+                       cannot be combined with --save-analysis, --parquet-dir, \
+                       query, --otlp-endpoint, --clean-output, --annotate, \
+                       --script or --plugin");
+            process::exit(1);
+        }
+        if options.repl && options.load_analysis.is_none() {
+            eprintln!("The repl subcommand explores an archive, which must be \
+                       specified with --load-analysis");
+            process::exit(1);
+        }
+        options
     }
+}
+
+
+/// Optional consumers of the analysis results, besides the final summary
+struct Reporters {
+    /// Display of the analysis progress on the terminal
+    progress: Option<Progress>,
 
-    /// Extract and decode the next sample from perf script's output, will
-    /// return Ok(None) when the end of perf script's output is reached.
-    pub fn next(&mut self) -> Result<Option<Sample>> {
-        // Reset the internal state of the sample reader
-        self.reset();
+    /// Prometheus metrics endpoint
+    metrics: Option<MetricsServer>,
 
-        // Load the first line of input. This is the sample's header, containing
-        // info such as the executable name, PID, event type, etc.
-        self.header_len = self.load_next_line()?;
+    /// Profile which will be exported to an OTLP collector
+    profile: Option<ProfileAggregator>,
 
-        // Detect if the end of input was reached, if so report it to the caller
-        if self.header_len == 0 {
-            return Ok(None);
+    /// Anomalous samples which will be printed at the end
+    dump: AnomalyDump,
+
+    /// Archive where the analyzed samples are saved
+    archive: Option<ArchiveWriter>,
+
+    /// Parquet tables where the analyzed samples are exported
+    tables: Option<TableExport>,
+
+    /// Database of the samples, for SQL queries
+    database: Option<SampleDatabase>,
+
+    /// Third-party plugins, which get to see every sample
+    plugins: Vec<Rc<Plugin>>,
+
+    /// User script, which also gets to see every sample
+    script: Option<SampleScript>,
+
+    /// Annotated copy of the samples, printed as they are analyzed
+    annotator: Option<SampleAnnotator>,
+
+    /// Copy of the samples without broken stack traces
+    clean_output: Option<CleanOutput>,
+}
+//
+impl Reporters {
+    /// Start with no reporter enabled
+    fn new() -> Self {
+        Self {
+            progress: None,
+            metrics: None,
+            profile: None,
+            dump: AnomalyDump::new(),
+            archive: None,
+            tables: None,
+            database: None,
+            plugins: Vec::new(),
+            script: None,
+            annotator: None,
+            clean_output: None,
         }
+    }
+}
+
 
-        // Load input lines into the buffer until a newline or EOF is reached,
-        // and record the position of the last useful byte in the buffer.
-        let last_line_end = loop {
-            let line_len = self.load_next_line()?;
-            if line_len <= 1 {
-                break self.buffer.len() - line_len;
+/// Here be the main application logic
+fn main() {
+    // Decode the command-line arguments
+    let options = Options::parse(env::args().skip(1));
+
+    // Load the configuration file, if any
+    let config = match options.config {
+        Some(ref path) => Config::load(path).unwrap_or_else(|e| {
+            eprintln!("Failed to load configuration from {}: {}", path.display(), e);
+            process::exit(EXIT_BAD_INPUT);
+        }),
+        None => Config::default(),
+    };
+
+    // Specific samples can be printed without analyzing the whole input, using
+    // its index, which is built in a first pass if needed
+    if let Some(ref numbers) = options.show_samples {
+        show_samples(numbers, &options);
+        return;
+    }
+
+    // If an analysis checkpoint was saved, resume from it
+    let checkpoint = options.checkpoint.as_ref().and_then(|path| {
+        Checkpoint::load(path).unwrap_or_else(|e| {
+            eprintln!("Failed to load the checkpoint {}: {}", path.display(), e);
+            process::exit(EXIT_BAD_INPUT);
+        })
+    });
+    let analysis_options = analysis_options(&options, &config);
+    let (mut stats, offset, saved_dump) = match checkpoint {
+        Some(Checkpoint { input, options: saved_options, offset, stats, dump }) => {
+            let path = options.checkpoint.as_ref().unwrap();
+            if input != input_identity(&options) {
+                eprintln!("The checkpoint {} was saved while analyzing another input \
+                           ({}), remove it to start over",
+                          path.display(),
+                          input.source);
+                process::exit(EXIT_BAD_INPUT);
             }
-            self.last_line_len = Some(line_len);
-        };
+            if let Some(difference) = saved_options.difference(&analysis_options) {
+                eprintln!("The checkpoint {} was saved with {}, resume with the \
+                           same options or remove it to start over",
+                          path.display(),
+                          difference);
+                process::exit(EXIT_BAD_INPUT);
+            }
+            eprintln!("Resuming analysis after {} samples", stats.num_samples);
+            (stats, offset, Some(dump))
+        },
+        None => (new_statistics(&options), 0, None),
+    };
+    let resumed = saved_dump.is_some();
+    for rule in &config.categories {
+        stats.add_custom_category(&rule.name);
+    }
 
-        // Extract the last stack frame of the sample, if any
-        let buffer = &self.buffer;
-        let last_stack_frame = self.last_line_len.map(move |last_line_len| {
-            let last_line_start = last_line_end - last_line_len;
-            &buffer[last_line_start..last_line_end]
+    // Load third-party plugins, whose categories are handled like those of
+    // the configuration file
+    let plugins = options.plugins.iter().map(|path| {
+        let plugin = Plugin::load(path).unwrap_or_else(|e| {
+            eprintln!("Failed to load plugin {}: {}", path.display(), e);
+            process::exit(1);
         });
+        for category in plugin.categories() {
+            stats.add_custom_category(category);
+        }
+        Rc::new(plugin)
+    }).collect::<Vec<_>>();
+
+    // Open the archive that samples should be read from, if any
+    let mut archive = options.load_analysis.as_ref().map(|path| {
+        ArchiveSamples::open_at(path, offset).unwrap_or_else(|e| {
+            eprintln!("Failed to open {}: {}", path.display(), e);
+            process::exit(EXIT_BAD_INPUT);
+        })
+    });
+    let mut perf_header = match archive {
+        Some(ref archive) => archive.metadata().perf_header.clone(),
+        None => read_perf_header(&options),
+    };
 
-        // Return the decoded sample of data
-        Ok(Some(Sample {
-            raw_sample_data: &self.buffer[..last_line_end],
-            header: &self.buffer[..self.header_len],
-            stack_trace: &self.buffer[self.header_len..last_line_end],
-            last_stack_frame,
-        }))
+    // The samples of a subsampled archive stand for more samples each, which
+    // a resumed analysis already accounted for
+    let archived_period = archive.as_ref().and_then(|a| a.metadata().sample_period);
+    if let (Some(archived_period), false) = (archived_period, resumed) {
+        stats.sample_period = Some(archived_period * stats.sample_period.unwrap_or(1));
+    }
+    if options.redact {
+        perf_header = redact::redact_perf_header(&perf_header);
+    }
+    if stats.metadata.is_none() {
+        stats.metadata = RecordingMetadata::parse(&perf_header);
+    }
+    if options.smt_report && !resumed {
+        match detect_smt_topology(&options, &perf_header) {
+            Some(topology) => stats.smt = Some(SmtStatistics::new(topology)),
+            None => eprintln!("Warning: the CPU topology is unknown, pass it with \
+                               --smt-siblings to get an SMT report"),
+        }
     }
 
-    /// Load the next line of input into the internal text buffer
-    fn load_next_line(&mut self) -> Result<usize> {
-        self.input.read_line(&mut self.buffer)
+    // This struct will analyze and classify the samples
+    let mut sample_analyzer = SampleAnalyzer::new();
+    let arch = options.arch.or_else(|| detect_arch(&perf_header));
+    if let Some(arch) = arch {
+        sample_analyzer = sample_analyzer.with_arch(arch);
+    }
+    if options.no_default_roots {
+        sample_analyzer = sample_analyzer.without_expected_root_funcs();
+    }
+    for &runtime in &options.runtimes {
+        sample_analyzer = sample_analyzer.with_runtime(runtime);
+    }
+    sample_analyzer =
+        sample_analyzer.with_expected_root_funcs(options.root_funcs.iter().cloned());
+    sample_analyzer = sample_analyzer.with_category_rules(config.categories.clone());
+    for plugin in plugins.iter().filter(|plugin| plugin.classifies()) {
+        let classifier = PluginClassifier(plugin.clone());
+        sample_analyzer = sample_analyzer.with_classifier_before(classifier);
+    }
+    if let Some(kernel_symbols) = load_kernel_symbols(&options) {
+        // Modules missing from kallsyms were likely unloaded since recording
+        if let Some(ref mut modules) = stats.modules {
+            if !kernel_symbols.modules().is_empty() {
+                modules.kallsyms_modules = Some(kernel_symbols.modules().clone());
+            }
+        }
+        sample_analyzer = sample_analyzer.with_kernel_symbols(kernel_symbols);
+    }
+    if let Some(mut stale_dsos) = find_stale_dsos(&options) {
+        // Samples are redacted before they are classified
+        if options.redact {
+            stale_dsos = stale_dsos.iter()
+                                   .map(|dso| redact::redact_path(dso).into_owned())
+                                   .collect();
+        }
+        for dso in &stale_dsos {
+            eprintln!("Warning: {} changed since the profile was recorded", dso);
+        }
+        sample_analyzer = sample_analyzer.with_stale_dsos(stale_dsos);
+    }
+    if options.check_frame_addresses {
+        let mapping_sizes = MappingSizes::new(options.path_map.clone());
+        sample_analyzer = sample_analyzer.with_mapping_sizes(mapping_sizes);
+    }
+    if options.check_return_addresses {
+        let call_sites = CallSites::new(options.path_map.clone());
+        sample_analyzer = sample_analyzer.with_call_sites(call_sites);
     }
-}
-///
-///
-/// This struct models one stack trace from perf script
-#[derive(Debug)]
-struct Sample<'a> {
-    /// This is the raw sample data, if you need it for custom processing
-    pub raw_sample_data: &'a str,
-
-    /// Header of the sample, where infos like the process ID lie
-    pub header: &'a str,
-
-    /// Full stack trace of the sample, in textual form
-    pub stack_trace: &'a str,
-
-    /// Quick access to the last stack frame of the stack trace, if any
-    pub last_stack_frame: Option<&'a str>,
-}
 
+    // When running perf script ourselves, the profiled processes may still be
+    // running, and their deleted libraries can then be read through /proc
+    if options.input_script.is_none() && archive.is_none() {
+        let live_mappings = LiveMappings::scan();
+        if !live_mappings.is_empty() {
+            sample_analyzer = sample_analyzer.with_live_mappings(live_mappings);
+        }
+    }
 
-/// Mechanism to analyze pre-parsed data samples and detect anomalies
-struct SampleAnalyzer {
-    /// These are the functions we expect to see at the end of stack traces
-    expected_root_funcs: HashSet<&'static str>,
+    // The show subcommand inspects one sample instead of analyzing them all
+    if let Some(locator) = options.show {
+        show_sample(locator, &sample_analyzer, &config, &options);
+        return;
+    }
 
-    /// These are the DSOs that we expect to see at the end of stack traces
-    expected_root_dsos: HashSet<&'static str>,
+    // The repl subcommand lets the user explore the archive interactively
+    if options.repl {
+        let path = options.load_analysis.clone().unwrap();
+        let mut repl = Repl::new(path, &sample_analyzer, &config)
+                           .with_stack_truncation(options.stack_truncation)
+                           .with_significance(options.significance);
+        if let Some(level) = options.confidence {
+            repl = repl.with_confidence(level);
+        }
+        if options.redact {
+            repl = repl.with_redaction();
+        }
+        if let Err(e) = repl.run(io::stdin().lock()) {
+            eprintln!("Failed to read commands: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
 
-    /// These "bad" DSOs are known to leave broken stack frames around, most
-    /// likely because we don't have DWARF debugging info for them
-    known_bad_dsos: HashSet<&'static str>,
-}
-//
-impl SampleAnalyzer {
-    /// Setup a sample analyzer
-    pub fn new() -> Self {
-        // These are the functions we expect to see on end of stack traces
-        let mut expected_root_funcs = HashSet::new();
-        expected_root_funcs.insert("_start");
-        expected_root_funcs.insert("native_irq_return_iret");
-        expected_root_funcs.insert("__libc_start_main");
-        expected_root_funcs.insert("_dl_start_user");
-        expected_root_funcs.insert("__clone");
-
-        let mut expected_root_dsos = HashSet::new();
-        expected_root_dsos.insert("([kernel.kallsyms])");
-        expected_root_dsos.insert("(/usr/bin/perf)");
-
-        // These DSOs are known to break stack traces (how evil of them!)
-        let mut known_bad_dsos = HashSet::new();
-        known_bad_dsos.insert("(/usr/lib64/xorg/modules/drivers/nvidia_drv.so)");
-        known_bad_dsos.insert("(/usr/lib64/libGLX_nvidia.so.384.98)");
-        known_bad_dsos.insert("(/usr/lib64/libGLX_nvidia.so.384.98)");
-
-        // Return the analysis harness
-        Self {
-            expected_root_funcs,
-            expected_root_dsos,
-            known_bad_dsos,
+    // Expose the statistics to Prometheus if asked to, which is mostly useful
+    // when analyzing a long-running stream of perf script output
+    let mut reporters = Reporters::new();
+    reporters.plugins = plugins.into_iter().filter(|plugin| plugin.reports()).collect();
+    let mut dump = AnomalyDump::new().with_stack_truncation(options.stack_truncation);
+    if let Some(max_samples) = options.max_dump_per_category {
+        dump = dump.with_max_per_category(max_samples);
+    }
+    if let Some(saved_dump) = saved_dump {
+        dump = dump.with_checkpoint(saved_dump);
+    }
+    reporters.dump = dump;
+    reporters.metrics = options.metrics_addr.as_ref().map(|addr| {
+        MetricsServer::start(addr.as_str(), &stats).unwrap_or_else(|e| {
+            eprintln!("Failed to serve metrics on {}: {}", addr, e);
+            process::exit(1);
+        })
+    });
+
+    // Aggregate stack traces into a profile, if it is to be exported
+    if options.otlp_endpoint.is_some() {
+        let mut profile =
+            ProfileAggregator::new().with_stack_truncation(options.stack_truncation);
+        if let Some(bucket_size) = options.address_bucket {
+            profile = profile.with_address_buckets(bucket_size);
+        }
+        if let Some(period) = stats.sample_period {
+            profile = profile.with_sample_period(period);
         }
+        reporters.profile = Some(profile);
     }
 
-    /// Classify a pre-parsed stack sample in various categories (see below)
-    pub fn classify<'a>(&self, sample: &'a Sample) -> SampleCategory<'a> {
-        // If there is no stack trace, report it
-        let last_stack_frame = match sample.last_stack_frame {
-            Some(last_line) => last_line,
-            None => return SampleCategory::NoStackTrace,
+    // Save the analyzed samples into an archive, if asked to
+    if let Some(ref path) = options.save_analysis {
+        let source = match (&archive, &options.input_script) {
+            (Some(archive), _) => archive.metadata().source.clone(),
+            (None, Some(input)) => input.display().to_string(),
+            (None, None) => format!("perf script {}", options.perf_args.join(" ")),
+        };
+        let source = if options.redact {
+            redact::redact_command_line(&source)
+        } else {
+            source
+        };
+        let metadata = ArchiveMetadata {
+            arch: arch.map(|arch| arch.to_string()),
+            perf_header,
+            sample_period: stats.sample_period,
+            ..ArchiveMetadata::new(source)
         };
+        let archive = ArchiveWriter::create(path, &metadata).unwrap_or_else(|e| {
+            eprintln!("Failed to create {}: {}", path.display(), e);
+            process::exit(EXIT_EXPORT_FAILED);
+        });
+        reporters.archive = Some(archive);
+    }
+    if let Some(ref dir) = options.parquet_dir {
+        let tables = TableExport::create(dir).unwrap_or_else(|e| {
+            eprintln!("Failed to create Parquet tables in {}: {}", dir.display(), e);
+            process::exit(EXIT_EXPORT_FAILED);
+        });
+        reporters.tables = Some(tables.with_stack_truncation(options.stack_truncation));
+    }
+    if options.query.is_some() {
+        let database = SampleDatabase::new().unwrap_or_else(|e| {
+            eprintln!("Failed to set up the sample database: {}", e);
+            process::exit(1);
+        });
+        reporters.database =
+            Some(database.with_stack_truncation(options.stack_truncation));
+    }
+    if let Some(ref path) = options.clean_output {
+        let mut clean_output = CleanOutput::create(path).unwrap_or_else(|e| {
+            eprintln!("Failed to create {}: {}", path.display(), e);
+            process::exit(EXIT_EXPORT_FAILED);
+        });
+        if options.trim_bad_dsos {
+            clean_output = clean_output.with_bad_dso_trimming();
+        }
+        reporters.clean_output = Some(clean_output);
+    }
+    if options.annotate {
+        reporters.annotator = Some(SampleAnnotator::new());
+    }
+    if let Some(ref path) = options.script {
+        let script = SampleScript::load(path).unwrap_or_else(|e| {
+            eprintln!("Failed to load script {}: {}", path.display(), e);
+            process::exit(1);
+        });
+        reporters.script = Some(script);
+    }
 
-        // Split the last line into columns, ignoring whitespace
-        let mut last_frame_columns = last_stack_frame.split_whitespace();
+    // Let use run perf script with user-picked arguments, or read its output
+    // from a file if we were asked to. Files are memory-mapped, which is a lot
+    // faster than reading them, but pipes must go through a buffered reader.
+    let outcome = match (archive.as_mut(), options.input_script.as_ref()) {
+        (Some(samples), _) => {
+            let path = options.load_analysis.as_ref().unwrap();
+            reporters.progress = Progress::new(Some(samples.file_size()),
+                                               offset,
+                                               stats.num_samples,
+                                               options.quiet);
+            let analysis = analyze(samples,
+                                   &sample_analyzer,
+                                   &mut stats,
+                                   &mut reporters,
+                                   &config,
+                                   &options);
+            analysis.map_err(|e| {
+                eprintln!("Failed to read {}: {}", path.display(), e);
+                EXIT_BAD_INPUT
+            })
+        },
+        (None, Some(path)) => {
+            if options.index {
+                index_input(path);
+            }
+            let mut samples = InputSamples::open_at(path, offset).unwrap_or_else(|e| {
+                eprintln!("Failed to open {}: {}", path.display(), e);
+                process::exit(EXIT_BAD_INPUT);
+            });
+            reporters.progress = Progress::new(samples.file_size(),
+                                               offset,
+                                               stats.num_samples,
+                                               options.quiet);
+            let analysis = analyze(&mut samples,
+                                   &sample_analyzer,
+                                   &mut stats,
+                                   &mut reporters,
+                                   &config,
+                                   &options);
 
-        // The first column is the instruction pointer for the last frame
-        let last_instruction_pointer = last_frame_columns.next().unwrap();
+            // The --header block of a pipe is only seen while reading samples
+            if let (InputSamples::Streamed(ref samples), None) = (&samples,
+                                                                  &stats.metadata) {
+                let perf_header = if options.redact {
+                    Cow::Owned(redact::redact_perf_header(samples.perf_header()))
+                } else {
+                    Cow::Borrowed(samples.perf_header())
+                };
+                stats.metadata = RecordingMetadata::parse(&perf_header);
+            }
+            analysis.map_err(|e| {
+                eprintln!("Failed to read {}: {}", path.display(), e);
+                EXIT_BAD_INPUT
+            })
+        },
+        (None, None) => {
+            // Warn about system settings which will degrade perf's output
+            if !options.skip_preflight {
+                for warning in SystemSettings::probe().warnings() {
+                    eprintln!("Warning: {}", warning);
+                }
+            }
 
-        // The second column is the function name
-        let last_function_name = last_frame_columns.next().unwrap();
+            let mut child = Command::new("perf")
+                                    .arg("script")
+                                    .args(&options.perf_args)
+                                    .stdout(Stdio::piped())
+                                    .spawn()
+                                    .unwrap_or_else(|e| {
+                                        report_spawn_error(&e);
+                                        process::exit(EXIT_PERF_NOT_STARTED);
+                                    });
+            // The copy of perf script's output, if any, includes the part that
+            // was already analyzed before resuming from a checkpoint
+            let output = child.stdout.take().unwrap();
+            let mut output = match options.tee {
+                Some(ref path) => {
+                    TeeReader::with_output(output, path).unwrap_or_else(|e| {
+                        eprintln!("Failed to create {}: {}", path.display(), e);
+                        process::exit(EXIT_EXPORT_FAILED);
+                    })
+                },
+                None => TeeReader::new(output),
+            };
+            let skipped = io::copy(&mut (&mut output).take(offset), &mut io::sink());
+            let mut samples = PerfSamples::with_offset(output, offset);
+            reporters.progress = Progress::new(None,
+                                               offset,
+                                               stats.num_samples,
+                                               options.quiet);
+            let analysis = skipped.and_then(|_| {
+                analyze(&mut samples,
+                        &sample_analyzer,
+                        &mut stats,
+                        &mut reporters,
+                        &config,
+                        &options)
+            });
 
-        // The last column is the DSO name
-        let last_dso = last_frame_columns.next().unwrap();
+            // The --header block, if any, was only seen while reading samples
+            if stats.metadata.is_none() {
+                let perf_header = if options.redact {
+                    Cow::Owned(redact::redact_perf_header(samples.perf_header()))
+                } else {
+                    Cow::Borrowed(samples.perf_header())
+                };
+                stats.metadata = RecordingMetadata::parse(&perf_header);
+            }
 
-        // After that, there may be an optional "(deleted))" marker
-        let opt_deleted = last_frame_columns.next();
+            // Close our end of the pipe, so that perf script does not block
+            // forever on a full pipe if we stopped reading early
+            let tee = samples.into_inner().finish();
 
-        // If the top function or DSO matches our expectations, we're good
-        if self.expected_root_dsos.contains(last_dso) ||
-           self.expected_root_funcs.contains(last_function_name)
-        {
-            return SampleCategory::Normal;
+            // Wait for the execution of perf script to complete, and check that
+            // it went well. Its error messages went straight to our stderr.
+            let status = child.wait();
+            let outcome = match (analysis, status) {
+                (Err(e), _) => {
+                    eprintln!("Failed to read perf script's output: {}", e);
+                    Err(EXIT_BAD_INPUT)
+                },
+                (Ok(()), Err(e)) => {
+                    eprintln!("Failed to wait for perf script: {}", e);
+                    Err(EXIT_PERF_FAILED)
+                },
+                (Ok(()), Ok(status)) if !status.success() => {
+                    eprintln!("perf script failed ({}), so its output may have \
+                               been truncated", status);
+                    Err(EXIT_PERF_FAILED)
+                },
+                (Ok(()), Ok(_)) => Ok(()),
+            };
+
+            // Report any failure to copy perf script's output
+            outcome.and_then(|()| {
+                tee.map_err(|e| {
+                    let path = options.tee.as_ref().unwrap();
+                    eprintln!("Failed to copy perf script's output to {}: {}",
+                              path.display(), e);
+                    EXIT_EXPORT_FAILED
+                })
+            })
+        },
+    };
+
+    // Get the progress display out of the way, then print the results
+    reporters.progress = None;
+    if let Some(annotator) = reporters.annotator.take() {
+        // In annotation mode, stdout is reserved for the annotated samples
+        if let Err(e) = annotator.finish() {
+            eprintln!("Failed to print the annotated samples: {}", e);
+            process::exit(1);
+        }
+    } else if let (Some(sql), Some(database)) = (options.query.as_ref(),
+                                          reporters.database.as_mut()) {
+        // In query mode, the query's result replaces the usual reports
+        if let Some(period) = stats.sample_period {
+            eprintln!("Warning: only 1 in {} samples was analyzed, the query's \
+                       counts must be scaled up by {}", period, period);
         }
+        match database.query(sql) {
+            Ok(result) if options.csv => result.print_csv(),
+            Ok(result) => result.print_table(),
+            Err(e) => {
+                eprintln!("Failed to run the query: {}", e);
+                process::exit(1);
+            }
+        }
+    } else {
+        stats.print_estimate_banner();
+        reporters.dump.print();
 
-        // Otherwise, let us analyze it further. First, perf uses an IP which is
-        // entirely composed of hex 'f's to denote incomplete DWARF stacks
-        if last_instruction_pointer.len() % 8 == 0 &&
-           last_instruction_pointer.chars().all(|c| c == 'f')
-        {
-            return SampleCategory::TruncatedStack;
+        // Print a summary of sample statistics at the end. If something went
+        // wrong midway, this is still useful, but we'll need to report the error.
+        println!();
+        stats.print_summary(&config, options.confidence, &options.significance);
+        for plugin in &reporters.plugins {
+            plugin.report();
         }
+        if let Some(script) = reporters.script.take() {
+            if let Err(e) = script.finish() {
+                let path = options.script.as_ref().unwrap();
+                eprintln!("Script {} failed: {}", path.display(), e);
+                process::exit(1);
+            }
+        }
+    }
+    if let Err(exit_code) = outcome {
+        process::exit(exit_code);
+    }
+
+    // Export the aggregated profile, if asked to
+    if let (Some(endpoint), Some(profile)) = (options.otlp_endpoint.as_ref(),
+                                               reporters.profile.as_mut()) {
+        if let Err(e) = profile.push(endpoint) {
+            eprintln!("Failed to push the profile to {}: {}", endpoint, e);
+            process::exit(EXIT_EXPORT_FAILED);
+        }
+    }
 
-        // Perhaps the caller was JIT-compiled? Perf can detect this quite well.
-        const JIT_START: &str = "(/tmp/perf-";
-        const JIT_END: &str = ".map)";
-        if last_dso.starts_with(JIT_START) && last_dso.ends_with(JIT_END) {
-            let pid = &last_dso[JIT_START.len()..last_dso.len()-JIT_END.len()];
-            let pid = pid.parse::<u32>().unwrap();
-            return SampleCategory::JitCompiledBy(pid);
+    // Complete the archive with the analysis results, if any
+    if let Some(archive) = reporters.archive.take() {
+        if let Err(e) = archive.finish(&stats) {
+            let path = options.save_analysis.as_ref().unwrap();
+            eprintln!("Failed to save the analysis to {}: {}", path.display(), e);
+            process::exit(EXIT_EXPORT_FAILED);
         }
+    }
 
-        // Perf sometimes inserts strange "deleted" markers next to DSO names,
-        // which are correlated with bad stack traces. I should investigate
-        // these further, in the meantime I'll give them special treatment.
-        if opt_deleted == Some("(deleted))") {
-            return SampleCategory::DeletedByPerf;
+    // Close the Parquet tables, if any
+    if let Some(tables) = reporters.tables.take() {
+        let dir = options.parquet_dir.as_ref().unwrap();
+        if let Err(e) = tables.finish() {
+            eprintln!("Failed to export Parquet tables to {}: {}", dir.display(), e);
+            process::exit(EXIT_EXPORT_FAILED);
+        }
+        if let Some(period) = stats.sample_period {
+            eprintln!("Warning: only 1 in {} samples was analyzed and exported to {}, \
+                       counts must be scaled up by {}", period, dir.display(), period);
         }
+    }
 
-        // Perhaps it comes from a library that is known to break stack traces?
-        // Let us try to find the last sensible DSO in the trace to check.
-        let last_valid_dso =
-            // Iterate over stack frames in reverse order
-            sample.stack_trace.lines().rev()
-                              // Find the DSO associated with each frame
-                              .map(|frame| frame.split_whitespace()
-                                                .rev()
-                                                .next()
-                                                .unwrap())
-                              // Look for the first valid DSO in the stack trace
-                              .skip_while(|&dso| dso == "([unknown])")
-                              // Extract it and return it as an Option
-                              .next();
+    // Close the cleaned up copy of the samples, if any
+    if let Some(clean_output) = reporters.clean_output.take() {
+        if let Err(e) = clean_output.finish() {
+            let path = options.clean_output.as_ref().unwrap();
+            eprintln!("Failed to write the cleaned up samples to {}: {}",
+                      path.display(), e);
+            process::exit(EXIT_EXPORT_FAILED);
+        }
+    }
 
-        // Did we find a single sensible DSO in that stack?
-        if let Some(valid_dso) = last_valid_dso {
-            // Does it belong to our list of known-bad DSOs?
-            let bad_dso_opt = self.known_bad_dsos.get(valid_dso);
-            if let Some(bad_dso) = bad_dso_opt {
-                // If so, report that to the user as the cause of the bad sample
-                return SampleCategory::BrokenByBadDSO(bad_dso);
+    // The analysis is complete, so the checkpoint is not needed anymore
+    if let Some(ref path) = options.checkpoint {
+        match fs::remove_file(path) {
+            Err(ref e) if e.kind() != ErrorKind::NotFound => {
+                eprintln!("Failed to remove the checkpoint {}: {}", path.display(), e);
+                process::exit(EXIT_EXPORT_FAILED);
+            },
+            _ => {},
+        }
+    }
+}
+
+
+/// Explain to the user why perf could not be started
+fn report_spawn_error(error: &io::Error) {
+    match error.kind() {
+        ErrorKind::NotFound => {
+            eprintln!("Could not find perf, please make sure that it is \
+                       installed and in your PATH");
+        },
+        ErrorKind::PermissionDenied => {
+            eprintln!("Not allowed to run perf, please check the permissions \
+                       of its executable");
+        },
+        _ => eprintln!("Failed to start perf script: {}", error),
+    }
+}
+
+
+/// Load the index of the input file, or build it in a first pass over the file
+/// if it is missing or out of date
+fn index_input(path: &Path) -> SampleIndex {
+    SampleIndex::open(path).unwrap_or_else(|e| {
+        eprintln!("Failed to index {}: {}", path.display(), e);
+        process::exit(EXIT_BAD_INPUT);
+    })
+}
+
+
+/// Print the samples of the input file which have some numbers and match the
+/// user's filters, as perf script printed them
+fn show_samples(numbers: &[RangeInclusive<usize>], options: &Options) {
+    let path = options.input_script.as_ref().unwrap();
+    let index = index_input(path);
+    let mut samples = MappedSamples::open(path).unwrap_or_else(|e| {
+        eprintln!("Failed to open {}: {}", path.display(), e);
+        process::exit(EXIT_BAD_INPUT);
+    });
+    let mut redact_buffer = Vec::new();
+    for number in numbers.iter().cloned().flatten() {
+        if number >= index.samples.len() {
+            eprintln!("No sample #{}, the input only has {} samples",
+                      number, index.samples.len());
+            process::exit(EXIT_BAD_INPUT);
+        }
+        let sample = match index.sample(&mut samples, number) {
+            Ok(Some(sample)) => sample,
+            Ok(None) => {
+                eprintln!("{} changed while it was being read, remove {} and retry",
+                          path.display(),
+                          SampleIndex::path_for(path).display());
+                process::exit(EXIT_BAD_INPUT);
+            },
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", path.display(), e);
+                process::exit(EXIT_BAD_INPUT);
             }
+        };
+        let is_wanted =
+            options.filter.as_ref().is_none_or(|filter| filter.matches(&sample)) &&
+            options.frame_filters.iter().all(|filter| filter.matches(&sample));
+        if is_wanted {
+            let sample = if options.redact {
+                redact::redact_sample(&sample, &mut redact_buffer)
+            } else {
+                sample
+            };
+            match sample.stack_id() {
+                Some(stack_id) => println!("Sample #{} (stack {}):", number, stack_id),
+                None => println!("Sample #{}:", number),
+            }
+            print!("{}", options.stack_truncation.raw_text(&sample));
+            println!();
         }
+    }
+}
 
-        // If the last DSO is "[unkown]", the stack trace is clearly broken, but
-        // at this stage I am out of ideas as for how that could happen
-        if last_dso == "([unknown])" {
-            return SampleCategory::BrokenLastFrame;
+
+/// Print the sample of the input file which some locator designates, along with
+/// what its header and stack frames were parsed into and how it was classified
+fn show_sample(locator: SampleLocator,
+               sample_analyzer: &SampleAnalyzer,
+               config: &Config,
+               options: &Options) {
+    let path = options.input_script.as_ref().unwrap();
+    let index = index_input(path);
+    let number = index.locate(locator).unwrap_or_else(|| {
+        eprintln!("No such sample, the input has {} samples", index.samples.len());
+        process::exit(EXIT_BAD_INPUT);
+    });
+    let mut samples = MappedSamples::open(path).unwrap_or_else(|e| {
+        eprintln!("Failed to open {}: {}", path.display(), e);
+        process::exit(EXIT_BAD_INPUT);
+    });
+    let sample = match index.sample(&mut samples, number) {
+        Ok(Some(sample)) => sample,
+        Ok(None) => {
+            eprintln!("{} changed while it was being read, remove {} and retry",
+                      path.display(),
+                      SampleIndex::path_for(path).display());
+            process::exit(EXIT_BAD_INPUT);
+        },
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", path.display(), e);
+            process::exit(EXIT_BAD_INPUT);
         }
+    };
+    let mut normalize_buffer = Vec::new();
+    let sample = match options.perf_format {
+        Some(format) if !format.is_canonical() => {
+            format.normalize(&sample, &mut normalize_buffer)
+        },
+        _ => sample,
+    };
+    let mut redact_buffer = Vec::new();
+    let sample = if options.redact {
+        redact::redact_sample(&sample, &mut redact_buffer)
+    } else {
+        sample
+    };
 
-        // If the last DSO is valid, but the top function of the stack trace is
-        // unexpected, it should be reported as a possible --max-stack-problem.
-        SampleCategory::UnexpectedLastFunc(last_function_name)
+    // Print the sample as perf script did, then what we made of it
+    let offset = index.samples[number].offset;
+    println!("Sample #{} at byte {} of {}:", number, offset, path.display());
+    print!("{}", options.stack_truncation.raw_text(&sample));
+    println!();
+    let unknown = |field: Option<String>| field.unwrap_or_else(|| "?".to_owned());
+    println!("Command: {}", sample.comm().unwrap_or("?"));
+    println!("PID/TID: {}/{}",
+             unknown(sample.pid().map(|pid| pid.to_string())),
+             unknown(sample.tid().map(|tid| tid.to_string())));
+    println!("CPU: {}", unknown(sample.cpu().map(|cpu| cpu.to_string())));
+    println!("Time: {}",
+             unknown(sample.timestamp().map(|time| format!("{:.6}s", time))));
+    println!("Event: {} (period {})",
+             sample.event().unwrap_or("?"),
+             unknown(sample.period().map(|period| period.to_string())));
+    println!("Stack ID: {}", unknown(sample.stack_id().map(|id| id.to_string())));
+    println!("Stack frames, leaf first:");
+    let zero_offset_callers = sample.zero_offset_callers().collect::<Vec<_>>();
+    let suspect_callers = sample.callers()
+                                .filter(|(_, frame)| {
+                                    sample_analyzer.follows_call(frame) == Some(false)
+                                })
+                                .map(|(depth, _)| depth)
+                                .collect::<Vec<_>>();
+    let mut depth = 0;
+    for frame in options.stack_truncation.frames(&sample) {
+        let frame = match frame {
+            TruncatedFrame::Frame(frame) => frame,
+            TruncatedFrame::Elided { num_frames, marker } => {
+                println!("       {}", marker);
+                depth += num_frames;
+                continue;
+            },
+        };
+        let mut notes = Vec::new();
+        if frame.is_unknown_symbol() {
+            notes.push("unknown symbol");
+        }
+        if frame.deleted {
+            notes.push("deleted DSO");
+        }
+        if frame.inferred {
+            notes.push("inferred");
+        }
+        if zero_offset_callers.contains(&depth) {
+            notes.push("caller at +0x0");
+        }
+        if suspect_callers.contains(&depth) {
+            notes.push("suspect return address");
+        }
+        println!("  {:>3}: {}{} in {} at {}{}",
+                 depth,
+                 frame.symbol,
+                 frame.offset.map_or(String::new(), |offset| format!("+{:#x}", offset)),
+                 frame.dso,
+                 frame.ip,
+                 if notes.is_empty() {
+                     String::new()
+                 } else {
+                     format!(" ({})", notes.join(", "))
+                 });
+        depth += 1;
+    }
+    let category = sample_analyzer.classify(&sample);
+    println!("Category: {} [{}]", category.key(), config.severity(&category));
+    println!("Classification: {:?}", category);
+    if let Some(hint) = category.hint() {
+        println!("Hint: {}", hint);
     }
 }
-///
-///
-/// Output of SampleAnalyzer's evaluation of a perf sample's quality
-#[derive(Debug)]
-pub enum SampleCategory<'a> {
-    /// This sample looks the way we expect, nothing special here.
-    Normal,
-
-    /// This sample has no strack trace attached to it.
-    NoStackTrace,
-
-    /// This sample most likely originates from a truncated DWARF stack.
-    TruncatedStack,
-
-    /// This sample was identified by perf as originating from a JIT compiler.
-    /// The PID of the process which generated the code is attached.
-    JitCompiledBy(u32),
-
-    /// This sample's last DSO has a (deleted) marker. Perf sometimes adds them,
-    /// I have no idea what they mean at this point in time.
-    DeletedByPerf,
-
-    /// This sample has a broken stack trace, which features a DSO that is known
-    /// to be problematic. We still lost info, but at least we know why.
-    BrokenByBadDSO(&'static str),
-
-    /// The bottom of the stack trace is clearly broken for this sample, but
-    /// it is not clear how that could happen.
-    BrokenLastFrame,
-
-    /// This sample has an unusual function at the top of the stack trace for no
-    /// clear reason. You may want to check perf script's --max-stack parameter.
-    UnexpectedLastFunc(&'a str),
+
+
+/// Read the header that perf script prints when run with --header, which is
+/// made of comment lines at the start of its output. This is only possible
+/// when reading perf script's output from a regular file, as reading a pipe
+/// ahead would consume the samples.
+fn read_perf_header(options: &Options) -> String {
+    const HEADER_SCAN_LEN: u64 = 64 * 1024;
+    let read_header = |path: &PathBuf| -> io::Result<String> {
+        if !fs::metadata(path)?.file_type().is_file() {
+            return Ok(String::new());
+        }
+        let mut header = Vec::new();
+        File::open(path)?.take(HEADER_SCAN_LEN).read_to_end(&mut header)?;
+        let header = String::from_utf8_lossy(&header);
+        Ok(header.lines()
+                 .filter(|line| line.starts_with('#'))
+                 .flat_map(|line| [line, "\n"])
+                 .collect())
+    };
+    options.input_script.as_ref()
+                        .and_then(|path| read_header(path).ok())
+                        .unwrap_or_default()
 }
 
 
-/// Here be the main application logic
-fn main() {
-    // Let use run perf script with user-picked arguments
-    let mut perf_script = Command::new("perf")
-                                  .arg("script")
-                                  .args(env::args().skip(1))
-                                  .stdout(Stdio::piped())
-                                  .spawn()
-                                  .unwrap();
+/// Figure out which CPU architecture the profile was recorded on. This can be
+/// told by the header of perf script's output, if it was printed with --header,
+/// otherwise we assume that the profile was recorded on this machine.
+fn detect_arch(perf_header: &str) -> Option<Arch> {
+    Arch::from_header(perf_header).or_else(Arch::host)
+}
 
-    // This struct fetches and decodes perf script data from stdin
-    let mut samples = PerfSamples::new(perf_script.stdout.take().unwrap());
 
-    // This struct will analyze and classify the samples
-    let sample_analyzer = SampleAnalyzer::new();
-
-    // We will aggregate statistics about the samples here
-    let mut num_samples = 0usize;
-    let mut num_normal_samples = 0usize;
-    let mut num_stack_less_samples = 0usize;
-    let mut num_truncated_stacks = 0usize;
-    let mut num_jit_samples = 0usize;
-    let mut num_deleted = 0usize;
-    let mut num_bad_dsos = 0usize;
-    let mut num_broken_last_frames = 0usize;
-    let mut num_unexpected_last_func = 0usize;
+/// Figure out which CPUs are SMT siblings on the machine that the profile was
+/// recorded on, which the user may tell, or else the header of perf script's
+/// output if it was printed with --header. Otherwise we assume that the profile
+/// was recorded on this machine.
+fn detect_smt_topology(options: &Options, perf_header: &str) -> Option<SmtTopology> {
+    options.smt_siblings.clone()
+                        .or_else(|| SmtTopology::from_header(perf_header))
+                        .or_else(SmtTopology::host)
+}
+
+
+/// Load the kernel symbols that the user provided, if any
+fn load_kernel_symbols(options: &Options) -> Option<KernelSymbols> {
+    let (path, symbols) = if let Some(ref path) = options.vmlinux {
+        (path, KernelSymbols::from_vmlinux(path))
+    } else if let Some(ref path) = options.kallsyms {
+        (path, KernelSymbols::from_kallsyms(path))
+    } else {
+        return None;
+    };
+    match symbols {
+        Ok(symbols) => Some(symbols),
+        Err(e) => {
+            eprintln!("Failed to load kernel symbols from {}: {}", path.display(), e);
+            process::exit(EXIT_BAD_INPUT);
+        }
+    }
+}
 
+
+/// Set up the statistics of a new analysis, with the reports that the user
+/// asked for
+fn new_statistics(options: &Options) -> Statistics {
+    let mut stats = Statistics::new();
+    stats.sample_period = options.sample_rate.map(|rate| rate.period);
+    if options.runtimes.contains(&Runtime::Jvm) {
+        stats.jvm = Some(JvmStatistics::new());
+    }
+    if options.runtimes.contains(&Runtime::Python) {
+        stats.python = Some(PythonStatistics::new());
+    }
+    if options.runtimes.contains(&Runtime::Wasm) {
+        stats.wasm = Some(WasmStatistics::new());
+    }
+    if options.runtimes.contains(&Runtime::Tokio) {
+        stats.tasks = Some(TaskStatistics::new());
+    }
+    let gc_runtime = options.runtimes.iter().any(|&runtime| {
+        runtime == Runtime::Jvm || runtime == Runtime::Go
+    });
+    if gc_runtime {
+        stats.gc = Some(GcStatistics::new());
+    }
+    if options.dso_report {
+        let mut symbolization = SymbolizationStatistics::new();
+        if let Some(bucket_size) = options.address_bucket {
+            symbolization = symbolization.with_address_buckets(bucket_size);
+        }
+        stats.symbolization = Some(symbolization);
+    }
+    if let Some(count) = options.top_functions {
+        let mut functions = FunctionStatistics::new(count)
+                                .with_skid_correction(options.skid_correction);
+        if options.top_functions_normal_only {
+            functions = functions.normal_only();
+        }
+        stats.functions = Some(functions);
+    }
+    if options.leaf_functions {
+        stats.leaves = Some(LeafStatistics::new());
+    }
+    if options.topdown {
+        stats.topdown = Some(TopDownStatistics::new());
+    }
+    if options.ipc_report {
+        stats.ipc = Some(IpcStatistics::new());
+    }
+    if options.cache_report {
+        stats.cache = Some(CacheStatistics::new());
+    }
+    if options.data_report {
+        stats.data = Some(DataAddressStatistics::new());
+    }
+    if options.c2c_report {
+        stats.sharing = Some(SharingStatistics::new());
+    }
+    if options.energy_report {
+        stats.energy = Some(EnergyStatistics::new());
+    }
+    if options.gpu_report {
+        stats.gpu = Some(GpuStatistics::new());
+    }
+    if options.guest_report {
+        stats.guests = Some(GuestStatistics::new());
+    }
+    if options.bpf_report {
+        stats.bpf = Some(BpfStatistics::new());
+    }
+    if options.probe_report {
+        stats.probes = Some(ProbeStatistics::new());
+    }
+    if options.itrace_report {
+        stats.itrace = Some(BranchTraceStatistics::new());
+    }
+    if options.ftrace_report {
+        stats.ftrace = Some(FunctionTraceStatistics::new());
+    }
+    if options.module_report {
+        stats.modules = Some(ModuleStatistics::new());
+    }
+    if let Some(ref event) = options.spike_event {
+        stats.spikes = Some(SpikeStatistics::new(event));
+    }
+    if !options.label_events.is_empty() {
+        stats.labels = Some(LabelStatistics::new(options.label_events.clone(),
+                                                 options.label_end_events.clone()));
+    }
+    if options.thread_report {
+        stats.threads = Some(ThreadStatistics::new());
+    }
+    if options.irq_report {
+        stats.interrupts = Some(InterruptStatistics::new());
+    }
+    if options.period_report {
+        stats.periods = Some(PeriodStatistics::new());
+    }
+    if options.throttle_report {
+        stats.throttling = Some(ThrottleStatistics::new());
+    }
+    if options.interval_report {
+        stats.intervals = Some(IntervalStatistics::new());
+    }
+    if options.coverage_report {
+        stats.coverage = Some(CoverageStatistics::new());
+    }
+    if options.entropy_report {
+        stats.entropy = Some(EntropyStatistics::new());
+    }
+    if options.cgroup_report {
+        stats.cgroups = Some(CgroupStatistics::new());
+    }
+    if options.check_frame_addresses {
+        stats.addresses = Some(AddressStatistics::new());
+    }
+    if options.dwarf_size_report {
+        stats.dwarf_sizes = Some(DwarfSizeStatistics::new());
+    }
+    if options.unwind_report {
+        stats.unwinding = Some(UnwindStatistics::new());
+    }
+    stats
+}
+
+
+/// Options of the analysis which a checkpoint can only be resumed with
+fn analysis_options(options: &Options, config: &Config) -> AnalysisOptions {
+    let mut filters = options.filter.iter()
+                                    .map(|filter| format!("{:?}", filter))
+                                    .collect::<Vec<_>>();
+    filters.extend(options.frame_filters.iter().map(|filter| format!("{:?}", filter)));
+    if options.exclude_idle {
+        filters.push("exclude idle".to_owned());
+    }
+
+    // Reports and stack fixups which are toggled on and off
+    let toggles = [
+        (options.dso_report, "dso"),
+        (options.top_functions_normal_only, "normal-only functions"),
+        (options.leaf_functions, "leaf functions"),
+        (options.topdown, "topdown"),
+        (options.ipc_report, "ipc"),
+        (options.cache_report, "cache"),
+        (options.data_report, "data"),
+        (options.c2c_report, "c2c"),
+        (options.energy_report, "energy"),
+        (options.gpu_report, "gpu"),
+        (options.guest_report, "guest"),
+        (options.bpf_report, "bpf"),
+        (options.probe_report, "probe"),
+        (options.itrace_report, "itrace"),
+        (options.ftrace_report, "ftrace"),
+        (options.module_report, "module"),
+        (options.thread_report, "thread"),
+        (options.irq_report, "irq"),
+        (options.smt_report, "smt"),
+        (options.period_report, "period"),
+        (options.throttle_report, "throttle"),
+        (options.interval_report, "interval"),
+        (options.coverage_report, "coverage"),
+        (options.entropy_report, "entropy"),
+        (options.cgroup_report, "cgroup"),
+        (options.dwarf_size_report, "dwarf size"),
+        (options.unwind_report, "unwind"),
+        (options.check_frame_addresses, "frame addresses"),
+        (options.check_return_addresses, "return addresses"),
+        (options.stitch_lbr, "stitch lbr"),
+        (options.repair_stacks, "repair stacks"),
+        (options.trim_bad_dsos, "trim bad dsos"),
+        (options.collapse_duplicate_frames, "collapse duplicate frames"),
+        (options.no_default_roots, "no default roots"),
+    ];
+    let mut reports = toggles.iter()
+                             .filter(|&&(enabled, _)| enabled)
+                             .map(|&(_, name)| name.to_owned())
+                             .collect::<Vec<_>>();
+
+    // Reports and categories which have settings
+    if let Some(count) = options.top_functions {
+        reports.push(format!("top {} functions, skid {:?}",
+                             count,
+                             options.skid_correction));
+    }
+    if let Some(bucket_size) = options.address_bucket {
+        reports.push(format!("address bucket {}", bucket_size));
+    }
+    if let Some(ref event) = options.spike_event {
+        reports.push(format!("spikes of {}", event));
+    }
+    let labels = options.label_events.iter().map(|event| format!("label {}", event));
+    reports.extend(labels);
+    let label_ends = options.label_end_events.iter()
+                                             .map(|event| format!("label end {}", event));
+    reports.extend(label_ends);
+    let runtimes = options.runtimes.iter()
+                                   .map(|runtime| format!("runtime {:?}", runtime));
+    reports.extend(runtimes);
+    reports.extend(options.root_funcs.iter().map(|func| format!("root {}", func)));
+    reports.extend(config.categories.iter().map(|rule| format!("category {:?}", rule)));
+    let plugins = options.plugins.iter().map(|path| format!("plugin {}", path.display()));
+    reports.extend(plugins);
+    AnalysisOptions {
+        filters,
+        reports,
+        sample_rate: options.sample_rate.map(|rate| (rate.period, options.sample_seed)),
+    }
+}
+
+
+/// Identity of the analysis input, against which checkpoints are checked
+fn input_identity(options: &Options) -> InputIdentity {
+    match (&options.load_analysis, &options.input_script) {
+        (Some(path), _) | (None, Some(path)) => {
+            InputIdentity::new(path.display().to_string(), path)
+        },
+        (None, None) => {
+            // perf script reads perf.data unless told otherwise
+            let mut perf_data = PathBuf::from("perf.data");
+            let mut perf_args = options.perf_args.iter();
+            while let Some(arg) = perf_args.next() {
+                if arg == "-i" || arg == "--input" {
+                    perf_data = perf_args.next().map_or(perf_data, PathBuf::from);
+                } else if let Some(path) = arg.strip_prefix("--input=") {
+                    perf_data = PathBuf::from(path);
+                }
+            }
+            InputIdentity::new(format!("perf script {}", options.perf_args.join(" ")),
+                               &perf_data)
+        },
+    }
+}
+
+
+/// Find the DSOs which changed since the profile was recorded, if asked to
+fn find_stale_dsos(options: &Options) -> Option<HashSet<String>> {
+    let buildid_list = if let Some(ref path) = options.buildid_list {
+        fs::read_to_string(path).unwrap_or_else(|e| {
+            eprintln!("Failed to read {}: {}", path.display(), e);
+            process::exit(EXIT_BAD_INPUT);
+        })
+    } else if options.check_build_ids {
+        // Forward the perf.data location, if the user specified it
+        let mut input_args = Vec::new();
+        let mut perf_args = options.perf_args.iter();
+        while let Some(arg) = perf_args.next() {
+            if arg == "-i" || arg == "--input" {
+                input_args.push(arg.clone());
+                input_args.extend(perf_args.next().cloned());
+            } else if arg.starts_with("--input=") {
+                input_args.push(arg.clone());
+            }
+        }
+        let output = Command::new("perf")
+                             .arg("buildid-list")
+                             .args(&input_args)
+                             .stderr(Stdio::inherit())
+                             .output()
+                             .unwrap_or_else(|e| {
+                                 report_spawn_error(&e);
+                                 process::exit(EXIT_PERF_NOT_STARTED);
+                             });
+        if !output.status.success() {
+            eprintln!("perf buildid-list failed ({})", output.status);
+            process::exit(EXIT_PERF_FAILED);
+        }
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    } else {
+        return None;
+    };
+    let recorded = buildid::parse_buildid_list(&buildid_list);
+    Some(buildid::find_stale_dsos(&recorded, &options.path_map))
+}
+
+
+/// Analyze the samples from perf script's output, aggregating statistics and
+/// printing out the weirdest ones
+fn analyze<S: SampleSource>(samples: &mut S,
+                            sample_analyzer: &SampleAnalyzer,
+                            stats: &mut Statistics,
+                            reporters: &mut Reporters,
+                            config: &Config,
+                            options: &Options) -> io::Result<()> {
     // Now, let's have a look at the parsed samples
-    while let Some(sample) = samples.next().unwrap() {
-        // Count the total amount of samples
-        num_samples += 1;
+    let mut stack_repair = if options.repair_stacks {
+        Some(StackRepair::new())
+    } else {
+        None
+    };
+    let checkpoint_input = options.checkpoint.as_ref().map(|_| {
+        (input_identity(options), analysis_options(options, config))
+    });
+    let mut last_checkpoint = stats.num_samples + stats.num_filtered_out +
+                              stats.num_skipped;
+    let mut stitch_buffer = Vec::new();
+    let mut repair_buffer = Vec::new();
+    let mut trim_buffer = Vec::new();
+    let mut collapse_buffer = Vec::new();
+    let mut fold_buffer = Vec::new();
+    let mut rename_buffer = Vec::new();
+    let mut redact_buffer = Vec::new();
+    let sample_rate = options.sample_rate.map(|rate| {
+        rate.with_seed(options.sample_seed)
+    });
+    let mut samples = NormalizedSamples::new(samples, options.perf_format);
+    loop {
+        // Periodically save the analysis state, if asked to. Records which are
+        // not samples do not count, so the state is only saved once per interval.
+        if let (Some(path), Some((input, analysis_options))) = (&options.checkpoint,
+                                                                  &checkpoint_input) {
+            let num_seen = stats.num_samples + stats.num_filtered_out +
+                           stats.num_skipped;
+            if num_seen > last_checkpoint
+               && num_seen.is_multiple_of(options.checkpoint_interval)
+            {
+                last_checkpoint = num_seen;
+                let checkpoint = Checkpoint {
+                    input: input.clone(),
+                    options: analysis_options.clone(),
+                    offset: samples.position(),
+                    stats: stats.clone(),
+                    dump: reporters.dump.clone(),
+                };
+                if let Err(e) = checkpoint.save(path) {
+                    eprintln!("Failed to save the checkpoint {}: {}", path.display(), e);
+                    process::exit(EXIT_EXPORT_FAILED);
+                }
+            }
+        }
+
+        // Keep the user informed of how far along we are
+        if let Some(ref mut progress) = reporters.progress {
+            progress.update(samples.position(), stats.num_samples);
+        }
 
-        // Analyze incoming samples and aggregate some statistics
+        // Fetch the next sample, if any
+        let position = samples.position();
+        let sample = match samples.next_sample()? {
+            Some(sample) => sample,
+            None => break,
+        };
+
+        // Names which may reveal what was profiled are redacted before any
+        // output sees them, but filters apply to the names that the user knows
+        let unredacted = &sample;
+        let redacted = if options.redact {
+            Some(redact::redact_sample(&sample, &mut redact_buffer))
+        } else {
+            None
+        };
+        let sample = redacted.as_ref().unwrap_or(&sample);
+
+        // Throttling is measured against the time range and CPUs covered by
+        // the whole recording, so every record goes into it, including those
+        // which are skipped or filtered out below
+        if let Some(ref mut throttling) = stats.throttling {
+            throttling.record(sample);
+        }
+
+        // Side-band records are not samples, but throttle records tell when
+        // events could not be sampled, so they are archived too. Records which
+        // are not classified still go to the annotated output, without
+        // annotation, so that it is a complete copy of the input.
+        if sample::is_sideband_record(sample.header) {
+            if let Some(ref mut archive) = reporters.archive {
+                archive.record(sample);
+            }
+            if let Some(ref mut annotator) = reporters.annotator {
+                annotator.pass_through(sample);
+            }
+            continue;
+        }
+
+        // Hardware trace decoders report decode errors inline, which are not
+        // samples either, but are archived since they delimit holes in the trace
+        if sample::is_trace_error(sample.header) {
+            if let Some(ref mut archive) = reporters.archive {
+                archive.record(sample);
+            }
+            if let Some(ref mut itrace) = stats.itrace {
+                itrace.record(sample);
+            }
+            if let Some(ref mut annotator) = reporters.annotator {
+                annotator.pass_through(sample);
+            }
+            continue;
+        }
+
+        // Only analyze a preview of the input, if asked to
+        if !sample_rate.is_none_or(|rate| rate.keeps(position)) {
+            stats.num_skipped += 1;
+            if let Some(ref mut annotator) = reporters.annotator {
+                annotator.pass_through(sample);
+            }
+            continue;
+        }
+
+        // Leave out the samples which the user is not interested in
+        let is_wanted =
+            options.filter.as_ref().is_none_or(|filter| filter.matches(unredacted)) &&
+            options.frame_filters.iter().all(|filter| filter.matches(unredacted));
+        if !is_wanted {
+            stats.num_filtered_out += 1;
+            if let Some(ref mut annotator) = reporters.annotator {
+                annotator.pass_through(sample);
+            }
+            continue;
+        }
+        if let Some(ref mut archive) = reporters.archive {
+            archive.record(sample);
+        }
+
+        // Analyze incoming samples and aggregate some statistics. Truncated
+        // DWARF stacks may be extended with the branch stack beforehand, in
+        // which case the extended sample is analyzed instead.
+        let category = sample_analyzer.classify(sample);
+        // Stitching and repair must not hide how perf unwound the stacks
+        if let Some(ref mut dwarf_sizes) = stats.dwarf_sizes {
+            dwarf_sizes.record(sample, &category, sample_analyzer.arch());
+        }
+        if let Some(ref mut unwinding) = stats.unwinding {
+            unwinding.record(sample, &category, sample_analyzer.arch());
+        }
+        let stitched = match category {
+            SampleCategory::TruncatedStack if options.stitch_lbr => {
+                lbr::stitch(sample, &mut stitch_buffer)
+            },
+            _ => None,
+        };
+        let stitched_category = stitched.as_ref().map(|stitched| {
+            stats.num_lbr_stitched += 1;
+            sample_analyzer.classify(stitched)
+        });
+        let raw_sample = sample;
+        let sample = stitched.as_ref().unwrap_or(sample);
+        let category = stitched_category.unwrap_or(category);
+
+        // Stacks which are still truncated may then be repaired using recent
+        // complete stacks of the same thread, as a last resort
+        let repaired = match (&category, stack_repair.as_mut()) {
+            (&SampleCategory::TruncatedStack, Some(repair)) => {
+                repair.repair(sample, &mut repair_buffer)
+            },
+            (category, Some(repair)) if stitched.is_none() => {
+                repair.observe(sample, category);
+                None
+            },
+            _ => None,
+        };
+        let repaired_category = repaired.as_ref().map(|repaired| {
+            stats.num_repaired += 1;
+            sample_analyzer.classify(repaired)
+        });
+        let sample = repaired.as_ref().unwrap_or(sample);
+        let category = repaired_category.unwrap_or(category);
+        stats.record(&category);
+        let num_duplicate_frames = sample.num_duplicate_frames();
+        if num_duplicate_frames > 0 {
+            stats.num_duplicate_frame_samples += 1;
+            stats.num_duplicate_frames += num_duplicate_frames;
+        }
+        let num_zero_offset_callers = sample.zero_offset_callers().count();
+        if num_zero_offset_callers > 0 {
+            stats.num_zero_offset_samples += 1;
+            stats.num_zero_offset_callers += num_zero_offset_callers;
+        }
+        if let Some(ref mut jvm) = stats.jvm {
+            jvm.record(sample);
+        }
+        if let Some(ref mut python) = stats.python {
+            python.record(sample);
+        }
+        if let Some(ref mut wasm) = stats.wasm {
+            wasm.record(sample);
+        }
+        if let Some(ref mut tasks) = stats.tasks {
+            tasks.record(sample);
+        }
+        if let Some(ref mut gc) = stats.gc {
+            gc.record(sample, &category);
+        }
+        if let Some(ref mut symbolization) = stats.symbolization {
+            symbolization.record(sample);
+        }
+        if let Some(ref mut threads) = stats.threads {
+            threads.record(sample, &category);
+        }
+        if let Some(ref mut interrupts) = stats.interrupts {
+            interrupts.record(sample, &category);
+        }
+        if let Some(ref mut smt) = stats.smt {
+            smt.record(sample, &category);
+        }
+        if let Some(ref mut periods) = stats.periods {
+            periods.record(sample);
+        }
+        if let Some(ref mut intervals) = stats.intervals {
+            intervals.record(sample);
+        }
+        if let Some(ref mut coverage) = stats.coverage {
+            coverage.record(sample);
+        }
+        if let Some(ref mut entropy) = stats.entropy {
+            entropy.record(sample);
+        }
+        if let Some(ref mut gpu) = stats.gpu {
+            gpu.record(sample);
+        }
+        if let Some(ref mut guests) = stats.guests {
+            guests.record(sample);
+        }
+        if let Some(ref mut bpf) = stats.bpf {
+            bpf.record(sample);
+        }
+        if let Some(ref mut probes) = stats.probes {
+            probes.record(sample);
+        }
+        if let Some(ref mut itrace) = stats.itrace {
+            itrace.record(sample);
+        }
+        if let Some(ref mut ftrace) = stats.ftrace {
+            ftrace.record(sample);
+        }
+        if let Some(ref mut modules) = stats.modules {
+            modules.record(sample, sample_analyzer.arch());
+        }
+        if let Some(ref mut spikes) = stats.spikes {
+            spikes.record(sample);
+        }
+        if let Some(ref mut labels) = stats.labels {
+            labels.record(sample, &category);
+        }
+        if let Some(ref mut cgroups) = stats.cgroups {
+            cgroups.record(sample, &category);
+        }
+        if let Some(ref mut addresses) = stats.addresses {
+            addresses.record(sample, &category, |dso| sample_analyzer.mapping_size(dso));
+        }
+        if let Some(ref mut metrics) = reporters.metrics {
+            metrics.update(stats);
+        }
+
+        // Consecutive duplicate frames may be collapsed, and V8 annotations
+        // stripped, in exports and call trees, which does not change the
+        // category of the sample
+        let collapsed = if options.collapse_duplicate_frames {
+            sample.without_duplicate_frames(&mut collapse_buffer)
+        } else {
+            None
+        };
+        let tree_sample = collapsed.as_ref().unwrap_or(sample);
+        let renamed = if options.strip_v8_annotations {
+            tree_sample.with_renamed_symbols(node::strip_v8_annotations,
+                                             &mut rename_buffer)
+        } else {
+            None
+        };
+        let tree_sample = renamed.as_ref().unwrap_or(tree_sample);
+
+        // Samples of async tasks may then be folded at the poll boundary, so
+        // that they are attributed to the future rather than to the executor
+        let folded = if options.runtimes.contains(&Runtime::Tokio) {
+            tasks::folded_len(tree_sample).map(|num_frames| {
+                tree_sample.truncated(num_frames, &mut fold_buffer)
+            })
+        } else {
+            None
+        };
+        let tree_sample = folded.as_ref().unwrap_or(tree_sample);
+
+        // Samples broken by a bad DSO may be cut at that DSO in exports. The
+        // remainder is classified again, but since it cannot end at a root, an
+        // unexpected last function is not held against it.
+        let remainder = match category {
+            SampleCategory::BrokenByBadDSO(_) if options.trim_bad_dsos => {
+                clean::trimmed_len(tree_sample, &category, true)
+                       .filter(|&num_frames| num_frames > 0)
+                       .map(|num_frames| {
+                           tree_sample.truncated(num_frames, &mut trim_buffer)
+                       })
+            },
+            _ => None,
+        };
+        let remainder_category = remainder.as_ref().map(|remainder| {
+            match (sample_analyzer.classify(remainder), &category) {
+                (SampleCategory::UnexpectedLastFunc(_),
+                 &SampleCategory::BrokenByBadDSO(bad_dso)) => {
+                    SampleCategory::BrokenByBadDSO(bad_dso)
+                },
+                (remainder_category, _) => remainder_category,
+            }
+        });
+        let exported = remainder.as_ref().unwrap_or(tree_sample);
+        let exported_category = remainder_category.as_ref().unwrap_or(&category);
+
+        // Idle samples are counted, but may be kept out of hot-path reports
+        let is_idle = matches!(category, SampleCategory::Idle);
+        if !(is_idle && options.exclude_idle) {
+            if let Some(ref mut functions) = stats.functions {
+                functions.record(tree_sample, &category);
+            }
+            if let Some(ref mut leaves) = stats.leaves {
+                leaves.record(sample, &category);
+            }
+            if let Some(ref mut topdown) = stats.topdown {
+                topdown.record(sample);
+            }
+            if let Some(ref mut ipc) = stats.ipc {
+                ipc.record(sample);
+            }
+            if let Some(ref mut cache) = stats.cache {
+                cache.record(sample);
+            }
+            if let Some(ref mut data) = stats.data {
+                data.record(sample);
+            }
+            if let Some(ref mut sharing) = stats.sharing {
+                sharing.record(sample);
+            }
+            if let Some(ref mut energy) = stats.energy {
+                energy.record(sample);
+            }
+            if let Some(ref mut profile) = reporters.profile {
+                profile.record(exported);
+            }
+        }
+        if let Some(ref mut tables) = reporters.tables {
+            tables.record(exported, exported_category);
+        }
+        if let Some(ref mut database) = reporters.database {
+            database.record(exported, exported_category);
+        }
+        for plugin in &reporters.plugins {
+            plugin.record(sample, &category);
+        }
+        if let Some(ref mut script) = reporters.script {
+            script.record(sample, &category);
+        }
+        if let Some(ref mut annotator) = reporters.annotator {
+            annotator.record(raw_sample, &category, config.severity(&category));
+        }
+        if let Some(ref mut clean_output) = reporters.clean_output {
+            let severity = config.severity(exported_category);
+            clean_output.record(exported, exported_category, severity);
+        }
+
+        // Dump some categories of samples
         use SampleCategory::*;
-        match sample_analyzer.classify(&sample) {
+        let heading: Cow<str> = match category {
             Normal => {
-                num_normal_samples += 1;
+                continue;
+            },
+            VdsoRoot => {
+                continue;
+            },
+            HardIrq | SoftIrq => {
+                continue;
+            },
+            Idle => {
+                continue;
+            },
+            Guest => {
+                // "Sample from a virtual machine guest".into()
                 continue;
             },
             NoStackTrace => {
-                num_stack_less_samples += 1;
-                // print!("Sample without a stack trace:");
+                // "Sample without a stack trace".into()
+                continue;
+            },
+            CorruptFrameAddress(_dso) => {
+                // "Sample with corrupt frame addresses".into()
+                continue;
+            },
+            SuspectReturnAddress(_dso) => {
+                // "Sample with suspect return addresses".into()
+                continue;
+            },
+            KernelSymbolsUnavailable => {
+                // "Sample without kernel symbols".into()
                 continue;
             },
             TruncatedStack => {
-                num_truncated_stacks += 1;
-                // print!("Sample with a truncated stack:");
+                // "Sample with a truncated stack".into()
+                continue;
+            },
+            BrokenBySignal(_depth) => {
+                // "Sample with a stack broken at a signal frame".into()
+                continue;
+            },
+            BrokenByCgo => {
+                // "Sample with a stack broken by cgo".into()
+                continue;
+            },
+            WasmCode => {
+                // "Sample ending in WebAssembly code".into()
                 continue;
             },
             JitCompiledBy(_pid) => {
-                num_jit_samples += 1;
-                // print!("JIT-compiled samples:");
+                // "JIT-compiled samples".into()
                 continue;
             },
-            DeletedByPerf => {
-                num_deleted += 1;
-                // print!("Deleted samples:");
+            BpfProgram(_tag) => {
+                // "Sample ending in a BPF program".into()
                 continue;
-            }
+            },
+            AnonymousCode(_dso) => {
+                // "Sample going through anonymous memory".into()
+                continue;
+            },
+            UpgradedLibrary(_dso) => {
+                // "Sample ending in an upgraded library".into()
+                continue;
+            },
+            AnonymousExecutable(_dso) => {
+                // "Sample ending in an anonymous executable file".into()
+                continue;
+            },
+            StaleDSO(_dso) => {
+                // "Sample going through a DSO which changed".into()
+                continue;
+            },
             BrokenByBadDSO(_dso) => {
-                num_bad_dsos += 1;
-                //print!("Sample broken by a known bad DSO:");
+                // "Sample broken by a known bad DSO".into()
+                continue;
+            },
+            WindowsCode(_dso) => {
+                // "Sample ending in Windows code".into()
                 continue;
             },
             BrokenLastFrame => {
-                num_broken_last_frames += 1;
-                // print!("Sample where the last frame is broken:");
+                // "Sample where the last frame is broken".into()
                 continue;
             },
             UnexpectedLastFunc(_name) => {
-                num_unexpected_last_func += 1;
                 // continue;
-                print!("Sample with an unusual last function:");
+                "Sample with an unusual last function".into()
             },
-        }
+            Custom(name) => {
+                let dump = config.categories.iter().any(|rule| {
+                    rule.name == name && rule.dump
+                });
+                if !dump { continue; }
+                format!("Sample in category \"{}\"", name).into()
+            },
+        };
 
-        // Print the full sample data for the weirdest ones
-        println!("\n{}", sample.raw_sample_data);
+        // Keep the full sample data for the weirdest ones, to be printed once
+        // the analysis is over
+        reporters.dump.record(&heading, sample);
     }
 
-    // Print a summary of sample statistics at the end
-    println!();
-    println!("Total samples: {}", num_samples);
-    println!("- Normal data samples: {}", num_normal_samples);
-    println!("- Samples without a stack trace: {}", num_stack_less_samples);
-    println!("- Truncated DWARF stacks: {}", num_truncated_stacks);
-    println!("- JIT-compiled samples: {}", num_jit_samples);
-    println!("- Deleted samples: {}", num_deleted);
-    println!("- Stack trace broken by a bad DSO: {}", num_bad_dsos);
-    println!("- Samples with broken last frame: {}", num_broken_last_frames);
-    println!("- Samples with unusual last frame: {}", num_unexpected_last_func);
-
-    // Wait for the execution of perf script to complete
-    perf_script.wait().unwrap();
+    // Make sure that the final statistics get exposed
+    if let Some(ref mut metrics) = reporters.metrics {
+        metrics.publish(stats);
+    }
+    Ok(())
 }
@@ -0,0 +1,255 @@
+//! Zero-copy extraction of samples from a memory-mapped perf script dump. This
+//! is much faster than going through a BufReader when the input is a file, as
+//! lines are located with memchr and samples are directly borrowed from the
+//! mapping instead of being copied into an intermediary String.
+//!
+//! Inputs which cannot be mapped, like named pipes or /dev/stdin, are read
+//! through a buffered reader instead, as perf script's output is.
+
+use memchr::memchr;
+use memmap2::Mmap;
+use sample::{self, PerfSamples, Sample, SampleSource};
+use std::fs::File;
+use std::io::{self, Error, ErrorKind, Read, Result};
+use std::path::Path;
+
+
+/// Mechanism to extract individual samples from a file containing the output
+/// of perf script, which is mapped in memory.
+pub struct MappedSamples {
+    map: Mmap,
+    position: usize,
+}
+//
+impl MappedSamples {
+    /// Map a file containing perf script's output into memory
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::open_at(path, 0)
+    }
+
+    /// Variant of open() which starts extracting samples at some byte offset
+    /// of the file (e.g. when resuming)
+    pub fn open_at(path: &Path, offset: u64) -> Result<Self> {
+        let file = File::open(path)?;
+        Self::from_map(map(&file)?, offset)
+    }
+
+    /// Extract samples from a mapped file, starting at some byte offset
+    fn from_map(map: Mmap, offset: u64) -> Result<Self> {
+        if offset > map.len() as u64 {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  "offset is past the end of the input file"));
+        }
+        Ok(Self {
+            map,
+            position: offset as usize,
+        })
+    }
+
+    /// Move to some byte offset of the file, where the next sample will be
+    /// extracted from (e.g. as located by an index)
+    pub fn seek(&mut self, offset: u64) -> Result<()> {
+        if offset > self.map.len() as u64 {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  "offset is past the end of the input file"));
+        }
+        self.position = offset as usize;
+        Ok(())
+    }
+
+    /// Extract and decode the next sample from the file, will return Ok(None)
+    /// when the end of the file is reached.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<Sample<'_>>> {
+        // Skip the comment lines of perf script's --header block, and blank lines
+        let mut start = self.position;
+        while let Some(b'#') | Some(b'\n') = self.map.get(start) {
+            start = self.line_end(start);
+        }
+
+        // Locate the first line of the sample, which is its header
+        let header_end = self.line_end(start);
+
+        // Detect if the end of input was reached, if so report it to the caller
+        if header_end == start {
+            return Ok(None);
+        }
+
+        // Side-band and trace records only span one line, and are not followed
+        // by a blank line, so they must not swallow the next sample
+        let next = self.map.get(header_end).cloned();
+        if sample::is_single_line_record(&self.map[start..header_end], next) {
+            self.position = header_end;
+            let header_len = header_end - start;
+            return Ok(Some(Sample::from_raw(&self.map[start..header_end], header_len)));
+        }
+
+        // Scan lines until a newline or EOF is reached, and record the position
+        // of the last useful byte of the sample and of its last stack frame.
+        let mut line_start = header_end;
+        let mut last_line_start = None;
+        let last_line_end = loop {
+            let line_end = self.line_end(line_start);
+            if line_end - line_start <= 1 {
+                self.position = line_end;
+                break line_start;
+            }
+            last_line_start = Some(line_start);
+            line_start = line_end;
+        };
+
+        let raw_sample_data = &self.map[start..last_line_end];
+
+        // Return the decoded sample of data
+        let header_len = header_end - start;
+        Ok(Some(Sample {
+            raw_sample_data,
+            header: &raw_sample_data[..header_len],
+            stack_trace: &raw_sample_data[header_len..],
+            last_stack_frame: last_line_start.map(|last_line_start| {
+                &raw_sample_data[last_line_start-start..]
+            }),
+        }))
+    }
+
+    /// Byte offset of the next sample in the file
+    pub fn position(&self) -> u64 {
+        self.position as u64
+    }
+
+    /// Total size of the file, in bytes
+    pub fn file_size(&self) -> u64 {
+        self.map.len() as u64
+    }
+
+    // Find the end of the line starting at some position of the file, newline
+    // included. This is the end of the file if there is no newline.
+    fn line_end(&self, line_start: usize) -> usize {
+        match memchr(b'\n', &self.map[line_start..]) {
+            Some(newline_pos) => line_start + newline_pos + 1,
+            None => self.map.len(),
+        }
+    }
+}
+//
+impl SampleSource for MappedSamples {
+    fn next_sample(&mut self) -> Result<Option<Sample<'_>>> {
+        self.next()
+    }
+
+    fn position(&self) -> u64 {
+        self.position as u64
+    }
+}
+///
+///
+/// Mechanism to extract samples from a file containing the output of perf
+/// script, which is mapped in memory if it is a regular file, and otherwise
+/// read through a buffered reader
+pub enum InputSamples {
+    /// Regular file, mapped in memory
+    Mapped(MappedSamples),
+
+    /// Anything else, e.g. a named pipe or /dev/stdin
+    Streamed(PerfSamples<File>),
+}
+//
+impl InputSamples {
+    /// Open a file containing perf script's output, starting at some byte
+    /// offset of it (e.g. when resuming)
+    pub fn open_at(path: &Path, offset: u64) -> Result<Self> {
+        let mut file = File::open(path)?;
+        if file.metadata()?.file_type().is_file() {
+            // A regular file which cannot be mapped can still be read
+            if let Ok(map) = map(&file) {
+                return MappedSamples::from_map(map, offset).map(InputSamples::Mapped);
+            }
+        }
+
+        // Streams cannot seek, so the part that was already analyzed before
+        // resuming is read and thrown away
+        io::copy(&mut (&mut file).take(offset), &mut io::sink())?;
+        Ok(InputSamples::Streamed(PerfSamples::with_offset(file, offset)))
+    }
+
+    /// Total size of the file, in bytes, if it is known upfront
+    pub fn file_size(&self) -> Option<u64> {
+        match *self {
+            InputSamples::Mapped(ref samples) => Some(samples.file_size()),
+            InputSamples::Streamed(_) => None,
+        }
+    }
+}
+//
+impl SampleSource for InputSamples {
+    fn next_sample(&mut self) -> Result<Option<Sample<'_>>> {
+        match *self {
+            InputSamples::Mapped(ref mut samples) => samples.next(),
+            InputSamples::Streamed(ref mut samples) => samples.next(),
+        }
+    }
+
+    fn position(&self) -> u64 {
+        match *self {
+            InputSamples::Mapped(ref samples) => samples.position(),
+            InputSamples::Streamed(ref samples) => samples.position(),
+        }
+    }
+}
+
+
+/// Map a file into memory
+fn map(file: &File) -> Result<Mmap> {
+    // This is only safe as long as nobody truncates the file under our feet. We
+    // cannot prevent that, but perf script dumps are not supposed to be
+    // modified once written, so we'll take the risk.
+    unsafe { Mmap::map(file) }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::process::{self, Command};
+    use std::thread;
+
+    #[test]
+    fn fifo_input() {
+        let script = "# hostname : box\n\
+                      prog 1 [000] 1.0: 1 cycles: \n\
+                      \t    7f00 foo (/lib/libfoo.so)\n\
+                      \n\
+                      prog 1 [000] 2.0: 1 cycles: \n\
+                      \t    7f10 bar (/lib/libfoo.so)\n";
+        let path = env::temp_dir().join(format!("psa-fifo-{}", process::id()));
+        let _ = fs::remove_file(&path);
+        assert!(Command::new("mkfifo").arg(&path).status().unwrap().success());
+
+        // Opening a FIFO blocks until both ends are open, so it is written by
+        // another thread
+        let writer_path = path.clone();
+        let writer = thread::spawn(move || {
+            let mut fifo = OpenOptions::new().write(true).open(writer_path).unwrap();
+            fifo.write_all(script.as_bytes()).unwrap();
+        });
+        let mut samples = InputSamples::open_at(&path, 0).unwrap();
+        assert_eq!(samples.file_size(), None);
+        let mut headers = Vec::new();
+        while let Some(sample) = samples.next_sample().unwrap() {
+            headers.push(sample.header_text().into_owned());
+        }
+        writer.join().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(headers, ["prog 1 [000] 1.0: 1 cycles: \n",
+                             "prog 1 [000] 2.0: 1 cycles: \n"]);
+        match samples {
+            InputSamples::Streamed(ref samples) => {
+                assert_eq!(samples.perf_header(), "# hostname : box\n");
+            },
+            InputSamples::Mapped(_) => panic!("a FIFO cannot be mapped"),
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Metadata about the recording, from the block of comment lines which perf
+//! script prints at the start of its output when run with --header, e.g.
+//!
+//! ```text
+//! # ========
+//! # captured on    : Tue Mar  4 10:12:44 2025
+//! # hostname : build-arm-07
+//! # perf version : 6.1.55
+//! # cmdline : /usr/bin/perf record -g ./clang
+//! # event : name = cycles:u, , id = { 42 }, size = 136, sample_period = 4000
+//! # ========
+//! #
+//! ```
+
+
+
+/// What perf script's --header block tells about the recording
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct RecordingMetadata {
+    /// Host that the profile was recorded on
+    pub hostname: Option<String>,
+
+    /// Kernel release of that host
+    pub os_release: Option<String>,
+
+    /// Version of perf which recorded the profile
+    pub perf_version: Option<String>,
+
+    /// CPU architecture of the host, as perf names it
+    pub arch: Option<String>,
+
+    /// Date at which the profile was recorded, as perf prints it
+    pub captured_on: Option<String>,
+
+    /// Command line of perf record
+    pub cmdline: Option<String>,
+
+    /// Configuration of each recorded event, as perf prints it
+    pub events: Vec<String>,
+}
+//
+impl RecordingMetadata {
+    /// Parse the comment lines of perf script's output. Returns None if they
+    /// contain none of the metadata that we know about.
+    pub fn parse(header: &str) -> Option<Self> {
+        let mut metadata = Self::default();
+        let fields = header.lines()
+                           .filter_map(|line| line.strip_prefix('#'))
+                           .filter_map(|line| line.split_once(':'));
+        for (key, value) in fields {
+            let value = value.trim().to_owned();
+            match key.trim() {
+                "hostname" => metadata.hostname = Some(value),
+                "os release" => metadata.os_release = Some(value),
+                "perf version" => metadata.perf_version = Some(value),
+                "arch" => metadata.arch = Some(value),
+                "captured on" => metadata.captured_on = Some(value),
+                "cmdline" => metadata.cmdline = Some(value),
+                "event" => metadata.events.push(value),
+                _ => {},
+            }
+        }
+        Some(metadata).filter(|metadata| *metadata != Self::default())
+    }
+
+    /// Name of each recorded event, from the "name = <event>" attribute of its
+    /// configuration
+    pub fn event_names(&self) -> impl Iterator<Item=&str> {
+        self.events.iter().filter_map(|config| event_attribute(config, "name"))
+    }
+
+    /// Truth that the recorded events sampled call chains, according to the
+    /// "sample_type" attribute of their configuration, if it was printed
+    pub fn records_call_graphs(&self) -> Option<bool> {
+        let sample_types = self.events.iter().filter_map(|config| {
+            event_attribute(config, "sample_type")
+        }).collect::<Vec<_>>();
+        if sample_types.is_empty() {
+            return None;
+        }
+        Some(sample_types.iter().any(|sample_type| {
+            sample_type.split('|').any(|flag| flag == "CALLCHAIN")
+        }))
+    }
+
+    /// Size of the user stack dumps of --call-graph dwarf, in bytes, according
+    /// to the "sample_stack_user" attribute of the events' configuration
+    pub fn user_stack_size(&self) -> Option<u64> {
+        self.events.iter()
+                   .filter_map(|config| event_attribute(config, "sample_stack_user"))
+                   .find_map(|size| size.parse().ok())
+    }
+
+    /// Print the metadata which perf script provided
+    pub fn print_report(&self) {
+        println!("Recording metadata:");
+        let fields = [
+            ("Host", &self.hostname),
+            ("OS release", &self.os_release),
+            ("Architecture", &self.arch),
+            ("perf version", &self.perf_version),
+            ("Captured on", &self.captured_on),
+            ("Command line", &self.cmdline),
+        ];
+        for (label, value) in fields.iter() {
+            if let Some(ref value) = **value {
+                println!("- {}: {}", label, value);
+            }
+        }
+        let events = self.event_names().collect::<Vec<_>>();
+        if !events.is_empty() {
+            println!("- Events: {}", events.join(", "));
+        }
+    }
+}
+
+
+/// Value of some "<key> = <value>" attribute of an event's configuration
+pub fn event_attribute<'a>(config: &'a str, key: &str) -> Option<&'a str> {
+    config.split(", ")
+          .filter_map(|attribute| attribute.split_once(" = "))
+          .find(|&(other, _)| other == key)
+          .map(|(_, value)| value)
+}
@@ -0,0 +1,172 @@
+//! Prometheus metrics endpoint, so that long-running analyses of a perf script
+//! stream can be monitored and alerted on while they are going on
+
+use stats::Statistics;
+use std::io::{BufRead, BufReader, Result, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+
+/// HTTP server exposing the analysis statistics on /metrics
+pub struct MetricsServer {
+    /// Latest statistics, shared with the server thread
+    snapshot: Arc<Mutex<Snapshot>>,
+
+    /// Number of analyzed samples, which is kept up to date so that the
+    /// server can compute the analysis throughput when it is scraped
+    num_samples: Arc<AtomicUsize>,
+
+    /// Time of the last publication
+    last_publish: Instant,
+}
+//
+impl MetricsServer {
+    /// Minimal delay between two publications of the statistics
+    const PUBLISH_PERIOD: Duration = Duration::from_secs(1);
+
+    /// Number of samples between two checks of the clock
+    const SAMPLES_PER_CHECK: usize = 256;
+
+    /// Time after which a client which does not send its request is dropped
+    const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Start serving metrics on some address, in a background thread
+    pub fn start<A: ToSocketAddrs>(addr: A, stats: &Statistics) -> Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let snapshot = Arc::new(Mutex::new(Snapshot { stats: stats.clone() }));
+        let num_samples = Arc::new(AtomicUsize::new(stats.num_samples));
+        let server_snapshot = snapshot.clone();
+        let server_num_samples = num_samples.clone();
+        thread::spawn(move || {
+            // The throughput is measured between two scrapes, so that it drops
+            // to zero when the input stalls
+            let mut last_scrape = (Instant::now(),
+                                   server_num_samples.load(Ordering::Relaxed));
+            for stream in listener.incoming().flatten() {
+                // A misbehaving client should not take the server down
+                let _ = serve(stream,
+                              &server_snapshot,
+                              &server_num_samples,
+                              &mut last_scrape);
+            }
+        });
+        Ok(Self {
+            snapshot,
+            num_samples,
+            last_publish: Instant::now(),
+        })
+    }
+
+    /// Account for the analysis of one more sample. The statistics which are
+    /// exposed to Prometheus are only updated periodically.
+    pub fn update(&mut self, stats: &Statistics) {
+        self.num_samples.store(stats.num_samples, Ordering::Relaxed);
+        if !stats.num_samples.is_multiple_of(Self::SAMPLES_PER_CHECK) {
+            return;
+        }
+        let elapsed = self.last_publish.elapsed();
+        if elapsed >= Self::PUBLISH_PERIOD {
+            self.publish(stats);
+        }
+    }
+
+    /// Expose the current statistics to Prometheus
+    pub fn publish(&mut self, stats: &Statistics) {
+        self.num_samples.store(stats.num_samples, Ordering::Relaxed);
+        self.snapshot.lock().unwrap().stats.clone_from(stats);
+        self.last_publish = Instant::now();
+    }
+}
+///
+///
+/// Statistics, as exposed to Prometheus
+struct Snapshot {
+    stats: Statistics,
+}
+//
+impl Snapshot {
+    /// Render the statistics in Prometheus' text exposition format, along with
+    /// the current analysis throughput
+    fn render(&self, samples_per_second: f64) -> String {
+        let mut text = String::new();
+        text.push_str("# HELP perf_script_analyze_samples_total Number of analyzed \
+                       samples\n");
+        text.push_str("# TYPE perf_script_analyze_samples_total counter\n");
+        text.push_str(&format!("perf_script_analyze_samples_total {}\n",
+                               self.stats.num_samples));
+        text.push_str("# HELP perf_script_analyze_category_samples_total Number of \
+                       analyzed samples in each category\n");
+        text.push_str("# TYPE perf_script_analyze_category_samples_total counter\n");
+        for (category, _label, count) in self.stats.counters() {
+            text.push_str(&format!("perf_script_analyze_category_samples_total\
+                                    {{category=\"{}\"}} {}\n",
+                                   escape_label(category.key()),
+                                   count));
+        }
+        text.push_str("# HELP perf_script_analyze_samples_per_second Analysis \
+                       throughput since the previous scrape\n");
+        text.push_str("# TYPE perf_script_analyze_samples_per_second gauge\n");
+        text.push_str(&format!("perf_script_analyze_samples_per_second {}\n",
+                               samples_per_second));
+        text.push_str("# HELP perf_script_analyze_sample_period Only 1 in this many \
+                       samples is analyzed, counts must be scaled up by it\n");
+        text.push_str("# TYPE perf_script_analyze_sample_period gauge\n");
+        text.push_str(&format!("perf_script_analyze_sample_period {}\n",
+                               self.stats.sample_period.unwrap_or(1)));
+        text
+    }
+}
+
+
+/// Answer an HTTP request from a Prometheus scraper, given the time and sample
+/// count of the previous scrape, which are updated
+fn serve(mut stream: TcpStream,
+         snapshot: &Mutex<Snapshot>,
+         num_samples: &AtomicUsize,
+         last_scrape: &mut (Instant, usize)) -> Result<()> {
+    // We only care about the request line, but must consume the headers
+    stream.set_read_timeout(Some(MetricsServer::READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    // Serve the metrics, or tell the client that there is nothing else here
+    let mut request = request_line.split_whitespace();
+    let (method, path) = (request.next(), request.next());
+    let (status, body) = match (method, path) {
+        (Some("GET"), Some("/metrics")) => {
+            let now = Instant::now();
+            let samples = num_samples.load(Ordering::Relaxed);
+            let elapsed = now.duration_since(last_scrape.0).as_secs_f64();
+            let samples_per_second = if elapsed > 0.0 {
+                samples.saturating_sub(last_scrape.1) as f64 / elapsed
+            } else {
+                0.0
+            };
+            *last_scrape = (now, samples);
+            ("200 OK", snapshot.lock().unwrap().render(samples_per_second))
+        },
+        _ => ("404 Not Found", "Metrics are served on /metrics\n".to_owned()),
+    };
+    write!(stream,
+           "HTTP/1.1 {}\r\n\
+            Content-Type: text/plain; version=0.0.4\r\n\
+            Content-Length: {}\r\n\
+            Connection: close\r\n\
+            \r\n\
+            {}",
+           status, body.len(), body)
+}
+
+
+/// Escape a Prometheus label value
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -0,0 +1,144 @@
+//! Symbolization of the frames which belong to loaded kernel modules
+//!
+//! perf symbolizes module code using the kallsyms of the machine it runs on,
+//! so module frames are left as "[unknown]" when perf script runs elsewhere
+//! than the recording machine, or after the module was unloaded. Frames of
+//! modules which perf could not even map are attributed to no DSO at all.
+
+use arch::Arch;
+use bpf::BpfProgram;
+use sample::Sample;
+use std::collections::{BTreeSet, HashMap};
+
+
+/// Number of modules which are displayed in the report
+const NUM_REPORTED_MODULES: usize = 10;
+
+/// Fraction of unsymbolized frames above which a module deserves a warning
+const MAX_UNKNOWN_RATE: f64 = 0.1;
+
+
+/// Per-module counts of symbolized and unsymbolized kernel frames
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ModuleStatistics {
+    /// Frames of each module, by module name
+    pub modules: HashMap<String, ModuleFrames>,
+
+    /// Unsymbolized kernel frames which perf did not attribute to any module
+    /// nor to the kernel image, as happens when a module was unloaded
+    pub num_unattributed: usize,
+
+    /// Modules which the user-provided kallsyms dump has symbols of, if any
+    pub kallsyms_modules: Option<BTreeSet<String>>,
+}
+//
+impl ModuleStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for the kernel frames of a new sample. Frames of the kernel
+    /// image and of BPF programs are not module frames, and are thus ignored.
+    pub fn record(&mut self, sample: &Sample, arch: Arch) {
+        for frame in sample.frames().filter(|frame| arch.is_kernel_frame(frame)) {
+            if BpfProgram::of_frame(&frame).is_some() {
+                continue;
+            }
+            let module = match module_name(frame.dso) {
+                Some(module) => module,
+                None => {
+                    if frame.dso == "[unknown]" && frame.is_unknown_symbol() {
+                        self.num_unattributed += 1;
+                    }
+                    continue;
+                },
+            };
+            let counters = match self.modules.get_mut(module) {
+                Some(counters) => counters,
+                None => self.modules.entry(module.to_owned()).or_default(),
+            };
+            if frame.is_unknown_symbol() {
+                counters.num_unknown += 1;
+            } else {
+                counters.num_named += 1;
+            }
+        }
+    }
+
+    /// Print the modules which have the highest share of unsymbolized frames,
+    /// and how to get their symbols
+    pub fn print_report(&self) {
+        if self.modules.is_empty() && self.num_unattributed == 0 {
+            println!("No kernel module frame");
+            return;
+        }
+        let mut modules = self.modules.iter()
+                                      .filter(|(_, frames)| frames.num_unknown > 0)
+                                      .collect::<Vec<_>>();
+        modules.sort_by(|a, b| {
+            b.1.unknown_rate().total_cmp(&a.1.unknown_rate())
+                              .then(b.1.num_unknown.cmp(&a.1.num_unknown))
+                              .then(a.0.cmp(b.0))
+        });
+        println!("Kernel modules with unsymbolized frames, worst first: {} of {}",
+                 modules.len(),
+                 self.modules.len());
+        let num_missing = modules.len();
+        for (module, frames) in modules.into_iter().take(NUM_REPORTED_MODULES) {
+            let rate = frames.unknown_rate();
+            let warning = if rate > MAX_UNKNOWN_RATE { " [warning]" } else { "" };
+            let unloaded = match self.kallsyms_modules {
+                Some(ref loaded) if !loaded.contains(module) => {
+                    ", absent from kallsyms (unloaded since recording?)"
+                },
+                _ => "",
+            };
+            println!("- {}: {} of {} frames ({:.1}%){}{}",
+                     module,
+                     frames.num_unknown,
+                     frames.num_unknown + frames.num_named,
+                     100.0 * rate,
+                     unloaded,
+                     warning);
+        }
+        if self.num_unattributed > 0 {
+            println!("Unsymbolized kernel frames outside of any known module: {} \
+                      [warning]",
+                     self.num_unattributed);
+        }
+        if num_missing > 0 || self.num_unattributed > 0 {
+            println!("Pass a copy of /proc/kallsyms taken while these modules were \
+                      loaded with --kallsyms, or install their debuginfo");
+        }
+    }
+}
+///
+///
+/// Counts of symbolized and unsymbolized frames for one kernel module
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ModuleFrames {
+    /// Frames which perf resolved to a named symbol
+    pub num_named: usize,
+
+    /// Frames which perf left as "[unknown]"
+    pub num_unknown: usize,
+}
+//
+impl ModuleFrames {
+    /// Fraction of the frames which perf left as "[unknown]"
+    fn unknown_rate(&self) -> f64 {
+        self.num_unknown as f64 / (self.num_unknown + self.num_named).max(1) as f64
+    }
+}
+
+
+/// Name of the kernel module which perf attributed a kernel frame to, if any.
+/// perf names module DSOs after the module in brackets, e.g. "[nvidia]".
+fn module_name(dso: &str) -> Option<&str> {
+    let name = dso.strip_prefix('[')?.strip_suffix(']')?;
+    if name == "unknown" || name.starts_with("kernel.") || name.starts_with("guest.") {
+        return None;
+    }
+    Some(name)
+}
@@ -0,0 +1,46 @@
+//! Node.js-specific analysis, which deals with the names that V8 gives to the
+//! JavaScript functions which it lists in perf maps (node --perf-basic-prof)
+//!
+//! These names are made of the kind of code, an optimization tier marker, the
+//! function name and the location of the function in its script, e.g.
+//! "LazyCompile:~handle /srv/app/index.js:10:20". Anonymous functions have no
+//! name, e.g. "JS:~ node:internal/main/run_main_module:1:1".
+
+
+/// Prefixes that V8 gives to the names of the JavaScript functions which it
+/// compiled or interprets, as opposed to builtins, stubs and regexps
+const JAVASCRIPT_PREFIXES: &[&str] = &[
+    "JS:",
+    "LazyCompile:",
+    "Function:",
+    "InterpretedFunction:",
+    "Script:",
+    "Eval:",
+];
+
+/// Markers of the tier that V8 is running a function in: interpreted (~),
+/// Sparkplug (^), Maglev (+) or TurboFan (*)
+const TIER_MARKERS: &[char] = &['~', '^', '+', '*'];
+
+
+/// Truth that a symbol is a JavaScript function from a V8 perf map
+pub fn is_javascript_symbol(symbol: &str) -> bool {
+    JAVASCRIPT_PREFIXES.iter().any(|prefix| symbol.starts_with(prefix))
+}
+
+/// Remove the annotations that V8 puts around the names of JavaScript
+/// functions, keeping only the function name (e.g. "handle" for the example
+/// above). Anonymous functions keep their location, so that they can still be
+/// told apart. Other symbols are returned as they are.
+pub fn strip_v8_annotations(symbol: &str) -> &str {
+    let name = match JAVASCRIPT_PREFIXES.iter().find_map(|p| symbol.strip_prefix(p)) {
+        Some(name) => name.trim_start_matches(TIER_MARKERS),
+        None => return symbol,
+    };
+    let name = match name.split_once(' ') {
+        Some(("", location)) => location,
+        Some((name, _location)) => name,
+        None => name,
+    };
+    if name.is_empty() { symbol } else { name }
+}
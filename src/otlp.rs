@@ -0,0 +1,319 @@
+//! Export of the analyzed stack traces as an OpenTelemetry profile, which is
+//! pushed to a collector so that continuous-profiling backends can ingest it.
+//!
+//! Profiles are sent using the JSON encoding of OTLP/HTTP, following the
+//! v1development layout of the profiling signal where functions, locations and
+//! stacks are shared through a dictionary. The gRPC transport is not supported,
+//! since it would require an HTTP/2 and protobuf stack. Each sample carries the
+//! identifier of its stack as an attribute, so that it can be cross-referenced
+//! with the other outputs.
+
+use sample::{Sample, StackId};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Result, Write};
+use std::net::TcpStream;
+use std::time::{SystemTime, UNIX_EPOCH};
+use truncate::{StackTruncation, TruncatedFrame};
+
+
+/// Default path of the profile ingestion endpoint of OTLP/HTTP collectors
+const DEFAULT_PATH: &str = "/v1development/profiles";
+
+
+/// Aggregation of stack traces into an OpenTelemetry profile
+pub struct ProfileAggregator {
+    /// String table, where each string is only stored once
+    strings: Vec<String>,
+    string_ids: HashMap<String, usize>,
+
+    /// Function table, as (name, file name) string indices
+    functions: Vec<(usize, usize)>,
+    function_ids: HashMap<(usize, usize), usize>,
+
+    /// Mapping table, as DSO file name string indices
+    mappings: Vec<usize>,
+    mapping_ids: HashMap<usize, usize>,
+
+    /// Location table, as (mapping, function) indices
+    locations: Vec<(usize, usize)>,
+    location_ids: HashMap<(usize, usize), usize>,
+
+    /// Identifier and number of samples of each stack, as location indices
+    /// from the leaf function to the root of the stack
+    stacks: HashMap<Vec<usize>, (StackId, u64)>,
+
+    /// Size of the address ranges which unsymbolized frames are aggregated
+    /// into, so that they do not collapse into a single "[unknown]" function
+    bucket_size: Option<u64>,
+
+    /// Cap on the number of frames of each stack
+    truncation: StackTruncation,
+
+    /// Number of samples which each recorded sample stands for, when only some
+    /// of the samples are analyzed
+    sample_period: u64,
+
+    /// Time when the aggregation started
+    start_time: SystemTime,
+}
+//
+impl ProfileAggregator {
+    /// Start with an empty profile. The first entry of each table is reserved
+    /// for the zero value, as OTLP requires.
+    pub fn new() -> Self {
+        let mut result = Self {
+            strings: Vec::new(),
+            string_ids: HashMap::new(),
+            functions: vec![(0, 0)],
+            function_ids: HashMap::new(),
+            mappings: vec![0],
+            mapping_ids: HashMap::new(),
+            locations: vec![(0, 0)],
+            location_ids: HashMap::new(),
+            stacks: HashMap::new(),
+            bucket_size: None,
+            truncation: StackTruncation::none(),
+            sample_period: 1,
+            start_time: SystemTime::now(),
+        };
+        result.intern("");
+        result
+    }
+
+    /// Name unsymbolized frames after the address range of some size which
+    /// they belong to, so that hot regions of stripped binaries remain visible
+    pub fn with_address_buckets(mut self, bucket_size: u64) -> Self {
+        self.bucket_size = Some(bucket_size);
+        self
+    }
+
+    /// Replace the middle frames of very deep stacks with a single function
+    pub fn with_stack_truncation(mut self, truncation: StackTruncation) -> Self {
+        self.truncation = truncation;
+        self
+    }
+
+    /// Scale sample counts up by some factor, when only 1 in that many samples
+    /// is recorded, so that the profile estimates the full counts
+    pub fn with_sample_period(mut self, period: u64) -> Self {
+        self.sample_period = period;
+        self
+    }
+
+    /// Account for a new sample
+    pub fn record(&mut self, sample: &Sample) {
+        let frames = self.truncation.frames(sample);
+        let stack = frames.into_iter().map(|frame| {
+            let (dso, name) = match frame {
+                TruncatedFrame::Frame(frame) => {
+                    let dso = self.intern(frame.dso);
+                    let bucket = match self.bucket_size {
+                        Some(size) if frame.is_unknown_symbol() => {
+                            frame.address_bucket(size)
+                        },
+                        _ => None,
+                    };
+                    let name = match bucket {
+                        Some(start) => self.intern(&format!("[unknown {:#x}]", start)),
+                        None => self.intern(frame.symbol),
+                    };
+                    (dso, name)
+                },
+                // Elided frames are replaced by a function named after them
+                TruncatedFrame::Elided { marker, .. } => {
+                    (self.intern(""), self.intern(&marker))
+                },
+            };
+            let function = intern(&mut self.functions,
+                                  &mut self.function_ids,
+                                  (name, dso));
+            let mapping = intern(&mut self.mappings, &mut self.mapping_ids, dso);
+            intern(&mut self.locations, &mut self.location_ids, (mapping, function))
+        }).collect::<Vec<_>>();
+        if let Some(stack_id) = sample.stack_id() {
+            self.stacks.entry(stack).or_insert((stack_id, 0)).1 += 1;
+        }
+    }
+
+    /// Index of a string in the string table, adding it if needed
+    fn intern(&mut self, s: &str) -> usize {
+        if let Some(&id) = self.string_ids.get(s) {
+            return id;
+        }
+        self.strings.push(s.to_owned());
+        self.string_ids.insert(s.to_owned(), self.strings.len() - 1);
+        self.strings.len() - 1
+    }
+
+    /// Build the OTLP payload (ProfilesData message) of the profile
+    pub fn to_otlp(&mut self) -> Value {
+        // Resolve the indices of the strings used by the profile's metadata
+        let sample_type = value_type(self.intern("samples"), self.intern("count"));
+        let stack_id_key = self.intern("perf_script_analyze.stack_id");
+
+        // Time span covered by the profile
+        let now = SystemTime::now();
+        let nanos = |time: SystemTime| {
+            time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos())
+        };
+        let duration = now.duration_since(self.start_time).map_or(0, |d| d.as_nanos());
+
+        // Each distinct stack is a sample, pointing into the stack table and
+        // into the attribute table (for its identifier), whose first entries
+        // are reserved for the zero value
+        let mut stack_table = vec![
+            object(vec![("locationIndices", Value::Array(vec![]))]),
+        ];
+        let mut attribute_table = vec![object(vec![])];
+        let mut samples = Vec::with_capacity(self.stacks.len());
+        for (stack, &(stack_id, count)) in &self.stacks {
+            samples.push(object(vec![
+                ("stackIndex", stack_table.len().into()),
+                ("attributeIndices", Value::Array(vec![attribute_table.len().into()])),
+                ("values",
+                 Value::Array(vec![int64(count as u128 * self.sample_period as u128)])),
+            ]));
+            stack_table.push(object(vec![
+                ("locationIndices",
+                 Value::Array(stack.iter().map(|&idx| idx.into()).collect())),
+            ]));
+            attribute_table.push(object(vec![
+                ("keyStrindex", stack_id_key.into()),
+                ("value", object(vec![("stringValue", stack_id.to_string().into())])),
+            ]));
+        }
+
+        // Assemble the dictionary
+        let mappings = self.mappings.iter().map(|&file| {
+            object(vec![("filenameStrindex", file.into())])
+        }).collect();
+        let locations = self.locations.iter().map(|&(mapping, function)| {
+            object(vec![
+                ("mappingIndex", mapping.into()),
+                ("line", Value::Array(vec![
+                    object(vec![("functionIndex", function.into())]),
+                ])),
+            ])
+        }).collect();
+        let functions = self.functions.iter().map(|&(name, file)| {
+            object(vec![
+                ("nameStrindex", name.into()),
+                ("systemNameStrindex", name.into()),
+                ("filenameStrindex", file.into()),
+            ])
+        }).collect();
+        let dictionary = object(vec![
+            ("mappingTable", Value::Array(mappings)),
+            ("locationTable", Value::Array(locations)),
+            ("functionTable", Value::Array(functions)),
+            ("stackTable", Value::Array(stack_table)),
+            ("attributeTable", Value::Array(attribute_table)),
+            ("stringTable",
+             Value::Array(self.strings.iter().map(|s| s.as_str().into()).collect())),
+        ]);
+
+        // Wrap the profile into the resource and scope layers of OTLP
+        let profile = object(vec![
+            ("sampleType", sample_type),
+            ("sample", Value::Array(samples)),
+            ("timeUnixNano", int64(nanos(self.start_time))),
+            ("durationNano", int64(duration)),
+        ]);
+        let scope_profiles = object(vec![
+            ("scope", object(vec![("name", "perf-script-analyze".into())])),
+            ("profiles", Value::Array(vec![profile])),
+        ]);
+        let resource_profiles = object(vec![
+            ("resource", object(vec![("attributes", Value::Array(vec![]))])),
+            ("scopeProfiles", Value::Array(vec![scope_profiles])),
+        ]);
+        object(vec![
+            ("resourceProfiles", Value::Array(vec![resource_profiles])),
+            ("dictionary", dictionary),
+        ])
+    }
+
+    /// Push the profile to an OTLP/HTTP collector, given a URL such as
+    /// "http://localhost:4318". The standard path is used if none is given.
+    pub fn push(&mut self, endpoint: &str) -> Result<()> {
+        // Decode the endpoint URL
+        let invalid_url = || {
+            Error::new(ErrorKind::InvalidInput,
+                       format!("invalid OTLP endpoint {}, expected \
+                                http://<host>:<port>[/<path>]", endpoint))
+        };
+        let rest = endpoint.strip_prefix("http://").ok_or_else(invalid_url)?;
+        let (host, path) = match rest.find('/') {
+            Some(pos) if pos + 1 < rest.len() => (&rest[..pos], &rest[pos..]),
+            Some(pos) => (&rest[..pos], DEFAULT_PATH),
+            None => (rest, DEFAULT_PATH),
+        };
+        if host.is_empty() {
+            return Err(invalid_url());
+        }
+
+        // Send the profile
+        let body = self.to_otlp().to_string();
+        let mut stream = TcpStream::connect(host)?;
+        write!(stream,
+               "POST {} HTTP/1.1\r\n\
+                Host: {}\r\n\
+                Content-Type: application/json\r\n\
+                Content-Length: {}\r\n\
+                Connection: close\r\n\
+                \r\n\
+                {}",
+               path, host, body.len(), body)?;
+
+        // Check that the collector accepted it
+        let mut status_line = String::new();
+        BufReader::new(stream).read_line(&mut status_line)?;
+        let status = status_line.split_whitespace().nth(1).unwrap_or("");
+        if status.starts_with('2') {
+            Ok(())
+        } else {
+            Err(Error::other(format!("collector answered \"{}\"", status_line.trim())))
+        }
+    }
+}
+//
+impl Default for ProfileAggregator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Index of an entry in a deduplicated table, adding it if needed
+fn intern<T: Copy + Eq + ::std::hash::Hash>(table: &mut Vec<T>,
+                                             ids: &mut HashMap<T, usize>,
+                                             entry: T) -> usize {
+    *ids.entry(entry).or_insert_with(|| {
+        table.push(entry);
+        table.len() - 1
+    })
+}
+
+
+/// Build a JSON object with static keys
+fn object(members: Vec<(&str, Value)>) -> Value {
+    Value::Object(members.into_iter()
+                         .map(|(key, value)| (key.to_owned(), value))
+                         .collect())
+}
+
+
+/// Encode a 64-bit integer, which the protobuf JSON mapping puts in a string
+fn int64(n: u128) -> Value {
+    n.to_string().into()
+}
+
+
+/// Build a ValueType message
+fn value_type(type_strindex: usize, unit_strindex: usize) -> Value {
+    object(vec![
+        ("typeStrindex", type_strindex.into()),
+        ("unitStrindex", unit_strindex.into()),
+    ])
+}
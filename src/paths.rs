@@ -0,0 +1,67 @@
+//! Translation of the DSO paths recorded by perf into paths on this machine,
+//! for profiles recorded inside of containers or chroots
+
+use std::path::{Component, Path, PathBuf};
+
+
+/// Rules for locating recorded DSOs on the host filesystem
+#[derive(Clone, Debug, Default)]
+pub struct PathMap {
+    /// Directory where the recorded filesystem root can be found, if any
+    sysroot: Option<PathBuf>,
+
+    /// Explicit (recorded prefix, host prefix) translations
+    mappings: Vec<(PathBuf, PathBuf)>,
+}
+//
+impl PathMap {
+    /// Start with DSO paths being used as is
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Look up recorded paths which have no explicit mapping in some directory
+    pub fn set_sysroot<P: Into<PathBuf>>(&mut self, sysroot: P) {
+        self.sysroot = Some(sysroot.into());
+    }
+
+    /// Add a mapping from the "host=container" syntax of --path-map, which
+    /// tells that recorded paths starting with "container" are to be found
+    /// under "host" on this machine
+    pub fn add_mapping(&mut self, spec: &str) -> Result<(), String> {
+        let mut parts = spec.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(host), Some(container)) if !host.is_empty() && !container.is_empty() => {
+                self.mappings.push((container.into(), host.into()));
+                // Longest prefixes must be tried first
+                self.mappings.sort_by_key(|(container, _)| {
+                    usize::MAX - container.components().count()
+                });
+                Ok(())
+            },
+            _ => Err(format!("Invalid path mapping {}, expected host=container", spec)),
+        }
+    }
+
+    /// Translate a recorded DSO path into a path on this machine
+    pub fn resolve(&self, dso: &str) -> PathBuf {
+        let dso = Path::new(dso);
+        for (container, host) in &self.mappings {
+            if let Ok(suffix) = dso.strip_prefix(container) {
+                return host.join(suffix);
+            }
+        }
+        match self.sysroot {
+            Some(ref sysroot) => {
+                // Path::join would discard the sysroot for absolute paths
+                let relative = dso.components().filter(|component| {
+                    !matches!(component, Component::RootDir | Component::Prefix(_))
+                });
+                let mut path = sysroot.clone();
+                path.extend(relative);
+                path
+            },
+            None => dso.to_owned(),
+        }
+    }
+}
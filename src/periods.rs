@@ -0,0 +1,173 @@
+//! Sanity checks on the sampling periods of each event, which detect when the
+//! weighting of a profile's samples cannot be trusted
+//!
+//! Samples are weighted by their period, so the profile is skewed when the
+//! period is stuck at 1 (which is what perf does when an event gets throttled,
+//! and also briefly after a task starts when sampling at a fixed frequency),
+//! when it changes abruptly, or when the event is multiplexed with others and
+//! therefore only sampled some of the time.
+
+use sample::Sample;
+use std::collections::HashMap;
+
+
+/// Ratio between consecutive periods which is considered a sudden change
+const JUMP_RATIO: u64 = 10;
+
+/// Ratio to the usual time between consecutive samples which is considered a
+/// gap in the sampling of an event
+const GAP_RATIO: f64 = 10.0;
+
+/// Fraction of samples with a period of 1 above which throttling is suspected
+const UNIT_PERIOD_WARNING: f64 = 0.01;
+
+
+/// Period statistics of each event
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct PeriodStatistics {
+    pub events: HashMap<String, EventPeriods>,
+
+    /// Last sample of each event on each CPU. This is only needed to compare
+    /// consecutive samples, so it is not saved in checkpoints.
+    #[serde(skip)]
+    last_samples: HashMap<u32, HashMap<String, LastSample>>,
+}
+//
+impl PeriodStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a new sample. Samples which do not tell which event they
+    /// come from or what their period is are ignored.
+    pub fn record(&mut self, sample: &Sample) {
+        let (event, period) = match (sample.event(), sample.period()) {
+            (Some(event), Some(period)) => (event, period),
+            _ => return,
+        };
+        let counters = match self.events.get_mut(event) {
+            Some(counters) => counters,
+            None => self.events.entry(event.to_owned()).or_default(),
+        };
+        counters.num_samples += 1;
+        if period == 1 {
+            counters.num_unit_periods += 1;
+        }
+        counters.min_period = match counters.num_samples {
+            1 => period,
+            _ => counters.min_period.min(period),
+        };
+        counters.max_period = counters.max_period.max(period);
+        counters.total_period = counters.total_period.saturating_add(period);
+
+        // Compare with the previous sample of the same event on the same CPU
+        let (cpu, time) = match (sample.cpu(), sample.timestamp()) {
+            (Some(cpu), Some(time)) => (cpu, time),
+            _ => return,
+        };
+        let cpu_samples = self.last_samples.entry(cpu).or_default();
+        let mut mean_interval = 0.0;
+        if let Some(last) = cpu_samples.get(event) {
+            let (low, high) = (last.period.min(period), last.period.max(period));
+            if high / low.max(1) >= JUMP_RATIO {
+                counters.num_jumps += 1;
+            }
+
+            // A gap during which other events kept being sampled on this CPU
+            // is a telltale sign of multiplexing
+            let interval = time - last.time;
+            let others_sampled = cpu_samples.iter().any(|(other_event, other)| {
+                other_event != event && other.time > last.time && other.time < time
+            });
+            if last.mean_interval > 0.0 &&
+               interval > GAP_RATIO * last.mean_interval &&
+               others_sampled
+            {
+                counters.num_gaps += 1;
+            }
+            mean_interval = if last.mean_interval > 0.0 {
+                0.9 * last.mean_interval + 0.1 * interval
+            } else {
+                interval
+            };
+        }
+        let last = LastSample { period, time, mean_interval };
+        match cpu_samples.get_mut(event) {
+            Some(old_last) => *old_last = last,
+            None => { cpu_samples.insert(event.to_owned(), last); },
+        }
+    }
+
+    /// Print the period statistics of each event, along with any sign that
+    /// their weighting is unreliable
+    pub fn print_report(&self) {
+        let mut events = self.events.iter().collect::<Vec<_>>();
+        events.sort_by(|a, b| a.0.cmp(b.0));
+        println!("Sample periods per event:");
+        for (event, counters) in events {
+            println!("- {}: {} samples, periods from {} to {} (mean {})",
+                     event,
+                     counters.num_samples,
+                     counters.min_period,
+                     counters.max_period,
+                     counters.total_period / counters.num_samples as u64);
+            let unit_fraction = counters.num_unit_periods as f64
+                                    / counters.num_samples as f64;
+            if unit_fraction > UNIT_PERIOD_WARNING {
+                println!("  - Period of 1 in {:.1}% of samples, the event may have \
+                          been throttled [warning]",
+                         100.0 * unit_fraction);
+            }
+            if counters.num_jumps > 0 {
+                println!("  - {} sudden period changes (x{} or more) between \
+                          consecutive samples of a CPU [warning]",
+                         counters.num_jumps,
+                         JUMP_RATIO);
+            }
+            if counters.num_gaps > 0 {
+                println!("  - {} sampling gaps while other events were sampled, the \
+                          event may have been multiplexed [warning]",
+                         counters.num_gaps);
+            }
+        }
+    }
+}
+///
+///
+/// Period statistics of one event
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct EventPeriods {
+    /// Samples of this event
+    pub num_samples: usize,
+
+    /// Samples with a period of 1
+    pub num_unit_periods: usize,
+
+    /// Range of sampling periods
+    pub min_period: u64,
+    pub max_period: u64,
+
+    /// Sum of all sampling periods
+    pub total_period: u64,
+
+    /// Sudden changes of period between consecutive samples of a CPU
+    pub num_jumps: usize,
+
+    /// Gaps in the sampling of this event while others were sampled
+    pub num_gaps: usize,
+}
+
+
+/// Last sample of an event on some CPU
+#[derive(Clone, Debug, PartialEq)]
+struct LastSample {
+    /// Sampling period
+    period: u64,
+
+    /// Time at which the sample was taken, in seconds
+    time: f64,
+
+    /// Moving average of the time between consecutive samples, in seconds
+    mean_interval: f64,
+}
@@ -0,0 +1,363 @@
+//! Third-party plugins, which are dynamic libraries providing custom sample
+//! classifiers and reports without requiring a fork of this program.
+//!
+//! Plugins use a C ABI, so that they can be written in any language and built
+//! with any Rust compiler. A plugin must export the following function...
+//!
+//! - `uint32_t psa_plugin_abi_version(void)`, which returns PLUGIN_ABI_VERSION
+//!
+//! ...and may export any of these:
+//!
+//! - `void *psa_plugin_new(void)` creates the plugin's state, which is passed
+//!   to the other functions (NULL if this function is not exported).
+//! - `const char *psa_plugin_category(void *state, size_t index)` gives the
+//!   name of the index-th category that the plugin may put samples in, or NULL
+//!   once all categories have been enumerated.
+//! - `int64_t psa_plugin_classify(void *state, const PluginSample *sample)`
+//!   returns the index of the category of a sample, or -1 to let the other
+//!   classifiers decide. Plugin classifiers run before the built-in ones.
+//! - `void psa_plugin_record(void *state, const PluginSample *sample,
+//!   PluginStr category)` is called for every classified sample.
+//! - `void psa_plugin_report(void *state)` is called at the end of the
+//!   analysis, and may print a report on stdout.
+//! - `void psa_plugin_free(void *state)` destroys the plugin's state.
+//!
+//! Strings and frames passed to the plugin are only valid during the call.
+//! These declarations are provided for C plugins by `include/psa_plugin.h`.
+
+use analysis::{Classifier, SampleCategory, SampleView};
+use libloading::Library;
+use sample::Sample;
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::ffi::CStr;
+use std::io::{Error, ErrorKind, Result};
+use std::os::raw::{c_char, c_void};
+use std::path::Path;
+use std::ptr;
+use std::rc::Rc;
+use std::str;
+
+
+/// Version of the plugin ABI, which is bumped on every incompatible change
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+
+/// Borrowed string, which is not NUL-terminated
+#[repr(C)]
+pub struct PluginStr {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+//
+impl PluginStr {
+    /// Borrow a Rust string
+    fn new(s: &str) -> Self {
+        Self { ptr: s.as_ptr(), len: s.len() }
+    }
+}
+///
+///
+/// Decoded stack frame, as seen by plugins
+#[repr(C)]
+pub struct PluginFrame {
+    /// Instruction pointer, or 0 if it could not be decoded
+    pub ip: u64,
+    pub symbol: PluginStr,
+    pub dso: PluginStr,
+}
+///
+///
+/// Decoded sample, as seen by plugins
+#[repr(C)]
+pub struct PluginSample {
+    /// Text of the sample, as printed by perf script
+    pub raw_text: PluginStr,
+
+    /// Name of the sampled thread, empty if unknown
+    pub comm: PluginStr,
+
+    /// Name of the sampled event, empty if unknown
+    pub event: PluginStr,
+
+    /// Process and thread ID, or -1 if unknown
+    pub pid: i64,
+    pub tid: i64,
+
+    /// Stack frames, from the leaf function to the root of the stack
+    pub frames: *const PluginFrame,
+    pub num_frames: usize,
+}
+///
+///
+/// Last sample presented to a plugin, which is kept from its classification
+/// to its recording so that it is only decoded once
+struct PresentedSample {
+    /// Location of the text of the sample, which identifies it
+    key: Option<(*const u8, usize)>,
+
+    /// Sample as passed to the plugin, pointing into the buffers below
+    sample: PluginSample,
+
+    /// Decoded stack frames, reused across samples
+    frames: Vec<PluginFrame>,
+
+    /// Text of the sample with invalid UTF-8 replaced, if needed
+    lossy_text: String,
+}
+//
+impl PresentedSample {
+    /// Set up the buffers
+    fn new() -> Self {
+        Self {
+            key: None,
+            sample: PluginSample {
+                raw_text: PluginStr::new(""),
+                comm: PluginStr::new(""),
+                event: PluginStr::new(""),
+                pid: -1,
+                tid: -1,
+                frames: ptr::null(),
+                num_frames: 0,
+            },
+            frames: Vec::new(),
+            lossy_text: String::new(),
+        }
+    }
+
+    /// Present a sample, unless it is the one which was presented last
+    fn present(&mut self, sample: &Sample) -> &PluginSample {
+        let key = (sample.raw_sample_data.as_ptr(), sample.raw_sample_data.len());
+        if self.key == Some(key) {
+            return &self.sample;
+        }
+        let raw_text = match str::from_utf8(sample.raw_sample_data) {
+            Ok(text) => PluginStr::new(text),
+            Err(_) => {
+                self.lossy_text.clear();
+                self.lossy_text.push_str(&sample.raw_text());
+                PluginStr::new(&self.lossy_text)
+            },
+        };
+        self.frames.clear();
+        self.frames.extend(sample.frames().map(|frame| {
+            PluginFrame {
+                ip: u64::from_str_radix(frame.ip, 16).unwrap_or(0),
+                symbol: PluginStr::new(frame.symbol),
+                dso: PluginStr::new(frame.dso),
+            }
+        }));
+        self.sample = PluginSample {
+            raw_text,
+            comm: PluginStr::new(sample.comm().unwrap_or("")),
+            event: PluginStr::new(sample.event().unwrap_or("")),
+            pid: sample.pid().map_or(-1, i64::from),
+            tid: sample.tid().map_or(-1, i64::from),
+            frames: self.frames.as_ptr(),
+            num_frames: self.frames.len(),
+        };
+        self.key = Some(key);
+        &self.sample
+    }
+
+    /// Forget the last sample, whose buffer may now be reused
+    fn forget(&mut self) {
+        self.key = None;
+    }
+}
+
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type NewFn = unsafe extern "C" fn() -> *mut c_void;
+type CategoryFn = unsafe extern "C" fn(*mut c_void, usize) -> *const c_char;
+type ClassifyFn = unsafe extern "C" fn(*mut c_void, *const PluginSample) -> i64;
+type RecordFn = unsafe extern "C" fn(*mut c_void, *const PluginSample, PluginStr);
+type ReportFn = unsafe extern "C" fn(*mut c_void);
+type FreeFn = unsafe extern "C" fn(*mut c_void);
+
+
+/// Dynamic library implementing the plugin ABI
+pub struct Plugin {
+    /// State of the plugin, as created by psa_plugin_new
+    state: *mut c_void,
+
+    /// Categories that the plugin may put samples in
+    categories: Vec<String>,
+
+    /// Entry points of the plugin
+    classify_fn: Option<ClassifyFn>,
+    record_fn: Option<RecordFn>,
+    report_fn: Option<ReportFn>,
+    free_fn: Option<FreeFn>,
+
+    /// Sample which was last presented to the plugin
+    presented: RefCell<PresentedSample>,
+
+    /// Library which the entry points come from, which must be kept loaded
+    _library: Library,
+}
+//
+impl Plugin {
+    /// Load a plugin. This runs arbitrary code from the library, which must be
+    /// trusted to implement the plugin ABI correctly.
+    pub fn load(path: &Path) -> Result<Self> {
+        unsafe {
+            let library = Library::new(path).map_err(Error::other)?;
+            let abi_version = library.get::<AbiVersionFn>(b"psa_plugin_abi_version\0")
+                                     .map_err(Error::other)?;
+            let abi_version = abi_version();
+            if abi_version != PLUGIN_ABI_VERSION {
+                return Err(Error::new(ErrorKind::InvalidData,
+                                      format!("plugin ABI version {} is not supported, \
+                                               expected {}",
+                                              abi_version, PLUGIN_ABI_VERSION)));
+            }
+            let new_fn = library.get::<NewFn>(b"psa_plugin_new\0").ok().map(|f| *f);
+            let category_fn =
+                library.get::<CategoryFn>(b"psa_plugin_category\0").ok().map(|f| *f);
+            let classify_fn =
+                library.get::<ClassifyFn>(b"psa_plugin_classify\0").ok().map(|f| *f);
+            let record_fn =
+                library.get::<RecordFn>(b"psa_plugin_record\0").ok().map(|f| *f);
+            let report_fn =
+                library.get::<ReportFn>(b"psa_plugin_report\0").ok().map(|f| *f);
+            let free_fn = library.get::<FreeFn>(b"psa_plugin_free\0").ok().map(|f| *f);
+
+            // Set up the plugin, and ask which categories it uses
+            let state = new_fn.map_or(ptr::null_mut(), |new_fn| new_fn());
+            let mut categories = Vec::new();
+            if let Some(category_fn) = category_fn {
+                loop {
+                    let name = category_fn(state, categories.len());
+                    if name.is_null() {
+                        break;
+                    }
+                    categories.push(CStr::from_ptr(name).to_string_lossy().into_owned());
+                }
+            }
+            Ok(Self {
+                state,
+                categories,
+                classify_fn,
+                record_fn,
+                report_fn,
+                free_fn,
+                presented: RefCell::new(PresentedSample::new()),
+                _library: library,
+            })
+        }
+    }
+
+    /// Categories that the plugin may put samples in
+    pub fn categories(&self) -> &[String] {
+        &self.categories
+    }
+
+    /// Truth that the plugin classifies samples
+    pub fn classifies(&self) -> bool {
+        self.classify_fn.is_some()
+    }
+
+    /// Truth that the plugin needs to see classified samples or to report
+    pub fn reports(&self) -> bool {
+        self.record_fn.is_some() || self.report_fn.is_some()
+    }
+
+    /// Let the plugin know about a newly classified sample, which is not
+    /// decoded again if the plugin has just classified it
+    pub fn record(&self, sample: &Sample, category: &SampleCategory) {
+        if let Some(record_fn) = self.record_fn {
+            let mut presented = self.presented.borrow_mut();
+            let plugin_sample = presented.present(sample);
+            unsafe {
+                record_fn(self.state, plugin_sample, PluginStr::new(category.key()))
+            }
+            presented.forget();
+        }
+    }
+
+    /// Let the plugin print its report
+    pub fn report(&self) {
+        if let Some(report_fn) = self.report_fn {
+            unsafe { report_fn(self.state) }
+        }
+    }
+}
+//
+impl Drop for Plugin {
+    fn drop(&mut self) {
+        if let Some(free_fn) = self.free_fn {
+            unsafe { free_fn(self.state) }
+        }
+    }
+}
+///
+///
+/// Classification stage backed by a plugin
+pub struct PluginClassifier(pub Rc<Plugin>);
+//
+impl Classifier for PluginClassifier {
+    fn classify<'a>(&'a self, sample: &SampleView<'_, 'a>) -> Option<SampleCategory<'a>> {
+        let plugin = &*self.0;
+        let classify_fn = plugin.classify_fn?;
+        let mut presented = plugin.presented.borrow_mut();
+        let plugin_sample = presented.present(sample.sample);
+        let index = unsafe { classify_fn(plugin.state, plugin_sample) };
+        // Only samples which are about to be recorded may be kept around
+        if plugin.record_fn.is_none() {
+            presented.forget();
+        }
+        let name = plugin.categories.get(usize::try_from(index).ok()?)?;
+        Some(SampleCategory::Custom(name))
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analysis::SampleAnalyzer;
+    use std::env;
+    use std::fs;
+    use std::process::{self, Command};
+
+    #[test]
+    fn c_plugin() {
+        let path = env::temp_dir().join(format!("psa-plugin-{}.so", process::id()));
+        let status = Command::new("cc").args(["-shared", "-fPIC", "-Iinclude"])
+                                       .arg("tests/fixtures/plugin.c")
+                                       .arg("-o").arg(&path)
+                                       .status().unwrap();
+        assert!(status.success());
+        let plugin = Rc::new(Plugin::load(&path).unwrap());
+        assert_eq!(plugin.categories(), ["foo"]);
+        assert!(plugin.classifies() && plugin.reports());
+        let classifier = PluginClassifier(plugin.clone());
+        let analyzer = SampleAnalyzer::new().with_classifier_before(classifier);
+
+        // The plugin only claims samples whose leaf function is in libfoo
+        let header = "prog 1/2 [000] 1.0: 1 cycles: \n";
+        let foo_text = format!("{}\t    7f00 foo (/lib/libfoo.so)\n\
+                                \t    7f10 _start (/usr/bin/prog)\n", header);
+        let foo = Sample::from_raw(foo_text.as_bytes(), header.len());
+        let other_text = format!("{}\t    7f20 main (/usr/bin/prog)\n\
+                                  \t    7f10 _start (/usr/bin/prog)\n", header);
+        let other = Sample::from_raw(other_text.as_bytes(), header.len());
+        for sample in &[foo, other] {
+            let category = analyzer.classify(sample);
+            assert_eq!(matches!(category, SampleCategory::Custom("foo")),
+                       sample.raw_sample_data == foo_text.as_bytes());
+            plugin.record(sample, &category);
+            assert_eq!(plugin.presented.borrow().key, None);
+        }
+
+        // Counters of the plugin: classified, recorded as foo, recorded as other
+        let counters = unsafe { *(plugin.state as *const [usize; 3]) };
+        assert_eq!(counters, [2, 1, 1]);
+        plugin.report();
+        drop(analyzer);
+        drop(plugin);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,121 @@
+//! Checks of the system settings which affect perf's ability to record and
+//! symbolize kernel stack frames. When these settings are too restrictive, the
+//! analysis reports lots of broken stacks, which are better explained upfront.
+//!
+//! Only the privileges of this process are probed. The perf executable may
+//! have been granted capabilities of its own, so the warnings only point out
+//! what may go wrong.
+
+use std::fs;
+
+
+/// Linux capability which grants access to performance monitoring
+const CAP_PERFMON: u32 = 38;
+
+/// Linux capability which grants access to kernel addresses
+const CAP_SYSLOG: u32 = 34;
+
+/// Linux capability which used to grant everything, including the above
+const CAP_SYS_ADMIN: u32 = 21;
+
+
+/// System settings which matter to perf, as far as we could probe them
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SystemSettings {
+    /// Value of /proc/sys/kernel/perf_event_paranoid
+    pub perf_event_paranoid: Option<i32>,
+
+    /// Value of /proc/sys/kernel/kptr_restrict
+    pub kptr_restrict: Option<i32>,
+
+    /// Effective capabilities of this process, if known, which do not account
+    /// for the file capabilities of the perf executable
+    pub effective_caps: Option<u64>,
+
+    /// Truth that we are running as root
+    pub is_root: bool,
+}
+//
+impl SystemSettings {
+    /// Probe the settings of the system we are running on
+    pub fn probe() -> Self {
+        let read_int = |path| {
+            fs::read_to_string(path).ok().and_then(|s| s.trim().parse().ok())
+        };
+        let status = fs::read_to_string("/proc/self/status").unwrap_or_default();
+        let status_field = |name: &str| {
+            status.lines()
+                  .find(|line| line.starts_with(name))
+                  .map(|line| line[name.len()..].trim().to_owned())
+        };
+        Self {
+            perf_event_paranoid: read_int("/proc/sys/kernel/perf_event_paranoid"),
+            kptr_restrict: read_int("/proc/sys/kernel/kptr_restrict"),
+            effective_caps: status_field("CapEff:").and_then(|caps| {
+                u64::from_str_radix(&caps, 16).ok()
+            }),
+            is_root: status_field("Uid:").and_then(|uids| {
+                // The effective UID is the second of the four listed UIDs
+                uids.split_whitespace().nth(1).map(|euid| euid == "0")
+            }).unwrap_or(false),
+        }
+    }
+
+    /// Truth that this process has some Linux capability. If capabilities
+    /// could not be probed, assume that only root has them.
+    fn has_cap(&self, cap: u32) -> bool {
+        match self.effective_caps {
+            Some(caps) => caps & (1 << cap) != 0,
+            None => self.is_root,
+        }
+    }
+
+    /// Truth that this process may monitor performance without restrictions
+    fn can_perfmon(&self) -> bool {
+        self.has_cap(CAP_PERFMON) || self.has_cap(CAP_SYS_ADMIN)
+    }
+
+    /// Truth that this process can see kernel symbol addresses
+    pub fn can_see_kernel_addresses(&self) -> bool {
+        match self.kptr_restrict {
+            Some(0) | None => true,
+            Some(1) => self.has_cap(CAP_SYSLOG),
+            Some(_) => false,
+        }
+    }
+
+    /// Produce actionable advice about the settings which will degrade the
+    /// quality of perf's output, if any
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        match self.perf_event_paranoid {
+            Some(level) if level > 2 && !self.can_perfmon() => {
+                warnings.push(format!(
+                    "kernel.perf_event_paranoid is {}, which prevents \
+                     unprivileged users from recording anything unless perf \
+                     was granted CAP_PERFMON. If recording fails, run perf \
+                     record as root, or lower it with \
+                     \"sysctl kernel.perf_event_paranoid=1\".", level));
+            },
+            Some(level) if level >= 2 && !self.can_perfmon() => {
+                warnings.push(format!(
+                    "kernel.perf_event_paranoid is {}, so samples recorded by \
+                     unprivileged users on this machine may not include \
+                     kernel stack frames, unless perf was granted CAP_PERFMON. \
+                     If they are missing, record as root or with CAP_PERFMON, \
+                     or lower it with \"sysctl kernel.perf_event_paranoid=1\".",
+                    level));
+            },
+            _ => {},
+        }
+        if !self.can_see_kernel_addresses() {
+            warnings.push(format!(
+                "kernel.kptr_restrict is {}, so perf may not be able to \
+                 resolve kernel symbols, in which case kernel frames show up \
+                 as [unknown]. Run as root, or lower it with \
+                 \"sysctl kernel.kptr_restrict=0\".",
+                self.kptr_restrict.unwrap_or_default()));
+        }
+        warnings
+    }
+}
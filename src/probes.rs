@@ -0,0 +1,256 @@
+//! Support for the samples of dynamic probes, which perf probe adds to kernel
+//! (kprobes) or user-space (uprobes) functions. perf script prints their hits
+//! as a header without a period, followed by the probed address and by the
+//! arguments that the probe fetched, e.g.
+//!
+//! ```text
+//! app  1234/1234 [002] 12345.678901: probe_libc:malloc: (7f3a2b1c4d50) size=0x20
+//! ```
+//!
+//! Hits are counted per probe, and the time between consecutive hits of a probe
+//! by the same thread tells how often that thread goes through the function.
+//! When a function has both an entry probe and a return probe (which perf probe
+//! names e.g. "malloc" and "malloc__return"), the hits of each thread are also
+//! paired to measure how long each call took.
+
+use intervals::{self, IntervalHistogram};
+use sample::Sample;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+
+/// Number of threads which are displayed for each probe
+const NUM_REPORTED_THREADS: usize = 5;
+
+/// Suffix which perf probe appends to the name of return probes
+const RETURN_SUFFIX: &str = "__return";
+
+/// Maximal number of calls which may be waiting for a return probe hit in each
+/// thread, beyond which the oldest calls are forgotten (their return probe
+/// hit was likely lost, or they never returned, e.g. due to longjmp)
+const MAX_PENDING_CALLS: usize = 64;
+
+/// Latency percentiles which are displayed for each probed function
+const REPORTED_PERCENTILES: [f64; 4] = [50.0, 90.0, 99.0, 99.9];
+
+
+/// One hit of a dynamic probe
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProbeHit<'a> {
+    /// Group of the probe (e.g. "probe_libc"), and name of the probe within
+    /// that group (e.g. "malloc", or "malloc__return" for a return probe)
+    pub group: &'a str,
+    pub name: &'a str,
+
+    /// Address which the probe was hit at, if perf script printed it
+    pub address: Option<u64>,
+
+    /// Arguments fetched by the probe, as name and value
+    pub arguments: Vec<(&'a str, &'a str)>,
+}
+//
+impl<'a> ProbeHit<'a> {
+    /// Decode a sample, if it is the hit of a dynamic probe
+    pub fn parse(sample: &Sample<'a>) -> Option<Self> {
+        let (group, name) = sample.event()?.split_once(':')?;
+        if !is_probe_group(group) {
+            return None;
+        }
+
+        // Return probes print both the function and the return address, as in
+        // "(ffffffff81234567 <- ffffffff81345678)"
+        let payload = sample.event_payload().unwrap_or("");
+        let (address, arguments) = match payload.strip_prefix('(')
+                                                .and_then(|p| p.split_once(')')) {
+            Some((addresses, arguments)) => {
+                let address = addresses.split_whitespace().next().and_then(|address| {
+                    u64::from_str_radix(address, 16).ok()
+                });
+                (address, arguments)
+            },
+            None => (None, payload),
+        };
+        Some(Self {
+            group,
+            name,
+            address,
+            arguments: arguments.split_whitespace()
+                                .filter_map(|argument| argument.split_once('='))
+                                .collect(),
+        })
+    }
+}
+
+
+/// Truth that an event group contains dynamic probes, either created by perf
+/// probe (e.g. "probe" or "probe_libc", or "sdt_libc" for the USDT markers of
+/// a library) or directly through tracefs
+fn is_probe_group(group: &str) -> bool {
+    group.starts_with("probe") || group.starts_with("sdt_") ||
+    group == "kprobes" || group == "uprobes"
+}
+///
+///
+/// Hits of each dynamic probe
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ProbeStatistics {
+    /// Hits of each probe, by event name (e.g. "probe_libc:malloc")
+    pub probes: HashMap<String, ProbeCounts>,
+}
+//
+impl ProbeStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a new sample. Samples of other events than probes are
+    /// ignored, and return probe hits are paired with the last entry probe hit
+    /// of the same thread to measure the latency of calls.
+    pub fn record(&mut self, sample: &Sample) {
+        let (event, hit) = match (sample.event(), ProbeHit::parse(sample)) {
+            (Some(event), Some(hit)) => (event, hit),
+            _ => return,
+        };
+        let counts = match self.probes.get_mut(event) {
+            Some(counts) => counts,
+            None => self.probes.entry(event.to_owned()).or_default(),
+        };
+        counts.num_hits += 1;
+        for (name, _) in hit.arguments {
+            if !counts.arguments.contains(name) {
+                counts.arguments.insert(name.to_owned());
+            }
+        }
+        let tid = match sample.tid() {
+            Some(tid) => tid,
+            None => return,
+        };
+        let thread = counts.threads.entry(tid).or_default();
+        thread.num_hits += 1;
+        let time = match sample.timestamp() {
+            Some(time) => (time * 1e9).round() as u64,
+            None => return,
+        };
+        if let Some(last_time) = thread.last_time.replace(time) {
+            thread.intervals.record(time.saturating_sub(last_time));
+        }
+        match event.strip_suffix(RETURN_SUFFIX) {
+            Some(entry) => self.record_return(entry, tid, time),
+            None => {
+                if thread.pending_calls.len() == MAX_PENDING_CALLS {
+                    thread.pending_calls.remove(0);
+                }
+                thread.pending_calls.push(time);
+            },
+        }
+    }
+
+    /// Pair a return probe hit with the last pending call of the matching
+    /// entry probe by the same thread, and record the latency of that call
+    fn record_return(&mut self, entry: &str, tid: u32, time: u64) {
+        let entry = match self.probes.get_mut(entry) {
+            Some(entry) => entry,
+            None => return,
+        };
+        let start = entry.threads.get_mut(&tid).and_then(|thread| {
+            thread.pending_calls.pop()
+        });
+        if let Some(start) = start {
+            entry.latencies.record(time.saturating_sub(start));
+        }
+    }
+
+    /// Print the hits of each probe, the distribution of the time between hits
+    /// of the same thread, and the threads which hit each probe the most
+    pub fn print_report(&self) {
+        if self.probes.is_empty() {
+            println!("No probe hit (add probes with perf probe, and record them with \
+                      e.g. perf record -e 'probe_*:*')");
+            return;
+        }
+        let mut probes = self.probes.iter().collect::<Vec<_>>();
+        probes.sort_by(|a, b| b.1.num_hits.cmp(&a.1.num_hits).then(a.0.cmp(b.0)));
+        for (idx, (event, counts)) in probes.into_iter().enumerate() {
+            if idx > 0 {
+                println!();
+            }
+            println!("{}: {} hits from {} threads",
+                     event,
+                     counts.num_hits,
+                     counts.threads.len());
+            if !counts.arguments.is_empty() {
+                let arguments = counts.arguments.iter().cloned().collect::<Vec<_>>();
+                println!("Arguments: {}", arguments.join(", "));
+            }
+            let mut intervals = IntervalHistogram::default();
+            for thread in counts.threads.values() {
+                intervals.merge(&thread.intervals);
+            }
+            if intervals.counts.iter().any(|&count| count > 0) {
+                println!("Time between hits of the same thread:");
+                intervals.print();
+            }
+            let num_calls = counts.latencies.counts.iter().sum::<usize>();
+            if num_calls > 0 {
+                let percentiles = REPORTED_PERCENTILES.iter().map(|&percent| {
+                    let latency = counts.latencies.percentile(percent).unwrap_or(0);
+                    format!("p{} {}", percent, intervals::format_nanoseconds(latency))
+                }).collect::<Vec<_>>();
+                println!("Latency of {} calls: {}, max {}",
+                         num_calls,
+                         percentiles.join(", "),
+                         intervals::format_nanoseconds(counts.latencies.max_interval));
+                counts.latencies.print();
+            }
+            let mut threads = counts.threads.iter().collect::<Vec<_>>();
+            threads.sort_by(|a, b| b.1.num_hits.cmp(&a.1.num_hits).then(a.0.cmp(b.0)));
+            for (tid, thread) in threads.into_iter().take(NUM_REPORTED_THREADS) {
+                if thread.num_hits > 1 {
+                    let longest_gap = thread.intervals.max_interval;
+                    println!("- TID {}: {} hits, longest gap {}",
+                             tid,
+                             thread.num_hits,
+                             intervals::format_nanoseconds(longest_gap));
+                } else {
+                    println!("- TID {}: {} hits", tid, thread.num_hits);
+                }
+            }
+        }
+    }
+}
+///
+///
+/// Hits of one dynamic probe
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ProbeCounts {
+    /// Number of hits
+    pub num_hits: usize,
+
+    /// Names of the arguments which the probe fetched
+    pub arguments: BTreeSet<String>,
+
+    /// Hits of each thread, by TID
+    pub threads: BTreeMap<u32, ThreadHits>,
+
+    /// Latency of the calls which were paired with a return probe hit, if
+    /// this is an entry probe
+    pub latencies: IntervalHistogram,
+}
+///
+///
+/// Hits of one dynamic probe by one thread
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ThreadHits {
+    /// Number of hits
+    pub num_hits: usize,
+
+    /// Time between consecutive hits
+    pub intervals: IntervalHistogram,
+
+    /// Time of the last hit, in nanoseconds
+    pub last_time: Option<u64>,
+
+    /// Start time of the calls which did not hit the return probe yet, in
+    /// nanoseconds, innermost call last
+    pub pending_calls: Vec<u64>,
+}
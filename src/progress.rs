@@ -0,0 +1,143 @@
+//! Progress reporting on the terminal, so that users know how long they'll have
+//! to wait when analyzing a huge perf script dump
+
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+
+/// Mechanism to periodically display the analysis progress on stderr
+pub struct Progress {
+    /// Total size of the input in bytes, if known (i.e. for files)
+    total_bytes: Option<u64>,
+
+    /// Input position and sample count when the analysis started, which may
+    /// not be zero when a checkpoint was resumed
+    start_position: u64,
+    start_samples: usize,
+
+    /// Time when the analysis started and when the display was last updated
+    start_time: Instant,
+    last_update: Instant,
+
+    /// Number of input records which went by since the clock was last checked
+    records_since_check: usize,
+
+    /// Truth that something was drawn and must be cleaned up
+    drawn: bool,
+}
+//
+impl Progress {
+    /// Minimal delay between two display updates
+    const UPDATE_PERIOD: Duration = Duration::from_millis(200);
+
+    /// Number of input records between two checks of the clock. Records are
+    /// counted whether they end up analyzed or not (e.g. filtered out), so
+    /// that the display keeps moving through inputs which are mostly skipped.
+    const RECORDS_PER_CHECK: usize = 4096;
+
+    /// Width of the progress bar, in characters
+    const BAR_WIDTH: usize = 30;
+
+    /// Set up progress reporting, starting at some input position and sample
+    /// count. Returns None if progress should not be displayed, because the
+    /// user asked for quiet operation or the output does not go to a terminal.
+    pub fn new(total_bytes: Option<u64>,
+               start_position: u64,
+               start_samples: usize,
+               quiet: bool) -> Option<Self> {
+        if quiet || !io::stdout().is_terminal() || !io::stderr().is_terminal() {
+            return None;
+        }
+        let now = Instant::now();
+        Some(Self {
+            total_bytes,
+            start_position,
+            start_samples,
+            start_time: now,
+            last_update: now,
+            records_since_check: 0,
+            drawn: false,
+        })
+    }
+
+    /// Account for one more input record, given the current input position and
+    /// sample count. The display is only updated periodically.
+    pub fn update(&mut self, position: u64, num_samples: usize) {
+        self.records_since_check += 1;
+        if self.records_since_check < Self::RECORDS_PER_CHECK {
+            return;
+        }
+        self.records_since_check = 0;
+        let now = Instant::now();
+        if now - self.last_update < Self::UPDATE_PERIOD {
+            return;
+        }
+        self.last_update = now;
+        self.draw(position, num_samples, now - self.start_time);
+    }
+
+    /// Erase the progress display, e.g. before printing something else
+    pub fn clear(&mut self) {
+        if self.drawn {
+            eprint!("\r\x1b[K");
+            self.drawn = false;
+        }
+    }
+
+    // Draw the progress display on stderr
+    fn draw(&mut self, position: u64, num_samples: usize, elapsed: Duration) {
+        let elapsed_secs = elapsed.as_secs_f64();
+        let new_samples = num_samples - self.start_samples;
+        let new_bytes = position.saturating_sub(self.start_position);
+        let samples_per_sec = new_samples as f64 / elapsed_secs;
+        let mut line = String::new();
+        match self.total_bytes {
+            // When the input size is known, draw a progress bar with an ETA
+            Some(total_bytes) if total_bytes > 0 => {
+                let fraction = (position as f64 / total_bytes as f64).min(1.0);
+                let filled = (fraction * Self::BAR_WIDTH as f64) as usize;
+                line.push('[');
+                line.extend((0..filled).map(|_| '#'));
+                line.extend((filled..Self::BAR_WIDTH).map(|_| '.'));
+                line.push_str(&format!("] {:5.1}%", fraction * 100.0));
+                let bytes_per_sec = new_bytes as f64 / elapsed_secs;
+                if bytes_per_sec > 0.0 {
+                    let remaining = total_bytes.saturating_sub(position);
+                    let eta = (remaining as f64 / bytes_per_sec) as u64;
+                    line.push_str(&format!(" | ETA {}", format_duration(eta)));
+                }
+            },
+
+            // Otherwise, only show how much data went through
+            _ => {
+                line.push_str(&format!("{:.1} MiB read",
+                                       position as f64 / (1024.0 * 1024.0)));
+            },
+        }
+        line.push_str(&format!(" | {} samples | {:.0} samples/s",
+                               num_samples, samples_per_sec));
+        let stderr = io::stderr();
+        let mut stderr = stderr.lock();
+        let _ = write!(stderr, "\r\x1b[K{}", line);
+        let _ = stderr.flush();
+        self.drawn = true;
+    }
+}
+//
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+
+/// Format a duration in seconds in a human-readable way
+fn format_duration(secs: u64) -> String {
+    if secs >= 3600 {
+        format!("{}h{:02}m{:02}s", secs / 3600, (secs / 60) % 60, secs % 60)
+    } else if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
@@ -0,0 +1,91 @@
+//! CPython-specific analysis, which recognizes the frames of the bytecode
+//! evaluator and tells how much time is spent in the interpreter itself
+
+use sample::{Sample, StackFrame};
+use std::path::Path;
+
+
+/// Truth that a frame belongs to CPython's bytecode evaluation loop
+pub fn is_eval_frame(frame: &StackFrame) -> bool {
+    matches!(frame.symbol,
+             "_PyEval_EvalFrameDefault" | "_PyEval_EvalFrame" |
+             "PyEval_EvalFrameEx" | "_PyEval_Vector" | "PyEval_EvalCode")
+}
+
+/// Truth that a frame is a Python function, as exposed to perf by CPython's
+/// perf trampolines (python -X perf), which name them "py::<func>:<file>"
+pub fn is_python_function(frame: &StackFrame) -> bool {
+    frame.symbol.starts_with("py::")
+}
+
+/// Truth that a frame belongs to the CPython interpreter binary or library
+pub fn is_interpreter_frame(frame: &StackFrame) -> bool {
+    let file_name = Path::new(frame.dso).file_name()
+                                        .and_then(|name| name.to_str())
+                                        .unwrap_or("");
+    is_eval_frame(frame) ||
+    file_name.starts_with("libpython") ||
+    file_name.starts_with("python")
+}
+
+/// Fold consecutive evaluator frames into a single one. Each Python-level call
+/// adds a few of them, which clutters stack traces without adding information.
+pub fn fold_eval_frames<'a, I>(frames: I) -> impl Iterator<Item=StackFrame<'a>>
+    where I: Iterator<Item=StackFrame<'a>>
+{
+    let mut last_was_eval = false;
+    frames.filter(move |frame| {
+        let is_eval = is_eval_frame(frame);
+        let keep = !(is_eval && last_was_eval);
+        last_was_eval = is_eval;
+        keep
+    })
+}
+
+
+/// Breakdown of the samples from a Python workload
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct PythonStatistics {
+    /// Samples which went through the bytecode evaluator
+    pub num_python_samples: usize,
+
+    /// Samples which were executing the interpreter itself, as opposed to
+    /// native extensions or the kernel
+    pub num_pure_interpreter: usize,
+
+    /// Evaluator frames which were folded away
+    pub num_folded_frames: usize,
+}
+//
+impl PythonStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a new sample
+    pub fn record(&mut self, sample: &Sample) {
+        if !sample.frames().any(|frame| is_eval_frame(&frame)) {
+            return;
+        }
+        self.num_python_samples += 1;
+        self.num_folded_frames += sample.frames().count() -
+                                  fold_eval_frames(sample.frames()).count();
+        if sample.frames().next().is_some_and(|leaf| is_interpreter_frame(&leaf)) {
+            self.num_pure_interpreter += 1;
+        }
+    }
+
+    /// Print the Python section of the report
+    pub fn print_report(&self) {
+        let percent = if self.num_python_samples == 0 {
+            0.0
+        } else {
+            100.0 * self.num_pure_interpreter as f64 / self.num_python_samples as f64
+        };
+        println!("Python samples: {}", self.num_python_samples);
+        println!("- In the interpreter itself: {} ({:.1}%)",
+                 self.num_pure_interpreter, percent);
+        println!("- Folded evaluator frames: {}", self.num_folded_frames);
+    }
+}
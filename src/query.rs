@@ -0,0 +1,220 @@
+//! SQL queries over the analyzed samples, for questions which the built-in
+//! reports do not answer. Samples are loaded into an in-memory SQLite database
+//! with the same layout as the Parquet export, i.e. a "samples" table and a
+//! "frames" table which refers to it through a sample_id column.
+
+use analysis::SampleCategory;
+use rusqlite::types::ValueRef;
+use rusqlite::{params, Connection};
+use sample::Sample;
+use std::io::{Error, Result};
+use truncate::{StackTruncation, TruncatedFrame};
+
+
+/// Layout of the database
+const SCHEMA: &str = "
+    CREATE TABLE samples (
+        sample_id INTEGER PRIMARY KEY,
+        comm TEXT,
+        pid INTEGER,
+        tid INTEGER,
+        cpu INTEGER,
+        time REAL,
+        period INTEGER,
+        event TEXT,
+        category TEXT NOT NULL,
+        depth INTEGER NOT NULL,
+        stack_id TEXT
+    );
+    CREATE TABLE frames (
+        sample_id INTEGER NOT NULL,
+        depth INTEGER NOT NULL,
+        ip INTEGER,
+        symbol TEXT NOT NULL,
+        dso TEXT NOT NULL
+    );
+";
+
+
+/// In-memory database of the analyzed samples. Insertion errors are deferred
+/// until the database is queried, so that they do not get in the way of the
+/// analysis itself.
+pub struct SampleDatabase {
+    connection: Connection,
+
+    /// Identifier of the next sample
+    next_sample_id: i64,
+
+    /// Cap on the number of frames which are inserted per sample
+    truncation: StackTruncation,
+
+    /// First error which occurred while inserting samples, if any
+    error: Option<Error>,
+}
+//
+impl SampleDatabase {
+    /// Set up an empty database
+    pub fn new() -> Result<Self> {
+        let connection = Connection::open_in_memory().map_err(Error::other)?;
+        connection.execute_batch(SCHEMA).map_err(Error::other)?;
+        // Inserting rows one transaction at a time would be very slow
+        connection.execute_batch("BEGIN").map_err(Error::other)?;
+        Ok(Self {
+            connection,
+            next_sample_id: 0,
+            truncation: StackTruncation::none(),
+            error: None,
+        })
+    }
+
+    /// Replace the middle frames of very deep stacks with a single marker row
+    pub fn with_stack_truncation(mut self, truncation: StackTruncation) -> Self {
+        self.truncation = truncation;
+        self
+    }
+
+    /// Add a newly classified sample to the database
+    pub fn record(&mut self, sample: &Sample, category: &SampleCategory) {
+        if self.error.is_none() {
+            if let Err(e) = self.try_record(sample, category) {
+                self.error = Some(Error::other(e));
+            }
+        }
+    }
+
+    /// Add a sample to the database, reporting errors immediately
+    fn try_record(&mut self,
+                  sample: &Sample,
+                  category: &SampleCategory) -> rusqlite::Result<()> {
+        let sample_id = self.next_sample_id;
+        self.next_sample_id += 1;
+        let mut insert_frame = self.connection.prepare_cached(
+            "INSERT INTO frames VALUES (?1, ?2, ?3, ?4, ?5)"
+        )?;
+        let mut depth = 0;
+        for frame in self.truncation.frames(sample) {
+            match frame {
+                TruncatedFrame::Frame(frame) => {
+                    let ip = u64::from_str_radix(frame.ip, 16).ok().map(|ip| ip as i64);
+                    insert_frame.execute(params![sample_id,
+                                                 depth,
+                                                 ip,
+                                                 frame.symbol,
+                                                 frame.dso])?;
+                    depth += 1;
+                },
+                TruncatedFrame::Elided { num_frames, marker } => {
+                    insert_frame.execute(params![sample_id,
+                                                 depth,
+                                                 None::<i64>,
+                                                 marker,
+                                                 ""])?;
+                    depth += num_frames;
+                },
+            }
+        }
+        let mut insert_sample = self.connection.prepare_cached(
+            "INSERT INTO samples VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)"
+        )?;
+        insert_sample.execute(params![
+            sample_id,
+            sample.comm(),
+            sample.pid(),
+            sample.tid(),
+            sample.cpu(),
+            sample.timestamp(),
+            sample.period().map(|period| period as i64),
+            sample.event(),
+            category.key(),
+            depth,
+            sample.stack_id().map(|id| id.to_string()),
+        ])?;
+        Ok(())
+    }
+
+    /// Run an SQL query on the samples
+    pub fn query(&mut self, sql: &str) -> Result<QueryResult> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        if !self.connection.is_autocommit() {
+            self.connection.execute_batch("COMMIT").map_err(Error::other)?;
+        }
+        let mut statement = self.connection.prepare(sql).map_err(Error::other)?;
+        let columns = statement.column_names()
+                               .into_iter()
+                               .map(str::to_owned)
+                               .collect::<Vec<_>>();
+        let mut rows = statement.query([]).map_err(Error::other)?;
+        let mut result = QueryResult { columns, rows: Vec::new() };
+        while let Some(row) = rows.next().map_err(Error::other)? {
+            let cells = (0..result.columns.len()).map(|idx| {
+                Ok(match row.get_ref(idx)? {
+                    ValueRef::Null => String::new(),
+                    ValueRef::Integer(n) => n.to_string(),
+                    ValueRef::Real(x) => x.to_string(),
+                    ValueRef::Text(text) | ValueRef::Blob(text) => {
+                        String::from_utf8_lossy(text).into_owned()
+                    },
+                })
+            }).collect::<rusqlite::Result<_>>().map_err(Error::other)?;
+            result.rows.push(cells);
+        }
+        Ok(result)
+    }
+}
+///
+///
+/// Result of an SQL query, in textual form
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QueryResult {
+    /// Names of the result columns
+    pub columns: Vec<String>,
+
+    /// Rows of the result, where NULL values are empty strings
+    pub rows: Vec<Vec<String>>,
+}
+//
+impl QueryResult {
+    /// Print the result as an aligned table
+    pub fn print_table(&self) {
+        let widths = (0..self.columns.len()).map(|idx| {
+            self.rows.iter()
+                     .map(|row| row[idx].chars().count())
+                     .chain(Some(self.columns[idx].chars().count()))
+                     .max()
+                     .unwrap_or(0)
+        }).collect::<Vec<_>>();
+        let print_row = |cells: &[String]| {
+            let line = cells.iter().zip(&widths)
+                                   .map(|(cell, &width)| format!("{:width$}", cell))
+                                   .collect::<Vec<_>>()
+                                   .join(" | ");
+            println!("{}", line.trim_end());
+        };
+        print_row(&self.columns);
+        let separator = widths.iter().map(|&width| "-".repeat(width)).collect::<Vec<_>>();
+        println!("{}", separator.join("-+-"));
+        for row in &self.rows {
+            print_row(row);
+        }
+    }
+
+    /// Print the result as CSV
+    pub fn print_csv(&self) {
+        let print_row = |cells: &[String]| {
+            let cells = cells.iter().map(|cell| {
+                if cell.contains([',', '"', '\n']) {
+                    format!("\"{}\"", cell.replace('"', "\"\""))
+                } else {
+                    cell.clone()
+                }
+            }).collect::<Vec<_>>();
+            println!("{}", cells.join(","));
+        };
+        print_row(&self.columns);
+        for row in &self.rows {
+            print_row(row);
+        }
+    }
+}
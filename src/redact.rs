@@ -0,0 +1,227 @@
+//! Redaction of the names which may reveal what was profiled, so that the
+//! analysis of a proprietary application can be shared in a bug report
+//!
+//! Command names, host names and the paths of the DSOs which are not part of
+//! the operating system are replaced by digests of themselves. The same name
+//! always gets the same digest, and paths keep their directory structure and
+//! file extensions, so the analysis is unchanged by redaction, except for the
+//! checks which look DSOs up on disk. Symbols are not redacted, since they are
+//! what the analysis is about.
+
+use jvm;
+use sample::{self, Sample, StackFrame};
+use std::borrow::Cow;
+
+
+/// Directories whose files belong to the operating system, whose paths are
+/// kept as is since they tell which libraries were involved
+const SYSTEM_DIRS: &[&str] = &[
+    "/usr/", "/lib/", "/lib32/", "/lib64/", "/bin/", "/sbin/", "/etc/",
+    "/system/", "/apex/", "/proc/", "/dev/", "/sys/",
+];
+
+/// Prefixes of the pseudo-paths of anonymous executable memory, which the
+/// kernel names after what the memory was created with
+const ANONYMOUS_PREFIXES: &[&str] = &["/SYSV", "/[aio]"];
+
+
+/// Digest of a name, which replaces it in the output
+pub fn redact_name(name: &str) -> String {
+    format!("{:08x}", sample::fnv1a(sample::FNV_OFFSET_BASIS, name.as_bytes()) as u32)
+}
+
+
+/// Redact the components of a DSO path, keeping the directory structure and
+/// the file extension. Pseudo-DSOs like "[vdso]" or "//anon", system files,
+/// perf maps and jitdump DSOs are kept as is, since the analysis relies on
+/// their names and they do not tell what was profiled.
+pub fn redact_path(path: &str) -> Cow<'_, str> {
+    if !path.starts_with('/') || path.starts_with("//") ||
+       SYSTEM_DIRS.iter().any(|dir| path.starts_with(dir)) ||
+       ANONYMOUS_PREFIXES.iter().any(|prefix| path.starts_with(prefix)) ||
+       jvm::is_perf_map(path)
+    {
+        return Cow::Borrowed(path);
+    }
+    let mut components = path[1..].split('/').collect::<Vec<_>>();
+    let file_name = components.pop().unwrap_or("");
+    let mut redacted = String::with_capacity(path.len());
+    for component in components {
+        redacted.push('/');
+        redacted.push_str(&redact_name(component));
+    }
+    redacted.push('/');
+    if let Some(name) = file_name.strip_prefix("memfd:") {
+        redacted.push_str("memfd:");
+        redacted.push_str(&redact_name(name));
+    } else if jvm::is_jitdump_dso(file_name) {
+        redacted.push_str(file_name);
+    } else {
+        // Shared library versions are kept along with the extension
+        let extension_start = file_name.find(".so")
+                                       .or_else(|| file_name.rfind('.'))
+                                       .filter(|&pos| pos > 0)
+                                       .unwrap_or(file_name.len());
+        redacted.push_str(&redact_name(&file_name[..extension_start]));
+        redacted.push_str(&file_name[extension_start..]);
+    }
+    Cow::Owned(redacted)
+}
+
+
+/// Copy of a sample where the command name and DSO paths are redacted, along
+/// with any other path in the header. The copy is built in a caller-provided
+/// buffer, so that it can be reused.
+pub fn redact_sample<'b>(sample: &Sample, buffer: &'b mut Vec<u8>) -> Sample<'b> {
+    buffer.clear();
+
+    // The command name is located in the raw header, since it need not be
+    // valid UTF-8. If no PID column tells where it ends, the whole leading
+    // field is redacted, so that the name never leaks.
+    let header = sample.header;
+    let comm_start = header.iter()
+                           .position(|b| !b.is_ascii_whitespace())
+                           .unwrap_or(header.len());
+    let comm_end = match pid_column_start(header) {
+        Some(pid_start) => {
+            header[..pid_start].iter()
+                               .rposition(|b| !b.is_ascii_whitespace())
+                               .map_or(comm_start, |pos| pos + 1)
+        },
+        None => {
+            header[comm_start..].iter()
+                                .position(|b| b.is_ascii_whitespace())
+                                .map_or(header.len(), |len| comm_start + len)
+        },
+    };
+    buffer.extend_from_slice(&header[..comm_start]);
+    if comm_end > comm_start {
+        let comm = String::from_utf8_lossy(&header[comm_start..comm_end]);
+        buffer.extend_from_slice(redact_name(&comm).as_bytes());
+    }
+    redact_words(&String::from_utf8_lossy(&header[comm_end..]), buffer, redact_path);
+    let header_len = buffer.len();
+    for line in sample.stack_trace.split_inclusive(|&b| b == b'\n') {
+        match StackFrame::parse_bytes(line) {
+            Some(frame) => {
+                // The DSO is replaced, keeping the markers that follow it
+                let dso_start = frame.dso.as_ptr() as usize - line.as_ptr() as usize;
+                buffer.extend_from_slice(&line[..dso_start]);
+                buffer.extend_from_slice(redact_path(frame.dso).as_bytes());
+                buffer.extend_from_slice(&line[dso_start + frame.dso.len()..]);
+            },
+            None => buffer.extend_from_slice(line),
+        }
+    }
+    Sample::from_raw(buffer, header_len)
+}
+
+
+/// Offset of the "pid" or "pid/tid" column of a raw sample header, which
+/// follows the (possibly space-separated) name of the sampled thread
+fn pid_column_start(header: &[u8]) -> Option<usize> {
+    let is_pid = |column: &[u8]| {
+        column.split(|&b| b == b'/').all(|id| {
+            !id.is_empty() && id.iter().all(u8::is_ascii_digit)
+        })
+    };
+    header.split(u8::is_ascii_whitespace)
+          .filter(|column| !column.is_empty())
+          .skip(1)
+          .find(|column| is_pid(column))
+          .map(|column| column.as_ptr() as usize - header.as_ptr() as usize)
+}
+
+
+/// Redact the header that perf script prints with --header, in which the
+/// host name and the command line of perf record may be sensitive
+pub fn redact_perf_header(perf_header: &str) -> String {
+    let mut redacted = Vec::with_capacity(perf_header.len());
+    for line in perf_header.split_inclusive('\n') {
+        let (key, value) = match line.split_once(" : ") {
+            Some(field) => field,
+            None => {
+                redacted.extend_from_slice(line.as_bytes());
+                continue;
+            },
+        };
+        redacted.extend_from_slice(key.as_bytes());
+        redacted.extend_from_slice(b" : ");
+        match key.trim() {
+            "# hostname" => redact_words(value, &mut redacted, |name| {
+                Cow::Owned(redact_name(name))
+            }),
+            "# cmdline" => {
+                redacted.extend_from_slice(redact_command_line(value).as_bytes())
+            },
+            _ => redacted.extend_from_slice(value.as_bytes()),
+        }
+    }
+    String::from_utf8(redacted).expect("redaction should keep text valid UTF-8")
+}
+
+
+/// Redact a perf command line, keeping only perf itself, its subcommands, its
+/// options and their numerical values
+pub fn redact_command_line(command_line: &str) -> String {
+    const PERF_WORDS: &[&str] = &["perf", "record", "script"];
+    let mut redacted = Vec::with_capacity(command_line.len());
+    redact_words(command_line, &mut redacted, |word| {
+        let is_kept = word.starts_with('-') || PERF_WORDS.contains(&word) ||
+                      word.ends_with("/perf") ||
+                      word.bytes().all(|b| b.is_ascii_digit());
+        if is_kept {
+            Cow::Borrowed(word)
+        } else if word.starts_with('/') {
+            redact_path(word)
+        } else {
+            Cow::Owned(redact_name(word))
+        }
+    });
+    String::from_utf8(redacted).expect("redaction should keep text valid UTF-8")
+}
+
+
+/// Redact the whitespace-separated words of some text, keeping the whitespace
+fn redact_words<F>(text: &str, buffer: &mut Vec<u8>, redact: F)
+    where F: Fn(&str) -> Cow<'_, str>
+{
+    let mut rest = text;
+    while !rest.is_empty() {
+        let word_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        if word_end > 0 {
+            buffer.extend_from_slice(redact(&rest[..word_end]).as_bytes());
+        }
+        let space_end = rest[word_end..].find(|c: char| !c.is_whitespace())
+                                        .map_or(rest.len(), |pos| word_end + pos);
+        buffer.extend_from_slice(&rest.as_bytes()[word_end..space_end]);
+        rest = &rest[space_end..];
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_utf8_comm() {
+        let header = b"secret\xffapp 12/12 [000] 1.000000: 1000 cycles:\n";
+        let mut text = header.to_vec();
+        text.extend_from_slice(b"\t    7f00 main (/opt/secret/app)\n");
+        let sample = Sample::from_raw(&text, header.len());
+        let mut buffer = Vec::new();
+        let redacted = redact_sample(&sample, &mut buffer);
+        let expected = format!("{} 12/12 [000] 1.000000: 1000 cycles:\n",
+                               redact_name("secret\u{fffd}app"));
+        assert_eq!(redacted.header, expected.as_bytes());
+        assert!(!redacted.stack_trace.windows(6).any(|w| w == b"secret"));
+
+        // Without a PID column, the leading field is redacted as a whole
+        let header = b"secret\xffapp garbage\n";
+        let sample = Sample::from_raw(header, header.len());
+        let redacted = redact_sample(&sample, &mut buffer);
+        let expected = format!("{} garbage\n", redact_name("secret\u{fffd}app"));
+        assert_eq!(redacted.header, expected.as_bytes());
+    }
+}
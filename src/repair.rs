@@ -0,0 +1,103 @@
+//! Best-effort repair of truncated stacks, using complete stacks which were
+//! recently observed on the same thread. If the outermost frames which could
+//! be unwound match a complete stack, its remaining frames are likely to be
+//! those which the truncated stack is missing.
+//!
+//! This is only a guess, so repaired frames are flagged with an "(inferred)"
+//! marker after their DSO, and should not be trusted for anything but reports.
+
+use analysis::SampleCategory;
+use sample::{OwnedFrame, Sample};
+use std::collections::{HashMap, VecDeque};
+
+
+/// Number of recent complete stacks which are remembered for each thread
+const MAX_STACKS_PER_THREAD: usize = 16;
+
+/// Maximal time between a truncated stack and the complete stack that it is
+/// repaired from, in seconds
+const MAX_TIME_GAP: f64 = 0.1;
+
+/// Number of outermost known frames which must match a complete stack
+const NUM_MATCHED_FRAMES: usize = 2;
+
+
+/// Memory of recent complete stacks, used to repair truncated ones
+#[derive(Default)]
+pub struct StackRepair {
+    /// Recent complete stacks of each thread, from oldest to newest
+    threads: HashMap<u32, VecDeque<CompleteStack>>,
+}
+//
+impl StackRepair {
+    /// Start with no known stacks
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remember the stack of a newly classified sample if it is complete
+    pub fn observe(&mut self, sample: &Sample, category: &SampleCategory) {
+        let tid = match (category, sample.tid()) {
+            (&SampleCategory::Normal, Some(tid)) => tid,
+            _ => return,
+        };
+        let stacks = self.threads.entry(tid).or_default();
+        if stacks.len() == MAX_STACKS_PER_THREAD {
+            stacks.pop_front();
+        }
+        stacks.push_back(CompleteStack {
+            time: sample.timestamp(),
+            frames: sample.frames().map(OwnedFrame::from).collect(),
+        });
+    }
+
+    /// Try to repair a truncated stack. The repaired sample is built in a
+    /// caller-provided buffer, so that it can be reused across samples.
+    pub fn repair<'b>(&self,
+                      sample: &Sample,
+                      buffer: &'b mut Vec<u8>) -> Option<Sample<'b>> {
+        // Find the frames which could be unwound
+        let frames = sample.frames().collect::<Vec<_>>();
+        let num_known = frames.iter().rposition(|frame| !frame.is_unknown_symbol())? + 1;
+        let known = &frames[num_known.saturating_sub(NUM_MATCHED_FRAMES)..num_known];
+
+        // Look for the most recent complete stack of the same thread which
+        // contains these frames, and was observed around the same time
+        let time = sample.timestamp();
+        let stacks = self.threads.get(&sample.tid()?)?;
+        let is_nearby = |stack: &&CompleteStack| match (time, stack.time) {
+            (Some(time), Some(stack_time)) => (time - stack_time).abs() <= MAX_TIME_GAP,
+            _ => true,
+        };
+        let (stack, end) = stacks.iter().rev().filter(is_nearby).find_map(|stack| {
+            let start = stack.frames.windows(known.len()).position(|window| {
+                window.iter().zip(known).all(|(complete, known)| {
+                    complete.symbol == known.symbol && complete.dso == known.dso
+                })
+            })?;
+            Some((stack, start + known.len()))
+        })?;
+        if end == stack.frames.len() {
+            return None;
+        }
+
+        // Complete the truncated stack with the missing frames
+        sample.truncated(num_known, buffer);
+        for frame in &stack.frames[end..] {
+            let frame = OwnedFrame { inferred: true, ..frame.clone() };
+            buffer.extend_from_slice(frame.to_script_text().as_bytes());
+            buffer.push(b'\n');
+        }
+        Some(Sample::from_raw(buffer, sample.header.len()))
+    }
+}
+
+
+/// Complete stack which was observed on some thread
+struct CompleteStack {
+    /// Time at which the stack was sampled, if known
+    time: Option<f64>,
+
+    /// Frames of the stack, from the leaf function to the root
+    frames: Vec<OwnedFrame>,
+}
@@ -0,0 +1,372 @@
+//! Interactive exploration of an analysis archive, in the spirit of perf
+//! report's interactive mode. The samples of the archive are narrowed down
+//! with filter expressions and sample categories, and the current selection can
+//! then be summarized, broken down by function, or dumped.
+//!
+//! Every command goes through the archive again, which is mapped in memory, so
+//! that archives of any size can be explored without loading them whole.
+
+use analysis::{SampleAnalyzer, SampleCategory};
+use archive::ArchiveSamples;
+use config::Config;
+use filter::Filter;
+use hotspots::FunctionStatistics;
+use redact;
+use sample::{self, Sample};
+use significance::Significance;
+use stats::Statistics;
+use std::io::{self, BufRead, Result, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use truncate::StackTruncation;
+
+
+/// Number of entries printed by the top and dump commands by default
+const DEFAULT_COUNT: usize = 10;
+
+/// Description of the commands, printed by the help command
+const HELP: &str = "\
+Commands:
+  filter <expr>      Select the samples which match a filter expression, e.g.
+                     comm == \"firefox\" && depth > 10
+  category <key>     Select the samples of a category, e.g. truncated_stack
+  clear              Select all samples again
+  selection          Print the current selection
+  count              Count the selected samples
+  summary            Print the category breakdown of the selected samples
+  top [N]            Print the N functions with the most selected samples
+  dump [N]           Print the first N selected samples
+  show <number>      Print one sample of the archive along with its category
+  results            Print the summary of the analysis which saved the archive,
+                     regardless of the selection
+  help               Print this help
+  quit               Leave the prompt";
+
+
+/// Interactive session over the samples of an analysis archive
+pub struct Repl<'a> {
+    /// Archive which is being explored
+    path: PathBuf,
+
+    /// Mechanism used to classify the samples
+    analyzer: &'a SampleAnalyzer,
+
+    /// Configuration, which tells the severity of each category
+    config: &'a Config,
+
+    /// Filter expression which selected samples must match, if any, along
+    /// with its text
+    filter: Option<(String, Filter)>,
+
+    /// Category which selected samples must belong to, if any
+    category: Option<String>,
+
+    /// Cap on the number of frames of printed stacks
+    truncation: StackTruncation,
+
+    /// Level of the confidence intervals of the summary, if any
+    confidence: Option<f64>,
+
+    /// How entries with few samples are treated in reports
+    significance: Significance,
+
+    /// Truth that names are redacted in printed samples
+    redact: bool,
+}
+//
+impl<'a> Repl<'a> {
+    /// Explore some archive, classifying samples with some analyzer
+    pub fn new(path: PathBuf, analyzer: &'a SampleAnalyzer, config: &'a Config) -> Self {
+        Self {
+            path,
+            analyzer,
+            config,
+            filter: None,
+            category: None,
+            truncation: StackTruncation::none(),
+            confidence: None,
+            significance: Significance::new(),
+            redact: false,
+        }
+    }
+
+    /// Replace the middle frames of very deep stacks with a marker line
+    pub fn with_stack_truncation(mut self, truncation: StackTruncation) -> Self {
+        self.truncation = truncation;
+        self
+    }
+
+    /// Display confidence intervals of some level in the summary
+    pub fn with_confidence(mut self, level: f64) -> Self {
+        self.confidence = Some(level);
+        self
+    }
+
+    /// Flag or hide report entries with few samples as specified
+    pub fn with_significance(mut self, significance: Significance) -> Self {
+        self.significance = significance;
+        self
+    }
+
+    /// Redact names in the samples which are printed
+    pub fn with_redaction(mut self) -> Self {
+        self.redact = true;
+        self
+    }
+
+    /// Read commands from some input and run them, until the input ends or the
+    /// user quits. A prompt is printed before each command.
+    pub fn run<R: BufRead>(&mut self, input: R) -> Result<()> {
+        println!("Exploring {}, type help for a list of commands", self.path.display());
+        let mut lines = input.lines();
+        loop {
+            print!("> ");
+            io::stdout().flush()?;
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => {
+                    println!();
+                    return Ok(());
+                },
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match line.parse() {
+                Ok(Command::Quit) => return Ok(()),
+                Ok(command) => {
+                    if let Err(e) = self.execute(command) {
+                        eprintln!("Failed to read {}: {}", self.path.display(), e);
+                    }
+                },
+                Err(message) => eprintln!("{}", message),
+            }
+        }
+    }
+
+    /// Run one command
+    pub fn execute(&mut self, command: Command) -> Result<()> {
+        match command {
+            Command::Help => println!("{}", HELP),
+            Command::Filter { text, filter } => {
+                self.filter = Some((text, filter));
+                self.print_selection();
+            },
+            Command::Category(key) => {
+                self.category = Some(key);
+                self.print_selection();
+            },
+            Command::Clear => {
+                self.filter = None;
+                self.category = None;
+                self.print_selection();
+            },
+            Command::Selection => self.print_selection(),
+            Command::Count => {
+                let mut count = 0;
+                self.for_each_selected(|_, _, _| {
+                    count += 1;
+                    true
+                })?;
+                println!("{} selected samples", count);
+            },
+            Command::Summary => {
+                let mut stats = Statistics::new();
+                self.for_each_selected(|_, _, category| {
+                    stats.record(category);
+                    true
+                })?;
+                stats.print_summary(self.config, self.confidence, &self.significance);
+            },
+            Command::Top(count) => {
+                let mut functions = FunctionStatistics::new(count);
+                self.for_each_selected(|_, sample, category| {
+                    functions.record(sample, category);
+                    true
+                })?;
+                functions.print_report(&self.significance);
+            },
+            Command::Dump(count) => {
+                let mut num_dumped = 0;
+                self.for_each_selected(|number, sample, category| {
+                    if num_dumped == count {
+                        return false;
+                    }
+                    self.print_sample(number, sample, category.key());
+                    num_dumped += 1;
+                    true
+                })?;
+                if num_dumped == 0 {
+                    println!("No sample is selected");
+                }
+            },
+            Command::Show(wanted) => self.show(wanted)?,
+            Command::Results => {
+                let mut samples = ArchiveSamples::open(&self.path)?;
+                while samples.next()?.is_some() {}
+                match samples.results() {
+                    Some(results) => {
+                        results.print_estimate_banner();
+                        results.print_summary(self.config,
+                                              self.confidence,
+                                              &self.significance);
+                    },
+                    None => println!("The archive does not contain analysis results"),
+                }
+            },
+            Command::Quit => {},
+        }
+        Ok(())
+    }
+
+    /// Describe the current selection
+    fn print_selection(&self) {
+        match (&self.filter, &self.category) {
+            (None, None) => println!("All samples are selected"),
+            (filter, category) => {
+                if let Some((text, _)) = filter {
+                    println!("Filter: {}", text);
+                }
+                if let Some(category) = category {
+                    println!("Category: {}", category);
+                }
+            },
+        }
+    }
+
+    /// Print the sample with some number, whether it is selected or not
+    fn show(&self, wanted: usize) -> Result<()> {
+        let mut samples = ArchiveSamples::open(&self.path)?;
+        let mut number = 0;
+        while let Some(sample) = samples.next()? {
+            if is_record(&sample) {
+                continue;
+            }
+            if number == wanted {
+                let category = self.analyzer.classify(&sample);
+                self.print_sample(number, &sample, category.key());
+                println!("Severity: {}", self.config.severity(&category));
+                if let Some(hint) = category.hint() {
+                    println!("Hint: {}", hint);
+                }
+                return Ok(());
+            }
+            number += 1;
+        }
+        println!("No such sample, the archive has {} samples", number);
+        Ok(())
+    }
+
+    /// Print a sample with its number and category
+    fn print_sample(&self, number: usize, sample: &Sample, category: &str) {
+        let mut redact_buffer = Vec::new();
+        let redacted = if self.redact {
+            Some(redact::redact_sample(sample, &mut redact_buffer))
+        } else {
+            None
+        };
+        let sample = redacted.as_ref().unwrap_or(sample);
+        match sample.stack_id() {
+            Some(stack_id) => {
+                println!("Sample #{} [{}] (stack {}):", number, category, stack_id)
+            },
+            None => println!("Sample #{} [{}]:", number, category),
+        }
+        print!("{}", self.truncation.raw_text(sample));
+        println!();
+    }
+
+    /// Go through the selected samples of the archive in order, along with
+    /// their number and category, until the callback returns false
+    fn for_each_selected<F>(&self, mut callback: F) -> Result<()>
+        where F: FnMut(usize, &Sample, &SampleCategory) -> bool
+    {
+        let mut samples = ArchiveSamples::open(&self.path)?;
+        let mut number = 0;
+        while let Some(sample) = samples.next()? {
+            if is_record(&sample) {
+                continue;
+            }
+            number += 1;
+            if !self.filter.as_ref().is_none_or(|(_, filter)| filter.matches(&sample)) {
+                continue;
+            }
+            let category = self.analyzer.classify(&sample);
+            if self.category.as_ref().is_some_and(|key| key != category.key()) {
+                continue;
+            }
+            if !callback(number - 1, &sample, &category) {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+///
+///
+/// Command of the interactive prompt
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Help,
+    Filter { text: String, filter: Filter },
+    Category(String),
+    Clear,
+    Selection,
+    Count,
+    Summary,
+    Top(usize),
+    Dump(usize),
+    Show(usize),
+    Results,
+    Quit,
+}
+//
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(line: &str) -> ::std::result::Result<Self, String> {
+        let line = line.trim();
+        let (name, argument) = match line.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, Some(argument.trim())),
+            None => (line, None),
+        };
+        let count = |argument: Option<&str>| match argument {
+            None => Ok(DEFAULT_COUNT),
+            Some(count) => count.parse().map_err(|_| {
+                format!("Invalid count {}, expected a number", count)
+            }),
+        };
+        match (name, argument) {
+            ("help", None) => Ok(Command::Help),
+            ("filter", Some(text)) => {
+                let filter = text.parse()?;
+                Ok(Command::Filter { text: text.to_owned(), filter })
+            },
+            ("category", Some(key)) => Ok(Command::Category(key.to_owned())),
+            ("clear", None) => Ok(Command::Clear),
+            ("selection", None) => Ok(Command::Selection),
+            ("count", None) => Ok(Command::Count),
+            ("summary", None) => Ok(Command::Summary),
+            ("top", argument) => count(argument).map(Command::Top),
+            ("dump", argument) => count(argument).map(Command::Dump),
+            ("show", Some(number)) => number.parse().map(Command::Show).map_err(|_| {
+                format!("Invalid sample number {}, expected a number", number)
+            }),
+            ("results", None) => Ok(Command::Results),
+            ("quit", None) | ("exit", None) => Ok(Command::Quit),
+            ("filter", None) | ("category", None) | ("show", None) => {
+                Err(format!("The {} command takes an argument, type help for \
+                             details", name))
+            },
+            _ => Err(format!("Unknown command {}, type help for a list of commands",
+                             line)),
+        }
+    }
+}
+
+
+/// Truth that a sample is actually a side-band or trace error record, which
+/// is not numbered
+fn is_record(sample: &Sample) -> bool {
+    sample::is_sideband_record(sample.header) || sample::is_trace_error(sample.header)
+}
@@ -0,0 +1,212 @@
+//! Knowledge about the stack traces produced by various language runtimes,
+//! which can be selected as presets to extend the analysis
+
+use std::path::Path;
+use std::str::FromStr;
+
+
+/// Language runtimes which we know something about
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Runtime {
+    /// Native code, as produced by C or C++ compilers. Always enabled.
+    Native,
+    Rust,
+
+    /// Rust async executors: tokio, async-std and smol
+    Tokio,
+
+    Jvm,
+    Python,
+    Go,
+
+    /// Windows programs running on Linux through Wine or Proton
+    Wine,
+
+    /// Node.js, and other V8 embedders which emit perf maps
+    Node,
+
+    /// WebAssembly runtimes: wasmtime and V8 (Node.js, Chromium)
+    Wasm,
+
+    /// Programs which drive a GPU through Mesa, amdgpu, i915 or NVIDIA drivers
+    Gpu,
+}
+//
+impl Runtime {
+    /// Functions which are expected to be found at the root of stack traces
+    pub fn expected_root_funcs(self) -> &'static [&'static str] {
+        match self {
+            Runtime::Native => &[
+                "_start",
+                "__libc_start_main",
+                "_dl_start_user",
+                "__clone",
+                "__clone3",
+                "start_thread",
+                "thread_start",
+            ],
+            Runtime::Rust | Runtime::Tokio => &[
+                "std::sys::unix::thread::Thread::new::thread_start",
+                "std::sys::pal::unix::thread::Thread::new::thread_start",
+            ],
+            Runtime::Jvm => &[
+                "thread_native_entry",
+                "java_start",
+                "JavaMain",
+                "ThreadJavaMain",
+            ],
+            Runtime::Python => &[
+                "pythread_wrapper",
+                "t_bootstrap",
+            ],
+            // The Go scheduler switches goroutine stacks in these functions,
+            // which perf cannot unwind through
+            Runtime::Go => &[
+                "runtime.goexit",
+                "runtime.rt0_go",
+                "runtime.mstart",
+                "runtime.mcall",
+                "runtime.systemstack",
+                "runtime.morestack",
+            ],
+            Runtime::Wine => &[
+                "RtlUserThreadStart",
+                "BaseThreadInitThunk",
+                "call_thread_func",
+                "signal_start_thread",
+            ],
+            // Node starts its main thread, libuv workers and V8 platform
+            // workers through these
+            Runtime::Node => &[
+                "node::Start",
+                "uv__thread_start",
+                "node::worker::Worker::StartThread",
+                "node::(anonymous namespace)::PlatformWorkerThread",
+            ],
+            // wasmtime runs async wasm code on fibers, whose stacks start here
+            Runtime::Wasm => &[
+                "wasmtime_fiber_start",
+            ],
+            // Driver worker threads, which are often the last frame that can
+            // be unwound when the driver was built without frame pointers
+            Runtime::Gpu => &[
+                "impl_thrd_routine",
+                "util_queue_thread_func",
+                "drm_sched_main",
+                "drm_sched_run_job_work",
+            ],
+        }
+    }
+
+    /// File names of DSOs which are known to break stack traces
+    pub fn known_bad_dsos(self) -> &'static [&'static str] {
+        match self {
+            // Proprietary Windows graphics drivers come without unwind info
+            Runtime::Wine => &[
+                "nvd3dumx.dll",
+                "nvwgf2umx.dll",
+                "nvoglv64.dll",
+                "atidxx64.dll",
+                "atiumd64.dll",
+                "amdxc64.dll",
+                "igd10iumd64.dll",
+                "igdumdim64.dll",
+            ],
+            // NVIDIA's proprietary Linux drivers come without unwind info too
+            Runtime::Gpu => &[
+                "nvidia_drv.so",
+                "libGLX_nvidia.so",
+                "libEGL_nvidia.so",
+                "libnvidia-glcore.so",
+                "libnvidia-eglcore.so",
+                "libnvidia-glsi.so",
+                "libnvidia-gpucomp.so",
+                "libnvidia-glvkspirv.so",
+                "libnvidia-rtcore.so",
+                "libcuda.so",
+            ],
+            _ => &[],
+        }
+    }
+}
+//
+impl FromStr for Runtime {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "native" => Ok(Runtime::Native),
+            "rust" => Ok(Runtime::Rust),
+            "tokio" => Ok(Runtime::Tokio),
+            "jvm" => Ok(Runtime::Jvm),
+            "python" => Ok(Runtime::Python),
+            "go" => Ok(Runtime::Go),
+            "wine" => Ok(Runtime::Wine),
+            "node" => Ok(Runtime::Node),
+            "wasm" => Ok(Runtime::Wasm),
+            "gpu" => Ok(Runtime::Gpu),
+            _ => Err(format!("Unknown runtime {}, expected one of native, rust, \
+                              tokio, jvm, python, go, wine, node, wasm or gpu", name)),
+        }
+    }
+}
+
+
+/// Truth that a function is part of the transition between Go and C code. Go
+/// and C use different stacks and calling conventions, so unwinding across
+/// these frames usually fails.
+pub fn is_cgo_transition(symbol: &str) -> bool {
+    matches!(symbol,
+             "runtime.cgocall" | "runtime.asmcgocall" | "runtime.cgocallback" |
+             "runtime.cgocallbackg" | "crosscall2") ||
+    symbol.starts_with("_cgo_")
+}
+
+
+/// File name of a DSO, which is more stable than its full path when the DSO
+/// is a Windows library (whose location depends on the Wine prefix)
+pub fn dso_file_name(dso: &str) -> &str {
+    Path::new(dso).file_name().and_then(|name| name.to_str()).unwrap_or(dso)
+}
+
+/// Remove the version suffix of a shared library's file name, which changes
+/// with every driver release (e.g. "libnvidia-glcore.so.550.54.14")
+pub fn strip_so_version(file_name: &str) -> &str {
+    match file_name.find(".so.") {
+        Some(pos) => &file_name[..pos + 3],
+        None => file_name,
+    }
+}
+
+/// Truth that a DSO is a Windows executable or library
+pub fn is_pe_dso(dso: &str) -> bool {
+    let extension = Path::new(dso).extension().and_then(|ext| ext.to_str());
+    extension.is_some_and(|ext| {
+        ext.eq_ignore_ascii_case("dll") || ext.eq_ignore_ascii_case("exe")
+    })
+}
+
+/// Truth that a DSO is Wine's preloader, which is where Wine processes start
+pub fn is_wine_preloader(dso: &str) -> bool {
+    matches!(dso_file_name(dso), "wine-preloader" | "wine64-preloader")
+}
+
+
+/// Remove the hash that Rust's legacy symbol mangling appends to function
+/// names, which perf keeps when demangling (e.g. "foo::bar::h0123456789abcdef")
+pub fn strip_rust_hash(symbol: &str) -> &str {
+    const HASH_LEN: usize = 16;
+    const HASH_PREFIX: &str = "::h";
+    let hash_start = match symbol.len().checked_sub(HASH_LEN + HASH_PREFIX.len()) {
+        Some(start) if symbol.is_char_boundary(start) => start,
+        _ => return symbol,
+    };
+    let (name, hash) = symbol.split_at(hash_start);
+    if hash.starts_with(HASH_PREFIX) &&
+       hash[HASH_PREFIX.len()..].bytes().all(|b| b.is_ascii_hexdigit())
+    {
+        name
+    } else {
+        symbol
+    }
+}
@@ -0,0 +1,1090 @@
+//! Extraction of individual samples from perf script's textual output
+
+use memchr::memmem;
+use serde_json;
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read, Result};
+use std::mem;
+use std::str;
+
+
+/// Common interface of the mechanisms which extract samples from perf script's
+/// output, so that the analysis does not need to care where the data comes from
+pub trait SampleSource {
+    /// Extract and decode the next sample, will return Ok(None) when the end
+    /// of perf script's output is reached.
+    fn next_sample(&mut self) -> Result<Option<Sample<'_>>>;
+
+    /// Byte offset of the next sample in perf script's output
+    fn position(&self) -> u64;
+}
+
+
+/// Initial capacity of the sample buffer, large enough for typical stack traces
+const INITIAL_BUFFER_CAPACITY: usize = 16 * 1024;
+
+/// Initial state of FNV-1a digests
+pub const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+
+/// Mechanism to extract individual samples from perf script's output
+pub struct PerfSamples<Input: Read> {
+    input: BufReader<Input>,
+    buffer: Vec<u8>,
+    header_len: usize,
+    last_line_len: Option<usize>,
+    position: u64,
+    perf_header: String,
+}
+//
+impl<Input: Read> PerfSamples<Input> {
+    /// Initialize with a Rust reader plugging into the output of perf script
+    /// (can be stdin, a pipe to a child process, a file... anything goes)
+    pub fn new(input: Input) -> Self {
+        Self::with_offset(input, 0)
+    }
+
+    /// Variant of new() for inputs which do not start at the beginning of perf
+    /// script's output, but at some byte offset of it (e.g. when resuming)
+    pub fn with_offset(input: Input, offset: u64) -> Self {
+        Self {
+            input: BufReader::new(input),
+            buffer: Vec::with_capacity(INITIAL_BUFFER_CAPACITY),
+            header_len: 0,
+            last_line_len: None,
+            position: offset,
+            perf_header: String::new(),
+        }
+    }
+
+    /// Byte offset of the next sample in perf script's output
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Comment lines which were skipped so far, which make up the metadata
+    /// block that perf script prints when run with --header
+    pub fn perf_header(&self) -> &str {
+        &self.perf_header
+    }
+
+    /// Stop extracting samples and get the input back. Any input which was
+    /// read ahead, but not extracted as samples yet, is lost.
+    pub fn into_inner(self) -> Input {
+        self.input.into_inner()
+    }
+
+    // Reset the reader's state, to be invoked when moving to a new sample. The
+    // buffer keeps its capacity, so that samples are read without allocating
+    // once it has grown to the size of the largest sample.
+    fn reset(&mut self) {
+        self.buffer.clear();
+        self.header_len = 0;
+        self.last_line_len = None;
+    }
+
+    /// Extract and decode the next sample from perf script's output, will
+    /// return Ok(None) when the end of perf script's output is reached.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<Sample<'_>>> {
+        // Reset the internal state of the sample reader
+        self.reset();
+
+        // Load the first line of input. This is the sample's header, containing
+        // info such as the executable name, PID, event type, etc. Comment lines
+        // of perf script's --header block, and blank lines, are skipped.
+        loop {
+            self.header_len = self.load_next_line()?;
+            match self.buffer.first() {
+                Some(b'#') => {
+                    self.perf_header.push_str(&String::from_utf8_lossy(&self.buffer));
+                },
+                Some(b'\n') => {},
+                _ => break,
+            }
+            self.buffer.clear();
+        }
+
+        // Detect if the end of input was reached, if so report it to the caller
+        if self.header_len == 0 {
+            return Ok(None);
+        }
+
+        // Side-band and trace records only span one line, and are not followed
+        // by a blank line, so they must not swallow the next sample
+        let next = self.input.fill_buf()?.first().cloned();
+        if is_single_line_record(&self.buffer[..self.header_len], next) {
+            return Ok(Some(Sample::from_raw(&self.buffer, self.header_len)));
+        }
+
+        // Load input lines into the buffer until a newline or EOF is reached,
+        // and record the position of the last useful byte in the buffer.
+        let last_line_end = loop {
+            let line_len = self.load_next_line()?;
+            if line_len <= 1 {
+                break self.buffer.len() - line_len;
+            }
+            self.last_line_len = Some(line_len);
+        };
+
+        // Extract the last stack frame of the sample, if any
+        let buffer = &self.buffer;
+        let last_stack_frame = self.last_line_len.map(move |last_line_len| {
+            let last_line_start = last_line_end - last_line_len;
+            &buffer[last_line_start..last_line_end]
+        });
+
+        // Return the decoded sample of data
+        Ok(Some(Sample {
+            raw_sample_data: &self.buffer[..last_line_end],
+            header: &self.buffer[..self.header_len],
+            stack_trace: &self.buffer[self.header_len..last_line_end],
+            last_stack_frame,
+        }))
+    }
+
+    /// Load the next line of input into the internal buffer. This is done
+    /// without any UTF-8 validation, since perf script's output may contain
+    /// invalid bytes (e.g. in weird comm names) and validating gigabytes of
+    /// text that we mostly won't look at would be a waste of time anyway.
+    fn load_next_line(&mut self) -> Result<usize> {
+        let line_len = self.input.read_until(b'\n', &mut self.buffer)?;
+        self.position += line_len as u64;
+        Ok(line_len)
+    }
+}
+//
+impl<Input: Read> SampleSource for PerfSamples<Input> {
+    fn next_sample(&mut self) -> Result<Option<Sample<'_>>> {
+        self.next()
+    }
+
+    fn position(&self) -> u64 {
+        self.position
+    }
+}
+///
+///
+/// This struct models one stack trace from perf script. The data is kept in the
+/// form of raw bytes, as perf script does not guarantee that it emits UTF-8.
+#[derive(Debug)]
+pub struct Sample<'a> {
+    /// This is the raw sample data, if you need it for custom processing
+    pub raw_sample_data: &'a [u8],
+
+    /// Header of the sample, where infos like the process ID lie
+    pub header: &'a [u8],
+
+    /// Full stack trace of the sample, in textual form
+    pub stack_trace: &'a [u8],
+
+    /// Quick access to the last stack frame of the stack trace, if any
+    pub last_stack_frame: Option<&'a [u8]>,
+}
+//
+impl<'a> Sample<'a> {
+    /// Iterate over the decoded stack frames of this sample, from the leaf
+    /// function that was executing to the root of the stack
+    pub fn frames(&self) -> impl DoubleEndedIterator<Item=StackFrame<'a>> {
+        self.stack_trace.split(|&b| b == b'\n').filter_map(StackFrame::parse_bytes)
+    }
+
+    /// Split the stack frames of this sample at the first signal trampoline,
+    /// if any. The first iterator covers the signal handler's frames, and the
+    /// second one covers the interrupted code's frames (trampoline excluded).
+    pub fn split_at_signal(&self) -> Option<(impl Iterator<Item=StackFrame<'a>>,
+                                             impl Iterator<Item=StackFrame<'a>>)> {
+        let boundary = self.frames().position(|frame| frame.is_signal_trampoline())?;
+        Some((self.frames().take(boundary), self.frames().skip(boundary + 1)))
+    }
+
+    /// Copy of this sample which only keeps the first stack frames, from the
+    /// leaf function up to some depth. The copy is built in a caller-provided
+    /// buffer, so that it can be reused across samples.
+    pub fn truncated<'b>(&self,
+                         num_frames: usize,
+                         buffer: &'b mut Vec<u8>) -> Sample<'b> {
+        buffer.clear();
+        buffer.extend_from_slice(self.header);
+        let lines = self.stack_trace.split(|&b| b == b'\n');
+        for line in lines.filter(|line| StackFrame::parse_bytes(line).is_some())
+                         .take(num_frames)
+        {
+            buffer.extend_from_slice(line);
+            buffer.push(b'\n');
+        }
+        Sample::from_raw(buffer, self.header.len())
+    }
+
+    /// Number of stack frames which are exact duplicates of the frame right
+    /// before them, as some unwinders emit at DWARF CFI boundaries
+    pub fn num_duplicate_frames(&self) -> usize {
+        let mut lines = self.stack_trace.split(|&b| b == b'\n')
+                                        .filter(|line| !line.is_empty());
+        let mut previous = match lines.next() {
+            Some(line) => line,
+            None => return 0,
+        };
+        lines.filter(|&line| mem::replace(&mut previous, line) == line).count()
+    }
+
+    /// Caller frames of the stack trace, along with their depth, i.e. the
+    /// frames whose address is a return address. The leaf frame and the frames
+    /// interrupted by a signal are not return addresses, and are skipped.
+    pub fn callers(&self) -> impl Iterator<Item=(usize, StackFrame<'a>)> + 'a {
+        let mut is_interrupted = true;
+        self.frames().enumerate().filter(move |(_, frame)| {
+            !mem::replace(&mut is_interrupted, frame.is_signal_trampoline())
+        })
+    }
+
+    /// Depths of the caller frames which perf resolved to the first byte of
+    /// their function ("+0x0"). Return addresses follow a call instruction, so
+    /// this is a classic sign of bogus symbolization.
+    pub fn zero_offset_callers(&self) -> impl Iterator<Item=usize> + 'a {
+        self.callers().filter(|(_, frame)| frame.offset == Some(0))
+                      .map(|(depth, _)| depth)
+    }
+
+    /// Copy of this sample where consecutive duplicate stack frames are
+    /// collapsed into one, or None if it has no duplicate frame. The copy is
+    /// built in a caller-provided buffer, so that it can be reused.
+    pub fn without_duplicate_frames<'b>(&self,
+                                        buffer: &'b mut Vec<u8>) -> Option<Sample<'b>> {
+        if self.num_duplicate_frames() == 0 {
+            return None;
+        }
+        buffer.clear();
+        buffer.extend_from_slice(self.header);
+        let mut previous = None;
+        for line in self.stack_trace.split(|&b| b == b'\n')
+                                    .filter(|line| !line.is_empty())
+        {
+            if previous != Some(line) {
+                buffer.extend_from_slice(line);
+                buffer.push(b'\n');
+            }
+            previous = Some(line);
+        }
+        Some(Sample::from_raw(buffer, self.header.len()))
+    }
+
+    /// Copy of this sample where the symbols of stack frames are renamed by
+    /// some function, or None if no symbol was renamed. The copy is built in
+    /// a caller-provided buffer, so that it can be reused.
+    pub fn with_renamed_symbols<'b, F>(&self,
+                                       rename: F,
+                                       buffer: &'b mut Vec<u8>) -> Option<Sample<'b>>
+        where F: Fn(&'a str) -> &'a str
+    {
+        if self.frames().all(|frame| rename(frame.symbol).len() == frame.symbol.len()) {
+            return None;
+        }
+        buffer.clear();
+        buffer.extend_from_slice(self.header);
+        for line in self.stack_trace.split_inclusive(|&b| b == b'\n') {
+            match StackFrame::parse_bytes(line) {
+                Some(frame) => {
+                    // The symbol is replaced, wherever it is in the line
+                    let symbol_start = frame.symbol.as_ptr() as usize
+                                       - line.as_ptr() as usize;
+                    buffer.extend_from_slice(&line[..symbol_start]);
+                    buffer.extend_from_slice(rename(frame.symbol).as_bytes());
+                    buffer.extend_from_slice(&line[symbol_start + frame.symbol.len()..]);
+                },
+                None => buffer.extend_from_slice(line),
+            }
+        }
+        Some(Sample::from_raw(buffer, self.header.len()))
+    }
+
+    /// Decode a sample from its text, which is made of a header line of known
+    /// length followed by one line per stack frame
+    pub fn from_raw(raw_sample_data: &'a [u8], header_len: usize) -> Self {
+        let stack_trace = &raw_sample_data[header_len..];
+        let last_line_start = stack_trace.strip_suffix(b"\n")
+                                         .unwrap_or(stack_trace)
+                                         .iter()
+                                         .rposition(|&b| b == b'\n')
+                                         .map_or(0, |pos| pos + 1);
+        Sample {
+            raw_sample_data,
+            header: &raw_sample_data[..header_len],
+            stack_trace,
+            last_stack_frame: Some(&stack_trace[last_line_start..])
+                                  .filter(|line| !line.is_empty()),
+        }
+    }
+
+    /// Variant of frames() which collapses PLT stubs and trampolines, for
+    /// outputs where they would only add noise
+    pub fn frames_without_stubs(&self)
+        -> impl DoubleEndedIterator<Item=StackFrame<'a>>
+    {
+        self.frames().filter(|frame| !frame.is_stub())
+    }
+
+    /// Raw sample data as text, with invalid UTF-8 replaced by U+FFFD
+    pub fn raw_text(&self) -> Cow<'a, str> {
+        String::from_utf8_lossy(self.raw_sample_data)
+    }
+
+    /// Sample header as text, with invalid UTF-8 replaced by U+FFFD
+    pub fn header_text(&self) -> Cow<'a, str> {
+        String::from_utf8_lossy(self.header)
+    }
+
+    /// Identifier of the stack trace of this sample, which only depends on the
+    /// symbols and DSOs of its frames, so that the same stack gets the same
+    /// identifier in every report and every run. Returns None if the sample has
+    /// no stack trace.
+    pub fn stack_id(&self) -> Option<StackId> {
+        let mut frames = self.frames().peekable();
+        frames.peek()?;
+        Some(StackId(frames.fold(FNV_OFFSET_BASIS, |hash, frame| {
+            let hash = fnv1a(fnv1a(hash, frame.symbol.as_bytes()), b"\0");
+            fnv1a(fnv1a(hash, frame.dso.as_bytes()), b"\0")
+        })))
+    }
+
+    /// Name of the thread which was sampled, which is the first column of the
+    /// header. Since it may contain spaces, it extends up to the PID column.
+    /// Returns None if the header is not valid UTF-8.
+    pub fn comm(&self) -> Option<&'a str> {
+        let header = str::from_utf8(self.header).ok()?;
+        let pid_start = pid_column(header).map_or(header.len(), |(pos, _)| pos);
+        Some(header[..pid_start].trim())
+    }
+
+    /// Process ID of the sample. Depending on the fields that perf script was
+    /// asked to print, this may actually be a thread ID.
+    pub fn pid(&self) -> Option<u32> {
+        let header = str::from_utf8(self.header).ok()?;
+        let (_, column) = pid_column(header)?;
+        column.split('/').next()?.parse().ok()
+    }
+
+    /// Thread ID of the sample, which is the second half of a "pid/tid" column
+    /// or the whole column if only one ID was printed (perf script's default)
+    pub fn tid(&self) -> Option<u32> {
+        let header = str::from_utf8(self.header).ok()?;
+        let (_, column) = pid_column(header)?;
+        column.split('/').next_back()?.parse().ok()
+    }
+
+    /// CPU which the sample was taken on, from the "[cpu]" column that follows
+    /// the PID column. Returns None if perf script did not print it.
+    pub fn cpu(&self) -> Option<u32> {
+        let header = str::from_utf8(self.header).ok()?;
+        let (pid_start, _) = pid_column(header)?;
+        let (_, column) = header_columns(&header[pid_start..]).nth(1)?;
+        column.strip_prefix('[')?.strip_suffix(']')?.parse().ok()
+    }
+
+    /// Time at which the sample was taken, in seconds, from the timestamp
+    /// column of the header. Returns None if perf script did not print it.
+    pub fn timestamp(&self) -> Option<f64> {
+        let header = str::from_utf8(self.header).ok()?;
+        let column = header_columns(header).map(|(_, column)| column)
+                                           .find(|column| is_timestamp(column))?;
+        column[..column.len()-1].parse().ok()
+    }
+
+    /// Precision level of the sampled event, i.e. how hard the hardware tried
+    /// to avoid IP skid, as requested with the "p" modifiers of the event name
+    /// (e.g. 2 for "cycles:pp"). The "P" modifier means maximal precision.
+    pub fn event_precision(&self) -> u8 {
+        let modifiers = self.event_modifiers();
+        if modifiers.contains('P') {
+            3
+        } else {
+            modifiers.chars().filter(|&c| c == 'p').count().min(3) as u8
+        }
+    }
+
+    /// Modifiers of the sampled event, e.g. "pp" for "cycles:pp" or "G" for an
+    /// event which only counts in virtual machine guests. Returns an empty
+    /// string if the event has no modifier or is unknown.
+    pub fn event_modifiers(&self) -> &'a str {
+        const MODIFIERS: &str = "ukhIGHpPSDWeb";
+        let event = match self.event() {
+            Some(event) => event,
+            None => return "",
+        };
+        match event.rfind(':') {
+            Some(pos) if event[pos+1..].chars().all(|c| MODIFIERS.contains(c)) => {
+                &event[pos+1..]
+            },
+            _ => "",
+        }
+    }
+
+    /// Sampling period of the sample, which is the numeric column between the
+    /// timestamp and the event name. Returns None if perf script did not print
+    /// it, in which case each sample should be given the same weight.
+    pub fn period(&self) -> Option<u64> {
+        let header = str::from_utf8(self.header).ok()?;
+        header_columns(header).map(|(_, column)| column)
+                              .skip_while(|column| !is_timestamp(column))
+                              .nth(1)?
+                              .parse()
+                              .ok()
+    }
+
+    /// Name of the event which was sampled (e.g. "cycles:u"), which is the
+    /// first column of the header after the timestamp that ends with a colon.
+    /// Returns None if the header is not valid UTF-8 or has no event column.
+    pub fn event(&self) -> Option<&'a str> {
+        let header = str::from_utf8(self.header).ok()?;
+        header_columns(header).map(|(_, column)| column)
+                              .skip_while(|column| !is_timestamp(column))
+                              .skip(1)
+                              .find(|column| column.ends_with(':'))
+                              .map(|column| &column[..column.len()-1])
+    }
+
+    /// Whatever perf script printed after the event name, such as the fields
+    /// of a tracepoint or the address and arguments of a probe (e.g.
+    /// "(7f3a2b1c4d50) size=0x20"). Returns None if there is no event column.
+    pub fn event_payload(&self) -> Option<&'a str> {
+        let header = str::from_utf8(self.header).ok()?;
+        let (start, event) = header_columns(header).skip_while(|&(_, c)| !is_timestamp(c))
+                                                   .skip(1)
+                                                   .find(|&(_, c)| c.ends_with(':'))?;
+        Some(header[start + event.len()..].trim())
+    }
+
+    /// Name of the sampled event without its PMU and modifiers, e.g. "cycles"
+    /// for "cycles:pp" or "cpu_core/cycles/u"
+    pub fn event_base_name(&self) -> Option<&'a str> {
+        let event = self.event()?;
+        match event.split('/').nth(1) {
+            Some(name) => Some(name),
+            None => event.split(':').next(),
+        }
+    }
+
+    /// Control group of the sampled thread (e.g. "/user.slice/user-1000.slice"),
+    /// which is the path printed after the event name when perf script is run
+    /// with -F +cgroup. Returns None if perf script did not print it.
+    pub fn cgroup(&self) -> Option<&'a str> {
+        let header = str::from_utf8(self.header).ok()?;
+        header_columns(header).map(|(_, column)| column)
+                              .skip_while(|column| !is_timestamp(column))
+                              .find(|column| column.starts_with('/'))
+    }
+
+    /// Frequency of the sampled CPU in MHz, from a "freq: <frequency> MHz"
+    /// field such as the one printed for Intel PT's core-to-bus ratio events.
+    /// Returns None if perf script did not print it.
+    pub fn cpu_frequency(&self) -> Option<u64> {
+        let header = str::from_utf8(self.header).ok()?;
+        let mut columns = header_columns(header).map(|(_, column)| column)
+                                                .skip_while(|&column| column != "freq:")
+                                                .skip(1);
+        let frequency = columns.next()?.parse().ok()?;
+        match columns.next() {
+            Some("MHz") => Some(frequency),
+            _ => None,
+        }
+    }
+
+    /// Data address of a sampled memory access, which perf script prints in
+    /// hexadecimal right after the event name when run with -F +addr. A data
+    /// source printed without the address is told apart by the "|OP ...|"
+    /// decoding which follows it. Only meaningful for memory sampling events.
+    pub fn data_address(&self) -> Option<u64> {
+        let header = str::from_utf8(self.header).ok()?;
+        let mut rest = header_columns(header).map(|(_, column)| column)
+                                             .skip_while(|column| !is_timestamp(column))
+                                             .skip(1)
+                                             .skip_while(|column| !column.ends_with(':'))
+                                             .skip(1);
+        let address = rest.next()?;
+        if rest.next().is_some_and(|next| next.starts_with('|')) {
+            return None;
+        }
+        u64::from_str_radix(address.trim_start_matches("0x"), 16).ok()
+    }
+
+    /// Memory hierarchy level which served a sampled memory access (e.g. "L3
+    /// hit" or "Local RAM hit"), from the "|LVL <level>|" part of the data
+    /// source that perf script prints with -F +data_src for mem-loads and
+    /// mem-stores events. Returns None if perf script did not print it.
+    pub fn memory_level(&self) -> Option<&'a str> {
+        self.data_source_field("LVL")
+    }
+
+    /// Kind of a sampled memory access (e.g. "LOAD" or "STORE"), from the
+    /// "|OP <operation>|" part of the data source
+    pub fn memory_operation(&self) -> Option<&'a str> {
+        self.data_source_field("OP")
+    }
+
+    /// Cache coherency snoop result of a sampled memory access (e.g. "HitM"
+    /// when the line was modified in another core's cache), from the "|SNP
+    /// <snoop>|" part of the data source
+    pub fn memory_snoop(&self) -> Option<&'a str> {
+        self.data_source_field("SNP")
+    }
+
+    /// Some "|<key> <value>|" part of the data source of a memory access
+    fn data_source_field(&self, key: &str) -> Option<&'a str> {
+        let header = str::from_utf8(self.header).ok()?;
+        header.split('|').skip(1).find_map(|field| {
+            field.strip_prefix(key)?.strip_prefix(' ').map(str::trim)
+        })
+    }
+
+    /// Type of side-band record (e.g. "THROTTLE" for PERF_RECORD_THROTTLE) if
+    /// this is not actually a sample, but one of the records which perf script
+    /// prints when asked to (e.g. with --show-throttle-events)
+    pub fn sideband_record(&self) -> Option<&'a str> {
+        let header = str::from_utf8(self.header).ok()?;
+        header_columns(header).map(|(_, column)| column)
+                              .skip_while(|column| !is_timestamp(column))
+                              .find_map(|column| column.strip_prefix("PERF_RECORD_"))
+    }
+}
+///
+///
+/// Content-addressed identifier of a stack trace, displayed as 16 hex digits
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct StackId(pub u64);
+//
+impl fmt::Display for StackId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+
+/// Truth that a header line is that of a side-band record rather than that of
+/// a sample. Such records are printed as a single line, without a stack trace.
+pub fn is_sideband_record(header: &[u8]) -> bool {
+    memmem::find(header, b" PERF_RECORD_").is_some()
+}
+
+/// Truth that a header line is that of a branch or instruction record which
+/// perf script decoded from a hardware trace (e.g. Intel PT with --itrace=b or
+/// --itrace=i) without a call chain. Such records print the traced address
+/// right after the event name, as in "1 branches:u: 401234 main+0x14 (/bin/app)
+/// => 401250 main+0x30 (/bin/app)", and are not followed by a stack trace.
+pub fn is_trace_record(header: &[u8]) -> bool {
+    if memmem::find(header, b" branches").is_none() &&
+       memmem::find(header, b" instructions").is_none() {
+        return false;
+    }
+    let sample = Sample::from_raw(header, header.len());
+    match (sample.event_base_name(), sample.event_payload()) {
+        (Some("branches"), Some(payload)) | (Some("instructions"), Some(payload)) => {
+            payload.ends_with(')') || payload.contains(" => ")
+        },
+        _ => false,
+    }
+}
+
+/// Truth that a line is a decode error which perf script inserted in the
+/// output of a hardware trace, such as " instruction trace error type 1 time
+/// 1.234 cpu 0 pid 1 tid 1 ip 0x401234 code 6: Trace doesn't match instruction"
+pub fn is_trace_error(header: &[u8]) -> bool {
+    memmem::find(header, b"instruction trace error ").is_some()
+}
+
+/// Truth that a header line is that of a function tracer event (e.g.
+/// ftrace:function or ftrace:funcgraph_entry), which are usually recorded
+/// without a call chain
+pub fn is_function_trace_record(header: &[u8]) -> bool {
+    memmem::find(header, b" ftrace:func").is_some()
+}
+
+/// Truth that a header line is that of a record which spans a single line,
+/// and thus must not swallow the lines that follow it, given the first byte of
+/// the next line (if any). Function tracer events only stand alone when they
+/// are not followed by a stack trace, whose lines start with a tab.
+pub fn is_single_line_record(header: &[u8], next: Option<u8>) -> bool {
+    is_sideband_record(header) || is_trace_error(header) || is_trace_record(header) ||
+    (is_function_trace_record(header) && next != Some(b'\t'))
+}
+
+
+/// Extend an FNV-1a digest, starting at FNV_OFFSET_BASIS, with some bytes
+pub fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+
+/// Split the "+0x<offset>" suffix of a symbol, if any, from the symbol itself
+pub fn split_symbol_offset(symbol: &str) -> (&str, Option<u64>) {
+    match symbol.rfind("+0x") {
+        Some(pos) if pos > 0 &&
+                     symbol.len() > pos + 3 &&
+                     symbol[pos+3..].bytes().all(|b| b.is_ascii_hexdigit()) => {
+            (&symbol[..pos], u64::from_str_radix(&symbol[pos+3..], 16).ok())
+        },
+        _ => (symbol, None),
+    }
+}
+
+
+/// Iterate over the whitespace-separated columns of a sample header, along with
+/// their byte offset within the header
+fn header_columns(header: &str) -> impl Iterator<Item=(usize, &str)> {
+    header.split_whitespace().map(move |column| {
+        (column.as_ptr() as usize - header.as_ptr() as usize, column)
+    })
+}
+
+
+/// Locate the "pid" or "pid/tid" column of a sample header, which follows the
+/// (possibly space-separated) name of the sampled thread
+fn pid_column(header: &str) -> Option<(usize, &str)> {
+    let is_pid = |column: &str| {
+        column.split('/').all(|id| {
+            !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit())
+        })
+    };
+    header_columns(header).skip(1).find(|&(_, column)| is_pid(column))
+}
+
+
+/// Truth that a sample header column is a timestamp, such as "12345.678901:"
+fn is_timestamp(column: &str) -> bool {
+    column.len() > 1 &&
+    column.ends_with(':') &&
+    column[..column.len()-1].bytes().all(|b| b.is_ascii_digit() || b == b'.')
+}
+///
+///
+/// One decoded stack frame, as printed by perf script
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StackFrame<'a> {
+    /// Instruction pointer, as a hexadecimal string
+    pub ip: &'a str,
+
+    /// Name of the function, "[unknown]" if perf could not figure it out
+    pub symbol: &'a str,
+
+    /// Name of the DSO (shared library, executable, kernel...), without the
+    /// surrounding parentheses that perf prints
+    pub dso: &'a str,
+
+    /// Truth that perf flagged the DSO with a "(deleted)" marker
+    pub deleted: bool,
+
+    /// Truth that this frame was not unwound by perf, but inferred from other
+    /// samples, which is flagged with an "(inferred)" marker
+    pub inferred: bool,
+
+    /// Offset of the instruction pointer within the function, which perf
+    /// prints after the symbol as in "main+0x1f" when run with -F +symoff
+    pub offset: Option<u64>,
+}
+//
+impl<'a> StackFrame<'a> {
+    /// Variant of parse() for raw perf script output. Stack frames which are
+    /// not valid UTF-8 are not decoded, since they cannot be borrowed as text.
+    pub fn parse_bytes(line: &'a [u8]) -> Option<Self> {
+        str::from_utf8(line).ok().and_then(Self::parse)
+    }
+
+    /// Decode a line of perf script stack trace output, which looks like
+    /// "<ip> <symbol> (<dso>)", where the DSO may be followed by "(deleted)"
+    /// and "(inferred)" markers and the symbol by a "+0x<offset>" suffix.
+    /// Returns None if the line does not look like a stack frame at all.
+    pub fn parse(line: &'a str) -> Option<Self> {
+        // The first column is the instruction pointer
+        let line = line.trim();
+        let ip_end = line.find(char::is_whitespace).unwrap_or(line.len());
+        let ip = &line[..ip_end];
+        let rest = line[ip_end..].trim_start();
+        if ip.is_empty() { return None; }
+
+        // The DSO is the parenthesized group at the end of the line. It must be
+        // located by matching parentheses, since symbols may contain spaces.
+        // Perf separates it from the symbol by a space, which tells it apart
+        // from symbols ending in parentheses, e.g. V8's "Function:~(anonymous)"
+        let dso_start = if rest.ends_with(')') {
+            let mut depth = 0usize;
+            rest.char_indices().rev().find(|&(_, c)| {
+                match c {
+                    ')' => depth += 1,
+                    '(' => depth -= 1,
+                    _ => {},
+                }
+                depth == 0
+            }).map(|(idx, _)| idx).filter(|&idx| {
+                rest[..idx].ends_with(char::is_whitespace)
+            })
+        } else {
+            None
+        };
+        let (symbol, mut dso) = match dso_start {
+            Some(start) => (rest[..start].trim_end(), &rest[start+1..rest.len()-1]),
+            None => (rest, ""),
+        };
+        let (symbol, offset) = split_symbol_offset(symbol);
+
+        // Stack repair appends an "(inferred)" marker inside of the DSO name
+        const INFERRED: &str = " (inferred)";
+        let inferred = dso.ends_with(INFERRED);
+        if inferred {
+            dso = &dso[..dso.len()-INFERRED.len()];
+        }
+
+        // Perf sometimes appends a "(deleted)" marker inside of the DSO name
+        const DELETED: &str = " (deleted)";
+        let deleted = dso.ends_with(DELETED);
+        if deleted {
+            dso = &dso[..dso.len()-DELETED.len()];
+        }
+
+        Some(Self { ip, symbol, dso, deleted, inferred, offset })
+    }
+
+    /// Truth that perf could not figure out which function this frame is in
+    pub fn is_unknown_symbol(&self) -> bool {
+        self.symbol == "[unknown]"
+    }
+
+    /// Start of the address range of some size (e.g. a 4 KiB page or a 64-byte
+    /// cache line) which contains this frame's instruction pointer. This lets
+    /// hot regions of stripped binaries be told apart without symbols.
+    pub fn address_bucket(&self, bucket_size: u64) -> Option<u64> {
+        let ip = u64::from_str_radix(self.ip, 16).ok()?;
+        Some(ip - ip % bucket_size)
+    }
+
+    /// Truth that this frame points into anonymous memory, such as the code of
+    /// a JIT compiler which does not emit a perf map, or self-modifying code
+    pub fn is_in_anonymous_memory(&self) -> bool {
+        matches!(self.dso, "//anon" | "[heap]" | "[stack]") ||
+        self.dso.starts_with("[anon:")
+    }
+
+    /// Figure out which kind of code this frame is executing
+    pub fn kind(&self) -> FrameKind {
+        const DL_TRAMPOLINE_PREFIX: &str = "_dl_runtime_resolve";
+        if self.symbol.ends_with("@plt") || self.symbol == "[plt]" {
+            FrameKind::PltStub
+        } else if self.symbol.starts_with(DL_TRAMPOLINE_PREFIX) {
+            FrameKind::Trampoline
+        } else {
+            FrameKind::Function
+        }
+    }
+
+    /// Truth that this frame is a signal trampoline, which marks the boundary
+    /// between a signal handler and the code that it interrupted
+    pub fn is_signal_trampoline(&self) -> bool {
+        matches!(self.symbol,
+                 "__restore_rt" | "__kernel_rt_sigreturn" | "__kernel_sigreturn")
+    }
+
+    /// Truth that this frame is a PLT stub or trampoline, which only forwards
+    /// control to another function and adds no information to a stack trace
+    pub fn is_stub(&self) -> bool {
+        self.kind() != FrameKind::Function
+    }
+}
+
+///
+///
+/// Kinds of code that a stack frame can be executing
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FrameKind {
+    /// A regular function
+    Function,
+
+    /// A PLT stub, which jumps to a function from another DSO
+    PltStub,
+
+    /// A dynamic linker trampoline, which resolves PLT entries lazily
+    Trampoline,
+}
+
+
+/// Owned version of a sample, which can outlive the perf script reader and be
+/// persisted to disk for later re-analysis
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct OwnedSample {
+    /// Header of the sample, without the trailing newline
+    pub header: String,
+
+    /// Stack frames of the sample, from leaf to root
+    pub frames: Vec<OwnedFrame>,
+}
+//
+impl OwnedSample {
+    /// Make an owned copy of a sample from perf script
+    pub fn from_sample(sample: &Sample) -> Self {
+        Self {
+            header: sample.header_text().trim_end().to_owned(),
+            frames: sample.frames().map(OwnedFrame::from).collect(),
+        }
+    }
+
+    /// Re-emit this sample in perf script's textual format, so that it can be
+    /// fed back to PerfSamples for re-analysis
+    pub fn to_script_text(&self) -> String {
+        let mut text = self.header.clone();
+        text.push('\n');
+        for frame in &self.frames {
+            text.push_str(&frame.to_script_text());
+            text.push('\n');
+        }
+        text.push('\n');
+        text
+    }
+}
+///
+///
+/// Owned version of a stack frame
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct OwnedFrame {
+    /// Instruction pointer, as a hexadecimal string
+    pub ip: String,
+
+    /// Name of the function
+    pub symbol: String,
+
+    /// Name of the DSO, without parentheses
+    pub dso: String,
+
+    /// Truth that perf flagged the DSO as deleted
+    pub deleted: bool,
+
+    /// Truth that the frame was inferred from other samples
+    #[serde(default)]
+    pub inferred: bool,
+
+    /// Offset of the instruction pointer within the function, if known
+    #[serde(default)]
+    pub offset: Option<u64>,
+}
+//
+impl OwnedFrame {
+    /// Borrow this frame as a StackFrame
+    pub fn as_frame(&self) -> StackFrame<'_> {
+        StackFrame {
+            ip: &self.ip,
+            symbol: &self.symbol,
+            dso: &self.dso,
+            deleted: self.deleted,
+            inferred: self.inferred,
+            offset: self.offset,
+        }
+    }
+
+    /// Re-emit this frame in perf script's textual format
+    pub fn to_script_text(&self) -> String {
+        format!("\t{:>16} {}{} ({}{}{})",
+                self.ip,
+                self.symbol,
+                self.offset.map_or(String::new(), |offset| format!("+{:#x}", offset)),
+                self.dso,
+                if self.deleted { " (deleted)" } else { "" },
+                if self.inferred { " (inferred)" } else { "" })
+    }
+}
+//
+impl<'a> From<StackFrame<'a>> for OwnedFrame {
+    fn from(frame: StackFrame<'a>) -> Self {
+        Self {
+            ip: frame.ip.to_owned(),
+            symbol: frame.symbol.to_owned(),
+            dso: frame.dso.to_owned(),
+            deleted: frame.deleted,
+            inferred: frame.inferred,
+            offset: frame.offset,
+        }
+    }
+}
+
+
+/// Serialize a sample as a single line of JSON
+pub fn write_json_line<W: ::std::io::Write>(out: &mut W,
+                                           sample: &OwnedSample) -> Result<()> {
+    serde_json::to_writer(&mut *out, sample)?;
+    writeln!(out)
+}
+
+/// Deserialize a sample from a single line of JSON
+pub fn read_json_line(line: &str) -> Result<OwnedSample> {
+    Ok(serde_json::from_str(line)?)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use node;
+
+    #[test]
+    fn header_fields() {
+        let header = "C2 CompilerThre 30112/30125 [001] 77001.121003: 5000 cycles:pp:\n";
+        let text = format!("{}\t    7f1e6a5c3e21 foo (/usr/lib/libfoo.so)\n", header);
+        let sample = Sample::from_raw(text.as_bytes(), header.len());
+        assert_eq!(sample.comm(), Some("C2 CompilerThre"));
+        assert_eq!(sample.pid(), Some(30112));
+        assert_eq!(sample.tid(), Some(30125));
+        assert_eq!(sample.cpu(), Some(1));
+        assert_eq!(sample.timestamp(), Some(77001.121003));
+        assert_eq!(sample.period(), Some(5000));
+        assert_eq!(sample.event(), Some("cycles:pp"));
+        assert_eq!(sample.event_precision(), 2);
+        assert_eq!(sample.sideband_record(), None);
+    }
+
+    #[test]
+    fn frame_markers() {
+        let frame = StackFrame::parse("\t    7f00 foo bar (/lib/libfoo.so (deleted))");
+        let frame = frame.unwrap();
+        assert_eq!((frame.ip, frame.symbol), ("7f00", "foo bar"));
+        assert_eq!(frame.dso, "/lib/libfoo.so");
+        assert!(frame.deleted && !frame.inferred);
+        let frame = StackFrame::parse("\t 0 foo (/lib/libfoo.so (inferred))").unwrap();
+        assert!(frame.inferred && !frame.deleted);
+        assert_eq!(StackFrame::parse("   "), None);
+    }
+
+    #[test]
+    fn v8_symbols() {
+        let line = "\t 7f01 LazyCompile:~handle /srv/a b.js:10:2 (/tmp/perf-9.map)";
+        let frame = StackFrame::parse(line).unwrap();
+        assert_eq!(frame.symbol, "LazyCompile:~handle /srv/a b.js:10:2");
+        assert_eq!(frame.dso, "/tmp/perf-9.map");
+        let frame = StackFrame::parse("\t 7f02 Function:~(anonymous)").unwrap();
+        assert_eq!((frame.symbol, frame.dso), ("Function:~(anonymous)", ""));
+        let frame = StackFrame::parse("\t 7f03 RegExp:\\d+) (/tmp/perf-9.map)").unwrap();
+        assert_eq!((frame.symbol, frame.dso), ("RegExp:\\d+)", "/tmp/perf-9.map"));
+
+        let header = "node 9/9 [000] 1.000000: 1 cycles:\n";
+        let text = format!("{}\t7f01 JS:*handle /srv/a.js:1:1 (/tmp/perf-9.map)\n\
+                            \t7f02 JS:~ /srv/a.js:2:1 (/tmp/perf-9.map)\n\
+                            \t7f03 node::Start (/usr/bin/node)\n",
+                           header);
+        let sample = Sample::from_raw(text.as_bytes(), header.len());
+        let mut buffer = Vec::new();
+        let renamed = sample.with_renamed_symbols(node::strip_v8_annotations, &mut buffer)
+                            .unwrap();
+        let symbols = renamed.frames().map(|frame| frame.symbol).collect::<Vec<_>>();
+        assert_eq!(symbols, ["handle", "/srv/a.js:2:1", "node::Start"]);
+    }
+
+    #[test]
+    fn sideband_records_stand_alone() {
+        let text = b"prog 1 [000] 1.0: PERF_RECORD_THROTTLE\n\
+                     prog 1 [000] 1.1: 1 cycles: \n\
+                     \t    7f00 foo (/usr/lib/libfoo.so)\n\
+                     \n";
+        let mut samples = PerfSamples::new(&text[..]);
+        let record = samples.next().unwrap().unwrap();
+        assert_eq!(record.sideband_record(), Some("THROTTLE"));
+        assert!(record.stack_trace.is_empty());
+        let sample = samples.next().unwrap().unwrap();
+        assert_eq!(sample.frames().count(), 1);
+        assert!(samples.next().unwrap().is_none());
+    }
+
+    #[test]
+    fn trace_records_stand_alone() {
+        let text = b"prog 1 [000] 1.0: 1 branches:u: 7f00 foo+0x4 (/lib/libfoo.so) => \
+                     7f10 bar+0x0 (/lib/libfoo.so)\n\
+                     \x20instruction trace error type 1 time 1.1 cpu 0 pid 1 tid 1 ip \
+                     0x7f10 code 6: Trace doesn't match instruction\n\
+                     prog 1 [000] 1.2: 1 instructions:u: 7f14 bar+0x4 (/lib/libfoo.so)\n\
+                     prog 1 [000] 1.3: 1 instructions:u: \n\
+                     \t    7f18 bar+0x8 (/lib/libfoo.so)\n\
+                     \n";
+        let mut samples = PerfSamples::new(&text[..]);
+        let branch = samples.next().unwrap().unwrap();
+        assert!(is_trace_record(branch.header) && branch.stack_trace.is_empty());
+        let error = samples.next().unwrap().unwrap();
+        assert!(is_trace_error(error.header) && error.stack_trace.is_empty());
+        let instruction = samples.next().unwrap().unwrap();
+        assert!(is_trace_record(instruction.header));
+        let sample = samples.next().unwrap().unwrap();
+        assert!(!is_trace_record(sample.header));
+        assert_eq!(sample.frames().count(), 1);
+        assert!(samples.next().unwrap().is_none());
+    }
+
+    #[test]
+    fn function_trace_records_stand_alone() {
+        let text = b"sh 1 [000] 1.0: ftrace:function: vfs_read <-- ksys_read\n\
+                     sh 1 [000] 1.1: ftrace:function: ksys_read <-- do_syscall_64\n\
+                     \t    7f00 ksys_read ([kernel.kallsyms])\n\
+                     \n\
+                     sh 1 [000] 1.2: ftrace:function: vfs_write <-- ksys_write\n";
+        let mut samples = PerfSamples::new(&text[..]);
+        let record = samples.next().unwrap().unwrap();
+        assert!(record.stack_trace.is_empty());
+        assert_eq!(record.event_payload(), Some("vfs_read <-- ksys_read"));
+        assert_eq!(samples.next().unwrap().unwrap().frames().count(), 1);
+        assert!(samples.next().unwrap().unwrap().stack_trace.is_empty());
+        assert!(samples.next().unwrap().is_none());
+    }
+
+    #[test]
+    fn event_modifiers() {
+        let text = b"qemu 1 [000] 1.0: 1 cycles:Gpp: \n";
+        let sample = Sample::from_raw(text, text.len() - 1);
+        assert_eq!(sample.event_modifiers(), "Gpp");
+        assert_eq!(sample.event_precision(), 2);
+        let text = b"prog 1 [000] 1.0: probe_libc:malloc: (7f00)\n";
+        let sample = Sample::from_raw(text, text.len() - 1);
+        assert_eq!(sample.event_modifiers(), "");
+    }
+
+    #[test]
+    fn duplicate_frames() {
+        let text = b"prog 1 [000] 1.0: 1 cycles: \n\
+                     \t    7f00 foo (/usr/lib/libfoo.so)\n\
+                     \t    7f00 foo (/usr/lib/libfoo.so)\n\
+                     \t    7f10 main (/usr/bin/prog)\n";
+        let sample = Sample::from_raw(text, 28);
+        assert_eq!(sample.num_duplicate_frames(), 1);
+        let mut buffer = Vec::new();
+        let collapsed = sample.without_duplicate_frames(&mut buffer).unwrap();
+        let symbols = collapsed.frames().map(|frame| frame.symbol).collect::<Vec<_>>();
+        assert_eq!(symbols, ["foo", "main"]);
+    }
+
+    #[test]
+    fn symbol_offsets() {
+        let frame = StackFrame::parse("7f00 operator+=+0x1f (/lib/libfoo.so)").unwrap();
+        assert_eq!((frame.symbol, frame.offset), ("operator+=", Some(0x1f)));
+        let frame = StackFrame::parse("7f00 foo (/lib/libfoo.so)").unwrap();
+        assert_eq!((frame.symbol, frame.offset), ("foo", None));
+
+        let text = b"prog 1 [000] 1.0: 1 cycles: \n\
+                     \t    7f00 foo+0x0 (/usr/lib/libfoo.so)\n\
+                     \t    7f10 __restore_rt+0x0 (/usr/lib/libc.so.6)\n\
+                     \t    7f20 bar+0x0 (/usr/lib/libfoo.so)\n\
+                     \t    7f30 baz+0x0 (/usr/lib/libfoo.so)\n\
+                     \t    7f40 main+0x4 (/usr/bin/prog)\n";
+        let sample = Sample::from_raw(text, 28);
+        let callers = sample.callers().map(|(depth, _)| depth).collect::<Vec<_>>();
+        assert_eq!(callers, [1, 3, 4]);
+        assert_eq!(sample.zero_offset_callers().collect::<Vec<_>>(), [1, 3]);
+    }
+
+    #[test]
+    fn stack_ids() {
+        let header = "prog 1 [000] 1.0: 1 cycles: \n";
+        let foo = "foo (/lib/libfoo.so)";
+        let main = "main (/bin/prog)";
+        let a = format!("{}\t    7f00 {}\n\t    4010 {}\n", header, foo, main);
+        let b = format!("{}\t    7e42 {}\n\t    4020 {}\n", header, foo, main);
+        let c = format!("{}\t    7f00 {}\n\t    4010 {}\n", header, main, foo);
+        let id = |text: &str| Sample::from_raw(text.as_bytes(), header.len()).stack_id();
+        assert_eq!(id(&a), id(&b));
+        assert!(id(&a).is_some() && id(&a) != id(&c));
+        assert_eq!(id(header), None);
+        assert_eq!(StackId(0xbeef).to_string(), "000000000000beef");
+    }
+}
@@ -0,0 +1,185 @@
+//! User scripts written in Rhai, which see every analyzed sample and can keep
+//! their own aggregates, as a lighter-weight alternative to plugins.
+//!
+//! A script may define the following functions, which are all optional:
+//!
+//! - `init()` returns the initial state of the script, which is an empty
+//!   object map if this function is not defined.
+//! - `on_sample(sample)` is called for every classified sample.
+//! - `on_end()` is called at the end of the analysis, and may print a report.
+//!
+//! Rhai functions cannot access global variables, so the state of the script
+//! is bound to `this` in on_sample() and on_end(). Samples are object maps with
+//! the fields comm, pid, tid, cpu, time, period, event, cgroup, freq, category
+//! and frames, where frames is an array of object maps with the fields ip,
+//! symbol and dso, starting from the leaf function. Unknown fields are set to
+//! `()`.
+
+use analysis::SampleCategory;
+use rhai::{Array, CallFnOptions, Dynamic, Engine, EvalAltResult, FuncArgs, Map, Scope};
+use rhai::{AST, INT};
+use sample::Sample;
+use std::io::{Error, Result};
+use std::path::Path;
+
+
+/// Compiled user script, along with its state. Script errors which occur
+/// during the analysis are deferred until the script is finished, so that they
+/// do not get in the way of the analysis itself.
+pub struct SampleScript {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+
+    /// State of the script, bound to `this`
+    state: Dynamic,
+
+    /// Truth that the script defines an on_sample() function
+    has_on_sample: bool,
+
+    /// First error which occurred while running the script, if any
+    error: Option<Error>,
+}
+//
+impl SampleScript {
+    /// Load a script, running its top-level statements and init() function
+    pub fn load(path: &Path) -> Result<Self> {
+        let engine = Engine::new();
+        let ast = engine.compile_file(path.to_owned()).map_err(script_error)?;
+        let mut scope = Scope::new();
+        engine.run_ast_with_scope(&mut scope, &ast).map_err(script_error)?;
+        let defines = |name: &str| ast.iter_functions().any(|f| f.name == name);
+        let state = if defines("init") {
+            let options = CallFnOptions::new().eval_ast(false);
+            engine.call_fn_with_options(options, &mut scope, &ast, "init", ())
+                  .map_err(script_error)?
+        } else {
+            Map::new().into()
+        };
+        let has_on_sample = defines("on_sample");
+        Ok(Self {
+            engine,
+            ast,
+            scope,
+            state,
+            has_on_sample,
+            error: None,
+        })
+    }
+
+    /// Pass a newly classified sample to the script
+    pub fn record(&mut self, sample: &Sample, category: &SampleCategory) {
+        if self.error.is_none() && self.has_on_sample {
+            let sample = sample_to_dynamic(sample, category);
+            if let Err(e) = self.call("on_sample", (sample,)) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    /// Let the script print its report, or report the first script error
+    pub fn finish(mut self) -> Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        if self.ast.iter_functions().any(|f| f.name == "on_end") {
+            self.call("on_end", ())?;
+        }
+        Ok(())
+    }
+
+    /// Call a function of the script, with its state bound to `this`
+    fn call(&mut self, name: &str, args: impl FuncArgs) -> Result<()> {
+        let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut self.state);
+        self.engine.call_fn_with_options::<Dynamic>(options,
+                                                    &mut self.scope,
+                                                    &self.ast,
+                                                    name,
+                                                    args)
+                   .map(|_| ())
+                   .map_err(script_error)
+    }
+}
+
+
+/// Present a sample to a script
+fn sample_to_dynamic(sample: &Sample, category: &SampleCategory) -> Dynamic {
+    fn opt<T: Into<Dynamic>>(value: Option<T>) -> Dynamic {
+        value.map_or(Dynamic::UNIT, Into::into)
+    }
+    let frames = sample.frames().map(|frame| {
+        let mut map = Map::new();
+        let ip = u64::from_str_radix(frame.ip, 16).ok().map(|ip| ip as INT);
+        map.insert("ip".into(), opt(ip));
+        map.insert("symbol".into(), frame.symbol.into());
+        map.insert("dso".into(), frame.dso.into());
+        Dynamic::from_map(map)
+    }).collect::<Array>();
+    let mut map = Map::new();
+    map.insert("comm".into(), opt(sample.comm()));
+    map.insert("pid".into(), opt(sample.pid().map(INT::from)));
+    map.insert("tid".into(), opt(sample.tid().map(INT::from)));
+    map.insert("cpu".into(), opt(sample.cpu().map(INT::from)));
+    map.insert("time".into(), opt(sample.timestamp()));
+    map.insert("period".into(), opt(sample.period().map(|period| period as INT)));
+    map.insert("event".into(), opt(sample.event()));
+    map.insert("cgroup".into(), opt(sample.cgroup()));
+    map.insert("freq".into(), opt(sample.cpu_frequency().map(|freq| freq as INT)));
+    map.insert("category".into(), category.key().into());
+    map.insert("frames".into(), frames.into());
+    Dynamic::from_map(map)
+}
+
+
+/// Convert a script error into an I/O error. Rhai errors may hold script
+/// values, which cannot be sent across threads, so only the message is kept.
+fn script_error(error: Box<EvalAltResult>) -> Error {
+    Error::other(error.to_string())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    /// Load a script from its source, and pass it the same sample twice
+    fn run(name: &str, source: &str) -> SampleScript {
+        let path = env::temp_dir().join(format!("psa-{}-{}.rhai", name, process::id()));
+        fs::write(&path, source).unwrap();
+        let mut script = SampleScript::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let header = "prog 1/2 [000] 1.0: 1 cycles: \n";
+        let text = format!("{}\t    7f00 foo (/lib/libfoo.so)\n", header);
+        let sample = Sample::from_raw(text.as_bytes(), header.len());
+        for _ in 0..2 {
+            script.record(&sample, &SampleCategory::Normal);
+        }
+        script
+    }
+
+    #[test]
+    fn count_samples() {
+        let script = run("count", "fn init() { #{ count: 0, tids: [] } }\n\
+                                   fn on_sample(sample) {\n\
+                                       this.count += 1;\n\
+                                       this.tids.push(sample.tid);\n\
+                                   }\n");
+        let state = script.state.clone_cast::<Map>();
+        assert_eq!(state["count"].as_int(), Ok(2));
+        let tids = state["tids"].clone_cast::<Array>();
+        assert_eq!(tids.iter().map(|tid| tid.as_int()).collect::<Vec<_>>(),
+                   [Ok(2), Ok(2)]);
+        script.finish().unwrap();
+    }
+
+    #[test]
+    fn deferred_error() {
+        let script = run("error", "fn on_sample(sample) { throw \"broken script\"; }\n\
+                                   fn on_end() { print(\"unreachable\"); }\n");
+        let error = script.finish().unwrap_err();
+        assert!(error.to_string().contains("broken script"));
+    }
+}
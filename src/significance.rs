@@ -0,0 +1,76 @@
+//! Statistical significance of the entries of per-function and per-DSO reports
+//!
+//! perf samples randomly, so the number of samples that a function or DSO got
+//! is only an estimate of the time that was spent in it, whose relative error
+//! is about 1/sqrt(count). Entries with only a handful of samples are thus
+//! mostly noise, which users should not waste their time chasing.
+
+
+/// Number of samples below which report entries are flagged by default, for
+/// which the relative error on the time spent is about 30%
+pub const DEFAULT_MIN_SAMPLES: usize = 10;
+
+
+/// How report entries with too few samples to be significant are dealt with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Significance {
+    /// Number of samples below which an entry is not significant
+    pub min_samples: usize,
+
+    /// Hide the entries which are not significant instead of flagging them
+    pub hide: bool,
+}
+//
+impl Significance {
+    /// Flag the entries with less than the default number of samples
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Truth that an entry with this many samples is significant
+    pub fn is_significant(&self, count: usize) -> bool {
+        count >= self.min_samples
+    }
+
+    /// Remove the entries which are not significant, if they should be hidden,
+    /// and tell how many were removed
+    pub fn select<T, F>(&self, entries: &mut Vec<T>, count: F) -> usize
+        where F: Fn(&T) -> usize
+    {
+        if !self.hide {
+            return 0;
+        }
+        let num_entries = entries.len();
+        entries.retain(|entry| self.is_significant(count(entry)));
+        num_entries - entries.len()
+    }
+
+    /// Suffix of the report line of an entry with this many samples
+    pub fn marker(&self, count: usize) -> &'static str {
+        if self.is_significant(count) { "" } else { " [few samples]" }
+    }
+
+    /// Explain what was done with the entries which are not significant, given
+    /// how many were hidden and how many were flagged
+    pub fn print_notice(&self, num_hidden: usize, num_flagged: usize) {
+        if num_hidden > 0 {
+            println!("{} entries with less than {} samples were hidden",
+                     num_hidden,
+                     self.min_samples);
+        }
+        if num_flagged > 0 {
+            println!("Entries with less than {} samples are flagged with [few samples], \
+                      they are likely to be noise",
+                     self.min_samples);
+        }
+    }
+}
+//
+impl Default for Significance {
+    fn default() -> Self {
+        Self {
+            min_samples: DEFAULT_MIN_SAMPLES,
+            hide: false,
+        }
+    }
+}
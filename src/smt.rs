@@ -0,0 +1,230 @@
+//! Detection of contention between the hardware threads of SMT cores (aka
+//! hyperthreads), which share a core's execution resources, so that code
+//! running on both siblings at once runs slower than on an idle core
+//!
+//! Samples are grouped in short time windows. Within a window, a CPU which got
+//! about as many non-idle samples as the busiest CPU is considered busy, and a
+//! core with several busy siblings is considered contended.
+
+use analysis::SampleCategory;
+use intervals;
+use sample::Sample;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::str::FromStr;
+
+
+/// Duration of the time windows which samples are grouped in, in nanoseconds
+const WINDOW_LEN: u64 = 10_000_000;
+
+/// Number of samples that the busiest CPU must get in a window for the busy
+/// CPUs of this window to be told apart from the others
+const MIN_WINDOW_SAMPLES: usize = 4;
+
+/// Fraction of the busiest CPU's samples from which a CPU is considered busy
+const BUSY_SHARE: f64 = 0.5;
+
+/// Fraction of a core's active windows above which contention deserves a
+/// warning
+const MAX_CONTENDED_SHARE: f64 = 0.25;
+
+/// Number of cores which are displayed in the report
+const NUM_REPORTED_CORES: usize = 10;
+
+
+/// Hardware threads of each SMT core of a machine
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct SmtTopology {
+    /// CPU numbers of the sibling threads of each core
+    pub cores: Vec<Vec<u32>>,
+}
+//
+impl SmtTopology {
+    /// Topology of the machine we are running on, from sysfs
+    pub fn host() -> Option<Self> {
+        let mut cores = Vec::new();
+        for entry in fs::read_dir("/sys/devices/system/cpu").ok()? {
+            let path = entry.ok()?.path().join("topology/thread_siblings_list");
+            if let Ok(siblings) = fs::read_to_string(path) {
+                let siblings = parse_cpu_list(siblings.trim())?;
+                if !cores.contains(&siblings) {
+                    cores.push(siblings);
+                }
+            }
+        }
+        Self::from_cores(cores)
+    }
+
+    /// Detect the topology from the metadata that perf script prints when run
+    /// with --header, which contains one "# sibling threads : <CPUs>" line
+    /// per core
+    pub fn from_header(header: &str) -> Option<Self> {
+        let cores = header.lines()
+                          .filter_map(|line| line.strip_prefix('#'))
+                          .filter_map(|line| line.split_once(':'))
+                          .filter(|(key, _)| key.trim() == "sibling threads")
+                          .map(|(_, cpus)| parse_cpu_list(cpus.trim()))
+                          .collect::<Option<Vec<_>>>()?;
+        Self::from_cores(cores)
+    }
+
+    /// Build a topology from the siblings of each core, if there are any
+    fn from_cores(mut cores: Vec<Vec<u32>>) -> Option<Self> {
+        cores.sort();
+        Some(Self { cores }).filter(|topology| !topology.cores.is_empty())
+    }
+
+    /// Truth that some core has more than one hardware thread
+    pub fn has_smt(&self) -> bool {
+        self.cores.iter().any(|siblings| siblings.len() > 1)
+    }
+}
+//
+impl FromStr for SmtTopology {
+    type Err = String;
+
+    /// Parse the siblings of each core, as CPU lists separated by colons, e.g.
+    /// "0,4:1,5:2,6:3,7"
+    fn from_str(s: &str) -> ::std::result::Result<Self, String> {
+        let cores = s.split(':').map(parse_cpu_list).collect::<Option<Vec<_>>>();
+        cores.and_then(Self::from_cores).ok_or_else(|| {
+            format!("Invalid SMT siblings {}, expected CPU lists separated by colons \
+                     (e.g. 0,4:1,5)", s)
+        })
+    }
+}
+
+
+/// Parse a list of CPU numbers and ranges, such as "0-3,8"
+fn parse_cpu_list(list: &str) -> Option<Vec<u32>> {
+    let mut cpus = Vec::new();
+    for range in list.split(',') {
+        match range.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (first.parse::<u32>().ok()?, last.parse().ok()?);
+                cpus.extend(first..=last);
+            },
+            None => cpus.push(range.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+///
+///
+/// Time windows in which the sibling threads of each core were busy together
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct SmtStatistics {
+    /// Topology of the machine that the profile was recorded on
+    pub topology: SmtTopology,
+
+    /// Windows of each core, by index in the topology
+    pub cores: BTreeMap<usize, CoreWindows>,
+
+    /// Window which is being filled, by index since time zero
+    pub current_window: Option<u64>,
+
+    /// Non-idle samples of each CPU in the current window
+    pub window_samples: HashMap<u32, usize>,
+}
+//
+impl SmtStatistics {
+    /// Start with all counters at zero, for a machine of known topology
+    pub fn new(topology: SmtTopology) -> Self {
+        Self {
+            topology,
+            ..Self::default()
+        }
+    }
+
+    /// Account for a newly classified sample. Idle samples and samples which
+    /// do not tell when and on which CPU they were taken are ignored.
+    pub fn record(&mut self, sample: &Sample, category: &SampleCategory) {
+        let (cpu, time) = match (sample.cpu(), sample.timestamp()) {
+            (Some(cpu), Some(time)) => (cpu, (time * 1e9).round() as u64),
+            _ => return,
+        };
+        let window = time / WINDOW_LEN;
+        match self.current_window {
+            Some(current) if window <= current => {},
+            _ => {
+                self.finish_window();
+                self.current_window = Some(window);
+            },
+        }
+        if let SampleCategory::Idle = *category {
+            return;
+        }
+        *self.window_samples.entry(cpu).or_insert(0) += 1;
+    }
+
+    /// Tell which cores had busy siblings in the current window, and start
+    /// over with an empty window
+    fn finish_window(&mut self) {
+        let max_samples = self.window_samples.values().cloned().max().unwrap_or(0);
+        if max_samples >= MIN_WINDOW_SAMPLES {
+            let window_samples = &self.window_samples;
+            let is_busy = |cpu: &u32| {
+                let samples = window_samples.get(cpu).cloned().unwrap_or(0);
+                samples as f64 >= BUSY_SHARE * max_samples as f64
+            };
+            for (idx, siblings) in self.topology.cores.iter().enumerate() {
+                let num_busy = siblings.iter().filter(|cpu| is_busy(cpu)).count();
+                if siblings.len() < 2 || num_busy == 0 {
+                    continue;
+                }
+                let windows = self.cores.entry(idx).or_default();
+                windows.num_active += 1;
+                if num_busy > 1 {
+                    windows.num_contended += 1;
+                }
+            }
+        }
+        self.window_samples.clear();
+    }
+
+    /// Print the cores whose sibling threads were most often busy together
+    pub fn print_report(&self) {
+        if !self.topology.has_smt() {
+            println!("No SMT core in the CPU topology");
+            return;
+        }
+        let mut stats = self.clone();
+        stats.finish_window();
+        let mut cores = stats.cores.iter()
+                                   .filter(|(_, windows)| windows.num_contended > 0)
+                                   .collect::<Vec<_>>();
+        if cores.is_empty() {
+            println!("No window of {} where SMT siblings were both busy",
+                     intervals::format_nanoseconds(WINDOW_LEN));
+            return;
+        }
+        cores.sort_by(|a, b| {
+            b.1.num_contended.cmp(&a.1.num_contended).then(a.0.cmp(b.0))
+        });
+        println!("SMT cores whose siblings were busy together, in windows of {}:",
+                 intervals::format_nanoseconds(WINDOW_LEN));
+        for (&idx, windows) in cores.into_iter().take(NUM_REPORTED_CORES) {
+            let cpus = stats.topology.cores[idx].iter()
+                                                .map(|cpu| cpu.to_string())
+                                                .collect::<Vec<_>>();
+            let share = windows.num_contended as f64 / windows.num_active as f64;
+            println!("- CPUs {}: {} of {} active windows ({:.1}%){}",
+                     cpus.join(","),
+                     windows.num_contended,
+                     windows.num_active,
+                     100.0 * share,
+                     if share > MAX_CONTENDED_SHARE { " [warning]" } else { "" });
+        }
+    }
+}
+///
+///
+/// Time windows of one SMT core
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct CoreWindows {
+    /// Windows in which at least one sibling was busy
+    pub num_active: usize,
+
+    /// Windows in which several siblings were busy
+    pub num_contended: usize,
+}
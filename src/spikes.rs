@@ -0,0 +1,201 @@
+//! Correlation of latency spikes with what the sampled code was doing right
+//! before them. The user picks an event which marks spikes, such as a USDT
+//! probe that an application hits when it misses a frame deadline, and the
+//! samples of the preceding time window tell where the time went.
+//!
+//! When the spike event tells which process it comes from, only the samples
+//! of that process are taken into account. Windows never reach back past the
+//! previous spike, so that no sample is attributed to two spikes.
+
+use intervals;
+use sample::Sample;
+use std::collections::{HashMap, VecDeque};
+
+
+/// Duration of the window preceding each spike, in nanoseconds
+const SPIKE_WINDOW: u64 = 10_000_000;
+
+/// Number of innermost frames which identify a stack in the report
+const STACK_DEPTH: usize = 4;
+
+/// Number of functions and stacks which are displayed in the report
+const NUM_REPORTED_ENTRIES: usize = 10;
+
+
+/// Functions and stacks which were sampled right before each spike
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct SpikeStatistics {
+    /// Name of the event which marks spikes
+    pub event: String,
+
+    /// Number of spikes
+    pub num_spikes: usize,
+
+    /// Number of samples with a stack trace, and number of those which were
+    /// taken in a window preceding a spike
+    pub num_samples: usize,
+    pub num_window_samples: usize,
+
+    /// Samples of each leaf function, as "symbol (dso)"
+    pub functions: HashMap<String, FunctionSamples>,
+
+    /// Samples of each stack in windows preceding spikes, by innermost frames
+    pub stacks: HashMap<String, usize>,
+
+    /// Samples of the current window, oldest first. They are only needed to
+    /// correlate samples with the next spike, so they are not saved in
+    /// checkpoints.
+    #[serde(skip)]
+    recent_samples: VecDeque<RecentSample>,
+
+    /// Time of the last spike of each process, in nanoseconds, which is not
+    /// saved in checkpoints for the same reason
+    #[serde(skip)]
+    last_spikes: HashMap<Option<u32>, u64>,
+}
+//
+impl SpikeStatistics {
+    /// Start with all counters at zero, with spikes marked by some event
+    pub fn new(event: &str) -> Self {
+        Self {
+            event: event.to_owned(),
+            ..Self::default()
+        }
+    }
+
+    /// Account for a new sample, which either marks a spike or may be part of
+    /// the window preceding the next one. Samples which do not tell when they
+    /// were taken are ignored.
+    pub fn record(&mut self, sample: &Sample) {
+        let time = match sample.timestamp() {
+            Some(time) => (time * 1e9).round() as u64,
+            None => return,
+        };
+        if sample.event() == Some(self.event.as_str()) {
+            self.record_spike(sample.pid(), time);
+            return;
+        }
+        let frames = sample.frames().take(STACK_DEPTH).collect::<Vec<_>>();
+        let function = match frames.first() {
+            Some(leaf) => format!("{} ({})", leaf.symbol, leaf.dso),
+            None => return,
+        };
+        self.num_samples += 1;
+        match self.functions.get_mut(&function) {
+            Some(counts) => counts.num_samples += 1,
+            None => {
+                self.functions.insert(function.clone(), FunctionSamples {
+                    num_samples: 1,
+                    num_before_spikes: 0,
+                });
+            },
+        }
+        let stack = frames.iter().map(|frame| frame.symbol).collect::<Vec<_>>();
+        while self.recent_samples.front().is_some_and(|recent| {
+            recent.time + SPIKE_WINDOW < time
+        }) {
+            self.recent_samples.pop_front();
+        }
+        self.recent_samples.push_back(RecentSample {
+            time,
+            pid: sample.pid(),
+            function,
+            stack: stack.join(" <- "),
+        });
+    }
+
+    /// Attribute the samples of the window preceding a spike to it
+    fn record_spike(&mut self, pid: Option<u32>, time: u64) {
+        self.num_spikes += 1;
+        let window_start = time.saturating_sub(SPIKE_WINDOW);
+        let window_start = match self.last_spikes.insert(pid, time) {
+            Some(last_spike) => window_start.max(last_spike),
+            None => window_start,
+        };
+        let window = self.recent_samples.iter().filter(|recent| {
+            recent.time >= window_start && recent.time <= time &&
+            (pid.is_none() || recent.pid == pid)
+        });
+        for recent in window {
+            self.num_window_samples += 1;
+            if let Some(counts) = self.functions.get_mut(&recent.function) {
+                counts.num_before_spikes += 1;
+            }
+            match self.stacks.get_mut(&recent.stack) {
+                Some(count) => *count += 1,
+                None => { self.stacks.insert(recent.stack.clone(), 1); },
+            }
+        }
+    }
+
+    /// Print the functions and stacks which dominated the windows preceding
+    /// spikes, along with their share of the whole profile for comparison
+    pub fn print_report(&self) {
+        if self.num_spikes == 0 {
+            println!("No spike, event {} was never seen", self.event);
+            return;
+        }
+        println!("{} spikes of {}, preceded by {} samples within {}",
+                 self.num_spikes,
+                 self.event,
+                 self.num_window_samples,
+                 intervals::format_nanoseconds(SPIKE_WINDOW));
+        if self.num_window_samples == 0 {
+            return;
+        }
+        let percent = |count: usize, total: usize| {
+            100.0 * count as f64 / total.max(1) as f64
+        };
+        let mut functions = self.functions.iter()
+                                          .filter(|(_, f)| f.num_before_spikes > 0)
+                                          .collect::<Vec<_>>();
+        functions.sort_by(|a, b| {
+            b.1.num_before_spikes.cmp(&a.1.num_before_spikes).then(a.0.cmp(b.0))
+        });
+        println!("Functions before spikes:");
+        for (function, counts) in functions.into_iter().take(NUM_REPORTED_ENTRIES) {
+            println!("- {}: {} ({:.1}% before spikes, {:.1}% overall)",
+                     function,
+                     counts.num_before_spikes,
+                     percent(counts.num_before_spikes, self.num_window_samples),
+                     percent(counts.num_samples, self.num_samples));
+        }
+        let mut stacks = self.stacks.iter().collect::<Vec<_>>();
+        stacks.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        println!("Stacks before spikes:");
+        for (stack, &count) in stacks.into_iter().take(NUM_REPORTED_ENTRIES) {
+            println!("- {}: {} ({:.1}%)",
+                     stack,
+                     count,
+                     percent(count, self.num_window_samples));
+        }
+    }
+}
+///
+///
+/// Samples of one leaf function
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct FunctionSamples {
+    /// Samples over the whole profile
+    pub num_samples: usize,
+
+    /// Samples in windows preceding spikes
+    pub num_before_spikes: usize,
+}
+///
+///
+/// Sample of the window preceding the next spike
+#[derive(Clone, Debug, PartialEq)]
+struct RecentSample {
+    /// Time of the sample, in nanoseconds
+    time: u64,
+
+    /// Process which was sampled, if known
+    pid: Option<u32>,
+
+    /// Leaf function, as "symbol (dso)"
+    function: String,
+
+    /// Innermost symbols of the stack, leaf first
+    stack: String,
+}
@@ -0,0 +1,625 @@
+//! Aggregate statistics about the samples that went through the analyzer
+
+use addresses::AddressStatistics;
+use analysis::{SampleCategory, Severity};
+use bpf::BpfStatistics;
+use c2c::SharingStatistics;
+use cache::CacheStatistics;
+use cgroups::CgroupStatistics;
+use config::Config;
+use coverage::CoverageStatistics;
+use data::DataAddressStatistics;
+use dwarf::DwarfSizeStatistics;
+use energy::EnergyStatistics;
+use entropy::EntropyStatistics;
+use ftrace::FunctionTraceStatistics;
+use gc::GcStatistics;
+use gpu::GpuStatistics;
+use guest::GuestStatistics;
+use hotspots::{FunctionStatistics, LeafStatistics};
+use interrupts::InterruptStatistics;
+use intervals::IntervalStatistics;
+use ipc::IpcStatistics;
+use itrace::BranchTraceStatistics;
+use jvm::JvmStatistics;
+use labels::LabelStatistics;
+use metadata::RecordingMetadata;
+use modules::ModuleStatistics;
+use periods::PeriodStatistics;
+use probes::ProbeStatistics;
+use python::PythonStatistics;
+use significance::Significance;
+use smt::SmtStatistics;
+use spikes::SpikeStatistics;
+use symbolization::SymbolizationStatistics;
+use tasks::TaskStatistics;
+use threads::ThreadStatistics;
+use throttle::ThrottleStatistics;
+use topdown::TopDownStatistics;
+use unwinding::UnwindStatistics;
+use wasm::WasmStatistics;
+
+
+/// Fraction of stack-less samples above which the recording as a whole is
+/// assumed to lack call graphs, rather than a few samples lacking one
+const MISSING_CALL_GRAPHS_FRACTION: f64 = 0.99;
+
+
+/// Counters of samples falling in each classification category
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Statistics {
+    pub num_samples: usize,
+    pub num_normal_samples: usize,
+    pub num_vdso_roots: usize,
+    pub num_hard_irqs: usize,
+    pub num_soft_irqs: usize,
+    pub num_idle_samples: usize,
+    pub num_guest_samples: usize,
+    pub num_stack_less_samples: usize,
+    pub num_corrupt_frame_addresses: usize,
+    pub num_suspect_returns: usize,
+    pub num_kernel_unsymbolized: usize,
+    pub num_truncated_stacks: usize,
+    pub num_broken_by_signal: usize,
+    pub num_broken_by_cgo: usize,
+    pub num_wasm_code: usize,
+    pub num_jit_samples: usize,
+    pub num_bpf_programs: usize,
+    pub num_anonymous_code: usize,
+    pub num_upgraded_libraries: usize,
+    pub num_anonymous_executable: usize,
+    pub num_stale_dsos: usize,
+    pub num_bad_dsos: usize,
+    pub num_windows_code: usize,
+    pub num_broken_last_frames: usize,
+    pub num_unexpected_last_func: usize,
+
+    /// Samples which were left out of the analysis by the user's filters
+    pub num_filtered_out: usize,
+
+    /// One in how many samples was analyzed, if the input was subsampled, which
+    /// is the factor by which the counters are scaled in the summary
+    pub sample_period: Option<u64>,
+
+    /// Samples which were left out of the analysis by subsampling
+    pub num_skipped: usize,
+
+    /// Truncated DWARF stacks which were extended using the branch stack
+    pub num_lbr_stitched: usize,
+
+    /// Truncated stacks which were repaired using other samples' stacks
+    pub num_repaired: usize,
+
+    /// Samples with consecutive duplicate stack frames, and how many frames
+    /// were duplicates
+    pub num_duplicate_frame_samples: usize,
+    pub num_duplicate_frames: usize,
+
+    /// Samples with callers resolved to the first byte of a function ("+0x0"),
+    /// and how many such callers there were
+    pub num_zero_offset_samples: usize,
+    pub num_zero_offset_callers: usize,
+
+    /// Counters of the user-defined categories, in configuration order
+    pub custom: Vec<(String, usize)>,
+
+    /// Breakdown of JVM samples, if the JVM preset is enabled
+    pub jvm: Option<JvmStatistics>,
+
+    /// Breakdown of Python samples, if the Python preset is enabled
+    pub python: Option<PythonStatistics>,
+
+    /// Breakdown of WebAssembly samples, if the WebAssembly preset is enabled
+    pub wasm: Option<WasmStatistics>,
+
+    /// Samples of each async task, if the tokio preset is enabled
+    pub tasks: Option<TaskStatistics>,
+
+    /// Mutator/GC split of garbage-collected processes, if the JVM or Go
+    /// preset is enabled
+    pub gc: Option<GcStatistics>,
+
+    /// Per-DSO symbolization rates, if the DSO report is enabled
+    pub symbolization: Option<SymbolizationStatistics>,
+
+    /// Hot function ranking, if the function report is enabled
+    pub functions: Option<FunctionStatistics>,
+
+    /// Leaf functions of each anomalous category, if that report is enabled
+    pub leaves: Option<LeafStatistics>,
+
+    /// Top-Down breakdown of leaf functions, if the Top-Down report is enabled
+    pub topdown: Option<TopDownStatistics>,
+
+    /// Instructions per cycle of leaf functions, if the IPC report is enabled
+    pub ipc: Option<IpcStatistics>,
+
+    /// Cache miss attribution, if the cache report is enabled
+    pub cache: Option<CacheStatistics>,
+
+    /// Hot data regions, if the data address report is enabled
+    pub data: Option<DataAddressStatistics>,
+
+    /// Contended cachelines, if the c2c report is enabled
+    pub sharing: Option<SharingStatistics>,
+
+    /// Energy attribution to functions, if the energy report is enabled
+    pub energy: Option<EnergyStatistics>,
+
+    /// GPU driver activity, if the GPU report is enabled
+    pub gpu: Option<GpuStatistics>,
+
+    /// Host and guest sample split, if the guest report is enabled
+    pub guests: Option<GuestStatistics>,
+
+    /// Time spent in each BPF program, if the BPF report is enabled
+    pub bpf: Option<BpfStatistics>,
+
+    /// Hits of dynamic probes, if the probe report is enabled
+    pub probes: Option<ProbeStatistics>,
+
+    /// Branches and basic blocks of a hardware trace, if the trace report is
+    /// enabled
+    pub itrace: Option<BranchTraceStatistics>,
+
+    /// Call trees of function tracer events, if the ftrace report is enabled
+    pub ftrace: Option<FunctionTraceStatistics>,
+
+    /// Symbolization of kernel module frames, if the module report is enabled
+    pub modules: Option<ModuleStatistics>,
+
+    /// Samples preceding latency spikes, if a spike event was chosen
+    pub spikes: Option<SpikeStatistics>,
+
+    /// Samples of each label, if label events were chosen
+    pub labels: Option<LabelStatistics>,
+
+    /// Per-thread stack trace quality, if the thread report is enabled
+    pub threads: Option<ThreadStatistics>,
+
+    /// Per-CPU interrupt accounting, if the IRQ report is enabled
+    pub interrupts: Option<InterruptStatistics>,
+
+    /// Contention between SMT siblings, if the SMT report is enabled
+    pub smt: Option<SmtStatistics>,
+
+    /// Sampling period sanity checks, if the period report is enabled
+    pub periods: Option<PeriodStatistics>,
+
+    /// Time during which events were throttled, if the throttle report is
+    /// enabled
+    pub throttling: Option<ThrottleStatistics>,
+
+    /// Time between consecutive samples of each event on each CPU, if the
+    /// interval report is enabled
+    pub intervals: Option<IntervalStatistics>,
+
+    /// Wall-clock coverage of each thread, if the coverage report is enabled
+    pub coverage: Option<CoverageStatistics>,
+
+    /// Diversity of the stacks of each process, if the entropy report is
+    /// enabled
+    pub entropy: Option<EntropyStatistics>,
+
+    /// Per-cgroup stack trace quality, if the cgroup report is enabled
+    pub cgroups: Option<CgroupStatistics>,
+
+    /// Frame address ranges of each DSO, if frame addresses are checked
+    pub addresses: Option<AddressStatistics>,
+
+    /// Depths of complete and truncated stacks, if the DWARF stack dump size
+    /// report is enabled
+    pub dwarf_sizes: Option<DwarfSizeStatistics>,
+
+    /// Stack statistics per event, if the unwinding report is enabled
+    pub unwinding: Option<UnwindStatistics>,
+
+    /// Metadata about the recording, if perf script printed it (--header)
+    pub metadata: Option<RecordingMetadata>,
+}
+//
+impl Statistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start counting the samples of a user-defined category, so that it
+    /// appears in the summary even if no sample ends up in it
+    pub fn add_custom_category(&mut self, name: &str) {
+        if !self.custom.iter().any(|(custom, _)| custom == name) {
+            self.custom.push((name.to_owned(), 0));
+        }
+    }
+
+    /// Account for a newly classified sample
+    pub fn record(&mut self, category: &SampleCategory) {
+        use self::SampleCategory::*;
+        self.num_samples += 1;
+        match *category {
+            Normal => self.num_normal_samples += 1,
+            VdsoRoot => self.num_vdso_roots += 1,
+            HardIrq => self.num_hard_irqs += 1,
+            SoftIrq => self.num_soft_irqs += 1,
+            Idle => self.num_idle_samples += 1,
+            Guest => self.num_guest_samples += 1,
+            NoStackTrace => self.num_stack_less_samples += 1,
+            CorruptFrameAddress(_dso) => self.num_corrupt_frame_addresses += 1,
+            SuspectReturnAddress(_dso) => self.num_suspect_returns += 1,
+            KernelSymbolsUnavailable => self.num_kernel_unsymbolized += 1,
+            TruncatedStack => self.num_truncated_stacks += 1,
+            BrokenBySignal(_depth) => self.num_broken_by_signal += 1,
+            BrokenByCgo => self.num_broken_by_cgo += 1,
+            WasmCode => self.num_wasm_code += 1,
+            JitCompiledBy(_pid) => self.num_jit_samples += 1,
+            BpfProgram(_tag) => self.num_bpf_programs += 1,
+            AnonymousCode(_dso) => self.num_anonymous_code += 1,
+            UpgradedLibrary(_dso) => self.num_upgraded_libraries += 1,
+            AnonymousExecutable(_dso) => self.num_anonymous_executable += 1,
+            StaleDSO(_dso) => self.num_stale_dsos += 1,
+            BrokenByBadDSO(_dso) => self.num_bad_dsos += 1,
+            WindowsCode(_dso) => self.num_windows_code += 1,
+            BrokenLastFrame => self.num_broken_last_frames += 1,
+            UnexpectedLastFunc(_name) => self.num_unexpected_last_func += 1,
+            Custom(name) => {
+                self.add_custom_category(name);
+                let counter = self.custom.iter_mut().find(|(custom, _)| custom == name);
+                counter.unwrap().1 += 1;
+            },
+        }
+    }
+
+    /// Counters of each sample category, along with a human-readable label.
+    /// Categories which carry data come with placeholder values.
+    pub fn counters(&self) -> Vec<(SampleCategory<'_>, &str, usize)> {
+        use self::SampleCategory::*;
+        let mut counters = vec![
+            (Normal, "Normal data samples", self.num_normal_samples),
+            (VdsoRoot, "Stacks ending in the vDSO", self.num_vdso_roots),
+            (HardIrq, "Samples in hardware interrupts", self.num_hard_irqs),
+            (SoftIrq, "Samples in softirqs", self.num_soft_irqs),
+            (Idle, "Idle or polling samples", self.num_idle_samples),
+            (Guest, "Samples from virtual machine guests", self.num_guest_samples),
+            (NoStackTrace, "Samples without a stack trace", self.num_stack_less_samples),
+            (CorruptFrameAddress(""),
+             "Stacks with corrupt frame addresses",
+             self.num_corrupt_frame_addresses),
+            (SuspectReturnAddress(""),
+             "Stacks with suspect return addresses",
+             self.num_suspect_returns),
+            (KernelSymbolsUnavailable,
+             "Samples without kernel symbols",
+             self.num_kernel_unsymbolized),
+            (TruncatedStack, "Truncated DWARF stacks", self.num_truncated_stacks),
+            (BrokenBySignal(0),
+             "Stacks broken at a signal frame",
+             self.num_broken_by_signal),
+            (BrokenByCgo, "Stacks broken by a cgo transition", self.num_broken_by_cgo),
+            (WasmCode, "Stacks ending in WebAssembly code", self.num_wasm_code),
+            (JitCompiledBy(0), "JIT-compiled samples", self.num_jit_samples),
+            (BpfProgram(""), "Stacks ending in a BPF program", self.num_bpf_programs),
+            (AnonymousCode(""),
+             "Stacks going through anonymous memory",
+             self.num_anonymous_code),
+            (UpgradedLibrary(""),
+             "Stacks ending in an upgraded library",
+             self.num_upgraded_libraries),
+            (AnonymousExecutable(""),
+             "Stacks ending in anonymous executable files",
+             self.num_anonymous_executable),
+            (StaleDSO(""), "DSO changed since recording", self.num_stale_dsos),
+            (BrokenByBadDSO(""), "Stack trace broken by a bad DSO", self.num_bad_dsos),
+            (WindowsCode(""), "Stacks ending in Windows code", self.num_windows_code),
+            (BrokenLastFrame,
+             "Samples with broken last frame",
+             self.num_broken_last_frames),
+            (UnexpectedLastFunc(""),
+             "Samples with unusual last frame",
+             self.num_unexpected_last_func),
+        ];
+        counters.extend(self.custom.iter().map(|(name, count)| {
+            (Custom(name), name.as_str(), *count)
+        }));
+        counters
+    }
+
+    /// Truth that essentially no sample has a stack trace, which means that
+    /// call graphs were not recorded (or not printed) at all
+    pub fn lacks_call_graphs(&self) -> bool {
+        self.num_samples > 0 &&
+        self.num_stack_less_samples as f64
+            >= MISSING_CALL_GRAPHS_FRACTION * self.num_samples as f64
+    }
+
+    /// Warn that every report is an estimate if only some of the samples were
+    /// analyzed. This is printed before any report, so it covers all of them.
+    pub fn print_estimate_banner(&self) {
+        if let Some(period) = self.sample_period {
+            println!("*** ESTIMATED: only 1 in {} samples was analyzed ({} skipped). \
+                      Counts of the summary are scaled up accordingly and marked \
+                      with ~, whereas the anomaly dump and detailed reports below \
+                      only count the analyzed samples ***",
+                     period,
+                     self.num_skipped);
+            println!();
+        }
+    }
+
+    /// Print a summary of sample statistics, along with the severity of each
+    /// anomaly which was encountered and what can be done about it. Since perf
+    /// samples randomly, a binomial confidence interval of the given level
+    /// (e.g. 0.95) can be displayed for each category, and per-function or
+    /// per-DSO entries with too few samples are flagged or hidden.
+    pub fn print_summary(&self,
+                         config: &Config,
+                         confidence: Option<f64>,
+                         significance: &Significance) {
+        let counters = self.counters();
+
+        // Remind which recording the analysis is about, if we know
+        if let Some(ref metadata) = self.metadata {
+            metadata.print_report();
+            println!();
+        }
+
+        // Without call graphs, there is nothing to analyze, so the user should
+        // know that before anything else
+        if self.lacks_call_graphs() {
+            self.print_missing_call_graphs();
+            println!();
+        }
+
+        // A subsampled analysis only yields estimates, which are obtained by
+        // scaling the counters up, while fractions are computed from the
+        // actual counters so that confidence intervals remain honest
+        let estimate = |count: usize| match self.sample_period {
+            Some(period) if count > 0 => format!("~{}", count as u64 * period),
+            _ => count.to_string(),
+        };
+
+        // Display the counters as a fraction of the total, with a confidence
+        // interval if asked to, and flag the anomalies which did occur
+        println!("Total samples: {}", estimate(self.num_samples));
+        if self.num_filtered_out > 0 {
+            println!("Filtered out samples: {}", estimate(self.num_filtered_out));
+        }
+        if self.num_lbr_stitched > 0 {
+            println!("Truncated stacks extended with the branch stack: {}",
+                     estimate(self.num_lbr_stitched));
+        }
+        if self.num_repaired > 0 {
+            println!("Truncated stacks repaired from nearby samples: {}",
+                     estimate(self.num_repaired));
+        }
+        if self.num_duplicate_frame_samples > 0 {
+            println!("Samples with duplicate consecutive frames: {} ({} duplicates)",
+                     estimate(self.num_duplicate_frame_samples),
+                     estimate(self.num_duplicate_frames));
+        }
+        if self.num_zero_offset_samples > 0 {
+            println!("Samples with callers at +0x0, a sign of bogus symbolization: \
+                      {} ({} frames)",
+                     estimate(self.num_zero_offset_samples),
+                     estimate(self.num_zero_offset_callers));
+        }
+        for &(ref category, label, count) in &counters {
+            let mut line = format!("- {}: {}", label, estimate(count));
+            if self.num_samples > 0 {
+                let fraction = count as f64 / self.num_samples as f64;
+                line.push_str(&format!(" ({:.1}%", 100.0 * fraction));
+                if let Some(level) = confidence {
+                    let (low, high) = wilson_interval(count, self.num_samples, level);
+                    line.push_str(&format!(", {}% CI: {:.1}-{:.1}%",
+                                           100.0 * level, 100.0 * low, 100.0 * high));
+                }
+                line.push(')');
+            }
+            let severity = config.severity(category);
+            if count > 0 && severity > Severity::Info {
+                line.push_str(&format!(" [{}]", severity));
+            }
+            println!("{}", line);
+        }
+
+        // Runtime-specific report sections
+        if let Some(ref jvm) = self.jvm {
+            println!();
+            jvm.print_report();
+        }
+        if let Some(ref python) = self.python {
+            println!();
+            python.print_report();
+        }
+        if let Some(ref wasm) = self.wasm {
+            println!();
+            wasm.print_report();
+        }
+        if let Some(ref tasks) = self.tasks {
+            println!();
+            tasks.print_report();
+        }
+        if let Some(ref gc) = self.gc {
+            println!();
+            gc.print_report();
+        }
+        if let Some(ref symbolization) = self.symbolization {
+            println!();
+            symbolization.print_report(significance);
+        }
+        if let Some(ref functions) = self.functions {
+            println!();
+            functions.print_report(significance);
+        }
+        if let Some(ref leaves) = self.leaves {
+            println!();
+            leaves.print_report(&counters, significance);
+        }
+        if let Some(ref topdown) = self.topdown {
+            println!();
+            topdown.print_report();
+        }
+        if let Some(ref ipc) = self.ipc {
+            println!();
+            ipc.print_report();
+        }
+        if let Some(ref cache) = self.cache {
+            println!();
+            cache.print_report();
+        }
+        if let Some(ref data) = self.data {
+            println!();
+            data.print_report();
+        }
+        if let Some(ref sharing) = self.sharing {
+            println!();
+            sharing.print_report();
+        }
+        if let Some(ref energy) = self.energy {
+            println!();
+            energy.print_report();
+        }
+        if let Some(ref gpu) = self.gpu {
+            println!();
+            gpu.print_report();
+        }
+        if let Some(ref guests) = self.guests {
+            println!();
+            guests.print_report();
+        }
+        if let Some(ref bpf) = self.bpf {
+            println!();
+            bpf.print_report();
+        }
+        if let Some(ref probes) = self.probes {
+            println!();
+            probes.print_report();
+        }
+        if let Some(ref itrace) = self.itrace {
+            println!();
+            itrace.print_report();
+        }
+        if let Some(ref ftrace) = self.ftrace {
+            println!();
+            ftrace.print_report();
+        }
+        if let Some(ref modules) = self.modules {
+            println!();
+            modules.print_report();
+        }
+        if let Some(ref spikes) = self.spikes {
+            println!();
+            spikes.print_report();
+        }
+        if let Some(ref labels) = self.labels {
+            println!();
+            labels.print_report();
+        }
+        if let Some(ref threads) = self.threads {
+            println!();
+            threads.print_report();
+        }
+        if let Some(ref interrupts) = self.interrupts {
+            println!();
+            interrupts.print_report();
+        }
+        if let Some(ref smt) = self.smt {
+            println!();
+            smt.print_report();
+        }
+        if let Some(ref periods) = self.periods {
+            println!();
+            periods.print_report();
+        }
+        if let Some(ref throttling) = self.throttling {
+            println!();
+            throttling.print_report();
+        }
+        if let Some(ref intervals) = self.intervals {
+            println!();
+            intervals.print_report();
+        }
+        if let Some(ref coverage) = self.coverage {
+            println!();
+            coverage.print_report();
+        }
+        if let Some(ref entropy) = self.entropy {
+            println!();
+            entropy.print_report();
+        }
+        if let Some(ref cgroups) = self.cgroups {
+            println!();
+            cgroups.print_report();
+        }
+        if let Some(ref addresses) = self.addresses {
+            println!();
+            addresses.print_report();
+        }
+        if let Some(ref dwarf_sizes) = self.dwarf_sizes {
+            println!();
+            let dump_size = self.metadata.as_ref()
+                                .and_then(RecordingMetadata::user_stack_size);
+            dwarf_sizes.print_report(dump_size);
+        }
+        if let Some(ref unwinding) = self.unwinding {
+            println!();
+            unwinding.print_report(self.metadata.as_ref());
+        }
+
+        // Some categories of samples have a well-known fix
+        let hints = counters.iter().filter(|&&(_, _, count)| count > 0)
+                            .filter_map(|&(ref category, label, _)| {
+                                category.hint().map(|hint| (label, hint))
+                            })
+                            .collect::<Vec<_>>();
+        if !hints.is_empty() {
+            println!();
+            println!("Hints:");
+            for (label, hint) in hints {
+                println!("- {}: {}", label, hint);
+            }
+        }
+    }
+
+    /// Explain that the recording has no call graphs, and how to get some
+    fn print_missing_call_graphs(&self) {
+        println!("*** NO CALL GRAPHS: {} of {} samples ({:.1}%) have no stack trace ***",
+                 self.num_stack_less_samples,
+                 self.num_samples,
+                 100.0 * self.num_stack_less_samples as f64 / self.num_samples as f64);
+        let records_call_graphs = self.metadata.as_ref()
+                                      .and_then(RecordingMetadata::records_call_graphs);
+        if records_call_graphs == Some(true) {
+            println!("The recorded events did sample call chains, so perf script was \
+                      probably told not to print them. Make sure that its -F option \
+                      includes the ip, sym and dso fields.");
+        } else {
+            println!("The profile was most likely recorded without call graphs, so the \
+                      stack trace analysis below is meaningless.");
+            println!("Record it again with perf record -g, or with perf record \
+                      --call-graph dwarf if the profiled code was built without frame \
+                      pointers.");
+        }
+    }
+}
+
+
+/// Wilson score interval of a binomial proportion, which unlike the textbook
+/// normal approximation behaves well for rare categories and small samples
+fn wilson_interval(successes: usize, trials: usize, level: f64) -> (f64, f64) {
+    let n = trials as f64;
+    let p = successes as f64 / n;
+    let z = normal_quantile(0.5 + level / 2.0);
+    let z2 = z * z;
+    let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+    let half_width = z / (1.0 + z2 / n) * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+    ((center - half_width).max(0.0), (center + half_width).min(1.0))
+}
+
+
+/// Quantile function of the standard normal distribution, for 0.5 <= p < 1,
+/// using the rational approximation 26.2.23 of Abramowitz and Stegun
+fn normal_quantile(p: f64) -> f64 {
+    let t = (-2.0 * (1.0 - p).ln()).sqrt();
+    t - (2.515517 + 0.802853 * t + 0.010328 * t * t) /
+        (1.0 + 1.432788 * t + 0.189269 * t * t + 0.001308 * t * t * t)
+}
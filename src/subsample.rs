@@ -0,0 +1,85 @@
+//! Deterministic subsampling of perf script's output, which gives a fast
+//! preview of the analysis of huge dumps
+//!
+//! Samples are picked by hashing their position in the input along with a
+//! seed, so that a given seed always picks the same samples, including when an
+//! analysis is resumed from a checkpoint, without aliasing with periodic
+//! workloads like picking every Nth sample in order would. The price is that
+//! each sample is kept with probability 1/N, so about 1/N of the samples are
+//! analyzed rather than exactly one in every N.
+
+use std::str::FromStr;
+
+
+/// Fraction of the samples which are analyzed, written as "1/N"
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SampleRate {
+    /// One in this many samples is analyzed
+    pub period: u64,
+
+    /// Seed of the choice of analyzed samples
+    pub seed: u64,
+}
+//
+impl SampleRate {
+    /// Pick a different set of samples
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Truth that the sample at some position of the input should be analyzed
+    pub fn keeps(&self, position: u64) -> bool {
+        // SplitMix64 finalizer, which mixes every input bit into the output
+        let mut hash = position ^ self.seed.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        hash ^= hash >> 31;
+        hash.is_multiple_of(self.period)
+    }
+}
+//
+impl FromStr for SampleRate {
+    type Err = String;
+
+    fn from_str(rate: &str) -> ::std::result::Result<Self, String> {
+        let period = rate.strip_prefix("1/").and_then(|period| period.parse().ok());
+        match period {
+            Some(period) if period > 0 => Ok(SampleRate { period, seed: 0 }),
+            _ => Err(format!("Invalid sample rate {}, expected 1/N with N > 0", rate)),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!("1/10".parse(), Ok(SampleRate { period: 10, seed: 0 }));
+        assert!("1/0".parse::<SampleRate>().is_err());
+        assert!("2/3".parse::<SampleRate>().is_err());
+        assert!("10".parse::<SampleRate>().is_err());
+    }
+
+    #[test]
+    fn picks() {
+        // Everything is kept at full rate
+        let full = "1/1".parse::<SampleRate>().unwrap();
+        assert!((0..10_000).all(|position| full.keeps(position)));
+
+        // A seed always picks the same samples, and another one picks others
+        let rate = "1/10".parse::<SampleRate>().unwrap().with_seed(42);
+        let picks = |rate: SampleRate| {
+            (0..100_000u64).filter(|&position| rate.keeps(position)).collect::<Vec<_>>()
+        };
+        assert_eq!(picks(rate), picks(rate));
+        assert_ne!(picks(rate), picks(rate.with_seed(43)));
+
+        // About 1 in 10 samples is kept
+        let num_kept = picks(rate).len();
+        assert!(num_kept > 9_000 && num_kept < 11_000, "kept {} samples", num_kept);
+    }
+}
@@ -0,0 +1,105 @@
+//! Frame-level symbolization statistics, which tell which DSOs perf could not
+//! symbolize. This is useful to prioritize which debuginfo packages to install.
+
+use sample::Sample;
+use significance::Significance;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+
+/// Number of DSOs which are displayed in the report
+const NUM_REPORTED_DSOS: usize = 10;
+
+/// Number of unsymbolized address ranges which are displayed per DSO
+const NUM_REPORTED_BUCKETS: usize = 3;
+
+
+/// Per-DSO counts of symbolized and unsymbolized stack frames
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct SymbolizationStatistics {
+    pub dsos: HashMap<String, DsoFrames>,
+
+    /// Size of the address ranges which unsymbolized frames are aggregated
+    /// into, if address-only statistics are enabled
+    pub bucket_size: Option<u64>,
+}
+//
+impl SymbolizationStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also aggregate unsymbolized frames by address range of some size, so that
+    /// the hot regions of stripped binaries can be told apart
+    pub fn with_address_buckets(mut self, bucket_size: u64) -> Self {
+        self.bucket_size = Some(bucket_size);
+        self
+    }
+
+    /// Account for the stack frames of a new sample. Frames whose DSO is
+    /// unknown cannot be attributed, and are thus ignored.
+    pub fn record(&mut self, sample: &Sample) {
+        for frame in sample.frames().filter(|frame| frame.dso != "[unknown]") {
+            if !self.dsos.contains_key(frame.dso) {
+                self.dsos.insert(frame.dso.to_owned(), DsoFrames::default());
+            }
+            let counters = self.dsos.get_mut(frame.dso).unwrap();
+            if frame.is_unknown_symbol() {
+                counters.num_unknown += 1;
+                let bucket = self.bucket_size.and_then(|size| frame.address_bucket(size));
+                if let Some(bucket) = bucket {
+                    *counters.unknown_buckets.entry(bucket).or_insert(0) += 1;
+                }
+            } else {
+                counters.num_named += 1;
+            }
+        }
+    }
+
+    /// Print the DSOs which have the most unsymbolized frames, dealing with
+    /// those which have too few of them as specified
+    pub fn print_report(&self, significance: &Significance) {
+        let mut dsos = self.dsos.iter()
+                                .filter(|(_, frames)| frames.num_unknown > 0)
+                                .collect::<Vec<_>>();
+        dsos.sort_by_key(|&(dso, frames)| (Reverse(frames.num_unknown), dso));
+        println!("DSOs with unsymbolized frames, worst first: {}", dsos.len());
+        let num_hidden = significance.select(&mut dsos, |dso| dso.1.num_unknown);
+        let mut num_flagged = 0;
+        for (dso, frames) in dsos.into_iter().take(NUM_REPORTED_DSOS) {
+            let total = frames.num_unknown + frames.num_named;
+            num_flagged += !significance.is_significant(frames.num_unknown) as usize;
+            println!("- {}: {} of {} frames ({:.1}%){}",
+                     dso,
+                     frames.num_unknown,
+                     total,
+                     100.0 * frames.num_unknown as f64 / total as f64,
+                     significance.marker(frames.num_unknown));
+
+            // Tell where the unsymbolized frames are, if we know
+            let mut buckets = frames.unknown_buckets.iter().collect::<Vec<_>>();
+            buckets.sort_by_key(|&(&start, &count)| (Reverse(count), start));
+            let bucket_size = self.bucket_size.unwrap_or(1);
+            for (start, count) in buckets.into_iter().take(NUM_REPORTED_BUCKETS) {
+                println!("  - {:#x}-{:#x}: {} frames",
+                         start, start + bucket_size - 1, count);
+            }
+        }
+        significance.print_notice(num_hidden, num_flagged);
+    }
+}
+///
+///
+/// Counts of symbolized and unsymbolized stack frames for one DSO
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct DsoFrames {
+    /// Frames which perf resolved to a named symbol
+    pub num_named: usize,
+
+    /// Frames which perf left as "[unknown]"
+    pub num_unknown: usize,
+
+    /// Unsymbolized frames in each address range, keyed by range start
+    pub unknown_buckets: HashMap<u64, usize>,
+}
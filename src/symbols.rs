@@ -0,0 +1,70 @@
+//! Tables of code symbols, in the textual format of nm and /proc/kallsyms
+
+use std::io::{Error, Result};
+use std::path::Path;
+use std::process::Command;
+
+
+/// Table of code symbols, sorted by address
+pub struct SymbolTable {
+    symbols: Vec<(u64, String)>,
+}
+//
+impl SymbolTable {
+    /// Load the symbols of an ELF file. We let nm do the ELF parsing, and extra
+    /// arguments can be passed to it (e.g. -D for the dynamic symbol table).
+    pub fn from_elf(path: &Path, nm_args: &[&str]) -> Result<Self> {
+        let output = Command::new("nm").arg("--defined-only")
+                                       .args(nm_args)
+                                       .arg(path)
+                                       .output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(Error::other(format!("nm failed ({}): {}",
+                                            output.status,
+                                            stderr.trim())));
+        }
+        Ok(Self::parse(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    /// Parse symbols in the "<address> <type> <name> [module]" format of nm
+    /// and /proc/kallsyms. Only code symbols are kept.
+    pub fn parse(text: &str) -> Self {
+        let mut symbols = text.lines().filter_map(|line| {
+            let mut columns = line.split_whitespace();
+            let address = u64::from_str_radix(columns.next()?, 16).ok()?;
+            let kind = columns.next()?;
+            // Dynamic symbols may carry a version suffix, e.g. "cos@@GLIBC_2.29"
+            let name = columns.next()?.split('@').next()?;
+            if kind.eq_ignore_ascii_case("t") || kind.eq_ignore_ascii_case("w") {
+                Some((address, name.to_owned()))
+            } else {
+                None
+            }
+        }).collect::<Vec<_>>();
+        symbols.sort_unstable_by_key(|&(address, _)| address);
+        Self { symbols }
+    }
+
+    /// Truth that no symbol was found
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Truth that all symbol addresses are zero, as happens when kernel symbols
+    /// are dumped with kernel pointer restrictions in effect
+    pub fn addresses_hidden(&self) -> bool {
+        self.symbols.iter().all(|&(address, _)| address == 0)
+    }
+
+    /// Name of the function containing an instruction pointer, if any
+    pub fn lookup(&self, ip: u64) -> Option<&str> {
+        let search = self.symbols.binary_search_by_key(&ip, |&(address, _)| address);
+        let idx = match search {
+            Ok(idx) => idx,
+            Err(0) => return None,
+            Err(idx) => idx - 1,
+        };
+        Some(&self.symbols[idx].1)
+    }
+}
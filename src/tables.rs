@@ -0,0 +1,308 @@
+//! Export of the analyzed samples as Apache Parquet tables, so that profiles
+//! can be loaded into data science tools (pandas, polars, DuckDB...) for custom
+//! analysis. Samples go into a "samples" table, and their stack frames go into
+//! a "frames" table which refers to them through a sample_id column.
+
+use analysis::SampleCategory;
+use parquet::basic::{Compression, Type as PhysicalType};
+use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int32Type, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use sample::Sample;
+use std::fs::{self, File};
+use std::io::{Error, Result};
+use std::path::Path;
+use std::sync::Arc;
+use truncate::{StackTruncation, TruncatedFrame};
+
+
+/// Schema of the samples table
+const SAMPLES_SCHEMA: &str = "
+    message samples {
+        required int64 sample_id;
+        optional binary comm (UTF8);
+        optional int32 pid;
+        optional int32 tid;
+        optional int32 cpu;
+        optional double time;
+        optional int64 period;
+        optional binary event (UTF8);
+        required binary category (UTF8);
+        required int32 depth;
+        optional binary stack_id (UTF8);
+    }
+";
+
+/// Schema of the frames table, where depth 0 is the leaf function
+const FRAMES_SCHEMA: &str = "
+    message frames {
+        required int64 sample_id;
+        required int32 depth;
+        optional int64 ip (INTEGER(64, false));
+        required binary symbol (UTF8);
+        required binary dso (UTF8);
+    }
+";
+
+/// Number of rows which are buffered before being written out as a row group
+const ROW_GROUP_SIZE: usize = 64 * 1024;
+
+
+/// Export of the analyzed samples as a pair of Parquet tables. Write errors are
+/// deferred until the export is finished, so that they do not get in the way
+/// of the analysis itself.
+pub struct TableExport {
+    samples: Table,
+    frames: Table,
+
+    /// Identifier of the next sample
+    next_sample_id: i64,
+
+    /// Cap on the number of frames which are written per sample
+    truncation: StackTruncation,
+
+    /// First error which occurred while writing, if any
+    error: Option<Error>,
+}
+//
+impl TableExport {
+    /// Create the samples.parquet and frames.parquet files in some directory,
+    /// which is created if needed
+    pub fn create(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            samples: Table::create(&dir.join("samples.parquet"), SAMPLES_SCHEMA)?,
+            frames: Table::create(&dir.join("frames.parquet"), FRAMES_SCHEMA)?,
+            next_sample_id: 0,
+            truncation: StackTruncation::none(),
+            error: None,
+        })
+    }
+
+    /// Replace the middle frames of very deep stacks with a single marker row
+    pub fn with_stack_truncation(mut self, truncation: StackTruncation) -> Self {
+        self.truncation = truncation;
+        self
+    }
+
+    /// Add a newly classified sample to the tables
+    pub fn record(&mut self, sample: &Sample, category: &SampleCategory) {
+        if self.error.is_none() {
+            if let Err(e) = self.try_record(sample, category) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    /// Add a sample to the tables, reporting errors immediately
+    fn try_record(&mut self, sample: &Sample, category: &SampleCategory) -> Result<()> {
+        let sample_id = self.next_sample_id;
+        self.next_sample_id += 1;
+        let mut depth = 0;
+        for frame in self.truncation.frames(sample) {
+            match frame {
+                TruncatedFrame::Frame(frame) => {
+                    let ip = u64::from_str_radix(frame.ip, 16).ok();
+                    self.frames.push_row(&[
+                        Cell::Int64(Some(sample_id)),
+                        Cell::Int32(Some(depth)),
+                        Cell::Int64(ip.map(|ip| ip as i64)),
+                        Cell::Text(Some(frame.symbol)),
+                        Cell::Text(Some(frame.dso)),
+                    ])?;
+                    depth += 1;
+                },
+                TruncatedFrame::Elided { num_frames, marker } => {
+                    self.frames.push_row(&[
+                        Cell::Int64(Some(sample_id)),
+                        Cell::Int32(Some(depth)),
+                        Cell::Int64(None),
+                        Cell::Text(Some(&marker)),
+                        Cell::Text(Some("")),
+                    ])?;
+                    depth += num_frames as i32;
+                },
+            }
+        }
+        let stack_id = sample.stack_id().map(|id| id.to_string());
+        self.samples.push_row(&[
+            Cell::Int64(Some(sample_id)),
+            Cell::Text(sample.comm()),
+            Cell::Int32(sample.pid().map(|pid| pid as i32)),
+            Cell::Int32(sample.tid().map(|tid| tid as i32)),
+            Cell::Int32(sample.cpu().map(|cpu| cpu as i32)),
+            Cell::Double(sample.timestamp()),
+            Cell::Int64(sample.period().map(|period| period as i64)),
+            Cell::Text(sample.event()),
+            Cell::Text(Some(category.key())),
+            Cell::Int32(Some(depth)),
+            Cell::Text(stack_id.as_deref()),
+        ])
+    }
+
+    /// Write the remaining rows and close the tables
+    pub fn finish(mut self) -> Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.samples.close()?;
+        self.frames.close()
+    }
+}
+///
+///
+/// Parquet file which rows are written to, one row group at a time
+struct Table {
+    writer: SerializedFileWriter<File>,
+
+    /// Rows which were not written yet, column by column
+    columns: Vec<Column>,
+
+    /// Number of rows which were not written yet
+    num_rows: usize,
+}
+//
+impl Table {
+    /// Create a Parquet file with some schema
+    fn create(path: &Path, schema: &str) -> Result<Self> {
+        let schema = Arc::new(parse_message_type(schema).map_err(Error::other)?);
+        let properties = WriterProperties::builder().set_compression(Compression::SNAPPY)
+                                                    .build();
+        let writer = SerializedFileWriter::new(File::create(path)?,
+                                               schema,
+                                               Arc::new(properties))
+                                          .map_err(Error::other)?;
+        let columns = writer.schema_descr().columns().iter().map(|column| {
+            Column {
+                values: match column.physical_type() {
+                    PhysicalType::INT32 => Values::Int32(Vec::new()),
+                    PhysicalType::INT64 => Values::Int64(Vec::new()),
+                    PhysicalType::DOUBLE => Values::Double(Vec::new()),
+                    _ => Values::Text(Vec::new()),
+                },
+                levels: if column.max_def_level() > 0 { Some(Vec::new()) } else { None },
+            }
+        }).collect();
+        Ok(Self {
+            writer,
+            columns,
+            num_rows: 0,
+        })
+    }
+
+    /// Add a row, whose cells must follow the schema
+    fn push_row(&mut self, row: &[Cell]) -> Result<()> {
+        for (column, cell) in self.columns.iter_mut().zip(row) {
+            let present = match (&mut column.values, cell) {
+                (&mut Values::Int32(ref mut values), &Cell::Int32(value)) => {
+                    values.extend(value);
+                    value.is_some()
+                },
+                (&mut Values::Int64(ref mut values), &Cell::Int64(value)) => {
+                    values.extend(value);
+                    value.is_some()
+                },
+                (&mut Values::Double(ref mut values), &Cell::Double(value)) => {
+                    values.extend(value);
+                    value.is_some()
+                },
+                (&mut Values::Text(ref mut values), &Cell::Text(value)) => {
+                    values.extend(value.map(ByteArray::from));
+                    value.is_some()
+                },
+                _ => panic!("row does not follow the table's schema"),
+            };
+            match column.levels {
+                Some(ref mut levels) => levels.push(present as i16),
+                None => assert!(present, "missing value in a required column"),
+            }
+        }
+        self.num_rows += 1;
+        if self.num_rows == ROW_GROUP_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write the buffered rows as a row group
+    fn flush(&mut self) -> Result<()> {
+        if self.num_rows == 0 {
+            return Ok(());
+        }
+        let mut row_group = self.writer.next_row_group().map_err(Error::other)?;
+        let mut columns = self.columns.iter_mut();
+        while let Some(mut writer) = row_group.next_column().map_err(Error::other)? {
+            let column = columns.next().unwrap();
+            let levels = column.levels.as_deref();
+            let written = match column.values {
+                Values::Int32(ref values) => {
+                    writer.typed::<Int32Type>().write_batch(values, levels, None)
+                },
+                Values::Int64(ref values) => {
+                    writer.typed::<Int64Type>().write_batch(values, levels, None)
+                },
+                Values::Double(ref values) => {
+                    writer.typed::<DoubleType>().write_batch(values, levels, None)
+                },
+                Values::Text(ref values) => {
+                    writer.typed::<ByteArrayType>().write_batch(values, levels, None)
+                },
+            };
+            written.map_err(Error::other)?;
+            writer.close().map_err(Error::other)?;
+            column.clear();
+        }
+        row_group.close().map_err(Error::other)?;
+        self.num_rows = 0;
+        Ok(())
+    }
+
+    /// Write the remaining rows and the file footer
+    fn close(mut self) -> Result<()> {
+        self.flush()?;
+        self.writer.close().map_err(Error::other)?;
+        Ok(())
+    }
+}
+///
+///
+/// Buffered values of a column, along with their definition levels if the
+/// column is optional
+struct Column {
+    values: Values,
+    levels: Option<Vec<i16>>,
+}
+//
+impl Column {
+    /// Forget about the values which were written
+    fn clear(&mut self) {
+        match self.values {
+            Values::Int32(ref mut values) => values.clear(),
+            Values::Int64(ref mut values) => values.clear(),
+            Values::Double(ref mut values) => values.clear(),
+            Values::Text(ref mut values) => values.clear(),
+        }
+        if let Some(ref mut levels) = self.levels {
+            levels.clear();
+        }
+    }
+}
+///
+///
+/// Buffered values of a column, by physical type
+enum Values {
+    Int32(Vec<i32>),
+    Int64(Vec<i64>),
+    Double(Vec<f64>),
+    Text(Vec<ByteArray>),
+}
+///
+///
+/// Value of a row's cell, which is None for missing optional values
+enum Cell<'a> {
+    Int32(Option<i32>),
+    Int64(Option<i64>),
+    Double(Option<f64>),
+    Text(Option<&'a str>),
+}
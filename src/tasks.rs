@@ -0,0 +1,139 @@
+//! Attribution of the samples of Rust async programs to the tasks which were
+//! being polled, for the tokio preset
+//!
+//! Async executors (tokio, async-std, smol...) poll each task from a deep stack
+//! of scheduler, task harness and panic-catching frames, which is the same for
+//! every task and drowns the future's own frames in call trees. Stacks are
+//! thus folded at the poll boundary: everything between the root and the
+//! outermost frame of the polled future is cut away.
+
+use runtime;
+use sample::{Sample, StackFrame};
+use std::collections::HashMap;
+
+
+/// Number of tasks which are displayed in the report
+const NUM_REPORTED_TASKS: usize = 10;
+
+/// Prefixes of the functions through which executors poll a task, one of
+/// which marks the poll boundary
+const TASK_POLL_PREFIXES: &[&str] = &[
+    "tokio::runtime::task::raw::poll",
+    "tokio::runtime::task::harness::Harness<",
+    "tokio::runtime::park::CachedParkThread::block_on",
+    "async_task::raw::RawTask<",
+    "async_executor::",
+    "async_global_executor::",
+    "async_std::task::",
+    "futures_executor::local_pool::",
+];
+
+/// Prefixes of the functions which sit between the poll boundary and the
+/// polled future, and which thus belong to the executor's plumbing too
+const PLUMBING_PREFIXES: &[&str] = &[
+    "tokio::runtime::task::",
+    "tokio::runtime::coop::",
+    "tokio::task::coop::",
+    "tokio::loom::",
+    "tokio::runtime::context::",
+    "tokio::runtime::scheduler::",
+    "std::panicking::try",
+    "std::panic::catch_unwind",
+    "__rust_try",
+    "<core::panic::unwind_safe::AssertUnwindSafe<F> as ",
+    "<core::pin::Pin<P> as core::future::future::Future>::poll",
+    "<tokio::runtime::blocking::task::BlockingTask<T> as ",
+    "core::cell::UnsafeCell<T>::with_mut",
+];
+
+
+/// Truth that a frame is where an executor polls a task
+pub fn is_task_poll(frame: &StackFrame) -> bool {
+    let symbol = runtime::strip_rust_hash(frame.symbol);
+    TASK_POLL_PREFIXES.iter().any(|prefix| symbol.starts_with(prefix))
+}
+
+/// Truth that a frame belongs to an executor's task polling machinery
+pub fn is_executor_plumbing(frame: &StackFrame) -> bool {
+    let symbol = runtime::strip_rust_hash(frame.symbol);
+    is_task_poll(frame) || PLUMBING_PREFIXES.iter().any(|p| symbol.starts_with(p))
+}
+
+/// Number of stack frames which a sample keeps once folded at the poll
+/// boundary, from the leaf function up to the outermost frame of the polled
+/// future, or None if the sample was not taken while polling a future
+pub fn folded_len(sample: &Sample) -> Option<usize> {
+    let frames = sample.frames().collect::<Vec<_>>();
+    let boundary = frames.iter().rposition(is_task_poll)?;
+    frames[..boundary].iter()
+                      .rposition(|frame| !is_executor_plumbing(frame))
+                      .map(|outermost| outermost + 1)
+}
+///
+///
+/// Samples of each async task, as identified by the outermost frame of the
+/// future that was being polled
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct TaskStatistics {
+    /// Samples of each task, by outermost future frame
+    pub tasks: HashMap<String, usize>,
+
+    /// Samples taken in the executor while it was polling a task, but outside
+    /// of the task's own code
+    pub num_executor: usize,
+
+    /// Samples which were not taken while polling a task
+    pub num_outside: usize,
+
+    /// Executor frames which were folded away
+    pub num_folded_frames: usize,
+}
+//
+impl TaskStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a new sample
+    pub fn record(&mut self, sample: &Sample) {
+        if !sample.frames().any(|frame| is_task_poll(&frame)) {
+            self.num_outside += 1;
+            return;
+        }
+        let num_frames = match folded_len(sample) {
+            Some(num_frames) => num_frames,
+            None => {
+                self.num_executor += 1;
+                return;
+            },
+        };
+        self.num_folded_frames += sample.frames().count() - num_frames;
+        let task = sample.frames().nth(num_frames - 1).map_or("", |frame| frame.symbol);
+        let task = runtime::strip_rust_hash(task);
+        match self.tasks.get_mut(task) {
+            Some(count) => *count += 1,
+            None => { self.tasks.insert(task.to_owned(), 1); },
+        }
+    }
+
+    /// Print the async tasks which were sampled the most
+    pub fn print_report(&self) {
+        let num_task_samples = self.tasks.values().sum::<usize>();
+        let num_polling = num_task_samples + self.num_executor;
+        println!("Async task samples: {}", num_polling);
+        if num_polling == 0 {
+            return;
+        }
+        let percent = |count: usize| 100.0 * count as f64 / num_polling as f64;
+        println!("- In executor plumbing: {} ({:.1}%)",
+                 self.num_executor, percent(self.num_executor));
+        println!("- Folded executor frames: {}", self.num_folded_frames);
+        let mut tasks = self.tasks.iter().collect::<Vec<_>>();
+        tasks.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        println!("Samples of each task, by outermost future frame:");
+        for (task, &count) in tasks.into_iter().take(NUM_REPORTED_TASKS) {
+            println!("- {}: {} ({:.1}%)", task, count, percent(count));
+        }
+    }
+}
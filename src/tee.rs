@@ -0,0 +1,62 @@
+//! Pass-through of perf script's output into a file while it is analyzed, so
+//! that a single perf script run can be both archived and analyzed.
+
+use std::fs::File;
+use std::io::{BufWriter, Error, Read, Result, Write};
+use std::path::Path;
+
+
+/// Reader which copies everything that goes through it into a file, if any.
+/// Write errors are deferred until the copy is finished, so that they do not
+/// get in the way of the analysis itself.
+pub struct TeeReader<Input: Read> {
+    input: Input,
+
+    /// File where the input is copied, if any
+    output: Option<BufWriter<File>>,
+
+    /// First error which occurred while writing, if any
+    error: Option<Error>,
+}
+//
+impl<Input: Read> TeeReader<Input> {
+    /// Read some input without copying it anywhere
+    pub fn new(input: Input) -> Self {
+        Self {
+            input,
+            output: None,
+            error: None,
+        }
+    }
+
+    /// Read some input and copy it into a newly created file
+    pub fn with_output(input: Input, path: &Path) -> Result<Self> {
+        Ok(Self {
+            output: Some(BufWriter::new(File::create(path)?)),
+            ..Self::new(input)
+        })
+    }
+
+    /// Stop reading the input and complete the copy
+    pub fn finish(mut self) -> Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        match self.output {
+            Some(ref mut output) => output.flush(),
+            None => Ok(()),
+        }
+    }
+}
+//
+impl<Input: Read> Read for TeeReader<Input> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = self.input.read(buf)?;
+        if let (Some(output), None) = (self.output.as_mut(), self.error.as_ref()) {
+            if let Err(e) = output.write_all(&buf[..len]) {
+                self.error = Some(e);
+            }
+        }
+        Ok(len)
+    }
+}
@@ -0,0 +1,90 @@
+//! Per-thread stack trace quality, which tells whether stack trace breakage is
+//! concentrated in specific threads (e.g. an audio callback running on a
+//! runtime-managed stack) or spread evenly across the program.
+
+use analysis::SampleCategory;
+use sample::Sample;
+use std::collections::HashMap;
+
+
+/// Number of threads which are displayed in the report
+const NUM_REPORTED_THREADS: usize = 10;
+
+
+/// Stack trace quality counters of each thread, by thread ID
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ThreadStatistics {
+    pub threads: HashMap<u32, ThreadQuality>,
+}
+//
+impl ThreadStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a newly classified sample. Samples are weighted by their
+    /// period, so that each thread's score reflects where time was spent.
+    pub fn record(&mut self, sample: &Sample, category: &SampleCategory) {
+        let tid = match sample.tid() {
+            Some(tid) => tid,
+            None => return,
+        };
+        let thread = self.threads.entry(tid).or_insert_with(|| ThreadQuality {
+            comm: sample.comm().unwrap_or("").to_owned(),
+            ..ThreadQuality::default()
+        });
+        let weight = sample.period().unwrap_or(1);
+        thread.num_samples += 1;
+        thread.total_weight += weight;
+        if let SampleCategory::Normal = *category {
+            thread.normal_weight += weight;
+        }
+    }
+
+    /// Print the threads with the worst stack trace quality
+    pub fn print_report(&self) {
+        let mut threads = self.threads.iter().collect::<Vec<_>>();
+        threads.sort_by(|&(tid1, thread1), &(tid2, thread2)| {
+            thread1.score().total_cmp(&thread2.score())
+                           .then(thread2.total_weight.cmp(&thread1.total_weight))
+                           .then(tid1.cmp(tid2))
+        });
+        println!("Stack trace quality per thread, worst first: {} threads",
+                 threads.len());
+        for (tid, thread) in threads.into_iter().take(NUM_REPORTED_THREADS) {
+            println!("- {} ({}): {:.1}% normal over {} samples",
+                     thread.comm,
+                     tid,
+                     100.0 * thread.score(),
+                     thread.num_samples);
+        }
+    }
+}
+///
+///
+/// Stack trace quality counters of one thread
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ThreadQuality {
+    /// Name of the thread, as of its first sample
+    pub comm: String,
+
+    /// Number of samples of this thread
+    pub num_samples: usize,
+
+    /// Sum of the periods of this thread's samples
+    pub total_weight: u64,
+
+    /// Sum of the periods of this thread's Normal samples
+    pub normal_weight: u64,
+}
+//
+impl ThreadQuality {
+    /// Fraction of this thread's sampled time which has a normal stack trace
+    pub fn score(&self) -> f64 {
+        if self.total_weight == 0 {
+            return 0.0;
+        }
+        self.normal_weight as f64 / self.total_weight as f64
+    }
+}
@@ -0,0 +1,142 @@
+//! Accounting of the time during which events were throttled by the kernel,
+//! from the PERF_RECORD_THROTTLE and PERF_RECORD_UNTHROTTLE side-band records
+//! that perf script prints when run with --show-throttle-events.
+//!
+//! While an event is throttled on some CPU, it is not sampled there, so the
+//! profile under-represents whatever was running at that time. Throttling is
+//! tracked per CPU, and compared to the CPU time covered by the recording.
+
+use sample::Sample;
+use std::collections::{BTreeSet, HashMap};
+
+
+/// Label of the throttled event when perf script does not print it
+const UNKNOWN_EVENT: &str = "all events";
+
+/// Fraction of the recording's CPU time above which throttling is worrying
+const THROTTLED_TIME_WARNING: f64 = 0.01;
+
+
+/// Throttling statistics of each event
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ThrottleStatistics {
+    pub events: HashMap<String, EventThrottling>,
+
+    /// Timestamps of the first and last sample or record, in nanoseconds
+    pub start_time: Option<u64>,
+    pub end_time: Option<u64>,
+
+    /// CPUs which samples or records were observed on
+    pub cpus: BTreeSet<u32>,
+
+    /// Time at which each event was throttled on each CPU, if it has not been
+    /// unthrottled since
+    throttled_since: HashMap<(String, Option<u32>), u64>,
+}
+//
+impl ThrottleStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a new sample or side-band record. Samples only tell which
+    /// time range and CPUs the recording covers.
+    pub fn record(&mut self, sample: &Sample) {
+        let time = sample.timestamp().map(to_nanoseconds);
+        if let Some(time) = time {
+            self.start_time = Some(self.start_time.map_or(time, |start| start.min(time)));
+            self.end_time = Some(self.end_time.map_or(time, |end| end.max(time)));
+        }
+        if let Some(cpu) = sample.cpu() {
+            self.cpus.insert(cpu);
+        }
+
+        // Throttle records open a throttled interval, unthrottle records close it
+        let record = match sample.sideband_record() {
+            Some(record) => record,
+            None => return,
+        };
+        let header = sample.header_text();
+        let record_pos = header.find("PERF_RECORD_").unwrap_or(header.len());
+        let event = sample.event()
+                          .filter(|event| header.find(event) < Some(record_pos))
+                          .unwrap_or(UNKNOWN_EVENT);
+        let key = (event.to_owned(), sample.cpu());
+        match record {
+            "THROTTLE" => {
+                let counters = self.events.entry(event.to_owned()).or_default();
+                counters.num_throttles += 1;
+                if let Some(time) = time {
+                    self.throttled_since.entry(key).or_insert(time);
+                }
+            },
+            "UNTHROTTLE" => {
+                let (since, time) = match (self.throttled_since.remove(&key), time) {
+                    (Some(since), Some(time)) => (since, time),
+                    _ => return,
+                };
+                let counters = self.events.entry(event.to_owned()).or_default();
+                counters.throttled_time += time.saturating_sub(since);
+            },
+            _ => {},
+        }
+    }
+
+    /// Print how long each event was throttled, compared to the CPU time that
+    /// the recording covers
+    pub fn print_report(&self) {
+        if self.events.is_empty() {
+            println!("No event was throttled (throttle records are only printed by \
+                      perf script --show-throttle-events)");
+            return;
+        }
+        let duration = match (self.start_time, self.end_time) {
+            (Some(start), Some(end)) => end - start,
+            _ => 0,
+        };
+        let cpu_time = duration * self.cpus.len().max(1) as u64;
+        let mut events = self.events.iter().collect::<Vec<_>>();
+        events.sort_by(|a, b| a.0.cmp(b.0));
+        println!("Throttling per event:");
+        for (event, counters) in events {
+            // Events which are still throttled are so until the end
+            let still_throttled = self.throttled_since.iter()
+                                      .filter(|((other, _), _)| other == event)
+                                      .map(|(_, &since)| {
+                                          self.end_time.unwrap_or(since)
+                                                       .saturating_sub(since)
+                                      })
+                                      .sum::<u64>();
+            let throttled_time = counters.throttled_time + still_throttled;
+            let fraction = throttled_time as f64 / cpu_time.max(1) as f64;
+            println!("- {}: throttled {} times, for {:.3}s of CPU time ({:.1}% of the \
+                      {:.3}s recording on {} CPUs){}",
+                     event,
+                     counters.num_throttles,
+                     throttled_time as f64 * 1e-9,
+                     100.0 * fraction,
+                     duration as f64 * 1e-9,
+                     self.cpus.len(),
+                     if fraction > THROTTLED_TIME_WARNING { " [warning]" } else { "" });
+        }
+    }
+}
+///
+///
+/// Throttling statistics of one event
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct EventThrottling {
+    /// Number of times the event was throttled on some CPU
+    pub num_throttles: usize,
+
+    /// Total time during which the event was throttled, summed over CPUs, in
+    /// nanoseconds
+    pub throttled_time: u64,
+}
+
+
+/// Convert a perf script timestamp to nanoseconds
+fn to_nanoseconds(seconds: f64) -> u64 {
+    (seconds * 1e9).round() as u64
+}
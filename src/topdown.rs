@@ -0,0 +1,178 @@
+//! Top-Down microarchitecture analysis, which tells whether the issue slots
+//! of the CPU pipeline were spent retiring useful work, or wasted on bad
+//! speculation, on a frontend which could not feed the pipeline, or on a
+//! backend which could not accept more work.
+//!
+//! Recent Intel CPUs expose each of these level-1 categories as a sampling
+//! event, which perf records as an event group, e.g.
+//! `perf record -e '{slots,topdown-retiring,topdown-bad-spec,topdown-fe-bound,
+//! topdown-be-bound}'`. Samples of the group's events are interleaved, so the
+//! breakdown of each function is computed from the sum of each event's periods
+//! over the samples where that function was the leaf.
+
+use sample::Sample;
+use std::collections::HashMap;
+
+
+/// Number of functions which are displayed in the report
+const NUM_REPORTED_FUNCTIONS: usize = 10;
+
+
+/// Top-Down breakdown of each leaf function
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct TopDownStatistics {
+    /// Slot counts of each leaf function, by DSO and symbol name
+    pub functions: HashMap<String, HashMap<String, TopDownCounts>>,
+
+    /// Slot counts of the whole profile
+    pub total: TopDownCounts,
+}
+//
+impl TopDownStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a new sample. Samples of other events than the Top-Down
+    /// ones, and samples without a stack trace, are ignored.
+    pub fn record(&mut self, sample: &Sample) {
+        let event = match sample.event_base_name().and_then(TopDownEvent::parse) {
+            Some(event) => event,
+            None => return,
+        };
+        let leaf = match sample.frames().next() {
+            Some(leaf) => leaf,
+            None => return,
+        };
+        let period = sample.period().unwrap_or(1);
+        self.total.record(event, period);
+        let symbols = match self.functions.get_mut(leaf.dso) {
+            Some(symbols) => symbols,
+            None => self.functions.entry(leaf.dso.to_owned()).or_default(),
+        };
+        match symbols.get_mut(leaf.symbol) {
+            Some(counts) => counts.record(event, period),
+            None => symbols.entry(leaf.symbol.to_owned()).or_default()
+                           .record(event, period),
+        }
+    }
+
+    /// Print the Top-Down breakdown of the profile and of the functions which
+    /// used the most issue slots
+    pub fn print_report(&self) {
+        if self.total.num_categories() == 0 {
+            println!("No Top-Down events in the profile (record them with e.g. \
+                      perf record -e '{{slots,topdown-retiring,topdown-bad-spec,\
+                      topdown-fe-bound,topdown-be-bound}}')");
+            return;
+        }
+        let mut functions = self.functions.iter().flat_map(|(dso, symbols)| {
+            symbols.iter().map(move |(symbol, counts)| (symbol, dso, counts))
+        }).filter(|&(_, _, counts)| counts.categories_sum() > 0).collect::<Vec<_>>();
+        functions.sort_by(|a, b| {
+            b.2.weight().cmp(&a.2.weight()).then(a.0.cmp(b.0)).then(a.1.cmp(b.1))
+        });
+        println!("Top-Down breakdown of issue slots:");
+        println!("{:>7} {:>9} {:>9} {:>9} {:>9}  Function",
+                 "Slots", "Retiring", "Bad spec", "Frontend", "Backend");
+        self.print_counts(&self.total, "<total>");
+        for (symbol, dso, counts) in functions.into_iter().take(NUM_REPORTED_FUNCTIONS) {
+            self.print_counts(counts, &format!("{} ({})", symbol, dso));
+        }
+        if self.total.num_categories() < TopDownCounts::NUM_CATEGORIES {
+            println!("Some Top-Down events were not recorded, so the breakdown is \
+                      incomplete [warning]");
+        }
+    }
+
+    /// Print one line of the breakdown table
+    fn print_counts(&self, counts: &TopDownCounts, name: &str) {
+        let total_weight = self.total.weight().max(1) as f64;
+        let sum = counts.categories_sum().max(1) as f64;
+        let percent = |slots: u64| 100.0 * slots as f64 / sum;
+        println!("{:>6.1}% {:>8.1}% {:>8.1}% {:>8.1}% {:>8.1}%  {}",
+                 100.0 * counts.weight() as f64 / total_weight,
+                 percent(counts.retiring),
+                 percent(counts.bad_speculation),
+                 percent(counts.frontend_bound),
+                 percent(counts.backend_bound),
+                 name);
+    }
+}
+///
+///
+/// Level-1 Top-Down event, or the total slot count which they break down
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TopDownEvent {
+    Slots,
+    Retiring,
+    BadSpeculation,
+    FrontendBound,
+    BackendBound,
+}
+//
+impl TopDownEvent {
+    /// Recognize a Top-Down event from its name, without PMU and modifiers
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "slots" => Some(TopDownEvent::Slots),
+            "topdown-retiring" => Some(TopDownEvent::Retiring),
+            "topdown-bad-spec" => Some(TopDownEvent::BadSpeculation),
+            "topdown-fe-bound" => Some(TopDownEvent::FrontendBound),
+            "topdown-be-bound" => Some(TopDownEvent::BackendBound),
+            _ => None,
+        }
+    }
+}
+///
+///
+/// Issue slots which each Top-Down event counted, i.e. the sum of the periods
+/// of its samples
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct TopDownCounts {
+    pub slots: u64,
+    pub retiring: u64,
+    pub bad_speculation: u64,
+    pub frontend_bound: u64,
+    pub backend_bound: u64,
+}
+//
+impl TopDownCounts {
+    /// Number of level-1 categories
+    const NUM_CATEGORIES: usize = 4;
+
+    /// Account for a sample of some Top-Down event
+    fn record(&mut self, event: TopDownEvent, period: u64) {
+        let counter = match event {
+            TopDownEvent::Slots => &mut self.slots,
+            TopDownEvent::Retiring => &mut self.retiring,
+            TopDownEvent::BadSpeculation => &mut self.bad_speculation,
+            TopDownEvent::FrontendBound => &mut self.frontend_bound,
+            TopDownEvent::BackendBound => &mut self.backend_bound,
+        };
+        *counter = counter.saturating_add(period);
+    }
+
+    /// Slots of each level-1 category
+    fn categories(&self) -> [u64; Self::NUM_CATEGORIES] {
+        [self.retiring, self.bad_speculation, self.frontend_bound, self.backend_bound]
+    }
+
+    /// Number of level-1 categories which were sampled
+    fn num_categories(&self) -> usize {
+        self.categories().iter().filter(|&&slots| slots > 0).count()
+    }
+
+    /// Slots of all level-1 categories. The categories are sampled separately,
+    /// so their breakdown is normalized to this sum rather than to the slots
+    /// event, which would not add up to 100% due to sampling noise.
+    fn categories_sum(&self) -> u64 {
+        self.categories().iter().sum()
+    }
+
+    /// Issue slots used, from the slots event if it was recorded
+    fn weight(&self) -> u64 {
+        if self.slots > 0 { self.slots } else { self.categories_sum() }
+    }
+}
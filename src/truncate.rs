@@ -0,0 +1,140 @@
+//! Truncation of very deep stacks in printed and exported samples, which keeps
+//! reports readable when deep recursion produces hundreds of frames
+//!
+//! The frames at both ends of a stack, which tell where the sample was taken
+//! and how the thread got started, are kept. The frames in the middle are
+//! replaced by a marker which tells how many there were and which functions
+//! appeared the most among them. The analysis itself still sees whole stacks.
+
+use sample::{Sample, StackFrame};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::ops::Range;
+
+
+/// Number of functions which are listed in the summary of elided frames
+const NUM_SUMMARIZED_FUNCTIONS: usize = 3;
+
+
+/// Cap on the number of frames of printed and exported stacks
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct StackTruncation {
+    /// Number of frames which are kept, half from the leaf and half from the
+    /// root of the stack, the leaf getting the extra frame if it is odd
+    pub max_frames: usize,
+}
+//
+impl StackTruncation {
+    /// Cap stacks at some number of frames
+    pub fn new(max_frames: usize) -> Self {
+        Self { max_frames }
+    }
+
+    /// Keep stacks whole
+    pub fn none() -> Self {
+        Self::new(usize::MAX)
+    }
+
+    /// Depths of the frames which are elided from a stack with some number of
+    /// frames, if it has too many
+    pub fn elided_range(&self, num_frames: usize) -> Option<Range<usize>> {
+        if num_frames <= self.max_frames {
+            return None;
+        }
+        let num_leaf_frames = self.max_frames - self.max_frames / 2;
+        Some(num_leaf_frames..num_frames - self.max_frames / 2)
+    }
+
+    /// Frames of a sample which are kept, in leaf to root order, with a marker
+    /// in place of the elided frames if any
+    pub fn frames<'a>(&self, sample: &Sample<'a>) -> Vec<TruncatedFrame<'a>> {
+        let frames = sample.frames().collect::<Vec<_>>();
+        let elided_range = match self.elided_range(frames.len()) {
+            Some(range) => range,
+            None => return frames.into_iter().map(TruncatedFrame::Frame).collect(),
+        };
+        let marker = marker(&frames[elided_range.clone()]);
+        let mut frames = frames.into_iter()
+                               .map(TruncatedFrame::Frame)
+                               .collect::<Vec<_>>();
+        frames.splice(elided_range.clone(), Some(TruncatedFrame::Elided {
+            num_frames: elided_range.len(),
+            marker,
+        }));
+        frames
+    }
+
+    /// Raw text of a sample, where the stack frame lines which are elided are
+    /// replaced by a marker line
+    pub fn raw_text<'a>(&self, sample: &Sample<'a>) -> Cow<'a, str> {
+        let frames = sample.frames().collect::<Vec<_>>();
+        let elided_range = match self.elided_range(frames.len()) {
+            Some(range) => range,
+            None => return sample.raw_text(),
+        };
+        let mut text = String::from_utf8_lossy(sample.header).into_owned();
+        let mut depth = 0;
+        for line in sample.stack_trace.split_inclusive(|&b| b == b'\n') {
+            if StackFrame::parse_bytes(line).is_some() {
+                let is_elided = elided_range.contains(&depth);
+                if depth == elided_range.start {
+                    text.push('\t');
+                    text.push_str(&marker(&frames[elided_range.clone()]));
+                    text.push('\n');
+                }
+                depth += 1;
+                if is_elided {
+                    continue;
+                }
+            }
+            text.push_str(&String::from_utf8_lossy(line));
+        }
+        Cow::Owned(text)
+    }
+}
+//
+impl Default for StackTruncation {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+///
+///
+/// Frame of a truncated stack
+#[derive(Clone, Debug, PartialEq)]
+pub enum TruncatedFrame<'a> {
+    /// Stack frame which was kept
+    Frame(StackFrame<'a>),
+
+    /// Marker of the frames which were elided
+    Elided {
+        /// Number of elided frames
+        num_frames: usize,
+
+        /// Description of the elided frames, e.g. "… +480 more frames: fib x480"
+        marker: String,
+    },
+}
+
+
+/// Describe elided frames by their number and most frequent functions
+fn marker(frames: &[StackFrame]) -> String {
+    // Functions are counted along with the depth where they first appear, so
+    // that ties are listed from the leaf side
+    let mut functions = HashMap::<&str, (usize, usize)>::new();
+    for (depth, frame) in frames.iter().enumerate() {
+        functions.entry(frame.symbol).or_insert((depth, 0)).1 += 1;
+    }
+    let mut functions = functions.into_iter().collect::<Vec<_>>();
+    functions.sort_by_key(|&(_, (depth, count))| (Reverse(count), depth));
+    let mut summary = functions.iter()
+                               .take(NUM_SUMMARIZED_FUNCTIONS)
+                               .map(|(symbol, (_, n))| format!("{} x{}", symbol, n))
+                               .collect::<Vec<_>>();
+    if functions.len() > NUM_SUMMARIZED_FUNCTIONS {
+        summary.push(format!("{} other functions",
+                             functions.len() - NUM_SUMMARIZED_FUNCTIONS));
+    }
+    format!("… +{} more frames: {}", frames.len(), summary.join(", "))
+}
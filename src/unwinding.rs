@@ -0,0 +1,227 @@
+//! Consistency of the call graph modes of a recording. perf record can unwind
+//! the stacks of different events differently (e.g. -e cycles/call-graph=fp/
+//! -e instructions/call-graph=dwarf/), and since each mode fails in its own
+//! way, stack trace quality then differs systematically between events. This
+//! is easily mistaken for a property of the profiled code, so stack statistics
+//! are broken down per event and unwinding mode.
+
+use analysis::SampleCategory;
+use arch::Arch;
+use metadata::{self, RecordingMetadata};
+use sample::Sample;
+use std::collections::HashMap;
+use std::fmt;
+
+
+/// Events with fewer samples than this are not compared with other events
+const MIN_COMPARED_SAMPLES: usize = 100;
+
+/// Difference of normal sample fractions between events, above which stack
+/// trace quality is considered to differ systematically
+const NORMAL_FRACTION_GAP: f64 = 0.25;
+
+/// Ratio of mean stack depths between events, above which stack shapes are
+/// considered to differ systematically
+const DEPTH_RATIO: f64 = 2.0;
+
+
+/// Ways in which perf record can unwind stacks
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum UnwindMode {
+    /// Frame pointer chains (--call-graph fp, or -g)
+    FramePointer,
+
+    /// Post-processing of user stack dumps (--call-graph dwarf)
+    Dwarf,
+
+    /// Hardware call stack (--call-graph lbr)
+    Lbr,
+}
+//
+impl UnwindMode {
+    /// Unwinding mode of an event, from the "sample_type" and
+    /// "branch_sample_type" attributes of its configuration. Returns None if
+    /// the event has no call graph, or its configuration was not printed.
+    pub fn from_event_config(config: &str) -> Option<Self> {
+        let has_flag = |key, flag| {
+            metadata::event_attribute(config, key).is_some_and(|flags| {
+                flags.split('|').any(|other| other == flag)
+            })
+        };
+        if !has_flag("sample_type", "CALLCHAIN") {
+            None
+        } else if has_flag("sample_type", "STACK_USER") {
+            Some(UnwindMode::Dwarf)
+        } else if has_flag("branch_sample_type", "CALL_STACK") {
+            Some(UnwindMode::Lbr)
+        } else {
+            Some(UnwindMode::FramePointer)
+        }
+    }
+}
+//
+impl fmt::Display for UnwindMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            UnwindMode::FramePointer => "fp",
+            UnwindMode::Dwarf => "dwarf",
+            UnwindMode::Lbr => "lbr",
+        };
+        write!(f, "{}", name)
+    }
+}
+///
+///
+/// Stack statistics of each sampled event, by event name
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct UnwindStatistics {
+    pub events: HashMap<String, EventStacks>,
+}
+//
+impl UnwindStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a newly classified sample, as perf unwound it
+    pub fn record(&mut self, sample: &Sample, category: &SampleCategory, arch: Arch) {
+        let event = sample.event().unwrap_or("(unknown event)");
+        let stacks = match self.events.get_mut(event) {
+            Some(stacks) => stacks,
+            None => self.events.entry(event.to_owned()).or_default(),
+        };
+        stacks.num_samples += 1;
+        match *category {
+            SampleCategory::Normal => stacks.num_normal += 1,
+            SampleCategory::TruncatedStack => stacks.num_truncated += 1,
+            _ => {},
+        }
+        for frame in sample.frames() {
+            if arch.is_truncation_marker(frame.ip) {
+                stacks.num_truncation_markers += 1;
+            } else {
+                stacks.num_frames += 1;
+            }
+        }
+    }
+
+    /// Print stack statistics per event and unwinding mode, and warn if
+    /// events were unwound inconsistently. The unwinding modes are taken from
+    /// the recording's metadata if available, otherwise they are guessed.
+    pub fn print_report(&self, metadata: Option<&RecordingMetadata>) {
+        let mut events = self.events.iter().map(|(event, stacks)| {
+            match metadata.and_then(|metadata| recorded_mode(metadata, event)) {
+                Some(mode) => (event, stacks, Some(mode), false),
+                None => (event, stacks, stacks.guess_mode(), true),
+            }
+        }).collect::<Vec<_>>();
+        events.sort_by(|a, b| a.0.cmp(b.0));
+        println!("Stack statistics per event and unwinding mode:");
+        for &(event, stacks, mode, guessed) in &events {
+            let mode = match (mode, guessed) {
+                (Some(mode), false) => mode.to_string(),
+                (Some(mode), true) => format!("{}, guessed", mode),
+                (None, _) => "unknown".to_owned(),
+            };
+            println!("- {} ({}): {} samples, {:.1}% normal, {:.1}% truncated, {:.1} \
+                      frames on average",
+                     event,
+                     mode,
+                     stacks.num_samples,
+                     100.0 * stacks.normal_fraction(),
+                     100.0 * stacks.num_truncated as f64 / stacks.num_samples as f64,
+                     stacks.mean_depth());
+        }
+
+        // Different unwinding modes are worth knowing about, and so are
+        // systematically different stacks, which hint at them
+        let mut modes = events.iter()
+                              .filter_map(|&(_, _, mode, _)| mode)
+                              .collect::<Vec<_>>();
+        modes.sort();
+        modes.dedup();
+        let compared = events.iter()
+                             .map(|&(_, stacks, _, _)| stacks)
+                             .filter(|stacks| stacks.num_samples >= MIN_COMPARED_SAMPLES)
+                             .collect::<Vec<_>>();
+        let extremes = |metric: &dyn Fn(&EventStacks) -> f64| {
+            compared.iter().fold((f64::INFINITY, 0.0f64), |(min, max), stacks| {
+                (min.min(metric(stacks)), max.max(metric(stacks)))
+            })
+        };
+        let (min_normal, max_normal) = extremes(&EventStacks::normal_fraction);
+        let (min_depth, max_depth) = extremes(&EventStacks::mean_depth);
+        let shapes_differ = compared.len() > 1 &&
+                            (max_normal - min_normal > NORMAL_FRACTION_GAP ||
+                             max_depth > DEPTH_RATIO * min_depth);
+        if modes.len() > 1 {
+            let modes = modes.iter().map(|mode| mode.to_string()).collect::<Vec<_>>();
+            println!("Warning: events were unwound with different call graph modes ({}), \
+                      so their stack trace quality differs for reasons unrelated to \
+                      the profiled code",
+                     modes.join(", "));
+        } else if shapes_differ {
+            println!("Warning: stack shapes differ systematically between events, which \
+                      suggests that they were unwound with different call graph modes");
+        }
+    }
+}
+///
+///
+/// Stack statistics of one event
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct EventStacks {
+    /// Number of samples of this event
+    pub num_samples: usize,
+
+    /// Number of those samples which were classified as Normal
+    pub num_normal: usize,
+
+    /// Number of those samples which were classified as truncated
+    pub num_truncated: usize,
+
+    /// Total number of stack frames of this event's samples
+    pub num_frames: usize,
+
+    /// Number of truncation markers, which only DWARF unwinding emits
+    pub num_truncation_markers: usize,
+}
+//
+impl EventStacks {
+    /// Fraction of this event's samples which have a normal stack trace
+    pub fn normal_fraction(&self) -> f64 {
+        self.num_normal as f64 / self.num_samples.max(1) as f64
+    }
+
+    /// Average number of stack frames of this event's samples
+    pub fn mean_depth(&self) -> f64 {
+        self.num_frames as f64 / self.num_samples.max(1) as f64
+    }
+
+    /// Unwinding mode which the stacks suggest, if any. Only DWARF unwinding
+    /// leaves a telltale sign, the truncation marker.
+    pub fn guess_mode(&self) -> Option<UnwindMode> {
+        if self.num_truncation_markers > 0 {
+            Some(UnwindMode::Dwarf)
+        } else {
+            None
+        }
+    }
+}
+
+
+/// Unwinding mode of a sampled event according to the recording's metadata.
+/// perf script may print the event name with different modifiers than the
+/// metadata does (e.g. "cycles:u" vs "cycles"), in which case the base names
+/// are compared.
+fn recorded_mode(metadata: &RecordingMetadata, event: &str) -> Option<UnwindMode> {
+    let base_name = |name: &str| name.split(':').next().unwrap_or(name).to_owned();
+    let configs = metadata.events.iter().filter_map(|config| {
+        Some((metadata::event_attribute(config, "name")?, config))
+    }).collect::<Vec<_>>();
+    let (_, config) = configs.iter().find(|&&(name, _)| name == event).or_else(|| {
+        configs.iter().find(|&&(name, _)| base_name(name) == base_name(event))
+    })?;
+    UnwindMode::from_event_config(config)
+}
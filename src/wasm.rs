@@ -0,0 +1,123 @@
+//! WebAssembly-specific analysis, which tells apart the samples taken in code
+//! generated from wasm modules, in JavaScript and in the host runtime, for
+//! wasmtime and V8 (Node.js, Chromium)
+//!
+//! Both runtimes describe their generated code to perf using perf maps
+//! (wasmtime --profile=perfmap, node --perf-prof) or jitdump files which perf
+//! inject --jit turns into DSOs (wasmtime --profile=jitdump). The names that
+//! they give to functions tell which kind of code these are.
+
+use jvm;
+use node;
+use sample::{Sample, StackFrame};
+
+
+/// Prefixes of the names of the functions generated from wasm modules, e.g.
+/// "wasm[0]::function[12]" (wasmtime) or "wasm-function[12]" (V8, older
+/// wasmtime versions)
+const WASM_PREFIXES: &[&str] = &["wasm[", "wasm-function[", "Wasm:"];
+
+/// Parts of the names of the trampolines between wasm and host code
+const TRAMPOLINE_MARKERS: &[&str] = &[
+    "_trampoline",
+    "js-to-wasm",
+    "wasm-to-js",
+    "JSToWasm",
+    "WasmToJs",
+];
+
+
+/// Which code a wasm runtime was executing when a sample was taken
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WasmCodeKind {
+    /// Code generated from a wasm module
+    Wasm,
+
+    /// Trampolines between wasm code and the host
+    Trampoline,
+
+    /// JavaScript code, when the runtime is V8
+    JavaScript,
+
+    /// Native code of the runtime or of the embedder
+    Host,
+}
+//
+impl WasmCodeKind {
+    /// Tell which code a stack frame belongs to
+    pub fn of_frame(frame: &StackFrame) -> Self {
+        let symbol = frame.symbol;
+        let is_wasm = WASM_PREFIXES.iter().any(|prefix| symbol.starts_with(prefix));
+        let is_generated = is_wasm || jvm::jit_pid(frame.dso).is_some();
+        if is_generated && TRAMPOLINE_MARKERS.iter().any(|m| symbol.contains(m)) {
+            WasmCodeKind::Trampoline
+        } else if is_wasm {
+            WasmCodeKind::Wasm
+        } else if node::is_javascript_symbol(symbol) {
+            WasmCodeKind::JavaScript
+        } else {
+            WasmCodeKind::Host
+        }
+    }
+
+    /// Truth that this code was generated by the runtime from a wasm module
+    pub fn is_wasm_generated(self) -> bool {
+        matches!(self, WasmCodeKind::Wasm | WasmCodeKind::Trampoline)
+    }
+}
+
+
+/// Breakdown of the samples from a WebAssembly workload
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct WasmStatistics {
+    pub num_wasm: usize,
+    pub num_trampoline: usize,
+    pub num_javascript: usize,
+    pub num_host: usize,
+
+    /// Samples whose leaf function was symbolized from a jitdump file
+    pub num_jitdump: usize,
+}
+//
+impl WasmStatistics {
+    /// Start with all counters at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a new sample. Samples without a stack trace are ignored.
+    pub fn record(&mut self, sample: &Sample) {
+        let leaf_frame = match sample.frames().next() {
+            Some(frame) => frame,
+            None => return,
+        };
+        match WasmCodeKind::of_frame(&leaf_frame) {
+            WasmCodeKind::Wasm => self.num_wasm += 1,
+            WasmCodeKind::Trampoline => self.num_trampoline += 1,
+            WasmCodeKind::JavaScript => self.num_javascript += 1,
+            WasmCodeKind::Host => self.num_host += 1,
+        }
+        if jvm::is_jitdump_dso(leaf_frame.dso) {
+            self.num_jitdump += 1;
+        }
+    }
+
+    /// Print the WebAssembly section of the report
+    pub fn print_report(&self) {
+        let total = self.num_wasm + self.num_trampoline + self.num_javascript +
+                    self.num_host;
+        let percent = |count: usize| {
+            if total == 0 { 0.0 } else { 100.0 * count as f64 / total as f64 }
+        };
+        println!("WebAssembly runtime samples: {}", total);
+        println!("- Wasm code: {} ({:.1}%)",
+                 self.num_wasm, percent(self.num_wasm));
+        println!("- Wasm/host trampolines: {} ({:.1}%)",
+                 self.num_trampoline, percent(self.num_trampoline));
+        println!("- JavaScript: {} ({:.1}%)",
+                 self.num_javascript, percent(self.num_javascript));
+        println!("- Host: {} ({:.1}%)",
+                 self.num_host, percent(self.num_host));
+        println!("- Of which symbolized from jitdump: {}", self.num_jitdump);
+    }
+}
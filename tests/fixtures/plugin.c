@@ -0,0 +1,60 @@
+/*
+ * Test plugin, which puts samples whose leaf function is in libfoo.so in the
+ * "foo" category, and counts the samples of each category that it sees.
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "psa_plugin.h"
+
+typedef struct {
+    size_t num_classified;
+    size_t num_foo;
+    size_t num_other;
+} State;
+
+uint32_t psa_plugin_abi_version(void) {
+    return PSA_PLUGIN_ABI_VERSION;
+}
+
+void *psa_plugin_new(void) {
+    return calloc(1, sizeof(State));
+}
+
+const char *psa_plugin_category(void *state, size_t index) {
+    (void)state;
+    return index == 0 ? "foo" : NULL;
+}
+
+static int str_equals(PluginStr str, const char *expected) {
+    return str.len == strlen(expected) && memcmp(str.ptr, expected, str.len) == 0;
+}
+
+int64_t psa_plugin_classify(void *state, const PluginSample *sample) {
+    ((State *)state)->num_classified += 1;
+    if (sample->num_frames > 0 && str_equals(sample->frames[0].dso, "/lib/libfoo.so")) {
+        return 0;
+    }
+    return -1;
+}
+
+void psa_plugin_record(void *state, const PluginSample *sample, PluginStr category) {
+    (void)sample;
+    if (str_equals(category, "foo")) {
+        ((State *)state)->num_foo += 1;
+    } else {
+        ((State *)state)->num_other += 1;
+    }
+}
+
+void psa_plugin_report(void *state) {
+    const State *s = state;
+    printf("%zu classified, %zu foo, %zu other\n",
+           s->num_classified, s->num_foo, s->num_other);
+}
+
+void psa_plugin_free(void *state) {
+    free(state);
+}
@@ -0,0 +1,125 @@
+//! Golden-file tests of the sample parser and classifier
+//!
+//! Each perf script output of tests/fixtures is parsed and classified, and a
+//! textual description of the result is compared with the matching file of
+//! tests/golden. When a behavior change is intended, the golden files can be
+//! regenerated by running the tests with UPDATE_GOLDEN=1, and the changes
+//! should then be reviewed like any other code change.
+
+extern crate perf_script_analyze;
+
+use perf_script_analyze::analysis::SampleAnalyzer;
+use perf_script_analyze::arch::Arch;
+use perf_script_analyze::format::NormalizedSamples;
+use perf_script_analyze::metadata::RecordingMetadata;
+use perf_script_analyze::sample::{PerfSamples, SampleSource};
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+
+/// Describe how every sample of some perf script output is parsed and
+/// classified. The architecture comes from the output's header if any, never
+/// from the machine running the tests, so that results are reproducible. The
+/// layout of the output is sniffed, as it is when analyzing a profile, and the
+/// metadata of perf script's --header block comes last.
+fn describe(script: &[u8]) -> String {
+    let arch = Arch::from_header(&String::from_utf8_lossy(script)).unwrap_or_default();
+    let analyzer = SampleAnalyzer::new().with_arch(arch);
+    let mut samples = PerfSamples::new(script);
+    let mut normalized = NormalizedSamples::new(&mut samples, None);
+    let mut output = String::new();
+    while let Some(sample) = normalized.next_sample().unwrap() {
+        writeln!(output,
+                 "sample comm={:?} pid={:?} tid={:?} cpu={:?} time={:?} period={:?} \
+                  event={:?}",
+                 sample.comm(),
+                 sample.pid(),
+                 sample.tid(),
+                 sample.cpu(),
+                 sample.timestamp(),
+                 sample.period(),
+                 sample.event()).unwrap();
+        let category = analyzer.classify(&sample);
+        writeln!(output,
+                 "  category {:?} [{}]",
+                 category,
+                 category.default_severity()).unwrap();
+        for frame in sample.frames() {
+            writeln!(output,
+                     "  frame {} {} ({}) {:?}{}",
+                     frame.ip,
+                     frame.symbol,
+                     frame.dso,
+                     frame.kind(),
+                     if frame.deleted { " deleted" } else { "" }).unwrap();
+        }
+    }
+    if let Some(metadata) = RecordingMetadata::parse(samples.perf_header()) {
+        writeln!(output, "metadata {:?}", metadata).unwrap();
+    }
+    output
+}
+
+
+/// Compare the description of a fixture with its golden file, or update the
+/// golden file if asked to
+fn check_fixture(name: &str) {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let fixture = root.join("fixtures").join(format!("{}.txt", name));
+    let golden: PathBuf = root.join("golden").join(format!("{}.out", name));
+    let script = fs::read(&fixture).unwrap();
+    let actual = describe(&script);
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&golden, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&golden).unwrap_or_else(|e| {
+        panic!("Failed to read {} ({}), run with UPDATE_GOLDEN=1 to create it",
+               golden.display(), e)
+    });
+    assert!(actual == expected,
+            "Output for {} does not match {}, run with UPDATE_GOLDEN=1 to update \
+             it if this is intended\n--- expected\n{}\n--- actual\n{}",
+            fixture.display(),
+            golden.display(),
+            expected,
+            actual);
+}
+
+
+#[test]
+fn x86_64() {
+    check_fixture("x86_64");
+}
+
+#[test]
+fn aarch64() {
+    check_fixture("aarch64");
+}
+
+#[test]
+fn jvm() {
+    check_fixture("jvm");
+}
+
+#[test]
+fn tracepoints() {
+    check_fixture("tracepoints");
+}
+
+#[test]
+fn broken_stacks() {
+    check_fixture("broken_stacks");
+}
+
+#[test]
+fn legacy() {
+    check_fixture("legacy");
+}
+
+#[test]
+fn symbol_offsets() {
+    check_fixture("symbol_offsets");
+}